@@ -0,0 +1,204 @@
+//! Chaos run of an in-memory cluster over lossy, slow links. No node ever fails, so every
+//! suspicion and every down is a false positive of the failure detector. Shows how the
+//! probe timeouts trade detection time against the false positives, and fails past a
+//! budget of false downs to serve as a regression scenario:
+//! `cargo run --release --features test-kit --example cball_chaos -- --loss-percent 10 --max-false-downs 0`
+extern crate pretty_env_logger;
+
+#[macro_use]
+extern crate log;
+
+use artillery_core::epidemic::prelude::*;
+use artillery_core::errors::*;
+use clap::*;
+use lightproc::recoverable_handle::RecoverableHandle;
+use std::net::SocketAddr;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Nodes joining over clean links must all see each other by then.
+const CONVERGENCE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default)]
+struct FalsePositives {
+    suspicions: usize,
+    downs: usize,
+    refuted: usize,
+}
+
+fn main() -> Result<()> {
+    pretty_env_logger::init();
+    let matches = App::new("Cannonball :: Chaos")
+        .author("Mahmut Bulut, vertexclique [ta] gmail [tod] com")
+        .version(crate_version!())
+        .about("Measures the false-positive suspicions of a healthy cluster over faulty links")
+        .arg(
+            Arg::with_name("nodes")
+                .long("nodes")
+                .default_value("8")
+                .help("Cluster size"),
+        )
+        .arg(
+            Arg::with_name("seconds")
+                .long("seconds")
+                .default_value("60")
+                .help("Duration of the faulty run"),
+        )
+        .arg(
+            Arg::with_name("loss-percent")
+                .long("loss-percent")
+                .default_value("5")
+                .help("Share of the datagrams lost"),
+        )
+        .arg(
+            Arg::with_name("latency-ms")
+                .long("latency-ms")
+                .default_value("20")
+                .help("Delay of every datagram"),
+        )
+        .arg(
+            Arg::with_name("jitter-ms")
+                .long("jitter-ms")
+                .default_value("20")
+                .help("Random extra delay of each datagram"),
+        )
+        .arg(
+            Arg::with_name("ping-timeout-ms")
+                .long("ping-timeout-ms")
+                .default_value("3000")
+                .help("Wait for an ack before probing indirectly"),
+        )
+        .arg(
+            Arg::with_name("suspicion-timeout-ms")
+                .long("suspicion-timeout-ms")
+                .default_value("3000")
+                .help("Minimum time a member stays suspect before it is down"),
+        )
+        .arg(
+            Arg::with_name("max-false-downs")
+                .long("max-false-downs")
+                .takes_value(true)
+                .help("Fails the run past this many downs"),
+        )
+        .get_matches();
+
+    let arg = |name: &str| -> u64 { value_t!(matches, name, u64).unwrap_or_else(|e| e.exit()) };
+    let size = arg("nodes").max(2);
+    let run_for = Duration::from_secs(arg("seconds"));
+    let faults = LinkFaults {
+        loss_percent: value_t!(matches, "loss-percent", u8)
+            .unwrap_or_else(|e| e.exit())
+            .min(100),
+        latency: Duration::from_millis(arg("latency-ms")),
+        jitter: Duration::from_millis(arg("jitter-ms")),
+    };
+    let max_false_downs = matches
+        .value_of("max-false-downs")
+        .map(|_| value_t!(matches, "max-false-downs", usize).unwrap_or_else(|e| e.exit()));
+    let config = ClusterConfig {
+        cluster_key: b"chaos".to_vec(),
+        ping_timeout: Duration::from_millis(arg("ping-timeout-ms")),
+        suspicion_timeout: Duration::from_millis(arg("suspicion-timeout-ms")),
+        ..Default::default()
+    };
+
+    let network = MemoryNetwork::new();
+    let seed = SocketAddr::from(([10, 0, 0, 1], 7946));
+    let mut nodes: Vec<(Cluster, RecoverableHandle<()>)> = Vec::new();
+    for i in 0..size {
+        let addr = SocketAddr::from(([10, 0, 0, 1], 7946 + i as u16));
+        let transport = network.bind(addr)?;
+        nodes.push(Cluster::new_cluster_with_transport(
+            Uuid::new_v4(),
+            ClusterConfig {
+                seeds: if addr == seed {
+                    Vec::new()
+                } else {
+                    vec![PeerAddr::from(seed)]
+                },
+                ..config.clone()
+            },
+            transport,
+        )?);
+    }
+
+    if !converge(&nodes, size as usize) {
+        println!("The cluster didn't converge over clean links");
+        process::exit(1);
+    }
+    drain(&nodes);
+
+    info!("Running with {:?} for {:?}", faults, run_for);
+    network.set_faults(faults);
+    let start = Instant::now();
+    let mut seen = FalsePositives::default();
+    while start.elapsed() < run_for {
+        thread::sleep(Duration::from_millis(100));
+        for (cluster, _) in &nodes {
+            while let Ok((_, event, _)) = cluster.events.try_recv() {
+                match event {
+                    ArtilleryMemberEvent::SuspectedDown(member, reason) => {
+                        debug!("False suspicion of {} ({:?})", member.node_id(), reason);
+                        seen.suspicions += 1;
+                    }
+                    ArtilleryMemberEvent::WentDown(member, reason) => {
+                        warn!("False down of {} ({:?})", member.node_id(), reason);
+                        seen.downs += 1;
+                    }
+                    ArtilleryMemberEvent::WentUp(_) => seen.refuted += 1,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    let node_minutes = size as f64 * run_for.as_secs_f64() / 60.0;
+    println!(
+        "{} nodes, {}% loss, {:?} latency, {:?} jitter, ping timeout {:?}, suspicion timeout {:?}",
+        size,
+        faults.loss_percent,
+        faults.latency,
+        faults.jitter,
+        config.ping_timeout,
+        config.suspicion_timeout
+    );
+    println!(
+        "False suspicions: {} ({:.2} per node-minute), refuted: {}, false downs: {}",
+        seen.suspicions,
+        seen.suspicions as f64 / node_minutes,
+        seen.refuted,
+        seen.downs
+    );
+
+    if let Some(max) = max_false_downs.filter(|max| seen.downs > *max) {
+        println!("Failed, more than {} false downs", max);
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Waits until every node sees the whole cluster.
+fn converge(nodes: &[(Cluster, RecoverableHandle<()>)], size: usize) -> bool {
+    let deadline = Instant::now() + CONVERGENCE_TIMEOUT;
+    while Instant::now() < deadline {
+        if nodes
+            .iter()
+            .all(|(cluster, _)| cluster.member_count() == size)
+        {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    false
+}
+
+/// Forgets the events of the joins.
+fn drain(nodes: &[(Cluster, RecoverableHandle<()>)]) {
+    for (cluster, _) in nodes {
+        while cluster.events.try_recv().is_ok() {}
+    }
+}
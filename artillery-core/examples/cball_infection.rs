@@ -12,7 +12,6 @@ use std::path::Path;
 use uuid::Uuid;
 
 use artillery_core::epidemic::prelude::*;
-use std::str::FromStr;
 
 fn main() {
     pretty_env_logger::init();
@@ -86,15 +85,17 @@ fn main() {
     let (cluster, _cluster_handle) = Cluster::new_cluster(host_key, config).unwrap();
 
     if let Some(seed_node) = seed_node {
-        cluster.add_seed_node(FromStr::from_str(&seed_node).unwrap());
+        cluster
+            .add_seed_node(seed_node.parse::<PeerAddr>().unwrap())
+            .unwrap();
     }
 
     warn!("STARTED: Event Poller");
-    for (members, event) in cluster.events.iter() {
+    for (members, event, stamp) in cluster.events.iter() {
         warn!("");
         warn!(" CLUSTER EVENT ");
         warn!("===============");
-        warn!("{:?}", event);
+        warn!("#{} {:?}", stamp.seq, event);
         warn!("");
 
         for member in members {
@@ -73,18 +73,18 @@ fn main() {
     thread::sleep(Duration::from_secs(1));
     for discovery in sd.events().iter() {
         if discovery.get().port() != this_node_cluster_port {
-            cluster.add_seed_node(discovery.get());
+            cluster.add_seed_node(discovery.get()).unwrap();
         }
     }
 }
 
 fn poll_cluster_events(listen_addr: &str, host_key: Uuid) {
     warn!("STARTED: Event Poller");
-    for (members, event) in get_cluster(listen_addr, host_key).events.iter() {
+    for (members, event, stamp) in get_cluster(listen_addr, host_key).events.iter() {
         warn!("");
         warn!(" CLUSTER EVENT ");
         warn!("===============");
-        warn!("{:?}", event);
+        warn!("#{} {:?}", stamp.seq, event);
         warn!("");
 
         for member in members {
@@ -18,7 +18,6 @@ use artillery_core::service_discovery::udp_anycast::prelude::*;
 use chrono::Duration;
 use once_cell::sync::OnceCell;
 use serde::*;
-use std::str::FromStr;
 use std::sync::mpsc::channel;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
@@ -113,18 +112,20 @@ fn main() {
         if discovery.port != epidemic_sd_config.port {
             debug!("Seed node address came");
             let seed_node = format!("{}:{}", epidemic_sd_config.ip, discovery.port);
-            cluster.add_seed_node(FromStr::from_str(&seed_node).unwrap());
+            cluster
+                .add_seed_node(seed_node.parse::<PeerAddr>().unwrap())
+                .unwrap();
         }
     }
 }
 
 fn poll_cluster_events(listen_addr: &str, host_key: Uuid) {
     warn!("STARTED: Event Poller");
-    for (members, event) in get_cluster(listen_addr, host_key).events.iter() {
+    for (members, event, stamp) in get_cluster(listen_addr, host_key).events.iter() {
         warn!("");
         warn!(" CLUSTER EVENT ");
         warn!("===============");
-        warn!("{:?}", event);
+        warn!("#{} {:?}", stamp.seq, event);
         warn!("");
 
         for member in members {
@@ -0,0 +1,222 @@
+//! Soak test of a local cluster under churn, failing when the protocol buffers of a node
+//! outgrow the limit or events come out of order:
+//! `cargo run --release --example cball_soak -- --minutes 240`
+extern crate pretty_env_logger;
+
+#[macro_use]
+extern crate log;
+
+use artillery_core::epidemic::prelude::*;
+use artillery_core::errors::*;
+use clap::*;
+use mio::{Events, Poll, Token};
+use rand::Rng;
+use std::net::SocketAddr;
+use std::process;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Ports of the churned nodes are reused after this many spawns.
+const PORT_RANGE: usize = 1000;
+
+/// Nodes leaving gracefully keep running this long to gossip their departure.
+const LEAVE_GRACE: Duration = Duration::from_secs(5);
+
+struct SoakNode {
+    cluster: Cluster,
+    driver: ClusterDriver,
+    last_seq: Option<u64>,
+}
+
+fn main() -> Result<()> {
+    pretty_env_logger::init();
+    let matches = App::new("Cannonball :: Soak")
+        .author("Mahmut Bulut, vertexclique [ta] gmail [tod] com")
+        .version(crate_version!())
+        .about("Runs a small local cluster under churn, checking that its state stays bounded")
+        .arg(
+            Arg::with_name("nodes")
+                .long("nodes")
+                .default_value("5")
+                .help("Cluster size"),
+        )
+        .arg(
+            Arg::with_name("minutes")
+                .long("minutes")
+                .default_value("240")
+                .help("Duration of the run"),
+        )
+        .arg(
+            Arg::with_name("churn-secs")
+                .long("churn-secs")
+                .default_value("30")
+                .help("Seconds between replacing a random node"),
+        )
+        .arg(
+            Arg::with_name("limit")
+                .long("limit")
+                .default_value("256")
+                .help("Largest size allowed for any protocol buffer of a node"),
+        )
+        .arg(
+            Arg::with_name("base-port")
+                .long("base-port")
+                .default_value("47000")
+                .help("First port of the nodes on 127.0.0.1"),
+        )
+        .get_matches();
+
+    let arg = |name: &str| -> u64 { value_t!(matches, name, u64).unwrap_or_else(|e| e.exit()) };
+    let size = arg("nodes").max(2);
+    let run_for = Duration::from_secs(arg("minutes") * 60);
+    let churn_every = Duration::from_secs(arg("churn-secs"));
+    let limit = arg("limit");
+    let base_port = value_t!(matches, "base-port", u16).unwrap_or_else(|e| e.exit());
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(256);
+    let seed = SocketAddr::from(([127, 0, 0, 1], base_port));
+    let mut spawned = 0;
+    let mut nodes = Vec::new();
+    let mut leaving: Vec<(Instant, SoakNode)> = Vec::new();
+    for _ in 0..size {
+        nodes.push(spawn(&mut poll, base_port, &mut spawned, seed)?);
+    }
+
+    let deadline = Instant::now() + run_for;
+    let mut next_churn = Instant::now() + churn_every;
+    let mut next_report = Instant::now();
+    let mut violations = 0;
+
+    while Instant::now() < deadline {
+        let timeout = nodes
+            .iter()
+            .map(|node| node.driver.timeout())
+            .min()
+            .unwrap_or_default()
+            .min(Duration::from_millis(100));
+        poll.poll(&mut events, Some(timeout))?;
+
+        for event in events.iter() {
+            for node in nodes.iter_mut().chain(leaving.iter_mut().map(|(_, n)| n)) {
+                if node.driver.handle_event(event)? {
+                    break;
+                }
+            }
+        }
+
+        for node in nodes.iter_mut().chain(leaving.iter_mut().map(|(_, n)| n)) {
+            node.driver.tick();
+            violations += check_event_order(node);
+        }
+        leaving.retain(|(since, _)| since.elapsed() < LEAVE_GRACE);
+
+        if Instant::now() >= next_churn {
+            // The first node is the seed, it stays. Every other node leaving crashes.
+            let victim = rand::thread_rng().gen_range(1, nodes.len());
+            let gone = nodes.remove(victim);
+            if spawned % 2 == 0 {
+                gone.cluster.leave_cluster();
+                leaving.push((Instant::now(), gone));
+            }
+
+            nodes.push(spawn(&mut poll, base_port, &mut spawned, seed)?);
+            next_churn = Instant::now() + churn_every;
+        }
+
+        if Instant::now() >= next_report {
+            if !report(&nodes, limit) {
+                process::exit(1);
+            }
+            next_report = Instant::now() + Duration::from_secs(60);
+        }
+    }
+
+    if !report(&nodes, limit) || violations > 0 {
+        println!("Soak failed, {} event ordering violations", violations);
+        process::exit(1);
+    }
+
+    println!("Soak passed after {} spawns", spawned);
+    Ok(())
+}
+
+fn spawn(
+    poll: &mut Poll,
+    base_port: u16,
+    spawned: &mut usize,
+    seed: SocketAddr,
+) -> Result<SoakNode> {
+    let port = match *spawned {
+        0 => base_port,
+        n => base_port + 1 + ((n - 1) % (PORT_RANGE - 1)) as u16,
+    };
+    let listen_addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let config = ClusterConfig {
+        cluster_key: b"soak".to_vec(),
+        listen_addr,
+        reap_after: Some(Duration::from_secs(60)),
+        seeds: if listen_addr == seed {
+            Vec::new()
+        } else {
+            vec![PeerAddr::from(seed)]
+        },
+        ..Default::default()
+    };
+
+    let (cluster, mut driver) = ClusterDriver::new(Uuid::new_v4(), config)?;
+    driver.register(poll, Token(*spawned))?;
+    *spawned += 1;
+    debug!("Spawned a node on {}", listen_addr);
+
+    Ok(SoakNode {
+        cluster,
+        driver,
+        last_seq: None,
+    })
+}
+
+/// Counts the events not following the previous one of the node.
+fn check_event_order(node: &mut SoakNode) -> usize {
+    let mut violations = 0;
+
+    while let Ok((_, event, stamp)) = node.cluster.events.try_recv() {
+        if let Some(last) = node.last_seq {
+            if stamp.seq != last + 1 {
+                error!("Event #{} after #{}: {:?}", stamp.seq, last, event);
+                violations += 1;
+            }
+        }
+        node.last_seq = Some(stamp.seq);
+    }
+
+    violations
+}
+
+/// Prints the footprint of every node, returns whether all of them are within the limit.
+fn report(nodes: &[SoakNode], limit: u64) -> bool {
+    let mut within = true;
+
+    for node in nodes {
+        let footprint = node.driver.footprint();
+        let largest = [
+            footprint.members,
+            footprint.state_changes,
+            footprint.wait_list,
+            footprint.pending_responses,
+            footprint.deferred_work,
+        ]
+        .iter()
+        .copied()
+        .max()
+        .unwrap_or_default();
+
+        println!("{:?}", footprint);
+        if largest as u64 > limit {
+            println!("Footprint over the limit of {}", limit);
+            within = false;
+        }
+    }
+
+    within
+}
@@ -8,8 +8,6 @@ macro_rules! cluster_init {
 
         use std::net::ToSocketAddrs;
 
-        use uuid::Uuid;
-
         use artillery_core::epidemic::prelude::*;
         use artillery_core::service_discovery::mdns::prelude::*;
 
@@ -33,7 +31,7 @@ macro_rules! cluster_init {
             // Initialize our cluster configuration
             let ap_cluster_config = ArtilleryAPClusterConfig {
                 app_name: String::from("artillery-ap"),
-                node_id: Uuid::new_v4(),
+                node_id: NodeId::random(),
                 sd_config: {
                     let mut config = MDNSServiceDiscoveryConfig::default();
                     config.local_service_addr.set_port(port);
@@ -71,11 +69,11 @@ macro_rules! cluster_init {
             let events_handle = spawn_blocking(
                 async move {
                     warn!("STARTED: Event Poller");
-                    for (members, event) in ap_events.cluster().events.iter() {
+                    for (members, event, stamp) in ap_events.cluster().events.iter() {
                         warn!("");
                         warn!(" CLUSTER EVENT ");
                         warn!("===============");
-                        warn!("{:?}", event);
+                        warn!("#{} {:?}", stamp.seq, event);
                         warn!("");
 
                         for member in members {
@@ -7,12 +7,11 @@ use lightproc::prelude::*;
 use futures::{select, FutureExt};
 use pin_utils::pin_mut;
 use std::{cell::Cell, sync::Arc};
-use uuid::Uuid;
 
 #[derive(Default, Clone)]
 pub struct ArtilleryAPClusterConfig {
     pub app_name: String,
-    pub node_id: Uuid,
+    pub node_id: NodeId,
     pub cluster_config: ClusterConfig,
     pub sd_config: MDNSServiceDiscoveryConfig,
 }
@@ -80,6 +79,10 @@ impl ArtilleryAPCluster {
             .filter(|discovery| {
                 discovery.get().port() != self.config.sd_config.local_service_addr.port()
             })
-            .for_each(|discovery| self.cluster.add_seed_node(discovery.get()))
+            .for_each(|discovery| {
+                if let Err(e) = self.cluster.add_seed_node(discovery.get()) {
+                    log_transport!(Warn, "Unable to add the discovered node: {}", e);
+                }
+            })
     }
 }
@@ -0,0 +1,181 @@
+use crate::epidemic::export;
+use crate::epidemic::prelude::*;
+use crate::epidemic::state::is_topology_change;
+use crate::errors::*;
+use crate::logging;
+
+use bastion_executor::prelude::*;
+use lightproc::prelude::*;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Topic of the relayed membership, the body is the memberlist JSON of [`export`].
+pub const MEMBERSHIP_TOPIC: &str = "artillery.members";
+
+/// Marks the payloads coming through a gateway, they are never relayed again.
+pub const RELAYED_PREFIX: &str = "relayed:";
+
+/// Node participating in two clusters. The topic of a payload is the part before its
+/// first `:`, payloads sent to the gateway with one of the `topics` are delivered to
+/// every live member of the other cluster as `relayed:<topic>:<body>`.
+#[derive(Default, Clone)]
+pub struct ArtilleryGatewayConfig {
+    pub node_id: NodeId,
+    /// Both clusters need their own name, key and listen address.
+    pub first: ClusterConfig,
+    pub second: ClusterConfig,
+    pub topics: Vec<String>,
+    /// Members of one cluster announced to the other on every join, leave or failure,
+    /// under [`MEMBERSHIP_TOPIC`]. `None` relays no membership.
+    pub membership_filter: Option<MemberFilter>,
+}
+
+pub struct ArtilleryGateway {
+    first: Arc<Cluster>,
+    second: Arc<Cluster>,
+    _handles: Vec<RecoverableHandle<()>>,
+}
+
+unsafe impl Send for ArtilleryGateway {}
+unsafe impl Sync for ArtilleryGateway {}
+
+impl ArtilleryGateway {
+    pub fn new(config: ArtilleryGatewayConfig) -> Result<Self> {
+        let (first_cluster, first_handle) =
+            Cluster::new_cluster(config.node_id, config.first.clone())?;
+        let (second_cluster, second_handle) =
+            Cluster::new_cluster(config.node_id, config.second.clone())?;
+        let first = Arc::new(first_cluster);
+        let second = Arc::new(second_cluster);
+
+        let forward = Relay {
+            source: first.clone(),
+            source_addr: first.local_addr(),
+            target: second.clone(),
+            topics: config.topics.clone(),
+            membership_filter: config.membership_filter.clone(),
+        };
+        let backward = Relay {
+            source: second.clone(),
+            source_addr: second.local_addr(),
+            target: first.clone(),
+            topics: config.topics,
+            membership_filter: config.membership_filter,
+        };
+
+        Ok(ArtilleryGateway {
+            first,
+            second,
+            _handles: vec![
+                first_handle,
+                second_handle,
+                forward.spawn(),
+                backward.spawn(),
+            ],
+        })
+    }
+
+    pub fn first(&self) -> Arc<Cluster> {
+        self.first.clone()
+    }
+
+    pub fn second(&self) -> Arc<Cluster> {
+        self.second.clone()
+    }
+
+    pub fn shutdown(&self) {
+        self.first.leave_cluster();
+        self.second.leave_cluster();
+    }
+}
+
+/// One direction of the gateway, consumes the events of the source cluster.
+struct Relay {
+    source: Arc<Cluster>,
+    source_addr: SocketAddr,
+    target: Arc<Cluster>,
+    topics: Vec<String>,
+    membership_filter: Option<MemberFilter>,
+}
+
+unsafe impl Send for Relay {}
+
+impl Relay {
+    fn spawn(self) -> RecoverableHandle<()> {
+        spawn_blocking(async move { self.run() }, ProcStack::default())
+    }
+
+    /// Runs until the source cluster stops.
+    fn run(&self) {
+        let _instance = logging::enter_instance(self.source.instance_name());
+        for (members, event, _) in &self.source.events {
+            if let ArtilleryMemberEvent::Payload(_, msg) = &event {
+                if let Some(relayed) = relayed_payload(msg, &self.topics) {
+                    self.deliver(&relayed);
+                }
+                continue;
+            }
+
+            if let Some(filter) = &self.membership_filter {
+                if is_topology_change(&event) {
+                    let announced: Vec<ArtilleryMember> =
+                        members.into_iter().filter(|m| filter(m)).collect();
+
+                    match export::export_members(&announced, self.source_addr) {
+                        Ok(json) => self
+                            .deliver(&format!("{}{}:{}", RELAYED_PREFIX, MEMBERSHIP_TOPIC, json)),
+                        Err(e) => log_dissemination!(
+                            Warn,
+                            "Unable to export the relayed membership: {}",
+                            e
+                        ),
+                    }
+                }
+            }
+        }
+
+        log_runtime!(Debug, "Gateway relay stopped with its source cluster");
+    }
+
+    fn deliver(&self, payload: &str) {
+        for member in self.target.view().alive_members() {
+            if member.is_remote() {
+                self.target.send_payload(member.host_key(), payload);
+            }
+        }
+    }
+}
+
+fn relayed_payload(msg: &str, topics: &[String]) -> Option<String> {
+    if msg.starts_with(RELAYED_PREFIX) {
+        return None;
+    }
+
+    let topic = msg.split(':').next()?;
+    if topics.iter().any(|t| t == topic) {
+        Some(format!("{}{}", RELAYED_PREFIX, msg))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn relays_configured_topics_once() {
+        let topics = vec![String::from("orders")];
+
+        assert_eq!(
+            relayed_payload("orders:42", &topics),
+            Some(String::from("relayed:orders:42"))
+        );
+        assert_eq!(relayed_payload("invoices:42", &topics), None);
+        assert_eq!(
+            relayed_payload("orders", &topics),
+            Some(String::from("relayed:orders"))
+        );
+        assert_eq!(relayed_payload("relayed:orders:42", &topics), None);
+    }
+}
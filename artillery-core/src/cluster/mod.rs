@@ -1 +1,3 @@
 pub mod ap;
+pub mod gateway;
+pub mod scheduler;
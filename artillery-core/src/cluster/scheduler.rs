@@ -0,0 +1,309 @@
+use crate::epidemic::prelude::*;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// FNV-1a, stable across builds and platforms so every node places the keys alike. The
+/// final mix of `MurmurHash3` spreads the close inputs, like the replicas of a member, around
+/// the ring.
+fn ring_hash(bytes: &[u8]) -> u64 {
+    let folded = bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+
+    let mixed = (folded ^ (folded >> 33)).wrapping_mul(0xff51_afd7_ed55_8ccd);
+    let mixed_twice = (mixed ^ (mixed >> 33)).wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    mixed_twice ^ (mixed_twice >> 33)
+}
+
+/// Consistent hash ring of members. Every member is placed `virtual_nodes` times, a key
+/// belongs to the first member placed after its hash.
+#[derive(Debug, Clone, Default)]
+pub struct HashRing {
+    points: Vec<(u64, NodeId)>,
+}
+
+impl HashRing {
+    pub fn new<I: IntoIterator<Item = NodeId>>(members: I, virtual_nodes: usize) -> Self {
+        let mut points: Vec<(u64, NodeId)> = members
+            .into_iter()
+            .flat_map(|member| {
+                (0_u64..).take(virtual_nodes).map(move |replica| {
+                    let mut bytes = member.as_bytes().to_vec();
+                    bytes.extend_from_slice(&replica.to_le_bytes());
+                    (ring_hash(&bytes), member)
+                })
+            })
+            .collect();
+        points.sort();
+
+        HashRing { points }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn owner(&self, key: &str) -> Option<NodeId> {
+        let hash = ring_hash(key.as_bytes());
+        let position = match self.points.binary_search_by(|(point, _)| point.cmp(&hash)) {
+            Ok(position) | Err(position) => position,
+        };
+
+        self.points
+            .get(position)
+            .or_else(|| self.points.first())
+            .map(|(_, member)| *member)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SchedulerConfig {
+    /// How often the ownership and the due tasks are checked.
+    pub tick: Duration,
+    /// A node runs a task only after owning it for this long, so that the previous owner
+    /// sees the topology change too. Should exceed the time the membership takes to converge.
+    pub handover_delay: Duration,
+    pub virtual_nodes: usize,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        SchedulerConfig {
+            tick: Duration::from_secs(1),
+            handover_delay: Duration::from_secs(10),
+            virtual_nodes: 64,
+        }
+    }
+}
+
+pub type ScheduledTask = Arc<dyn Fn() + Send + Sync>;
+
+struct TaskState {
+    interval: Duration,
+    task: ScheduledTask,
+    /// Next run while this node owns the task.
+    next_run: Option<Instant>,
+}
+
+/// Named periodic tasks and the ring deciding which node runs them.
+pub(crate) struct Schedule {
+    config: SchedulerConfig,
+    tasks: HashMap<String, TaskState>,
+    members: Vec<NodeId>,
+    ring: HashRing,
+}
+
+impl Schedule {
+    pub(crate) fn new(config: SchedulerConfig) -> Self {
+        Schedule {
+            config,
+            tasks: HashMap::new(),
+            members: Vec::new(),
+            ring: HashRing::default(),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, name: String, interval: Duration, task: ScheduledTask) {
+        self.tasks.insert(
+            name,
+            TaskState {
+                interval,
+                task,
+                next_run: None,
+            },
+        );
+    }
+
+    pub(crate) fn remove(&mut self, name: &str) {
+        self.tasks.remove(name);
+    }
+
+    pub(crate) fn owner(&self, name: &str) -> Option<NodeId> {
+        self.ring.owner(name)
+    }
+
+    /// Follows the membership, returns the tasks of `me` due at `now`.
+    pub(crate) fn due(
+        &mut self,
+        me: NodeId,
+        mut members: Vec<NodeId>,
+        now: Instant,
+    ) -> Vec<ScheduledTask> {
+        members.sort();
+        members.dedup();
+        if members != self.members {
+            self.ring = HashRing::new(members.iter().copied(), self.config.virtual_nodes);
+            self.members = members;
+        }
+
+        let ring = &self.ring;
+        let handover_delay = self.config.handover_delay;
+        let mut due = Vec::new();
+        for (name, state) in &mut self.tasks {
+            if ring.owner(name) != Some(me) {
+                state.next_run = None;
+                continue;
+            }
+
+            let next_run = *state.next_run.get_or_insert(now + handover_delay);
+            if now >= next_run {
+                state.next_run = Some(now + state.interval);
+                due.push(state.task.clone());
+            }
+        }
+
+        due
+    }
+}
+
+/// Runs named periodic tasks on the ring owner of their name only. Ownership moves with
+/// the membership: a node takes a task over `handover_delay` after it became its owner.
+/// During stable membership at most one node runs a task; while the members disagree
+/// on the membership two of them can. Tasks run on the scheduler thread, one after the other.
+pub struct ClusterScheduler {
+    schedule: Arc<Mutex<Schedule>>,
+    running: Arc<AtomicBool>,
+    worker: Option<thread::JoinHandle<()>>,
+}
+
+impl ClusterScheduler {
+    pub fn start(cluster: &Cluster, config: SchedulerConfig) -> Self {
+        let view = cluster.view();
+        let tick = config.tick;
+        let schedule = Arc::new(Mutex::new(Schedule::new(config)));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let worker_schedule = schedule.clone();
+        let worker_running = running.clone();
+        let worker = thread::spawn(move || {
+            while worker_running.load(Ordering::SeqCst) {
+                let (current, members) = ring_members(&view);
+                let due = match current {
+                    Some(me) => worker_schedule
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .due(me, members, Instant::now()),
+                    None => Vec::new(),
+                };
+
+                for task in due {
+                    task();
+                }
+
+                thread::sleep(tick);
+            }
+        });
+
+        ClusterScheduler {
+            schedule,
+            running,
+            worker: Some(worker),
+        }
+    }
+
+    /// Runs `task` every `interval` on the owner of `name`, replacing the task of that name.
+    pub fn schedule<N, F>(&self, name: N, interval: Duration, task: F)
+    where
+        N: Into<String>,
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.lock().insert(name.into(), interval, Arc::new(task));
+    }
+
+    pub fn unschedule(&self, name: &str) {
+        self.lock().remove(name);
+    }
+
+    /// Member running the task of that name, as far as this node knows.
+    pub fn owner(&self, name: &str) -> Option<NodeId> {
+        self.lock().owner(name)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Schedule> {
+        self.schedule.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for ClusterScheduler {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// This node and the members which haven't failed, suspects keep their tasks until down.
+fn ring_members(view: &SharedMembershipView) -> (Option<NodeId>, Vec<NodeId>) {
+    let members = view.read();
+    let me = members
+        .members()
+        .iter()
+        .find(|m| m.is_current())
+        .map(ArtilleryMember::node_id);
+    let up = members
+        .members()
+        .iter()
+        .filter_map(|m| match m.state() {
+            ArtilleryMemberState::Alive | ArtilleryMemberState::Suspect => Some(m.node_id()),
+            ArtilleryMemberState::Down | ArtilleryMemberState::Left => None,
+        })
+        .collect();
+
+    (me, up)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ring_owners_move_only_off_removed_members() {
+        let members: Vec<NodeId> = (0..4).map(|_| NodeId::random()).collect();
+        let full = HashRing::new(members.clone(), 64);
+        let reduced = HashRing::new(members[..3].to_vec(), 64);
+
+        let keys: Vec<String> = (0..200).map(|i| format!("task-{}", i)).collect();
+        for key in &keys {
+            let owner = full.owner(key).unwrap();
+            if owner != members[3] {
+                assert_eq!(reduced.owner(key), Some(owner));
+            }
+        }
+        assert!(keys.iter().any(|key| full.owner(key) == Some(members[3])));
+        assert_eq!(HashRing::new(Vec::new(), 64).owner("task"), None);
+    }
+
+    #[test]
+    fn tasks_run_on_their_owner_after_the_handover() {
+        let (a, b) = (NodeId::random(), NodeId::random());
+        let ring = HashRing::new(vec![a, b], 64);
+        let name = (0..)
+            .map(|i| format!("task-{}", i))
+            .find(|name| ring.owner(name) == Some(b))
+            .unwrap();
+
+        let config = SchedulerConfig::default();
+        let delay = config.handover_delay;
+        let interval = Duration::from_secs(30);
+        let mut on_a = Schedule::new(config.clone());
+        let mut on_b = Schedule::new(config);
+        on_a.insert(name.clone(), interval, Arc::new(|| {}));
+        on_b.insert(name, interval, Arc::new(|| {}));
+        let start = Instant::now();
+
+        // Alone, a owns everything but waits for the handover
+        assert!(on_a.due(a, vec![a], start).is_empty());
+        assert!(on_a.due(a, vec![a, b], start + delay).is_empty());
+
+        assert!(on_b.due(b, vec![a, b], start).is_empty());
+        assert_eq!(on_b.due(b, vec![b, a], start + delay).len(), 1);
+        assert!(on_b.due(b, vec![a, b], start + delay).is_empty());
+        assert_eq!(on_b.due(b, vec![a, b], start + delay + interval).len(), 1);
+        assert!(on_a.due(a, vec![a, b], start + delay * 3).is_empty());
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 // DISCO = 34726
 /// Default Service Discovery Port
 pub const CONST_SERVICE_DISCOVERY_PORT: u16 = 34726;
@@ -10,3 +12,63 @@ pub const CONST_INFECTION_PORT: u16 = 27845;
 // Behave like this is the size. Normally 512 is enough.
 /// Default UDP cast packet size
 pub const CONST_PACKET_SIZE: usize = 1 << 16;
+
+/// Upper bound of the application heartbeat blob piggybacked on pings
+pub const CONST_HEARTBEAT_PAYLOAD_LIMIT: usize = 128;
+
+/// Upper bound of the metadata of a member, keys and values, gossiped with its record
+pub const CONST_METADATA_LIMIT: usize = 512;
+
+/// Maximum number of gossiped records of a kind piggybacked on a single message
+pub const CONST_BROADCAST_BATCH: usize = 8;
+
+/// Members carried by a single full state sync message
+pub const CONST_SYNC_CHUNK: usize = 16;
+
+/// Upper bound of a message sent over TCP, e.g. the full state of a state sync
+pub const CONST_TCP_FRAME_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Exchanges over TCP not done by then are given up
+pub const CONST_TCP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Members summarized by a single multicast digest
+pub const CONST_DIGEST_CHUNK: usize = 16;
+
+/// Pings carry a membership digest in pull gossip mode up to this many members
+pub const CONST_PULL_DIGEST_LIMIT: usize = 512;
+
+/// Undelivered diagnostics kept before new ones get dropped
+pub const CONST_DIAGNOSTICS_CAPACITY: usize = 256;
+
+/// How often `Cluster::flush` checks the outbound queue
+pub const CONST_FLUSH_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Protocol rounds between the attempts to upgrade a relayed route to a direct one
+pub const CONST_RELAY_UPGRADE_ROUNDS: usize = 30;
+
+/// How often a node repeats its reachability observation while it isn't empty
+pub const CONST_REACHABILITY_REFRESH: Duration = Duration::from_secs(30);
+
+/// How long expired lock leases are remembered, so that late gossip doesn't revive them
+pub const CONST_LOCK_RETENTION: Duration = Duration::from_secs(600);
+
+/// Room left in relayed messages for the relay header
+pub const CONST_RELAY_ENVELOPE_OVERHEAD: usize = 128;
+
+/// Shutdown orders older than this are refused, so that they don't stop the nodes started since
+pub const CONST_SHUTDOWN_ORDER_RETENTION: Duration = Duration::from_secs(600);
+
+/// Upper bound of an application payload broadcast over the gossip
+pub const CONST_USER_PAYLOAD_LIMIT: usize = 512;
+
+/// Broadcast payloads piggybacked on a single message, as they are larger than the other records
+pub const CONST_USER_PAYLOAD_BATCH: usize = 2;
+
+/// How long broadcast payloads are remembered, older ones are refused as possible duplicates
+pub const CONST_USER_PAYLOAD_RETENTION: Duration = Duration::from_secs(300);
+
+/// Upper bound of an application message sent to a single member, so that it fits a datagram
+pub const CONST_MESSAGE_LIMIT: usize = 1024;
+
+/// Nodes obeying a shutdown order keep gossiping their leave and the order this long at most
+pub const CONST_SHUTDOWN_ORDER_GRACE: Duration = Duration::from_secs(10);
@@ -0,0 +1,406 @@
+use super::resolver::{Resolver, SystemResolver};
+use crate::errors::*;
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Address of a peer, either already resolved or a host name resolved when it's used,
+/// e.g. `"node1.internal:7001".parse::<PeerAddr>()`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PeerAddr {
+    Socket(SocketAddr),
+    Host(String, u16),
+}
+
+impl PeerAddr {
+    pub fn host<H: Into<String>>(host: H, port: u16) -> Self {
+        PeerAddr::Host(host.into(), port)
+    }
+
+    /// The address if it doesn't need a resolution.
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        match self {
+            PeerAddr::Socket(addr) => Some(*addr),
+            PeerAddr::Host(..) => None,
+        }
+    }
+
+    /// Every address the peer resolves to, blocking on the system resolver for host names.
+    pub fn resolve(&self) -> Result<Vec<SocketAddr>> {
+        self.resolve_with(&SystemResolver)
+    }
+
+    /// Every address the peer resolves to, blocking on the resolver for host names.
+    pub fn resolve_with(&self, resolver: &dyn Resolver) -> Result<Vec<SocketAddr>> {
+        match self {
+            PeerAddr::Socket(addr) => Ok(vec![*addr]),
+            PeerAddr::Host(host, port) => {
+                let addrs = resolver.resolve(host, *port)?;
+                if addrs.is_empty() {
+                    return Err(ArtilleryError::InvalidArgument(format!(
+                        "{} resolves to no address",
+                        self
+                    )));
+                }
+
+                Ok(addrs)
+            }
+        }
+    }
+}
+
+impl From<SocketAddr> for PeerAddr {
+    fn from(addr: SocketAddr) -> Self {
+        PeerAddr::Socket(addr)
+    }
+}
+
+impl FromStr for PeerAddr {
+    type Err = ArtilleryError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Ok(addr) = s.parse::<SocketAddr>() {
+            return Ok(PeerAddr::Socket(addr));
+        }
+
+        let mut parts = s.rsplitn(2, ':');
+        let port = parts.next().and_then(|p| p.parse::<u16>().ok());
+        let host = parts.next().filter(|h| !h.is_empty() && !h.contains(':'));
+
+        match (host, port) {
+            (Some(name), Some(number)) => Ok(PeerAddr::host(name, number)),
+            _ => Err(ArtilleryError::InvalidArgument(format!(
+                "'{}' is not a host:port address",
+                s
+            ))),
+        }
+    }
+}
+
+/// As its `host:port` text, e.g. in the events published over the event bridge.
+impl Serialize for PeerAddr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl fmt::Display for PeerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PeerAddr::Socket(addr) => write!(f, "{}", addr),
+            PeerAddr::Host(host, port) => write!(f, "{}:{}", host, port),
+        }
+    }
+}
+
+/// Seed given by host name. Its addresses are refreshed periodically, and the seed is
+/// contacted on the next one every round until any of them answers.
+#[derive(Debug)]
+pub(crate) struct HostSeed {
+    addr: PeerAddr,
+    candidates: Vec<SocketAddr>,
+    current: usize,
+    resolved_at: Option<Instant>,
+    resolving: bool,
+    answered: bool,
+}
+
+impl HostSeed {
+    pub(crate) fn new(addr: PeerAddr) -> Self {
+        HostSeed {
+            addr,
+            candidates: Vec::new(),
+            current: 0,
+            resolved_at: None,
+            resolving: false,
+            answered: false,
+        }
+    }
+
+    pub(crate) fn addr(&self) -> &PeerAddr {
+        &self.addr
+    }
+
+    pub(crate) fn needs_resolution(&self, now: Instant, interval: Duration) -> bool {
+        !self.resolving
+            && self
+                .resolved_at
+                .is_none_or(|at| now.duration_since(at) >= interval)
+    }
+
+    pub(crate) fn start_resolution(&mut self) {
+        self.resolving = true;
+    }
+
+    /// A failed resolution keeps the previous addresses until the next attempt.
+    pub(crate) fn resolved(&mut self, candidates: Vec<SocketAddr>, now: Instant) {
+        self.resolving = false;
+        self.resolved_at = Some(now);
+
+        if candidates.is_empty() {
+            return;
+        }
+
+        let target = self.target();
+        self.current = target
+            .and_then(|t| candidates.iter().position(|c| *c == t))
+            .unwrap_or(0);
+        self.candidates = candidates;
+    }
+
+    pub(crate) fn target(&self) -> Option<SocketAddr> {
+        self.candidates.get(self.current).copied()
+    }
+
+    /// Moves on to the next address for the round after.
+    pub(crate) fn fail_over(&mut self) {
+        if !self.candidates.is_empty() {
+            self.current = (self.current + 1) % self.candidates.len();
+        }
+    }
+
+    pub(crate) fn is_at(&self, addr: SocketAddr) -> bool {
+        self.candidates.contains(&addr)
+    }
+
+    pub(crate) fn is_joining(&self) -> bool {
+        !self.answered
+    }
+
+    pub(crate) fn answered(&mut self) {
+        self.answered = true;
+    }
+}
+
+/// How contacting a seed went so far, see `Cluster::seed_contacts`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SeedContact {
+    pub seed: PeerAddr,
+    /// Heartbeats sent to the seed until it answered, or so far.
+    pub attempts: usize,
+    /// Between the last heartbeat to the answering address and the first answer,
+    /// `None` until the seed answers.
+    pub rtt: Option<Duration>,
+}
+
+impl SeedContact {
+    pub fn is_contacted(&self) -> bool {
+        self.rtt.is_some()
+    }
+}
+
+#[derive(Debug)]
+struct TrackedSeed {
+    contact: SeedContact,
+    last_sent: HashMap<SocketAddr, Instant>,
+}
+
+/// Contact attempts of every seed added, a seed counts as contacted on its first answer.
+#[derive(Debug, Default)]
+pub(crate) struct SeedTracker {
+    seeds: Vec<TrackedSeed>,
+}
+
+impl SeedTracker {
+    pub(crate) fn attempted(&mut self, seed: &PeerAddr, target: SocketAddr, now: Instant) {
+        let index = if let Some(index) = self.seeds.iter().position(|s| s.contact.seed == *seed) {
+            index
+        } else {
+            self.seeds.push(TrackedSeed {
+                contact: SeedContact {
+                    seed: seed.clone(),
+                    attempts: 0,
+                    rtt: None,
+                },
+                last_sent: HashMap::new(),
+            });
+            self.seeds.len() - 1
+        };
+
+        let tracked = &mut self.seeds[index];
+        if !tracked.contact.is_contacted() {
+            tracked.contact.attempts += 1;
+            tracked.last_sent.insert(target, now);
+        }
+    }
+
+    /// Seeds contacted for the first time by this answer.
+    pub(crate) fn answered(&mut self, src: SocketAddr, now: Instant) -> Vec<SeedContact> {
+        self.seeds
+            .iter_mut()
+            .filter_map(|s| {
+                if s.contact.is_contacted() {
+                    return None;
+                }
+                let sent = s.last_sent.remove(&src)?;
+                s.contact.rtt = Some(now.saturating_duration_since(sent));
+                s.last_sent.clear();
+                Some(s.contact.clone())
+            })
+            .collect()
+    }
+
+    pub(crate) fn contacts(&self) -> Vec<SeedContact> {
+        self.seeds.iter().map(|s| s.contact.clone()).collect()
+    }
+}
+
+/// Address of `peer` as seen by a socket bound to `listen_addr`. A dual stack IPv6 socket
+/// reaches IPv4 peers at their IPv4-mapped address, other mixes of families can't be reached.
+pub(crate) fn peer_addr_for(
+    listen_addr: SocketAddr,
+    peer: SocketAddr,
+    dual_stack: bool,
+) -> std::result::Result<SocketAddr, String> {
+    match (listen_addr, peer) {
+        (SocketAddr::V4(_), SocketAddr::V4(_)) | (SocketAddr::V6(_), SocketAddr::V6(_)) => Ok(peer),
+        (SocketAddr::V6(_), SocketAddr::V4(v4)) if dual_stack => Ok(SocketAddr::new(
+            IpAddr::V6(v4.ip().to_ipv6_mapped()),
+            v4.port(),
+        )),
+        (SocketAddr::V6(_), SocketAddr::V4(_)) => Err(format!(
+            "{} is IPv4 but listen_addr {} is IPv6, enable dual_stack to reach it \
+             through its IPv4-mapped address",
+            peer, listen_addr
+        )),
+        (SocketAddr::V4(_), SocketAddr::V6(_)) => Err(format!(
+            "{} is IPv6 but listen_addr {} is IPv4, listen on an IPv6 address to reach it",
+            peer, listen_addr
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::state::ArtilleryMemberEvent;
+    use crate::epidemic::test_kit::*;
+
+    #[test]
+    fn parses_addresses_and_host_names() {
+        let socket: SocketAddr = "127.0.0.1:7001".parse().unwrap();
+
+        assert_eq!(
+            "127.0.0.1:7001".parse::<PeerAddr>().unwrap(),
+            PeerAddr::Socket(socket)
+        );
+        assert!("[::1]:7001"
+            .parse::<PeerAddr>()
+            .unwrap()
+            .socket_addr()
+            .is_some());
+        assert_eq!(
+            "node1.internal:7001".parse::<PeerAddr>().unwrap(),
+            PeerAddr::host("node1.internal", 7001)
+        );
+        assert_eq!(
+            PeerAddr::host("node1.internal", 7001).to_string(),
+            "node1.internal:7001"
+        );
+
+        assert!("node1.internal".parse::<PeerAddr>().is_err());
+        assert!(":7001".parse::<PeerAddr>().is_err());
+        assert!("node1.internal:http".parse::<PeerAddr>().is_err());
+        assert!("::1:7001".parse::<PeerAddr>().is_err());
+    }
+
+    #[test]
+    fn host_seed_fails_over_and_keeps_its_target() {
+        let now = Instant::now();
+        let interval = Duration::from_secs(60);
+        let first: SocketAddr = "10.0.0.1:7001".parse().unwrap();
+        let second: SocketAddr = "10.0.0.2:7001".parse().unwrap();
+        let mut seed = HostSeed::new(PeerAddr::host("seed.internal", 7001));
+
+        assert!(seed.needs_resolution(now, interval));
+        seed.start_resolution();
+        assert!(!seed.needs_resolution(now, interval));
+
+        seed.resolved(vec![first, second], now);
+        assert_eq!(seed.target(), Some(first));
+        seed.fail_over();
+        assert_eq!(seed.target(), Some(second));
+
+        seed.resolved(vec![second, first], now + interval);
+        assert_eq!(seed.target(), Some(second));
+        seed.resolved(Vec::new(), now + interval * 2);
+        assert!(seed.is_at(first));
+        assert!(!seed.needs_resolution(now + interval * 2, interval));
+    }
+
+    #[test]
+    fn tracks_the_attempts_until_the_first_answer() {
+        let now = Instant::now();
+        let (first, second): (SocketAddr, SocketAddr) = (
+            "10.0.0.1:7001".parse().unwrap(),
+            "10.0.0.2:7001".parse().unwrap(),
+        );
+        let seed = PeerAddr::host("seed.internal", 7001);
+        let mut tracker = SeedTracker::default();
+
+        tracker.attempted(&seed, first, now);
+        tracker.attempted(&seed, second, now + Duration::from_secs(1));
+        tracker.attempted(&seed, first, now + Duration::from_secs(2));
+        assert!(tracker
+            .answered("10.0.0.3:7001".parse().unwrap(), now)
+            .is_empty());
+
+        let contacted = tracker.answered(first, now + Duration::from_millis(2050));
+        assert_eq!(
+            contacted,
+            vec![SeedContact {
+                seed,
+                attempts: 3,
+                rtt: Some(Duration::from_millis(50)),
+            }]
+        );
+        assert!(tracker
+            .answered(second, now + Duration::from_secs(3))
+            .is_empty());
+        assert_eq!(tracker.contacts(), contacted);
+    }
+
+    #[test]
+    fn maps_ipv4_peers_only_for_dual_stack_sockets() {
+        let v4: SocketAddr = "10.0.0.1:7001".parse().unwrap();
+        let v6: SocketAddr = "[fd00::1]:7001".parse().unwrap();
+        let any_v4: SocketAddr = "0.0.0.0:7000".parse().unwrap();
+        let any_v6: SocketAddr = "[::]:7000".parse().unwrap();
+
+        assert_eq!(peer_addr_for(any_v4, v4, false), Ok(v4));
+        assert_eq!(peer_addr_for(any_v6, v6, false), Ok(v6));
+        assert!(peer_addr_for(any_v6, v4, false)
+            .unwrap_err()
+            .contains("dual_stack"));
+        assert_eq!(
+            peer_addr_for(any_v6, v4, true),
+            Ok("[::ffff:10.0.0.1]:7001".parse().unwrap())
+        );
+        assert!(peer_addr_for(any_v4, v6, true).is_err());
+    }
+
+    #[test]
+    fn reports_the_first_answer_of_a_seed() {
+        let a = TestPeer::new();
+        let mut tester = tester_with(|config| {
+            config.seeds = vec![a.addr().into()];
+        });
+
+        tester
+            .tick(Duration::from_secs(1))
+            .expect_sent(heartbeat_to(&a))
+            .tick(Duration::from_secs(1))
+            .expect_sent(heartbeat_to(&a))
+            .recv(ack_from(&a))
+            .expect_event("a contacted after two attempts", |e| {
+                if let ArtilleryMemberEvent::SeedContacted(contact) = e {
+                    contact.attempts == 2 && contact.is_contacted()
+                } else {
+                    false
+                }
+            });
+    }
+}
@@ -0,0 +1,178 @@
+use super::broadcast::BroadcastQueue;
+use super::primitives::OperationId;
+use chrono::{DateTime, Duration, Utc};
+use serde::*;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Administrative order for the members matching the label `selector` to leave the cluster
+/// gracefully and stop, e.g. to tear down a test cluster. Carries the operator key, in
+/// clear like the cluster key, and is obeyed and passed on only by the nodes configured
+/// with the same operator key.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShutdownOrder {
+    #[serde(rename = "i")]
+    id: Uuid,
+    #[serde(rename = "k")]
+    operator_key: Vec<u8>,
+    #[serde(rename = "s")]
+    selector: String,
+    #[serde(rename = "t")]
+    issued_at: DateTime<Utc>,
+}
+
+impl ShutdownOrder {
+    pub(crate) fn new(operator_key: Vec<u8>, selector: String, issued_at: DateTime<Utc>) -> Self {
+        ShutdownOrder {
+            id: Uuid::new_v4(),
+            operator_key,
+            selector,
+            issued_at,
+        }
+    }
+
+    pub fn id(&self) -> OperationId {
+        self.id.into()
+    }
+
+    pub fn selector(&self) -> &str {
+        &self.selector
+    }
+}
+
+/// Shutdown orders seen by this node, the authentic ones are gossiped on.
+#[derive(Debug)]
+pub(crate) struct ShutdownOrders {
+    seen: HashMap<Uuid, DateTime<Utc>>,
+    retention: Duration,
+    queue: BroadcastQueue<ShutdownOrder>,
+}
+
+impl ShutdownOrders {
+    pub fn new(retransmits: usize, retention: Duration) -> Self {
+        ShutdownOrders {
+            seen: HashMap::new(),
+            retention,
+            queue: BroadcastQueue::new(retransmits),
+        }
+    }
+
+    /// Returns whether the order is new and carries the operator key. Orders older than
+    /// the retention are refused, they would stop the nodes started since.
+    pub fn apply(
+        &mut self,
+        order: ShutdownOrder,
+        operator_key: Option<&[u8]>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let authentic = operator_key.is_some_and(|key| order.operator_key == key);
+        let expired = order
+            .issued_at
+            .checked_add_signed(self.retention)
+            .is_some_and(|until| until <= now);
+        if !authentic || expired || self.seen.contains_key(&order.id) {
+            return false;
+        }
+
+        self.seen.insert(order.id, order.issued_at);
+        self.queue.push(order);
+
+        true
+    }
+
+    pub fn next_batch(&mut self, max: usize) -> Vec<ShutdownOrder> {
+        self.queue.next_batch(max)
+    }
+
+    /// Whether every order was gossiped as many times as the other records are.
+    pub fn is_gossiped(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn prune(&mut self, now: DateTime<Utc>) {
+        let retention = self.retention;
+        self.seen.retain(|_, issued_at| {
+            issued_at
+                .checked_add_signed(retention)
+                .is_none_or(|until| until > now)
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::state::{ArtilleryClusterRequest, ArtilleryMemberEvent};
+    use crate::epidemic::test_kit::*;
+
+    #[test]
+    fn accepts_fresh_orders_with_the_operator_key_once() {
+        let now = Utc::now();
+        let key: &[u8] = b"operator";
+        let mut orders = ShutdownOrders::new(2, Duration::minutes(10));
+
+        let order = ShutdownOrder::new(key.to_vec(), "role=test".to_string(), now);
+        assert!(!orders.apply(order.clone(), None, now));
+        assert!(!orders.apply(order.clone(), Some(b"cluster"), now));
+        assert!(orders.apply(order.clone(), Some(key), now));
+        assert!(!orders.apply(order.clone(), Some(key), now));
+        assert_eq!(orders.next_batch(4), vec![order]);
+        assert!(!orders.is_gossiped());
+        orders.next_batch(4);
+        assert!(orders.is_gossiped());
+
+        let stale = ShutdownOrder::new(key.to_vec(), String::new(), now - Duration::minutes(11));
+        assert!(!orders.apply(stale, Some(key), now));
+
+        orders.prune(now + Duration::minutes(11));
+        assert!(orders.seen.is_empty());
+    }
+
+    #[test]
+    fn obeys_shutdown_orders_carrying_the_operator_key() {
+        let a = TestPeer::new();
+        let mut tester = tester_with(|config| {
+            config.operator_key = Some(b"operator".to_vec());
+        });
+
+        tester
+            .recv(heartbeat_from(&a).with_shutdown_order(b"guess", ""))
+            .recv(heartbeat_from(&a).with_shutdown_order(b"operator", "role=test"))
+            .tick(std::time::Duration::from_secs(1));
+        assert!(tester.state().is_running());
+
+        tester
+            .recv(heartbeat_from(&a).with_shutdown_order(b"operator", ""))
+            .expect_event("shutdown ordered", |e| {
+                matches!(e, ArtilleryMemberEvent::ShutdownOrdered(_))
+            });
+        for _ in 0..11 {
+            tester.tick(std::time::Duration::from_secs(1));
+        }
+        assert!(!tester.state().is_running());
+    }
+
+    #[test]
+    fn stops_the_event_loop_on_its_own_order() {
+        let a = TestPeer::new();
+        let mut tester = tester_with(|config| {
+            config.operator_key = Some(b"operator".to_vec());
+        });
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        tester
+            .recv(heartbeat_from(&a))
+            .request(ArtilleryClusterRequest::OrderShutdown(String::new(), tx))
+            .expect_event("shutdown ordered", |e| {
+                matches!(e, ArtilleryMemberEvent::ShutdownOrdered(_))
+            });
+        assert!(rx.recv().unwrap().is_some());
+        assert!(tester.state().is_running());
+
+        for _ in 0..11 {
+            tester.tick(std::time::Duration::from_secs(1));
+        }
+        assert!(!tester.state().is_running());
+        assert!(tester.take_sent(&a).iter().any(|m| !m.shutdown.is_empty()));
+    }
+}
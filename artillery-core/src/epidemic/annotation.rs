@@ -0,0 +1,250 @@
+use super::broadcast::BroadcastQueue;
+use super::primitives::NodeId;
+use chrono::{DateTime, Utc};
+use serde::*;
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+/// Who may write the annotations of a member, has to be the same cluster-wide.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnotationPolicy {
+    /// Any node can annotate any member, the latest write wins.
+    LastWriterWins,
+    /// Only the annotated member itself writes its annotations.
+    OwnerOnly,
+}
+
+/// How far the annotations of a key travel, see `ClusterConfig::annotation_scopes`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnotationScope {
+    /// Gossiped to every member.
+    Cluster,
+    /// Gossiped only to the members in the zone of this node, never gossiped by a node
+    /// without a zone.
+    Zone,
+    /// Kept by the node which wrote or received it, never gossiped.
+    Local,
+}
+
+/// Key-value note attached to a member, e.g. `ops: draining for maintenance`.
+/// A `None` value is the tombstone of a removed annotation.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    #[serde(rename = "m")]
+    member: Uuid,
+    #[serde(rename = "k")]
+    key: String,
+    #[serde(rename = "v")]
+    value: Option<String>,
+    #[serde(rename = "a")]
+    author: Uuid,
+    #[serde(rename = "t")]
+    updated_at: DateTime<Utc>,
+}
+
+impl Annotation {
+    pub(crate) fn new(member: Uuid, key: String, value: Option<String>, author: Uuid) -> Self {
+        Annotation {
+            member,
+            key,
+            value,
+            author,
+            updated_at: Utc::now(),
+        }
+    }
+
+    pub fn member(&self) -> NodeId {
+        self.member.into()
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn value(&self) -> Option<&str> {
+        self.value.as_deref()
+    }
+
+    pub fn author(&self) -> NodeId {
+        self.author.into()
+    }
+
+    /// Latest update wins, ties are broken by the author to converge everywhere.
+    fn supersedes(&self, other: &Annotation) -> bool {
+        (self.updated_at, self.author) > (other.updated_at, other.author)
+    }
+}
+
+/// Annotations known by this node and their dissemination queue.
+#[derive(Debug)]
+pub(crate) struct Annotations {
+    table: HashMap<(Uuid, String), Annotation>,
+    queue: BroadcastQueue<Annotation>,
+    policy: AnnotationPolicy,
+    scopes: HashMap<String, AnnotationScope>,
+}
+
+impl Annotations {
+    pub fn new(
+        retransmits: usize,
+        policy: AnnotationPolicy,
+        scopes: HashMap<String, AnnotationScope>,
+    ) -> Self {
+        Annotations {
+            table: HashMap::new(),
+            queue: BroadcastQueue::new(retransmits),
+            policy,
+            scopes,
+        }
+    }
+
+    /// Scope of the key, keys without a scope are gossiped cluster-wide.
+    pub fn scope(&self, key: &str) -> AnnotationScope {
+        scope_of(&self.scopes, key)
+    }
+
+    /// Whether the annotation may be gossiped to a member, in the zone of this node or not.
+    pub fn reaches(&self, annotation: &Annotation, same_zone: bool) -> bool {
+        reaches(&self.scopes, annotation, same_zone)
+    }
+
+    pub fn permits(&self, annotation: &Annotation) -> bool {
+        match self.policy {
+            AnnotationPolicy::LastWriterWins => true,
+            AnnotationPolicy::OwnerOnly => annotation.author == annotation.member,
+        }
+    }
+
+    /// Merges the annotation, returns it back if it changed our view.
+    pub fn apply(&mut self, annotation: Annotation) -> Option<Annotation> {
+        if !self.permits(&annotation) {
+            return None;
+        }
+
+        let key = (annotation.member, annotation.key.clone());
+        if let Some(known) = self.table.get(&key) {
+            if !annotation.supersedes(known) {
+                return None;
+            }
+        }
+
+        self.table.insert(key, annotation.clone());
+        let (member, name) = (annotation.member, annotation.key.clone());
+        if self.scope(&annotation.key) != AnnotationScope::Local {
+            self.queue.push_superseding(annotation.clone(), |pending| {
+                pending.member == member && pending.key == name
+            });
+        }
+
+        Some(annotation)
+    }
+
+    /// Current annotations of the member, without the removed ones.
+    pub fn of_member(&self, member: &Uuid) -> BTreeMap<String, String> {
+        self.table
+            .values()
+            .filter(|a| a.member == *member)
+            .filter_map(|a| a.value.clone().map(|v| (a.key.clone(), v)))
+            .collect()
+    }
+
+    /// Current annotations the member wrote about itself, as records.
+    pub fn written_by(&self, member: &Uuid) -> Vec<Annotation> {
+        self.table
+            .values()
+            .filter(|a| a.member == *member && a.author == *member && a.value.is_some())
+            .cloned()
+            .collect()
+    }
+
+    /// Next batch for a member, in the zone of this node or not.
+    pub fn next_batch(&mut self, max: usize, same_zone: bool) -> Vec<Annotation> {
+        let scopes = &self.scopes;
+        self.queue
+            .next_batch_where(max, |a| reaches(scopes, a, same_zone))
+    }
+}
+
+fn scope_of(scopes: &HashMap<String, AnnotationScope>, key: &str) -> AnnotationScope {
+    scopes.get(key).copied().unwrap_or(AnnotationScope::Cluster)
+}
+
+fn reaches(
+    scopes: &HashMap<String, AnnotationScope>,
+    annotation: &Annotation,
+    same_zone: bool,
+) -> bool {
+    match scope_of(scopes, &annotation.key) {
+        AnnotationScope::Cluster => true,
+        AnnotationScope::Zone => same_zone,
+        AnnotationScope::Local => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration;
+
+    fn annotation(member: Uuid, value: &str, author: Uuid, age_secs: i64) -> Annotation {
+        Annotation {
+            updated_at: Utc::now() - Duration::seconds(age_secs),
+            ..Annotation::new(member, "ops".into(), Some(value.into()), author)
+        }
+    }
+
+    #[test]
+    fn last_writer_wins() {
+        let member = Uuid::new_v4();
+        let mut annotations = Annotations::new(3, AnnotationPolicy::LastWriterWins, HashMap::new());
+
+        assert!(annotations
+            .apply(annotation(member, "draining", Uuid::new_v4(), 1))
+            .is_some());
+        assert!(annotations
+            .apply(annotation(member, "stale", Uuid::new_v4(), 10))
+            .is_none());
+
+        assert_eq!(annotations.of_member(&member)["ops"], "draining");
+    }
+
+    #[test]
+    fn owner_only_rejects_other_authors() {
+        let member = Uuid::new_v4();
+        let mut annotations = Annotations::new(3, AnnotationPolicy::OwnerOnly, HashMap::new());
+
+        assert!(annotations
+            .apply(annotation(member, "draining", Uuid::new_v4(), 0))
+            .is_none());
+        assert!(annotations
+            .apply(annotation(member, "draining", member, 0))
+            .is_some());
+    }
+
+    #[test]
+    fn keeps_scoped_annotations_within_their_scope() {
+        let member = Uuid::new_v4();
+        let scopes = vec![
+            ("rack".to_string(), AnnotationScope::Zone),
+            ("host".to_string(), AnnotationScope::Local),
+        ]
+        .into_iter()
+        .collect();
+        let mut annotations = Annotations::new(3, AnnotationPolicy::LastWriterWins, scopes);
+
+        for key in &["ops", "rack", "host"] {
+            let value = Some("value".to_string());
+            let written = Annotation::new(member, key.to_string(), value, member);
+            assert!(annotations.apply(written).is_some());
+        }
+        assert_eq!(annotations.of_member(&member).len(), 3);
+
+        let keys = |batch: Vec<Annotation>| -> Vec<String> {
+            let mut keys: Vec<String> = batch.into_iter().map(|a| a.key).collect();
+            keys.sort();
+            keys
+        };
+        assert_eq!(keys(annotations.next_batch(5, false)), vec!["ops"]);
+        assert_eq!(keys(annotations.next_batch(5, true)), vec!["ops", "rack"]);
+    }
+}
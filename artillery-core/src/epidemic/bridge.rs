@@ -0,0 +1,147 @@
+use super::codec::MessageCodec;
+use super::state::ArtilleryClusterEvent;
+use crate::errors::*;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+/// Republishes cluster events to local processes over a Unix domain socket.
+///
+/// Every event is written to all connected clients as one frame: a big-endian `u32`
+/// length followed by the `(members, event, stamp)` tuple encoded with the bridge codec.
+/// Clients which can't keep up are disconnected instead of stalling the cluster thread.
+pub struct EventBridge {
+    path: PathBuf,
+    listener: UnixListener,
+    clients: Vec<UnixStream>,
+    codec: MessageCodec,
+}
+
+impl EventBridge {
+    /// Binds the bridge socket, a stale socket file left from a previous run is replaced.
+    pub fn bind<P: AsRef<Path>>(socket_path: P, codec: MessageCodec) -> Result<Self> {
+        let path = socket_path.as_ref().to_path_buf();
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(EventBridge {
+            path,
+            listener,
+            clients: Vec::new(),
+            codec,
+        })
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub fn publish(&mut self, event: &ArtilleryClusterEvent) {
+        self.accept_pending();
+        if self.clients.is_empty() {
+            return;
+        }
+
+        let frame = match self.codec.encode(event).map(length_prefixed) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => {
+                log_events!(
+                    Error,
+                    "Event #{} is too large for a bridge frame",
+                    (event.2).seq
+                );
+                return;
+            }
+            Err(e) => {
+                log_events!(
+                    Error,
+                    "Unable to encode event #{} for the bridge: {}",
+                    (event.2).seq,
+                    e
+                );
+                return;
+            }
+        };
+
+        self.clients.retain(|client| {
+            let mut stream: &UnixStream = client;
+            stream.write_all(&frame).is_ok()
+        });
+    }
+
+    fn accept_pending(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if stream.set_nonblocking(true).is_ok() {
+                        self.clients.push(stream);
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log_events!(Warn, "Event bridge accept failed on {:?}: {}", self.path, e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for EventBridge {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn length_prefixed(payload: Vec<u8>) -> Option<Vec<u8>> {
+    let len = u32::try_from(payload.len()).ok()?;
+    let mut frame = Vec::with_capacity(4 + payload.len());
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend(payload);
+    Some(frame)
+}
+
+#[cfg(test)]
+mod test {
+    use super::EventBridge;
+    use crate::epidemic::codec::MessageCodec;
+    use crate::epidemic::member::ArtilleryMember;
+    use crate::epidemic::primitives::Timestamp;
+    use crate::epidemic::state::{ArtilleryEventStamp, ArtilleryMemberEvent};
+    use std::convert::TryFrom;
+    use std::io::Read;
+    use std::os::unix::net::UnixStream;
+    use uuid::Uuid;
+
+    #[test]
+    fn publishes_length_prefixed_frames() {
+        let path = std::env::temp_dir().join(format!("artillery-bridge-{}.sock", Uuid::new_v4()));
+        let mut bridge = EventBridge::bind(&path, MessageCodec::Json).unwrap();
+        let mut client = UnixStream::connect(&path).unwrap();
+
+        let member = ArtilleryMember::current(Uuid::new_v4());
+        let stamp = ArtilleryEventStamp {
+            seq: 1,
+            timestamp: Timestamp::now(),
+        };
+        bridge.publish(&(vec![], ArtilleryMemberEvent::Joined(member), stamp));
+        assert_eq!(bridge.client_count(), 1);
+
+        let mut len = [0_u8; 4];
+        client.read_exact(&mut len).unwrap();
+        let mut payload = vec![0_u8; usize::try_from(u32::from_be_bytes(len)).unwrap()];
+        client.read_exact(&mut payload).unwrap();
+
+        let json: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(json[2]["seq"], 1);
+
+        drop(bridge);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,65 @@
+use std::cmp::Reverse;
+
+/// Retransmission queue for gossiped records which are not member states.
+/// Each record is piggybacked on a bounded number of outgoing messages,
+/// least transmitted records go first.
+#[derive(Debug)]
+pub struct BroadcastQueue<T> {
+    retransmits: usize,
+    items: Vec<(T, usize)>,
+}
+
+impl<T: Clone> BroadcastQueue<T> {
+    pub fn new(retransmits: usize) -> Self {
+        BroadcastQueue {
+            retransmits,
+            items: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, item: T) {
+        if self.retransmits > 0 {
+            self.items.push((item, self.retransmits));
+        }
+    }
+
+    /// Queues the `item` dropping the pending records it supersedes.
+    pub fn push_superseding<F>(&mut self, item: T, superseded: F)
+    where
+        F: Fn(&T) -> bool,
+    {
+        self.items.retain(|(pending, _)| !superseded(pending));
+        self.push(item);
+    }
+
+    pub fn next_batch(&mut self, max: usize) -> Vec<T> {
+        self.next_batch_where(max, |_| true)
+    }
+
+    /// Next batch of the records the recipient may get, the others stay queued for it.
+    pub fn next_batch_where<F>(&mut self, max: usize, eligible: F) -> Vec<T>
+    where
+        F: Fn(&T) -> bool,
+    {
+        self.items.sort_by_key(|r| Reverse(r.1));
+
+        let batch = self
+            .items
+            .iter_mut()
+            .filter(|(item, _)| eligible(item))
+            .take(max)
+            .map(|(item, remaining)| {
+                *remaining -= 1;
+                item.clone()
+            })
+            .collect();
+
+        self.items.retain(|&(_, remaining)| remaining > 0);
+
+        batch
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
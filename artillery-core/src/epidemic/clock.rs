@@ -0,0 +1,73 @@
+use std::fmt::Debug;
+use std::time::{Duration, Instant};
+
+/// Monotonic time of the protocol, every interval and timeout of the `ClusterConfig` is
+/// measured on it. Deadlines on the wall clock, like reaping, are not, but the leases
+/// move along with it.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Real time to wait until `protocol` time went by on this clock.
+    fn real_duration(&self, protocol: Duration) -> Duration {
+        protocol
+    }
+}
+
+/// Time as it goes by on the host.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Protocol time running `factor` times faster than the host time, so multi-round
+/// scenarios of integration tests complete in milliseconds. Every member of the test
+/// has to share the same factor.
+#[cfg(any(test, feature = "test-kit"))]
+#[derive(Debug, Clone, Copy)]
+pub struct AcceleratedClock {
+    origin: Instant,
+    factor: u32,
+}
+
+#[cfg(any(test, feature = "test-kit"))]
+impl AcceleratedClock {
+    pub fn new(factor: u32) -> Self {
+        AcceleratedClock {
+            origin: Instant::now(),
+            factor: factor.max(1),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-kit"))]
+impl Clock for AcceleratedClock {
+    fn now(&self) -> Instant {
+        self.origin + self.origin.elapsed() * self.factor
+    }
+
+    fn real_duration(&self, protocol: Duration) -> Duration {
+        protocol / self.factor
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn accelerates_the_protocol_time() {
+        let clock = AcceleratedClock::new(50);
+        let start = clock.now();
+        let wait = clock.real_duration(Duration::from_secs(1));
+        assert_eq!(wait, Duration::from_millis(20));
+
+        thread::sleep(wait);
+        assert!(clock.now().duration_since(start) >= Duration::from_secs(1));
+        assert_eq!(AcceleratedClock::new(0).real_duration(wait), wait);
+    }
+}
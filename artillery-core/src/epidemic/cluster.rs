@@ -1,69 +1,837 @@
 use super::state::ArtilleryEpidemic;
-use crate::epidemic::cluster_config::ClusterConfig;
-use crate::epidemic::state::{ArtilleryClusterEvent, ArtilleryClusterRequest};
+use crate::constants::{
+    CONST_FLUSH_POLL_INTERVAL, CONST_HEARTBEAT_PAYLOAD_LIMIT, CONST_MESSAGE_LIMIT,
+    CONST_METADATA_LIMIT, CONST_USER_PAYLOAD_LIMIT,
+};
+use crate::epidemic::address::{peer_addr_for, PeerAddr, SeedContact};
+use crate::epidemic::cluster_config::{ClusterConfig, ClusterThreadPriority};
+use crate::epidemic::diagnostics::ClusterDiagnostic;
+use crate::epidemic::event_log::EventLog;
+use crate::epidemic::export;
+use crate::epidemic::health::ClusterHealth;
+use crate::epidemic::lanes::{request_lanes, RequestSender};
+use crate::epidemic::lock::Lease;
+use crate::epidemic::member::{self, ArtilleryMember};
+use crate::epidemic::membership::MemberPage;
+use crate::epidemic::primitives::{NodeId, OperationId, Timestamp};
+use crate::epidemic::rpc::RpcHandler;
+use crate::epidemic::selector::Selector;
+use crate::epidemic::state::{
+    ArtilleryClusterEvent, ArtilleryClusterRequest, HeartbeatCallback, MemberFilter,
+};
+use crate::epidemic::transaction::RemovalTransactionStatus;
+use crate::epidemic::transport::{DefaultTransport, Transport};
+use crate::epidemic::view::SharedMembershipView;
+use crate::epidemic::wireguard::{self, WireGuardExport};
 use crate::errors::*;
+use crate::logging;
 use bastion_executor::prelude::*;
 use lightproc::{proc_stack::ProcStack, recoverable_handle::RecoverableHandle};
+use std::collections::HashMap;
 use std::convert::AsRef;
 use std::net::SocketAddr;
+use std::thread;
+use std::time::{Duration, Instant};
 use std::{
     future::Future,
     pin::Pin,
-    sync::mpsc::{channel, Receiver, Sender},
+    sync::mpsc::{channel, Receiver, RecvTimeoutError, TrySendError},
+    sync::Arc,
     task::{Context, Poll},
 };
 use uuid::Uuid;
 
 pub struct Cluster {
     pub events: Receiver<ArtilleryClusterEvent>,
-    comm: Sender<ArtilleryClusterRequest>,
+    pub diagnostics: Receiver<ClusterDiagnostic>,
+    comm: RequestSender,
+    view: SharedMembershipView,
+    event_log: EventLog,
+    listen_addr: SocketAddr,
+    dual_stack: bool,
+    instance_name: Arc<str>,
+    /// Whether dropping waits for the event loop thread to exit.
+    owns_event_loop: bool,
 }
 
 impl Cluster {
-    pub fn new_cluster(
-        host_key: Uuid,
+    pub fn new_cluster<I: Into<NodeId>>(
+        host_key: I,
+        config: ClusterConfig,
+    ) -> Result<(Self, RecoverableHandle<()>)> {
+        let server_socket = DefaultTransport::bind(config.listen_addr)?;
+        Cluster::new_cluster_with_transport(host_key, config, server_socket)
+    }
+
+    /// Cluster gossiping over the given transport, e.g. a
+    /// [`MemoryNetwork`](super::loopback::MemoryNetwork) endpoint in tests. The listen
+    /// address of the configuration is replaced by the address of the transport.
+    pub fn new_cluster_with_transport<I: Into<NodeId>, T: Transport + 'static>(
+        host_key: I,
         config: ClusterConfig,
+        transport: T,
     ) -> Result<(Self, RecoverableHandle<()>)> {
         let (event_tx, event_rx) = channel::<ArtilleryClusterEvent>();
-        let (internal_tx, mut internal_rx) = channel::<ArtilleryClusterRequest>();
+        let (internal_tx, internal_rx) = request_lanes(&config);
 
-        let (poll, state) =
-            ArtilleryEpidemic::new(host_key, config, event_tx, internal_tx.clone())?;
+        let thread_priority = config.thread_priority;
+        let cpu_affinity = config.cpu_affinity;
 
-        debug!("Starting Artillery Cluster");
+        let (poll, mut state) = ArtilleryEpidemic::with_transport(
+            host_key.into().into(),
+            config,
+            Box::new(transport),
+            event_tx,
+            internal_tx.clone(),
+        )?;
+        let cluster = Cluster::attach(&mut state, event_rx, internal_tx, true);
+
+        log_runtime!(
+            Debug,
+            "Starting Artillery Cluster {}",
+            cluster.instance_name
+        );
+        let instance_name = cluster.instance_name.clone();
+        let event_loop = thread::Builder::new()
+            .name(format!("{}-event-loop", instance_name))
+            .spawn(move || {
+                let _instance = logging::enter_instance(instance_name);
+                for failure in tune_cluster_thread(thread_priority, cpu_affinity) {
+                    log_runtime!(Warn, "{}", failure);
+                }
+                ArtilleryEpidemic::event_loop(&internal_rx, poll, state)
+                    .expect("Failed to create event loop");
+            })?;
+        // The handle completes with the event loop thread, and recovers from its panics
         let cluster_handle = spawn_blocking(
             async move {
-                ArtilleryEpidemic::event_loop(&mut internal_rx, poll, state)
-                    .expect("Failed to create event loop");
+                if let Err(panic) = event_loop.join() {
+                    std::panic::resume_unwind(panic);
+                }
             },
             ProcStack::default(),
         );
 
-        Ok((
-            Self {
-                events: event_rx,
-                comm: internal_tx,
-            },
-            cluster_handle,
-        ))
+        Ok((cluster, cluster_handle))
     }
 
-    pub fn add_seed_node(&self, addr: SocketAddr) {
-        let _ = self.comm.send(ArtilleryClusterRequest::AddSeed(addr));
+    /// Control surface talking to the state machine over its request lanes.
+    pub(crate) fn attach(
+        state: &mut ArtilleryEpidemic,
+        events: Receiver<ArtilleryClusterEvent>,
+        comm: RequestSender,
+        owns_event_loop: bool,
+    ) -> Self {
+        let listen_addr = state.config().listen_addr;
+        let instance_name = Arc::from(state.config().instance_name());
+        Cluster {
+            events,
+            diagnostics: state
+                .take_diagnostics()
+                .expect("Diagnostics of a new cluster are available"),
+            comm,
+            view: state.view(),
+            event_log: state.event_log(),
+            listen_addr,
+            dual_stack: state.config().dual_stack,
+            instance_name,
+            owns_event_loop,
+        }
     }
 
-    pub fn send_payload<T: AsRef<str>>(&self, id: Uuid, msg: T) {
+    /// Configured `instance_name`, or the `cluster_name`. Labels the metrics of this
+    /// cluster when a process runs several.
+    pub fn instance_name(&self) -> &str {
+        &self.instance_name
+    }
+
+    /// Address the gossip transport is bound to, peers reach this node there. Holds the
+    /// port picked by the OS when the configured `listen_addr` has port `0`.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.listen_addr
+    }
+
+    /// Seeds given by host name are resolved in the background and re-resolved periodically,
+    /// skipping the addresses of another family than the `listen_addr`. Fails with
+    /// `InvalidArgument` for such an address, see `ClusterConfig::dual_stack`.
+    pub fn add_seed_node<A: Into<PeerAddr>>(&self, addr: A) -> Result<()> {
+        let seed = addr.into();
+        if let Some(socket_addr) = seed.socket_addr() {
+            if let Err(mismatch) = peer_addr_for(self.listen_addr, socket_addr, self.dual_stack) {
+                bail!(ArtilleryError::InvalidArgument, "The seed {}", mismatch);
+            }
+        }
+
+        let _ = self.comm.send(ArtilleryClusterRequest::AddSeed(seed));
+        Ok(())
+    }
+
+    /// Attempts and round trip time of every seed added, including the configured ones.
+    /// Each seed also emits a `SeedContacted` event when it answers the first time.
+    pub fn seed_contacts(&self) -> Vec<SeedContact> {
+        let (tx, rx) = channel();
+
+        if self
+            .comm
+            .send(ArtilleryClusterRequest::SeedContacts(tx))
+            .is_err()
+        {
+            return Vec::new();
+        }
+
+        rx.recv().unwrap_or_default()
+    }
+
+    /// Sends the payload to the member, waits while the payload lane is full. Panics once
+    /// the event loop is gone, see [`try_send_payload`](Cluster::try_send_payload).
+    pub fn send_payload<I: Into<NodeId>, T: AsRef<str>>(&self, id: I, msg: T) {
         self.comm
             .send(ArtilleryClusterRequest::Payload(
-                id,
+                id.into().into(),
                 msg.as_ref().to_string(),
             ))
             .unwrap();
     }
 
+    /// Queues the payload to the member without waiting or panicking, so the callers can
+    /// retry as they see fit. Fails with `QueueFull` while the payload lane is full, and
+    /// with `UnknownMember` for the members missing from the [`view`](Cluster::view).
+    pub fn try_send_payload<I: Into<NodeId>, T: AsRef<str>>(
+        &self,
+        id: I,
+        msg: T,
+    ) -> std::result::Result<(), SendPayloadError> {
+        let node = id.into();
+        {
+            let view = self.view.read();
+            if view.is_draining() {
+                return Err(SendPayloadError::Draining);
+            }
+            if view.get(&node).is_none() {
+                return Err(SendPayloadError::UnknownMember(node));
+            }
+        }
+
+        self.comm
+            .try_send(ArtilleryClusterRequest::Payload(
+                node.into(),
+                msg.as_ref().to_string(),
+            ))
+            .map_err(|e| match e {
+                TrySendError::Full(_) => SendPayloadError::QueueFull,
+                TrySendError::Disconnected(_) => SendPayloadError::NotConnected,
+            })
+    }
+
+    /// Broadcasts an opaque payload to every member, piggybacked on the gossip. The others
+    /// emit `MemberPayload` once it reached them, at most once each. At most
+    /// `CONST_USER_PAYLOAD_LIMIT` bytes.
+    pub fn broadcast_payload(&self, payload: Vec<u8>) -> Result<()> {
+        if payload.len() > CONST_USER_PAYLOAD_LIMIT {
+            bail!(
+                ArtilleryError::InvalidArgument,
+                "Payload is {} bytes, limit is {}",
+                payload.len(),
+                CONST_USER_PAYLOAD_LIMIT
+            );
+        }
+
+        Ok(self
+            .comm
+            .send(ArtilleryClusterRequest::BroadcastPayload(payload))?)
+    }
+
+    /// Sends a message to the member, again until it acknowledges it, at most
+    /// `message_retries` times. The member emits `MemberMessage` once, this node emits
+    /// `MessageUndelivered` with the returned id if it gave up. At most
+    /// `CONST_MESSAGE_LIMIT` bytes.
+    pub fn send_to<I: Into<NodeId>>(&self, id: I, bytes: &[u8]) -> Result<OperationId> {
+        if bytes.len() > CONST_MESSAGE_LIMIT {
+            bail!(
+                ArtilleryError::InvalidArgument,
+                "Message is {} bytes, limit is {}",
+                bytes.len(),
+                CONST_MESSAGE_LIMIT
+            );
+        }
+
+        let message_id = OperationId::random();
+        self.comm.send(ArtilleryClusterRequest::SendMessage(
+            message_id.into(),
+            id.into().into(),
+            bytes.to_vec(),
+        ))?;
+
+        Ok(message_id)
+    }
+
+    /// Asserts that the given member is dead, e.g. when an external system already
+    /// knows the host is gone. The member goes `Down` cluster-wide without suspicion.
+    pub fn confirm_down<I: Into<NodeId>>(&self, id: I) {
+        let _ = self
+            .comm
+            .send(ArtilleryClusterRequest::ConfirmDown(id.into().into()));
+    }
+
+    /// Installs a key opening the encrypted messages, on every member before it's used.
+    pub fn install_key(&self, key: Vec<u8>) -> Result<()> {
+        let (tx, rx) = channel();
+        self.comm
+            .send(ArtilleryClusterRequest::InstallKey(key, tx))?;
+        rx.recv()?
+    }
+
+    /// Seals the messages with the installed key, once every member installed it.
+    pub fn use_key(&self, key: Vec<u8>) -> Result<()> {
+        let (tx, rx) = channel();
+        self.comm.send(ArtilleryClusterRequest::UseKey(key, tx))?;
+        rx.recv()?
+    }
+
+    /// Removes a key no member seals with anymore, the key in use can't be removed.
+    pub fn remove_key(&self, key: Vec<u8>) -> Result<()> {
+        let (tx, rx) = channel();
+        self.comm
+            .send(ArtilleryClusterRequest::RemoveKey(key, tx))?;
+        rx.recv()?
+    }
+
+    /// Feeds the failure of a member known to an external system, e.g. its pod was
+    /// deleted. Weighed alongside the probes as set by `ClusterConfig::external_signals`.
+    pub fn report_external_failure<I: Into<NodeId>>(&self, id: I) {
+        let _ = self
+            .comm
+            .send(ArtilleryClusterRequest::ExternalFailure(id.into().into()));
+    }
+
+    /// Feeds the liveness of a member known to an external system, a suspicion of the
+    /// member needs fresh evidence then.
+    pub fn report_external_alive<I: Into<NodeId>>(&self, id: I) {
+        let _ = self
+            .comm
+            .send(ArtilleryClusterRequest::ExternalAlive(id.into().into()));
+    }
+
+    /// Picks up to `k` live members passing the `filter` uniformly at random.
+    /// Meant for applications doing their own random peer selection on top of the membership.
+    pub fn sample_members<F>(&self, k: usize, filter: F) -> Vec<ArtilleryMember>
+    where
+        F: Fn(&ArtilleryMember) -> bool + Send + Sync + 'static,
+    {
+        let (tx, rx) = channel();
+        let shared: MemberFilter = Arc::new(filter);
+
+        if self
+            .comm
+            .send(ArtilleryClusterRequest::SampleMembers(k, shared, tx))
+            .is_err()
+        {
+            return Vec::new();
+        }
+
+        rx.recv().unwrap_or_default()
+    }
+
+    /// Outbound work still queued: state changes (e.g. this node's `Left`) no member has
+    /// acknowledged yet and deferred bulk messages. Zero once the event loop is gone.
+    pub fn pending_outbound(&self) -> usize {
+        let (tx, rx) = channel();
+
+        if self
+            .comm
+            .send(ArtilleryClusterRequest::PendingOutbound(tx))
+            .is_err()
+        {
+            return 0;
+        }
+
+        rx.recv().unwrap_or_default()
+    }
+
+    /// Live record of this node as gossiped, its host key, state, incarnation, zone and
+    /// maintenance flag. Its address is [`local_addr`](Cluster::local_addr), annotations
+    /// are in the view. `None` once the event loop is gone.
+    pub fn local_member(&self) -> Option<ArtilleryMember> {
+        let (tx, rx) = channel();
+
+        if self
+            .comm
+            .send(ArtilleryClusterRequest::LocalMember(tx))
+            .is_err()
+        {
+            return None;
+        }
+
+        rx.recv().ok()
+    }
+
+    /// Members of the cluster right now, this node included and the members which left
+    /// excluded. Empty once the event loop is gone.
+    pub fn members(&self) -> Vec<ArtilleryMember> {
+        let (tx, rx) = channel();
+
+        if self
+            .comm
+            .send(ArtilleryClusterRequest::GetMembers(tx))
+            .is_err()
+        {
+            return Vec::new();
+        }
+
+        rx.recv().unwrap_or_default()
+    }
+
+    /// Page of the [`members`](Cluster::members) by host key, after the cursor of the
+    /// previous page. The event loop only clones the members of the page, so that the
+    /// snapshots of large clusters don't hold up the protocol. Empty once the event loop
+    /// is gone.
+    pub fn members_page(&self, after: Option<NodeId>, limit: usize) -> MemberPage {
+        let (tx, rx) = channel();
+
+        if self
+            .comm
+            .send(ArtilleryClusterRequest::GetMembersPage(
+                after.map(Uuid::from),
+                limit,
+                tx,
+            ))
+            .is_err()
+        {
+            return MemberPage::default();
+        }
+
+        rx.recv().unwrap_or_default()
+    }
+
+    /// The [`members`](Cluster::members) page by page, the protocol runs in between. The
+    /// members joining or leaving meanwhile may be missed, none is listed twice.
+    pub fn member_pages(&self, page_size: usize) -> MemberPages<'_> {
+        MemberPages {
+            cluster: self,
+            cursor: None,
+            page_size,
+            done: false,
+        }
+    }
+
+    /// Number of [`members`](Cluster::members) as of the last membership event, read from
+    /// the [`view`](Cluster::view) without a round trip to the event loop.
+    pub fn member_count(&self) -> usize {
+        self.view.read().members().len()
+    }
+
+    /// Address the other members see this node at, to register it in external systems.
+    /// Differs from the [`local_addr`](Cluster::local_addr) behind a NAT or when bound to
+    /// an unspecified address. It is the `local_addr` until a peer gossiped this node,
+    /// and `None` once the event loop is gone.
+    pub fn advertised_addr(&self) -> Option<SocketAddr> {
+        let (tx, rx) = channel();
+
+        if self
+            .comm
+            .send(ArtilleryClusterRequest::AdvertisedAddr(tx))
+            .is_err()
+        {
+            return None;
+        }
+
+        rx.recv().ok()
+    }
+
+    /// Health score of the cluster as seen by this node, see [`ClusterHealth`].
+    /// `None` once the event loop is gone.
+    pub fn health(&self) -> Option<ClusterHealth> {
+        let (tx, rx) = channel();
+
+        if self.comm.send(ArtilleryClusterRequest::Health(tx)).is_err() {
+            return None;
+        }
+
+        rx.recv().ok()
+    }
+
+    /// Waits until the outbound queue is drained, e.g. after `leave_cluster` and before
+    /// dropping the cluster. Returns whether it was drained within the timeout. A node
+    /// without peers never drains, nobody acknowledges its changes.
+    pub fn flush(&self, timeout: Duration) -> bool {
+        let start = Instant::now();
+
+        loop {
+            if self.pending_outbound() == 0 {
+                return true;
+            }
+
+            if start.elapsed() >= timeout {
+                return false;
+            }
+
+            thread::sleep(CONST_FLUSH_POLL_INTERVAL.min(timeout));
+        }
+    }
+
+    /// Registers a small blob (at most `CONST_HEARTBEAT_PAYLOAD_LIMIT` bytes) piggybacked on
+    /// the outgoing pings of this node, e.g. for lightweight load telemetry.
+    pub fn set_heartbeat_payload(&self, payload: Vec<u8>) -> Result<()> {
+        if payload.len() > CONST_HEARTBEAT_PAYLOAD_LIMIT {
+            bail!(
+                ArtilleryError::InvalidArgument,
+                "Heartbeat payload is {} bytes, limit is {}",
+                payload.len(),
+                CONST_HEARTBEAT_PAYLOAD_LIMIT
+            );
+        }
+
+        Ok(self
+            .comm
+            .send(ArtilleryClusterRequest::SetHeartbeatPayload(Some(payload)))?)
+    }
+
+    /// Replaces the metadata of this node, gossiped with its member record. The members
+    /// emit `MemberMetadataChanged` once it reached them. At most `CONST_METADATA_LIMIT`
+    /// bytes of keys and values.
+    pub fn update_metadata(&self, metadata: HashMap<String, Vec<u8>>) -> Result<()> {
+        let size = member::metadata_size(&metadata);
+        if size > CONST_METADATA_LIMIT {
+            bail!(
+                ArtilleryError::InvalidArgument,
+                "Metadata is {} bytes, limit is {}",
+                size,
+                CONST_METADATA_LIMIT
+            );
+        }
+
+        Ok(self
+            .comm
+            .send(ArtilleryClusterRequest::UpdateMetadata(metadata))?)
+    }
+
+    pub fn clear_heartbeat_payload(&self) {
+        let _ = self
+            .comm
+            .send(ArtilleryClusterRequest::SetHeartbeatPayload(None));
+    }
+
+    /// Announces planned downtime of this node. Until the ETA passes, if one is given,
+    /// the other members keep it suspect instead of declaring it down when it doesn't answer.
+    pub fn start_maintenance(&self, eta: Option<Timestamp>) {
+        let _ = self.comm.send(ArtilleryClusterRequest::SetMaintenance(
+            true,
+            eta.map(Timestamp::into),
+        ));
+    }
+
+    pub fn end_maintenance(&self) {
+        let _ = self
+            .comm
+            .send(ArtilleryClusterRequest::SetMaintenance(false, None));
+    }
+
+    /// Callback invoked on the cluster thread with heartbeat blobs of the other members.
+    /// Keep it lightweight, it runs inside the event loop.
+    pub fn on_heartbeat_payload<F>(&self, callback: F)
+    where
+        F: Fn(&ArtilleryMember, &[u8]) + Send + Sync + 'static,
+    {
+        let shared: HeartbeatCallback = Arc::new(callback);
+        let _ = self
+            .comm
+            .send(ArtilleryClusterRequest::OnHeartbeatPayload(shared));
+    }
+
+    /// Handles the calls of the method made by the other members with `Cluster::call`,
+    /// replacing its previous handler. Keep it lightweight, it runs inside the event loop.
+    pub fn on_rpc<F>(&self, method: &str, handler: F)
+    where
+        F: Fn(&ArtilleryMember, &[u8]) -> std::result::Result<Vec<u8>, String>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let shared: RpcHandler = Arc::new(handler);
+        let _ = self.comm.send(ArtilleryClusterRequest::OnRpc(
+            method.to_string(),
+            Some(shared),
+        ));
+    }
+
+    /// The calls of the method are answered with a failure again.
+    pub fn remove_rpc_handler(&self, method: &str) {
+        let _ = self
+            .comm
+            .send(ArtilleryClusterRequest::OnRpc(method.to_string(), None));
+    }
+
+    /// Calls the handler of the method on the member and waits for its response. Fails
+    /// with `Rpc` when the member isn't live or its handler failed, and with `RpcTimeout`
+    /// when no response came in time. Calls aren't retried, requests and responses are
+    /// at most `CONST_MESSAGE_LIMIT` bytes.
+    pub fn call<I: Into<NodeId>>(
+        &self,
+        id: I,
+        method: &str,
+        body: &[u8],
+        timeout: Duration,
+    ) -> Result<Vec<u8>> {
+        if body.len() > CONST_MESSAGE_LIMIT {
+            bail!(
+                ArtilleryError::InvalidArgument,
+                "Request is {} bytes, limit is {}",
+                body.len(),
+                CONST_MESSAGE_LIMIT
+            );
+        }
+
+        let (tx, rx) = channel();
+        self.comm.send(ArtilleryClusterRequest::Call(
+            id.into().into(),
+            method.to_string(),
+            body.to_vec(),
+            timeout,
+            tx,
+        ))?;
+
+        match rx.recv_timeout(timeout) {
+            Ok(response) => response,
+            Err(RecvTimeoutError::Timeout) => Err(ArtilleryError::RpcTimeout(format!(
+                "no response to '{}' within {:?}",
+                method, timeout
+            ))),
+            Err(RecvTimeoutError::Disconnected) => Err(ArtilleryError::Receive(
+                "the cluster stopped before the response".to_string(),
+            )),
+        }
+    }
+
+    /// Marks the `members` as scheduled for removal in one gossiped operation,
+    /// so consumers (hash rings, schedulers...) can plan data movement before they leave.
+    /// Returns the id of the transaction to commit or abort later.
+    pub fn schedule_removal<I: Into<NodeId>>(&self, members: Vec<I>) -> OperationId {
+        let id = OperationId::random();
+        let leaving = members.into_iter().map(|m| m.into().into()).collect();
+        let _ = self
+            .comm
+            .send(ArtilleryClusterRequest::ScheduleRemoval(id.into(), leaving));
+
+        id
+    }
+
+    /// Confirms the removal, scheduled members leave the cluster gracefully.
+    pub fn commit_removal(&self, txn: OperationId) {
+        let _ = self.comm.send(ArtilleryClusterRequest::FinishRemoval(
+            txn.into(),
+            RemovalTransactionStatus::Committed,
+        ));
+    }
+
+    pub fn abort_removal(&self, txn: OperationId) {
+        let _ = self.comm.send(ArtilleryClusterRequest::FinishRemoval(
+            txn.into(),
+            RemovalTransactionStatus::Aborted,
+        ));
+    }
+
     pub fn leave_cluster(&self) {
         let _ = self.comm.send(ArtilleryClusterRequest::LeaveCluster);
     }
+
+    /// Comes back after `leave_cluster` on the same socket. This node restarts as a new run
+    /// with a higher incarnation and contacts the seeds again, backing off until answered.
+    pub fn rejoin(&self) {
+        let _ = self.comm.send(ArtilleryClusterRequest::Rejoin);
+    }
+
+    /// Attaches an annotation to any member, subject to the cluster annotation policy.
+    pub fn annotate<I, K, V>(&self, member: I, key: K, value: V)
+    where
+        I: Into<NodeId>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let _ = self.comm.send(ArtilleryClusterRequest::Annotate(
+            member.into().into(),
+            key.as_ref().to_string(),
+            Some(value.as_ref().to_string()),
+        ));
+    }
+
+    pub fn remove_annotation<I: Into<NodeId>, K: AsRef<str>>(&self, member: I, key: K) {
+        let _ = self.comm.send(ArtilleryClusterRequest::Annotate(
+            member.into().into(),
+            key.as_ref().to_string(),
+            None,
+        ));
+    }
+
+    /// Orders the members matching the label selector, e.g. `role=test`, to leave gracefully
+    /// and stop, this node included if it matches. An empty selector targets every member.
+    /// Needs the `operator_key` configured, nodes without the same key ignore the order.
+    /// Returns the id of the order.
+    pub fn order_shutdown(&self, selector: &str) -> Result<OperationId> {
+        selector.parse::<Selector>()?;
+        let (tx, rx) = channel();
+        self.comm.send(ArtilleryClusterRequest::OrderShutdown(
+            selector.to_string(),
+            tx,
+        ))?;
+
+        match rx.recv()? {
+            Some(id) => Ok(id),
+            None => Err(ArtilleryError::InvalidConfiguration(
+                "Shutdown orders need the operator_key configured".to_string(),
+            )),
+        }
+    }
+
+    /// Takes or renews the advisory lock `name` for `ttl`, `None` while another node holds it.
+    /// Best-effort only: partitioned nodes can hold the same lock at once, the loser of the
+    /// conflict gets a `LockLost` event once they hear each other. Don't rely on it for
+    /// mutual exclusion where correctness matters.
+    pub fn try_lock<N: AsRef<str>>(&self, name: N, ttl: Duration) -> Option<Lease> {
+        let (tx, rx) = channel();
+
+        if self
+            .comm
+            .send(ArtilleryClusterRequest::TryLock(
+                name.as_ref().to_string(),
+                ttl,
+                tx,
+            ))
+            .is_err()
+        {
+            return None;
+        }
+
+        rx.recv().ok().flatten()
+    }
+
+    /// Releases the lock if this node holds it.
+    pub fn unlock<N: AsRef<str>>(&self, name: N) {
+        let _ = self
+            .comm
+            .send(ArtilleryClusterRequest::Unlock(name.as_ref().to_string()));
+    }
+
+    /// The live lease on the lock as known by this node.
+    pub fn lock_holder<N: AsRef<str>>(&self, name: N) -> Option<Lease> {
+        let (tx, rx) = channel();
+
+        if self
+            .comm
+            .send(ArtilleryClusterRequest::LockHolder(
+                name.as_ref().to_string(),
+                tx,
+            ))
+            .is_err()
+        {
+            return None;
+        }
+
+        rx.recv().ok().flatten()
+    }
+
+    /// Current members in the memberlist node JSON, see [`export`](super::export).
+    pub fn export_members_json(&self) -> Result<String> {
+        export::export_members(self.view.read().members(), self.listen_addr)
+    }
+
+    /// Current members as a [`MemberColumns`](export::MemberColumns) snapshot, built straight
+    /// from the view for clusters too big for the JSON export.
+    pub fn export_member_columns(&self) -> export::MemberColumns {
+        export::MemberColumns::of(self.view.read().members(), self.listen_addr)
+    }
+
+    /// `WireGuard` `[Peer]` sections of the current members, see [`wireguard`](super::wireguard).
+    pub fn wireguard_peers(&self, export: &WireGuardExport) -> String {
+        wireguard::wireguard_peers(self.view.read().members(), export)
+    }
+
+    /// Seeds the cluster with an exported inventory, returns the number of members imported.
+    pub fn import_members_json(&self, json: &str) -> Result<usize> {
+        let members = export::import_members(json)?;
+        let count = members.len();
+        let _ = self
+            .comm
+            .send(ArtilleryClusterRequest::ImportMembers(members));
+
+        Ok(count)
+    }
+
+    /// Lock-protected membership read model, e.g. `cluster.view().alive_members()`.
+    pub fn view(&self) -> SharedMembershipView {
+        self.view.clone()
+    }
+
+    /// Latest events for readers going through them at their own pace, see `event_log_capacity`.
+    pub fn event_log(&self) -> EventLog {
+        self.event_log.clone()
+    }
+
+    /// Members matching a label selector such as `role=storage,zone=eu-west`, labels being
+    /// the annotations of the members and their zone. Answered from an index of the view.
+    pub fn select(&self, selector: &str) -> Result<Vec<ArtilleryMember>> {
+        let parsed: Selector = selector.parse()?;
+        Ok(self.view.select(&parsed))
+    }
+}
+
+/// Applies the scheduling options to the thread which is going to run the event loop.
+/// The event loop has a thread of its own, the options don't leak to other work.
+/// Returns why the options which couldn't be applied were skipped.
+fn tune_cluster_thread(
+    priority: Option<ClusterThreadPriority>,
+    cpu_affinity: Option<usize>,
+) -> Vec<ArtilleryError> {
+    let mut failures = Vec::new();
+
+    if let Some(core) = cpu_affinity {
+        let core_id = core_affinity::get_core_ids()
+            .unwrap_or_default()
+            .into_iter()
+            .find(|c| c.id == core);
+
+        match core_id {
+            Some(id) => core_affinity::set_for_current(id),
+            None => failures.push(ArtilleryError::InvalidConfiguration(format!(
+                "CPU core {} is not available to pin the cluster thread",
+                core
+            ))),
+        }
+    }
+
+    if let Some(level) = priority {
+        if let Err(e) = thread_priority::set_current_thread_priority(level.into()) {
+            failures.push(ArtilleryError::InvalidConfiguration(format!(
+                "Unable to set the cluster thread priority: {:?}",
+                e
+            )));
+        }
+    }
+
+    failures
+}
+
+/// Pages of the members by host key, see [`Cluster::member_pages`].
+pub struct MemberPages<'a> {
+    cluster: &'a Cluster,
+    cursor: Option<NodeId>,
+    page_size: usize,
+    done: bool,
+}
+
+impl Iterator for MemberPages<'_> {
+    type Item = Vec<ArtilleryMember>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let page = self.cluster.members_page(self.cursor, self.page_size);
+        self.cursor = page.next;
+        self.done = page.next.is_none();
+
+        Some(page.members).filter(|members| !members.is_empty())
+    }
 }
 
 impl Future for Cluster {
@@ -86,6 +854,117 @@ impl Drop for Cluster {
 
         let _ = self.comm.send(ArtilleryClusterRequest::Exit(tx));
 
-        rx.recv().unwrap();
+        if self.owns_event_loop {
+            let _ = rx.recv();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::member::ArtilleryMemberState;
+    use crate::epidemic::state::ArtilleryMemberEvent;
+    use crate::epidemic::test_kit::*;
+
+    #[test]
+    fn reports_the_thread_options_it_cannot_apply() {
+        let failures = thread::spawn(|| tune_cluster_thread(None, Some(usize::MAX)))
+            .join()
+            .unwrap();
+
+        assert_eq!(failures.len(), 1);
+        assert!(matches!(
+            &failures[0],
+            ArtilleryError::InvalidConfiguration(reason) if reason.contains("CPU core")
+        ));
+    }
+
+    #[test]
+    fn counts_the_outbound_changes_until_acknowledged() {
+        let a = TestPeer::new();
+        let mut tester = ProtocolTester::new();
+        let pending = |tester: &mut ProtocolTester| {
+            let (tx, rx) = channel();
+            tester.request(ArtilleryClusterRequest::PendingOutbound(tx));
+            rx.recv().unwrap()
+        };
+        tester.recv(heartbeat_from(&a));
+        assert!(pending(&mut tester) > 0);
+
+        for _ in 0..10 {
+            tester.tick(Duration::from_secs(1)).recv(ack_from(&a));
+            if pending(&mut tester) == 0 {
+                return;
+            }
+        }
+        panic!("Outbound changes left after the acks");
+    }
+
+    #[test]
+    fn advertises_the_port_bound_in_place_of_port_zero() {
+        let config = ClusterConfig {
+            listen_addr: SocketAddr::from(([127, 0, 0, 1], 0)),
+            ping_interval: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let (seed, _seed_handle) = Cluster::new_cluster(Uuid::new_v4(), config.clone()).unwrap();
+        let (joiner, _joiner_handle) = Cluster::new_cluster(
+            Uuid::new_v4(),
+            ClusterConfig {
+                seeds: vec![PeerAddr::from(seed.local_addr())],
+                ..config
+            },
+        )
+        .unwrap();
+        assert_ne!(seed.local_addr().port(), 0);
+        assert_ne!(joiner.local_addr().port(), 0);
+
+        let joined = std::iter::from_fn(|| seed.events.recv_timeout(Duration::from_secs(30)).ok())
+            .find_map(|(_, event, _)| match event {
+                ArtilleryMemberEvent::Joined(m) => Some(m),
+                _ => None,
+            })
+            .expect("The joiner never joined");
+        assert_eq!(joined.remote_host(), Some(joiner.local_addr()));
+    }
+
+    #[test]
+    fn answers_the_live_record_of_this_node() {
+        let a = TestPeer::new();
+        let mut tester = tester_with(|config| {
+            config.zone = Some("east".to_string());
+        });
+        let myself = |tester: &mut ProtocolTester| {
+            let (tx, rx) = channel();
+            tester.request(ArtilleryClusterRequest::LocalMember(tx));
+            rx.recv().unwrap()
+        };
+
+        let record = myself(&mut tester);
+        assert_eq!(record.host_key(), tester.host_key());
+        assert_eq!(record.state(), ArtilleryMemberState::Alive);
+        assert_eq!(record.zone(), Some("east"));
+        assert!(record.run_id().is_some());
+
+        // Refuting a suspicion and updating the metadata show up right away
+        let suspected = ArtilleryMember::new(
+            tester.host_key(),
+            a.addr(),
+            record.incarnation_number(),
+            ArtilleryMemberState::Suspect,
+        );
+        let metadata: HashMap<String, Vec<u8>> = vec![("role".to_string(), b"storage".to_vec())]
+            .into_iter()
+            .collect();
+        tester
+            .recv(heartbeat_from(&a).with_state_changes(vec![suspected]))
+            .request(ArtilleryClusterRequest::UpdateMetadata(metadata.clone()));
+
+        let refuted = myself(&mut tester);
+        assert_eq!(refuted.state(), ArtilleryMemberState::Alive);
+        assert!(refuted.incarnation_number() > record.incarnation_number());
+        assert_eq!(refuted.metadata(), &metadata);
+        assert_eq!(refuted.run_id(), record.run_id());
     }
 }
@@ -1,15 +1,294 @@
 use crate::constants::*;
-use chrono::Duration;
-use std::net::{SocketAddr, ToSocketAddrs};
+use crate::epidemic::address::{peer_addr_for, PeerAddr};
+use crate::epidemic::annotation::{AnnotationPolicy, AnnotationScope};
+use crate::epidemic::clock::Clock;
+use crate::epidemic::codec::{MessageCodec, PADDING_HEADER};
+#[cfg(feature = "compression")]
+use crate::epidemic::compression::GossipCompression;
+use crate::epidemic::encryption::{Encryption, Keyring};
+use crate::epidemic::journal::ChangeStore;
+use crate::epidemic::member;
+use crate::epidemic::primitives::NodeId;
+use crate::epidemic::resolver::Resolver;
+use crate::epidemic::tap::MessageTap;
+use crate::epidemic::wireguard::WireGuardExport;
+use crate::errors::*;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{SocketAddr, SocketAddrV4, ToSocketAddrs};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Scheduling priority of the cluster thread.
+/// Failure detection accuracy depends on the event loop running on time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClusterThreadPriority {
+    Min,
+    Specific(u32),
+    Max,
+}
+
+/// Selection of the relays probing a suspected member on our behalf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndirectPingStrategy {
+    /// Relays are picked uniformly at random
+    Random,
+    /// Relays in the zone of the target are preferred, to avoid false negatives
+    /// caused by cross-zone link issues
+    SameZone,
+}
+
+/// What the event loop does once the receiver of the cluster events is dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventConsumerPolicy {
+    /// The events are dropped silently, the node keeps running.
+    DropEvents,
+    /// The events are dropped, the disconnection is logged once.
+    LogAndDrop,
+    /// The event loop stops, like on an exit request.
+    Shutdown,
+}
+
+/// What makes room once the state change buffer is full. Changes of the same member are
+/// always merged first, the change of this node is never dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateChangeOverflow {
+    /// The oldest change already gossiped `broadcast_retransmits` times is dropped,
+    /// otherwise the oldest one.
+    DropOldestTransmitted,
+    /// The oldest change is dropped and a full state sync starts right away, so the
+    /// dropped changes still reach a peer.
+    FullSync,
+}
+
+/// Weight of the liveness signals reported by an external system, e.g. an orchestrator,
+/// through `Cluster::report_external_failure` and `Cluster::report_external_alive`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExternalSignals {
+    /// The reports are logged and otherwise ignored.
+    Ignore,
+    /// A failure report suspects the member right away and counts as a failed direct
+    /// probe, the indirect probes still have to fail. An alive report restarts the
+    /// suspicion of a suspect, which needs fresh evidence then.
+    Corroborate,
+    /// A failure report declares the member down like `Cluster::confirm_down`, an alive
+    /// report is weighed like with `Corroborate`.
+    Trust,
+}
+
+/// How the `initial_members` are tracked until they are heard from. Seeds have no host
+/// key, they are always contacted without being members.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitialMemberState {
+    /// Registered as suspects and probed, declared down once the suspicion times out.
+    /// Members which never came up are alerted on.
+    Suspect,
+    /// Contacted like seeds until they answer, only members from then on. Nothing is
+    /// reported about members which never came up.
+    Unknown,
+}
 
 #[derive(Debug, Clone)]
 pub struct ClusterConfig {
+    /// Human readable name of the cluster, gossiped and reported when a node with a different
+    /// cluster key tries to join. Membership is still decided by the `cluster_key`.
+    pub cluster_name: String,
+    /// Names this node in its log lines and thread names, and for the metrics labels
+    /// through `Cluster::instance_name`, when a process runs several clusters.
+    /// `None` uses the `cluster_name`.
+    pub instance_name: Option<String>,
+    /// Shared by the members, carried in clear unless `encryption` derives its key from it.
     pub cluster_key: Vec<u8>,
     pub ping_interval: Duration,
     pub network_mtu: usize,
     pub ping_request_host_count: usize,
     pub ping_timeout: Duration,
+    /// Port `0` binds a free port, see `Cluster::local_addr`.
     pub listen_addr: SocketAddr,
+    /// Whether an IPv6 `listen_addr` also reaches IPv4 peers, at their IPv4-mapped
+    /// addresses. Needs a socket accepting both families (`IPV6_V6ONLY` off), the
+    /// default of most systems. Peers of the other family are rejected otherwise.
+    pub dual_stack: bool,
+    /// Priority of the cluster thread, `None` keeps the OS default.
+    pub thread_priority: Option<ClusterThreadPriority>,
+    /// Core id to pin the cluster thread to, `None` leaves it unpinned.
+    pub cpu_affinity: Option<usize>,
+    /// Bulk requests (user payloads) processed per loop iteration,
+    /// protocol-critical work is always processed first.
+    pub bulk_work_budget: usize,
+    /// Deferred bulk requests beyond this are shed, oldest first.
+    pub max_deferred_work: usize,
+    /// Control requests of the `Cluster` queued for the event loop, senders wait beyond.
+    pub control_queue_capacity: usize,
+    /// Payloads queued for the event loop besides the deferred ones, senders wait beyond.
+    pub data_queue_capacity: usize,
+    /// Indirect probe requests still unanswered after this long are forgotten.
+    pub wait_list_ttl: Duration,
+    /// Members waiting on the indirect probe of the same target, the oldest request
+    /// is dropped beyond this.
+    pub max_waiting_per_target: usize,
+    /// State changes waiting to be acknowledged, `state_change_overflow` makes room beyond this.
+    pub max_state_changes: usize,
+    pub state_change_overflow: StateChangeOverflow,
+    /// Gossip bandwidth cap of this node, when exceeded piggybacked state changes
+    /// and payloads are deferred to the next window. `None` disables the cap.
+    pub max_gossip_bytes_per_sec: Option<usize>,
+    /// Pre-known topology. These members are probed immediately, instead of being
+    /// discovered seed by seed.
+    pub initial_members: Vec<(NodeId, SocketAddr)>,
+    /// State of the `initial_members` and of the imported members until they answer.
+    pub initial_member_state: InitialMemberState,
+    /// Seeds contacted at startup, host names are resolved in the background.
+    pub seeds: Vec<PeerAddr>,
+    /// Host names of the seeds are resolved again this often, to follow DNS changes.
+    pub seed_resolution_interval: Duration,
+    /// Resolver of the seed host names, run by a worker thread off the event loop.
+    /// `None` uses the system resolver.
+    pub resolver: Option<Arc<dyn Resolver>>,
+    /// Time source of the protocol intervals and timeouts, e.g. an `AcceleratedClock` in
+    /// integration tests. `None` uses the system clock.
+    pub clock: Option<Arc<dyn Clock>>,
+    /// Publicly reachable members introducing this node to the members it can't reach,
+    /// e.g. both behind NATs. Messages go through them until the NATs are punched.
+    pub rendezvous_members: Vec<SocketAddr>,
+    /// Rounds of punching towards an unreachable member before relaying for good.
+    pub hole_punch_attempts: usize,
+    /// A member failing direct probes but answering indirect ones this many times in a row
+    /// is reached through the member relaying the last answer. `None` never relays.
+    pub relay_after_indirect_acks: Option<usize>,
+    /// Messages relayed for other members per second, the rest is dropped.
+    pub max_relayed_per_sec: Option<usize>,
+    /// Times a `Cluster::send_to` message is sent again until the member acknowledges it,
+    /// before `MessageUndelivered` is emitted.
+    pub message_retries: usize,
+    /// Wait for the acknowledgement of a `Cluster::send_to` message before sending it again.
+    pub message_retry_interval: Duration,
+    /// Gossips which members this node can't reach, to report the members unreachable
+    /// from part of the cluster only with `PartiallyUnreachable`.
+    pub track_reachability: bool,
+    /// Stops the event loop, failing it with `IdentityConflict`, when another live node
+    /// uses the host key of this node. The conflict is reported with an event either way.
+    pub stop_on_identity_conflict: bool,
+    /// Handling of the events once nobody receives them anymore.
+    pub event_consumer_gone: EventConsumerPolicy,
+    /// Latest events kept for `Cluster::event_log` readers, 0 keeps none. Readers relying
+    /// on the log only should drop the events receiver with `EventConsumerPolicy::DropEvents`.
+    pub event_log_capacity: usize,
+    /// Re-probe suspected members (directly and indirectly) at every tick until they
+    /// are refuted or confirmed down, rather than only when they are randomly picked.
+    pub probe_suspects_every_tick: bool,
+    pub external_signals: ExternalSignals,
+    /// How many outgoing messages carry each gossiped record (e.g. removal transactions).
+    pub broadcast_retransmits: usize,
+    /// Pack the queued state changes in a random order into each message, the least
+    /// transmitted first still, instead of the queue order. Messages which don't fit every
+    /// change then carry different ones, a lost path doesn't delay the same changes each time.
+    pub shuffle_state_changes: bool,
+    /// Emit a `ProtocolRoundSummary` event at the end of every protocol round.
+    pub round_summaries: bool,
+    /// Finished removal transactions are forgotten after this.
+    pub removal_transaction_retention: Duration,
+    /// Availability zone of this node, gossiped with its member record.
+    pub zone: Option<String>,
+    /// Application ports of this node by service name, gossiped with its member record
+    /// so the other members build its endpoints, e.g. `"grpc" => 9090`.
+    pub ports: BTreeMap<String, u16>,
+    /// Application metadata of this node, gossiped with its member record and updated
+    /// with `Cluster::update_metadata`. At most `CONST_METADATA_LIMIT` bytes.
+    pub metadata: HashMap<String, Vec<u8>>,
+    pub indirect_ping_strategy: IndirectPingStrategy,
+    /// Anti-entropy: full member list exchange with one random peer on this slow timer,
+    /// bounding the worst-case inconsistency. `None` disables it.
+    pub full_sync_interval: Option<Duration>,
+    /// Push/pull the full state over a TCP connection instead of the `Sync` datagrams,
+    /// answering the syncs of the peers on a TCP listener at the listen address. Takes
+    /// the member list of both ends at once, however many members there are.
+    pub tcp_sync: bool,
+    /// Sends the messages not fitting the `network_mtu`, e.g. carrying large records, over
+    /// a TCP connection to the listen address of the peer instead of dropping them. The
+    /// peers need it on too, to listen for them.
+    pub tcp_fallback: bool,
+    /// LAN accelerator: IPv4 multicast group and port receiving a digest of the membership
+    /// from every node each round, members pull the records they lack from its sender.
+    /// Needs the gossip socket bound to the LAN interface. `None` disables it.
+    pub multicast_digests: Option<SocketAddrV4>,
+    /// Pull gossip: pings carry a digest of the membership instead of the queued state
+    /// changes, the acks answer with the records the pinger lacks. Digests are answered
+    /// whatever this setting, so that it can be turned on node by node.
+    pub pull_gossip: bool,
+    /// Wire encoding of the messages sent. Messages of every codec are decoded, so that it
+    /// can be changed node by node.
+    pub codec: MessageCodec,
+    /// Encryption of the messages with a key derived from the `cluster_key`.
+    pub encryption: Encryption,
+    /// Keys of the encryption instead of the `cluster_key`, rotated with
+    /// `Cluster::install_key`, `Cluster::use_key` and `Cluster::remove_key`.
+    pub keyring: Option<Keyring>,
+    /// Dictionary compression of the encoded messages, has to be the same cluster-wide.
+    #[cfg(feature = "compression")]
+    pub compression: Option<GossipCompression>,
+    /// Unix domain socket path republishing the cluster events to local processes.
+    #[cfg(unix)]
+    pub event_bridge: Option<PathBuf>,
+    /// Traffic capture hook, sees every message sent and received.
+    pub message_tap: Option<MessageTap>,
+    /// `WireGuard` peer file kept up to date with the live members, see
+    /// [`wireguard`](super::wireguard). `None` keeps none.
+    pub wireguard: Option<WireGuardExport>,
+    /// Journal of the `Down` declarations of this node, replayed on restart until every
+    /// live member acknowledged them, e.g. a `FileChangeStore`. `None` keeps them in memory.
+    pub change_store: Option<Arc<dyn ChangeStore>>,
+    /// Restarts of a member within `restart_storm_window` above which
+    /// `MemberRestartStorm` is emitted. `None` disables the detection.
+    pub restart_storm_threshold: Option<usize>,
+    pub restart_storm_window: Duration,
+    /// Flaps and convergence lag within this window lower the `ClusterHealth` score.
+    pub health_window: Duration,
+    /// Rounds of extra gossip after a join, leave or failure, to spread it faster. 0 disables bursts.
+    pub gossip_burst_rounds: usize,
+    /// Extra members gossiped to in every burst round.
+    pub gossip_burst_fanout: usize,
+    /// Sustained send failures or silence after which this node considers itself cut off.
+    pub local_connectivity_timeout: Duration,
+    /// Pads every datagram up to a multiple of this many bytes against traffic analysis,
+    /// the padding counts against the bandwidth cap. Has to be the same cluster-wide.
+    pub padding_bucket: Option<usize>,
+    /// Lateness of the periodic work above which `LoopLag` is diagnosed.
+    pub loop_lag_threshold: Duration,
+    /// Event loop watchdog: after this many late ticks in a row this node flags itself
+    /// degraded, emits `EventLoopSlow` and holds off declaring others down, until as many
+    /// ticks are on time again. `None` disables the watchdog.
+    pub slow_loop_ticks: Option<usize>,
+    /// Lateness of a tick, on the monotonic or the wall clock, taken as a suspend and resume
+    /// of the host. `None` disables the detection.
+    pub suspend_detection_threshold: Option<Duration>,
+    /// After a resume nobody is declared down for this long, everyone is probed again first.
+    pub resume_grace_period: Duration,
+    /// Seeds are contacted again with doubling delays up to this after `Cluster::rejoin`,
+    /// until a member answers.
+    pub rejoin_backoff_limit: Duration,
+    /// Down and left members are removed from the member lists after this long.
+    /// `None` keeps them forever.
+    pub reap_after: Option<Duration>,
+    /// The removal is announced this much ahead, so that all nodes reap together.
+    pub reap_announcement_lead: Duration,
+    /// Gossip about a reaped member from before its removal is ignored for this long.
+    pub reap_tombstone_retention: Duration,
+    /// Minimum time a member stays suspect before it can be declared down.
+    pub suspicion_timeout: Duration,
+    /// Failed direct re-probes required to declare a suspect down.
+    pub suspicion_direct_probes: usize,
+    /// Failed indirect probes required to declare a suspect down, capped to the relays available.
+    pub suspicion_indirect_probes: usize,
+    /// Who may write member annotations, has to be the same cluster-wide.
+    pub annotation_policy: AnnotationPolicy,
+    /// Annotation keys kept local or within the zone, e.g. host-level details. The other
+    /// keys are gossiped cluster-wide. Should be the same cluster-wide.
+    pub annotation_scopes: HashMap<String, AnnotationScope>,
+    /// Key authenticating the administrative orders, see `Cluster::order_shutdown`. Nodes
+    /// without it neither obey nor pass on the orders. `None` disables them.
+    pub operator_key: Option<Vec<u8>>,
 }
 
 impl Default for ClusterConfig {
@@ -17,12 +296,319 @@ impl Default for ClusterConfig {
         let directed = SocketAddr::from(([127, 0, 0, 1], CONST_INFECTION_PORT));
 
         ClusterConfig {
+            cluster_name: String::from("default"),
+            instance_name: None,
             cluster_key: b"default".to_vec(),
-            ping_interval: Duration::seconds(1),
+            ping_interval: Duration::from_secs(1),
             network_mtu: CONST_PACKET_SIZE,
             ping_request_host_count: 3,
-            ping_timeout: Duration::seconds(3),
+            ping_timeout: Duration::from_secs(3),
             listen_addr: directed.to_socket_addrs().unwrap().next().unwrap(),
+            dual_stack: false,
+            thread_priority: None,
+            cpu_affinity: None,
+            bulk_work_budget: 64,
+            max_deferred_work: 4096,
+            control_queue_capacity: 1024,
+            data_queue_capacity: 1024,
+            wait_list_ttl: Duration::from_secs(10),
+            max_waiting_per_target: 8,
+            max_state_changes: 4096,
+            state_change_overflow: StateChangeOverflow::DropOldestTransmitted,
+            max_gossip_bytes_per_sec: None,
+            initial_members: Vec::new(),
+            initial_member_state: InitialMemberState::Suspect,
+            seeds: Vec::new(),
+            seed_resolution_interval: Duration::from_secs(60),
+            resolver: None,
+            clock: None,
+            rendezvous_members: Vec::new(),
+            hole_punch_attempts: 3,
+            relay_after_indirect_acks: Some(3),
+            max_relayed_per_sec: None,
+            message_retries: 3,
+            message_retry_interval: Duration::from_secs(1),
+            track_reachability: true,
+            stop_on_identity_conflict: true,
+            event_consumer_gone: EventConsumerPolicy::Shutdown,
+            event_log_capacity: 1024,
+            probe_suspects_every_tick: true,
+            external_signals: ExternalSignals::Corroborate,
+            broadcast_retransmits: 6,
+            shuffle_state_changes: false,
+            round_summaries: false,
+            removal_transaction_retention: Duration::from_secs(60 * 60),
+            zone: None,
+            ports: BTreeMap::new(),
+            metadata: HashMap::new(),
+            indirect_ping_strategy: IndirectPingStrategy::Random,
+            full_sync_interval: Some(Duration::from_secs(5 * 60)),
+            tcp_sync: false,
+            tcp_fallback: false,
+            multicast_digests: None,
+            pull_gossip: false,
+            codec: MessageCodec::Json,
+            encryption: Encryption::Plaintext,
+            keyring: None,
+            #[cfg(feature = "compression")]
+            compression: None,
+            #[cfg(unix)]
+            event_bridge: None,
+            message_tap: None,
+            wireguard: None,
+            change_store: None,
+            restart_storm_threshold: Some(5),
+            restart_storm_window: Duration::from_secs(10 * 60),
+            health_window: Duration::from_secs(60),
+            gossip_burst_rounds: 3,
+            gossip_burst_fanout: 2,
+            local_connectivity_timeout: Duration::from_secs(10),
+            padding_bucket: None,
+            loop_lag_threshold: Duration::from_millis(500),
+            slow_loop_ticks: Some(3),
+            suspend_detection_threshold: Some(Duration::from_secs(5)),
+            resume_grace_period: Duration::from_secs(10),
+            rejoin_backoff_limit: Duration::from_secs(30),
+            reap_after: None,
+            reap_announcement_lead: Duration::from_secs(30),
+            reap_tombstone_retention: Duration::from_secs(60 * 60),
+            suspicion_timeout: Duration::from_secs(3),
+            suspicion_direct_probes: 1,
+            suspicion_indirect_probes: 1,
+            annotation_policy: AnnotationPolicy::LastWriterWins,
+            annotation_scopes: HashMap::new(),
+            operator_key: None,
+        }
+    }
+}
+
+impl ClusterConfig {
+    /// Rejects configurations the event loop can't run with.
+    pub fn instance_name(&self) -> &str {
+        self.instance_name.as_deref().unwrap_or(&self.cluster_name)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let peers = self
+            .seeds
+            .iter()
+            .filter_map(|seed| seed.socket_addr().map(|addr| ("seed", addr)))
+            .chain(
+                self.initial_members
+                    .iter()
+                    .map(|(_, addr)| ("initial member", *addr)),
+            )
+            .chain(
+                self.rendezvous_members
+                    .iter()
+                    .map(|addr| ("rendezvous member", *addr)),
+            );
+        for (role, addr) in peers {
+            if let Err(mismatch) = peer_addr_for(self.listen_addr, addr, self.dual_stack) {
+                bail!(
+                    ArtilleryError::InvalidConfiguration,
+                    "The {} {}",
+                    role,
+                    mismatch
+                );
+            }
+        }
+
+        if let Some(name) = &self.instance_name {
+            if name.is_empty() || name.contains('\0') {
+                bail!(
+                    ArtilleryError::InvalidConfiguration,
+                    "instance_name must be a non empty name without NUL characters"
+                );
+            }
+        }
+
+        if self.ping_interval == Duration::from_secs(0) {
+            bail!(
+                ArtilleryError::InvalidConfiguration,
+                "ping_interval can't be zero"
+            );
+        }
+
+        if self.ping_timeout == Duration::from_secs(0) {
+            bail!(
+                ArtilleryError::InvalidConfiguration,
+                "ping_timeout can't be zero"
+            );
+        }
+
+        // Padding, relay envelope, codec tag and encryption all come out of the MTU
+        let overhead = self.padding_bucket.map_or(0, |_| PADDING_HEADER)
+            + CONST_RELAY_ENVELOPE_OVERHEAD
+            + self.codec.header_len()
+            + self.encryption.overhead();
+        if self.network_mtu <= overhead || self.network_mtu > CONST_PACKET_SIZE {
+            bail!(
+                ArtilleryError::InvalidConfiguration,
+                "network_mtu should be within {}..={}, got {}",
+                overhead + 1,
+                CONST_PACKET_SIZE,
+                self.network_mtu
+            );
+        }
+
+        if self.control_queue_capacity == 0 || self.data_queue_capacity == 0 {
+            bail!(
+                ArtilleryError::InvalidConfiguration,
+                "control_queue_capacity and data_queue_capacity can't be zero"
+            );
+        }
+
+        if self.max_waiting_per_target == 0 {
+            bail!(
+                ArtilleryError::InvalidConfiguration,
+                "max_waiting_per_target can't be zero"
+            );
+        }
+
+        if let Some(group) = self.multicast_digests {
+            if !group.ip().is_multicast() {
+                bail!(
+                    ArtilleryError::InvalidConfiguration,
+                    "multicast_digests should be a multicast group, got {}",
+                    group
+                );
+            }
+        }
+
+        if let Some(key) = &self.operator_key {
+            if key.is_empty() || *key == self.cluster_key {
+                bail!(
+                    ArtilleryError::InvalidConfiguration,
+                    "operator_key can't be empty nor the cluster_key"
+                );
+            }
+        }
+
+        let default_key = ClusterConfig::default().cluster_key;
+        if self.keyring.is_none()
+            && self.encryption != Encryption::Plaintext
+            && (self.cluster_key.is_empty() || self.cluster_key == default_key)
+        {
+            bail!(
+                ArtilleryError::InvalidConfiguration,
+                "encryption needs a cluster_key of its own, not an empty nor the default one"
+            );
+        }
+
+        if let Some(keyring) = &self.keyring {
+            if keyring.keys().any(<[u8]>::is_empty) {
+                bail!(
+                    ArtilleryError::InvalidConfiguration,
+                    "the keys of the keyring can't be empty"
+                );
+            }
+        }
+
+        if self.slow_loop_ticks == Some(0) {
+            bail!(
+                ArtilleryError::InvalidConfiguration,
+                "slow_loop_ticks can't be zero"
+            );
+        }
+
+        if self
+            .ports
+            .iter()
+            .any(|(service, port)| service.is_empty() || *port == 0)
+        {
+            bail!(
+                ArtilleryError::InvalidConfiguration,
+                "ports need a service name and a port other than 0"
+            );
+        }
+
+        if member::metadata_size(&self.metadata) > CONST_METADATA_LIMIT {
+            bail!(
+                ArtilleryError::InvalidConfiguration,
+                "metadata can't exceed {} bytes",
+                CONST_METADATA_LIMIT
+            );
+        }
+
+        if let Some(export) = self.wireguard.as_ref() {
+            if export.default_port == 0 {
+                bail!(
+                    ArtilleryError::InvalidConfiguration,
+                    "wireguard default_port can't be zero"
+                );
+            }
+        }
+
+        if self.message_retry_interval == Duration::from_secs(0) {
+            bail!(
+                ArtilleryError::InvalidConfiguration,
+                "message_retry_interval can't be zero"
+            );
+        }
+
+        if self.health_window == Duration::from_secs(0) {
+            bail!(
+                ArtilleryError::InvalidConfiguration,
+                "health_window can't be zero"
+            );
+        }
+
+        if self.max_state_changes == 0 {
+            bail!(
+                ArtilleryError::InvalidConfiguration,
+                "max_state_changes can't be zero"
+            );
+        }
+
+        if let Some(bucket) = self.padding_bucket {
+            if bucket == 0 || bucket > self.network_mtu {
+                bail!(
+                    ArtilleryError::InvalidConfiguration,
+                    "padding_bucket should be within 1..={}, got {}",
+                    self.network_mtu,
+                    bucket
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl From<ClusterThreadPriority> for thread_priority::ThreadPriority {
+    fn from(priority: ClusterThreadPriority) -> Self {
+        match priority {
+            ClusterThreadPriority::Min => thread_priority::ThreadPriority::Min,
+            ClusterThreadPriority::Specific(p) => thread_priority::ThreadPriority::Specific(p),
+            ClusterThreadPriority::Max => thread_priority::ThreadPriority::Max,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rejects_zero_intervals() {
+        assert!(ClusterConfig::default().validate().is_ok());
+
+        let configs = vec![
+            ClusterConfig {
+                ping_interval: Duration::from_secs(0),
+                ..Default::default()
+            },
+            ClusterConfig {
+                ping_timeout: Duration::from_secs(0),
+                ..Default::default()
+            },
+        ];
+        for config in configs {
+            assert!(matches!(
+                config.validate(),
+                Err(ArtilleryError::InvalidConfiguration(reason)) if reason.contains("can't be zero")
+            ));
         }
     }
 }
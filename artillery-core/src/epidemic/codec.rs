@@ -0,0 +1,243 @@
+use crate::errors::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::convert::TryFrom;
+
+/// Length header in front of a padded datagram.
+pub const PADDING_HEADER: usize = 2;
+
+/// First byte of a message tagged with its codec, no JSON nor CBOR message starts with it.
+pub const WIRE_MAGIC: u8 = 0xff;
+
+/// Magic byte and codec id in front of a tagged message.
+pub const WIRE_HEADER: usize = 2;
+
+/// Encoding of the gossip messages on the wire. Members decode the messages of every codec,
+/// the codec of a cluster can be changed one member at a time once all of them run a
+/// release knowing it.
+///
+/// JSON and CBOR messages are sent untagged, as older releases expect them. The other
+/// codecs prepend [`WIRE_MAGIC`] and their id, future formats get new ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageCodec {
+    Json,
+    /// Canonical CBOR, deterministic byte output for the same message which keeps
+    /// signatures and HMACs stable, while being smaller than JSON.
+    ///
+    /// Struct fields are emitted in declaration order with definite lengths and shortest
+    /// integer forms. Wire types only contain sequences and structs, maps which might be
+    /// added to them should be ordered (`BTreeMap`) to keep this property.
+    Cbor,
+    /// `MessagePack` with named struct fields, fields added with a default stay compatible.
+    MessagePack,
+    /// Bincode, the most compact and fastest, but fields can't be added to the messages
+    /// without breaking the members of the previous release.
+    Bincode,
+}
+
+impl MessageCodec {
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>> {
+        match self {
+            MessageCodec::Json => Ok(serde_json::to_vec(value)?),
+            MessageCodec::Cbor => serde_cbor::to_vec(value)
+                .map_err(|e| ArtilleryError::Unexpected(format!("CBOR encoding failed: {}", e))),
+            MessageCodec::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| {
+                ArtilleryError::Unexpected(format!("MessagePack encoding failed: {}", e))
+            }),
+            MessageCodec::Bincode => bincode::serialize(value)
+                .map_err(|e| ArtilleryError::Unexpected(format!("Bincode encoding failed: {}", e))),
+        }
+    }
+
+    /// Id following [`WIRE_MAGIC`], `None` for the untagged codecs.
+    fn wire_id(self) -> Option<u8> {
+        match self {
+            MessageCodec::Json | MessageCodec::Cbor => None,
+            MessageCodec::MessagePack => Some(1),
+            MessageCodec::Bincode => Some(2),
+        }
+    }
+
+    /// Bytes the tag adds in front of an encoded message.
+    pub fn header_len(self) -> usize {
+        self.wire_id().map_or(0, |_| WIRE_HEADER)
+    }
+
+    /// Prepends the tag of the codec to an encoded message.
+    pub fn tag(self, encoded: Vec<u8>) -> Vec<u8> {
+        match self.wire_id() {
+            Some(id) => {
+                let mut tagged = Vec::with_capacity(WIRE_HEADER + encoded.len());
+                tagged.extend_from_slice(&[WIRE_MAGIC, id]);
+                tagged.extend_from_slice(&encoded);
+                tagged
+            }
+            None => encoded,
+        }
+    }
+
+    /// Codec of a received message and the message without its tag. Untagged messages are
+    /// JSON when they start as an object, CBOR otherwise.
+    pub fn detect(buf: &[u8]) -> Result<(MessageCodec, &[u8])> {
+        match buf {
+            [WIRE_MAGIC, id, message @ ..] => [MessageCodec::MessagePack, MessageCodec::Bincode]
+                .iter()
+                .copied()
+                .find(|codec| codec.wire_id() == Some(*id))
+                .map(|codec| (codec, message))
+                .ok_or_else(|| {
+                    ArtilleryError::ClusterMessageDecode(format!("unknown message codec {}", id))
+                }),
+            [b'{', ..] => Ok((MessageCodec::Json, buf)),
+            _ => Ok((MessageCodec::Cbor, buf)),
+        }
+    }
+
+    /// Bytes a sequence of `len` elements takes beyond its encoded elements, compared to
+    /// the empty sequence. Lets a message be sized element by element.
+    pub fn sequence_overhead(self, len: usize) -> usize {
+        match self {
+            // Separating commas
+            MessageCodec::Json => len.saturating_sub(1),
+            // Definite length array header, one byte when empty
+            MessageCodec::Cbor => match len {
+                0..=23 => 0,
+                24..=0xff => 1,
+                0x100..=0xffff => 2,
+                0x1_0000..=0xffff_ffff => 4,
+                _ => 8,
+            },
+            // Array header, one byte up to 15 elements
+            MessageCodec::MessagePack => match len {
+                0..=15 => 0,
+                16..=0xffff => 2,
+                _ => 4,
+            },
+            // Fixed size length prefix
+            MessageCodec::Bincode => 0,
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, buf: &[u8]) -> Result<T> {
+        match self {
+            MessageCodec::Json => Ok(serde_json::from_slice(buf)?),
+            MessageCodec::Cbor => Ok(serde_cbor::from_slice(buf)?),
+            MessageCodec::MessagePack => Ok(rmp_serde::from_read_ref(buf)?),
+            MessageCodec::Bincode => Ok(bincode::deserialize(buf)?),
+        }
+    }
+}
+
+/// Frames the encoded message as `[u16 BE length][message][zeros]`, rounded up to a
+/// multiple of `bucket` bytes and capped to `max_len`, so the datagram sizes don't leak
+/// the cluster size or health to observers.
+pub fn pad(encoded: &[u8], bucket: usize, max_len: usize) -> Result<Vec<u8>> {
+    let len = u16::try_from(encoded.len())?;
+    let framed = PADDING_HEADER + encoded.len();
+    let padded = (framed.div_ceil(bucket) * bucket).min(max_len).max(framed);
+
+    let mut datagram = Vec::with_capacity(padded);
+    datagram.extend_from_slice(&len.to_be_bytes());
+    datagram.extend_from_slice(encoded);
+    datagram.resize(padded, 0);
+
+    Ok(datagram)
+}
+
+/// Strips the padding added by [`pad`].
+pub fn unpad(datagram: &[u8]) -> Result<&[u8]> {
+    if datagram.len() < PADDING_HEADER {
+        bail!(
+            ArtilleryError::ClusterMessageDecode,
+            "padded datagram of {} bytes has no length header",
+            datagram.len()
+        );
+    }
+
+    let len = usize::from(u16::from_be_bytes([datagram[0], datagram[1]]));
+    datagram
+        .get(PADDING_HEADER..PADDING_HEADER + len)
+        .ok_or_else(|| {
+            ArtilleryError::ClusterMessageDecode(format!(
+                "padded datagram of {} bytes is shorter than its {} bytes message",
+                datagram.len(),
+                len
+            ))
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{pad, unpad, MessageCodec};
+    use crate::epidemic::member::ArtilleryMember;
+    use uuid::Uuid;
+
+    #[test]
+    fn cbor_is_deterministic_and_roundtrips() {
+        let member = ArtilleryMember::current(Uuid::new_v4());
+
+        let first = MessageCodec::Cbor.encode(&member).unwrap();
+        let second = MessageCodec::Cbor.encode(&member).unwrap();
+        assert_eq!(first, second);
+        assert!(first.len() < MessageCodec::Json.encode(&member).unwrap().len());
+
+        let decoded: ArtilleryMember = MessageCodec::Cbor.decode(&first).unwrap();
+        assert_eq!(decoded, member);
+    }
+
+    #[test]
+    fn detects_the_codec_of_received_messages() {
+        let member = ArtilleryMember::current(Uuid::new_v4());
+        let codecs = [
+            MessageCodec::Json,
+            MessageCodec::Cbor,
+            MessageCodec::MessagePack,
+            MessageCodec::Bincode,
+        ];
+
+        for codec in codecs.iter().copied() {
+            let tagged = codec.tag(codec.encode(&member).unwrap());
+            let (detected, message) = MessageCodec::detect(&tagged).unwrap();
+            assert_eq!(detected, codec);
+            assert_eq!(tagged.len() - message.len(), codec.header_len());
+            assert_eq!(detected.decode::<ArtilleryMember>(message).unwrap(), member);
+        }
+        assert!(MessageCodec::detect(&[super::WIRE_MAGIC, 0xee, 0]).is_err());
+    }
+
+    #[test]
+    fn sequence_sizes_add_up() {
+        let members: Vec<ArtilleryMember> = (0..300)
+            .map(|_| ArtilleryMember::current(Uuid::new_v4()))
+            .collect();
+
+        let codecs = [
+            MessageCodec::Json,
+            MessageCodec::Cbor,
+            MessageCodec::MessagePack,
+            MessageCodec::Bincode,
+        ];
+        for codec in codecs.iter().copied() {
+            let empty = codec.encode(&Vec::<ArtilleryMember>::new()).unwrap().len();
+            let mut total = empty;
+            for (i, member) in members.iter().enumerate() {
+                total += codec.encode(member).unwrap().len();
+                let prefix = &members[..=i];
+                assert_eq!(
+                    codec.encode(&prefix).unwrap().len(),
+                    total + codec.sequence_overhead(prefix.len())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn padding_rounds_up_to_buckets() {
+        let datagram = pad(b"hello", 64, 1400).unwrap();
+        assert_eq!(datagram.len(), 64);
+        assert_eq!(unpad(&datagram).unwrap(), b"hello");
+
+        assert_eq!(pad(&[1; 100], 64, 120).unwrap().len(), 120);
+        assert!(unpad(&datagram[..4]).is_err());
+    }
+}
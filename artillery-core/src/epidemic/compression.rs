@@ -0,0 +1,92 @@
+use crate::errors::*;
+use std::fmt;
+use std::sync::Arc;
+
+/// Raw-content dictionary shipped with the crate, made of the recurring fragments of JSON
+/// encoded gossip messages. Clusters using another codec should train their own.
+pub const BUNDLED_DICTIONARY: &[u8] = include_bytes!("../../dictionaries/gossip-json.dict");
+
+/// Upper bound of a decompressed message, no datagram can carry more.
+const MAX_DECOMPRESSED: usize = 64 * 1024;
+
+/// Zstd compression of the encoded messages with a shared dictionary. Small datagrams
+/// compress poorly on their own, the dictionary provides the context they lack.
+/// Has to be the same cluster-wide, including the dictionary.
+#[derive(Clone)]
+pub struct GossipCompression {
+    pub dictionary: Arc<Vec<u8>>,
+    /// Zstd compression level, `1..=22`.
+    pub level: i32,
+}
+
+impl Default for GossipCompression {
+    fn default() -> Self {
+        GossipCompression {
+            dictionary: Arc::new(BUNDLED_DICTIONARY.to_vec()),
+            level: 3,
+        }
+    }
+}
+
+impl GossipCompression {
+    pub fn with_dictionary(dictionary: Vec<u8>) -> Self {
+        GossipCompression {
+            dictionary: Arc::new(dictionary),
+            ..Default::default()
+        }
+    }
+
+    /// Trains a dictionary of at most `max_size` bytes from encoded messages of the cluster.
+    pub fn train<S: AsRef<[u8]>>(samples: &[S], max_size: usize) -> Result<Self> {
+        let dictionary = zstd::dict::from_samples(samples, max_size)?;
+        Ok(Self::with_dictionary(dictionary))
+    }
+
+    pub fn compress(&self, encoded: &[u8]) -> Result<Vec<u8>> {
+        let mut compressor = zstd::block::Compressor::with_dict(self.dictionary.to_vec());
+        Ok(compressor.compress(encoded, self.level)?)
+    }
+
+    pub fn decompress(&self, compressed: &[u8]) -> Result<Vec<u8>> {
+        let mut decompressor = zstd::block::Decompressor::with_dict(self.dictionary.to_vec());
+        decompressor
+            .decompress(compressed, MAX_DECOMPRESSED)
+            .map_err(|e| ArtilleryError::ClusterMessageDecode(e.to_string()))
+    }
+}
+
+impl fmt::Debug for GossipCompression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GossipCompression")
+            .field("dictionary_len", &self.dictionary.len())
+            .field("level", &self.level)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const HEARTBEAT: &[u8] = br#"{"sender":"6f0c1b7e-2f5a-4d8e-9a43-0d1f3c6b8a11","cluster_name":"default","cluster_key":[100,101,102,97,117,108,116],"request":"Heartbeat","state_changes":[],"heartbeat":null,"transactions":[],"annotations":[]}"#;
+
+    #[test]
+    fn bundled_dictionary_roundtrip() {
+        let compression = GossipCompression::default();
+        let compressed = compression.compress(HEARTBEAT).unwrap();
+
+        assert!(compressed.len() < HEARTBEAT.len() / 2);
+        assert_eq!(compression.decompress(&compressed).unwrap(), HEARTBEAT);
+    }
+
+    #[test]
+    fn different_dictionary_does_not_decode() {
+        let compressed = GossipCompression::default().compress(HEARTBEAT).unwrap();
+        let other = GossipCompression::with_dictionary(b"unrelated content".to_vec());
+
+        assert!(other
+            .decompress(&compressed)
+            .map(|d| d != HEARTBEAT)
+            .unwrap_or(true));
+    }
+}
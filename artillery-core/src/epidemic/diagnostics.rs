@@ -0,0 +1,58 @@
+use super::primitives::NodeId;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// Protocol level problems, delivered next to the membership events so applications
+/// can alert on the cluster health. Dropped when the consumer falls behind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClusterDiagnostic {
+    /// Undecodable datagram, it was dropped.
+    DecodeFailure {
+        from: SocketAddr,
+        reason: String,
+    },
+    /// Message from another cluster, or with a wrong cluster key.
+    KeyMismatch {
+        from: SocketAddr,
+        sender: NodeId,
+        cluster_name: String,
+    },
+    SendError {
+        to: SocketAddr,
+        reason: String,
+    },
+    /// The periodic protocol work ran this much later than scheduled.
+    LoopLag(Duration),
+    /// Ticks were this far apart, the host was most likely suspended.
+    ClockJump(Duration),
+    /// Bulk requests dropped since the last report because the deferred queue was full.
+    WorkShed {
+        shed: u64,
+        backlog: usize,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constants::CONST_DIAGNOSTICS_CAPACITY;
+    use crate::epidemic::test_kit::*;
+
+    #[test]
+    fn drops_the_diagnostics_the_consumer_lags_behind() {
+        let mut tester = tester_with(|config| {
+            config.loop_lag_threshold = Duration::from_millis(500);
+        });
+        tester.tick_late(Duration::from_secs(1), Duration::from_millis(100));
+        assert!(tester.drain_diagnostics().is_empty());
+
+        for _ in 0..CONST_DIAGNOSTICS_CAPACITY + 10 {
+            tester.tick_late(Duration::from_secs(1), Duration::from_secs(1));
+        }
+        let diagnostics = tester.drain_diagnostics();
+        assert_eq!(diagnostics.len(), CONST_DIAGNOSTICS_CAPACITY);
+        assert!(diagnostics
+            .iter()
+            .all(|d| *d == ClusterDiagnostic::LoopLag(Duration::from_secs(1))));
+    }
+}
@@ -0,0 +1,207 @@
+use super::member::{self, ArtilleryMember, ArtilleryMemberState, ArtilleryStateChange};
+use serde::*;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DigestEntry {
+    #[serde(rename = "k")]
+    host_key: Uuid,
+    #[serde(rename = "i")]
+    incarnation: u64,
+    #[serde(rename = "s")]
+    state: ArtilleryMemberState,
+}
+
+impl DigestEntry {
+    /// Stand-in record of the member, to compare it with full records.
+    fn as_member(&self) -> ArtilleryMember {
+        let placeholder = SocketAddr::from(([0, 0, 0, 0], 0));
+        ArtilleryMember::new(self.host_key, placeholder, self.incarnation, self.state)
+    }
+}
+
+/// Whether the candidate record carries newer knowledge than the known one.
+fn is_newer(candidate: &ArtilleryMember, known: &ArtilleryMember) -> bool {
+    let newest = member::most_uptodate_member_data(candidate, known);
+
+    std::ptr::eq(newest, candidate)
+        && (newest.state(), newest.incarnation_number())
+            != (known.state(), known.incarnation_number())
+}
+
+/// Compact summary of member records, their incarnation and state only. Receivers compare
+/// it with their membership and pull the member records they lack.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemberDigest {
+    #[serde(rename = "m")]
+    entries: Vec<DigestEntry>,
+}
+
+impl MemberDigest {
+    pub fn of(members: &[ArtilleryMember]) -> Self {
+        MemberDigest {
+            entries: members
+                .iter()
+                .map(|m| DigestEntry {
+                    host_key: m.host_key(),
+                    incarnation: m.incarnation_number(),
+                    state: m.state(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Members of the digest unknown to `known` or known with older records, except `me`.
+    pub(crate) fn missing_from(
+        &self,
+        known: &HashMap<Uuid, ArtilleryMember>,
+        me: Uuid,
+    ) -> Vec<Uuid> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.host_key != me)
+            .filter(|entry| {
+                known
+                    .get(&entry.host_key)
+                    .is_none_or(|member| is_newer(&entry.as_member(), member))
+            })
+            .map(|entry| entry.host_key)
+            .collect()
+    }
+
+    /// Records of `known` the digest lacks or summarizes with older knowledge.
+    pub fn outdated_in(&self, known: &[ArtilleryMember]) -> Vec<ArtilleryMember> {
+        let summarized = self.as_members();
+
+        known
+            .iter()
+            .filter(|member| {
+                summarized
+                    .get(&member.host_key())
+                    .is_none_or(|entry| is_newer(member, entry))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Keeps the changes the digest doesn't already summarize as known.
+    pub fn retain_unknown(&self, changes: &mut Vec<ArtilleryStateChange>) {
+        let summarized = self.as_members();
+
+        changes.retain(|change| {
+            summarized
+                .get(&change.member().host_key())
+                .is_none_or(|entry| is_newer(change.member(), entry))
+        });
+    }
+
+    fn as_members(&self) -> HashMap<Uuid, ArtilleryMember> {
+        self.entries
+            .iter()
+            .map(|entry| (entry.host_key, entry.as_member()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::state::Request;
+    use crate::epidemic::test_kit::*;
+    use std::time::Duration;
+
+    #[test]
+    fn finds_the_members_lacking_locally() {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 4000));
+        let me = Uuid::new_v4();
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        let remote = vec![
+            ArtilleryMember::new(me, addr, 3, ArtilleryMemberState::Suspect),
+            ArtilleryMember::new(a, addr, 1, ArtilleryMemberState::Alive),
+            ArtilleryMember::new(b, addr, 2, ArtilleryMemberState::Suspect),
+            ArtilleryMember::new(c, addr, 0, ArtilleryMemberState::Alive),
+        ];
+        let known: HashMap<Uuid, ArtilleryMember> = vec![
+            ArtilleryMember::new(a, addr, 1, ArtilleryMemberState::Alive),
+            ArtilleryMember::new(b, addr, 2, ArtilleryMemberState::Alive),
+        ]
+        .into_iter()
+        .map(|m| (m.host_key(), m))
+        .collect();
+
+        let mut missing = MemberDigest::of(&remote).missing_from(&known, me);
+        missing.sort();
+        let mut expected = vec![b, c];
+        expected.sort();
+        assert_eq!(missing, expected);
+    }
+
+    #[test]
+    fn answers_with_the_records_the_sender_lacks() {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 4000));
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        let digest = MemberDigest::of(&[
+            ArtilleryMember::new(a, addr, 1, ArtilleryMemberState::Alive),
+            ArtilleryMember::new(b, addr, 2, ArtilleryMemberState::Suspect),
+        ]);
+        let known = vec![
+            ArtilleryMember::new(a, addr, 1, ArtilleryMemberState::Alive),
+            ArtilleryMember::new(b, addr, 3, ArtilleryMemberState::Alive),
+            ArtilleryMember::new(c, addr, 0, ArtilleryMemberState::Alive),
+        ];
+
+        let outdated: Vec<Uuid> = digest
+            .outdated_in(&known)
+            .iter()
+            .map(ArtilleryMember::host_key)
+            .collect();
+        assert_eq!(outdated.len(), 2);
+        assert!(outdated.contains(&b) && outdated.contains(&c));
+
+        let mut changes: Vec<ArtilleryStateChange> =
+            known.into_iter().map(ArtilleryStateChange::new).collect();
+        digest.retain_unknown(&mut changes);
+        assert_eq!(changes.len(), 2);
+    }
+
+    #[test]
+    fn pulls_the_members_a_digest_reveals() {
+        let (a, b) = (TestPeer::new(), TestPeer::new());
+        let mut tester = ProtocolTester::new();
+
+        tester
+            .recv(heartbeat_from(&a))
+            .expect_sent(ack_to(&a))
+            .recv(digest_from(&a, &[b.member(ArtilleryMemberState::Alive)]))
+            .expect_sent(pull_request_to(&a));
+    }
+
+    #[test]
+    fn pull_gossip_pings_carry_digests_and_acks_the_gaps() {
+        let a = TestPeer::new();
+        let mut tester = tester_with(|config| {
+            config.pull_gossip = true;
+        });
+
+        tester
+            .recv(heartbeat_from(&a))
+            .expect_sent(ack_to(&a))
+            .tick(Duration::from_secs(1))
+            .expect_sent(expectation(&a, "heartbeat with a digest", |m| {
+                m.request == Request::Heartbeat && m.digest.is_some() && m.state_changes.is_empty()
+            }));
+
+        // The digest lacks only the record of the node under test
+        tester
+            .recv(heartbeat_from(&a).with_digest(&[a.member(ArtilleryMemberState::Alive)]))
+            .expect_sent(expectation(&a, "ack with the missing record", |m| {
+                m.request == Request::Ack
+                    && m.state_changes.len() == 1
+                    && m.state_changes[0].member().is_current()
+            }));
+    }
+}
@@ -0,0 +1,268 @@
+use super::cluster::Cluster;
+use super::cluster_config::ClusterConfig;
+use super::lanes::{request_lanes, RequestReceiver};
+use super::nat::RelayStats;
+use super::primitives::NodeId;
+use super::state::{ArtilleryEpidemic, StateFootprint, WorkStats};
+use super::traffic::TrafficStats;
+use super::transport::{DefaultTransport, Transport};
+use crate::constants::CONST_PACKET_SIZE;
+use crate::errors::*;
+use crate::logging;
+use mio::event::Event;
+use mio::{Poll, Token};
+use std::sync::mpsc::channel;
+use std::time::{Duration, Instant};
+
+/// Runs the cluster protocol inside an existing mio reactor instead of its own thread.
+///
+/// ```ignore
+/// let (cluster, mut driver) = ClusterDriver::new(host_key, config)?;
+/// driver.register(&mut poll, Token(7))?;
+/// loop {
+///     poll.poll(&mut events, Some(driver.timeout()))?;
+///     for event in events.iter() {
+///         if !driver.handle_event(event)? {
+///             // Event of the application
+///         }
+///     }
+///     driver.tick();
+/// }
+/// ```
+///
+/// The returned [`Cluster`] is the usual control surface, dropping it stops the driver.
+pub struct ClusterDriver {
+    state: ArtilleryEpidemic,
+    own_poll: Poll,
+    requests: RequestReceiver,
+    token: Option<Token>,
+    last_tick: Instant,
+    buf: Vec<u8>,
+}
+
+impl ClusterDriver {
+    pub fn new<I: Into<NodeId>>(
+        host_key: I,
+        config: ClusterConfig,
+    ) -> Result<(Cluster, ClusterDriver)> {
+        let server_socket = DefaultTransport::bind(config.listen_addr)?;
+        ClusterDriver::with_transport(host_key, config, server_socket)
+    }
+
+    /// Driver gossiping over the given transport, registered with the application reactor
+    /// like the UDP socket. The listen address of the configuration is replaced by the
+    /// address of the transport.
+    pub fn with_transport<I: Into<NodeId>, T: Transport + 'static>(
+        host_key: I,
+        config: ClusterConfig,
+        transport: T,
+    ) -> Result<(Cluster, ClusterDriver)> {
+        let (event_tx, event_rx) = channel();
+        let (internal_tx, requests) = request_lanes(&config);
+
+        let (own_poll, mut state) = ArtilleryEpidemic::with_transport(
+            host_key.into().into(),
+            config,
+            Box::new(transport),
+            event_tx,
+            internal_tx.clone(),
+        )?;
+        let cluster = Cluster::attach(&mut state, event_rx, internal_tx, false);
+        let last_tick = state.now();
+
+        Ok((
+            cluster,
+            ClusterDriver {
+                state,
+                own_poll,
+                requests,
+                token: None,
+                last_tick,
+                buf: vec![0_u8; CONST_PACKET_SIZE],
+            },
+        ))
+    }
+
+    /// Registers the gossip socket with the application reactor under `token`.
+    pub fn register(&mut self, poll: &mut Poll, token: Token) -> Result<()> {
+        self.state
+            .reregister_socket(self.own_poll.registry(), poll.registry(), token)?;
+        self.token = Some(token);
+
+        Ok(())
+    }
+
+    /// Handles the event if it is for the gossip socket, returns whether it was.
+    /// Fails with `IdentityConflict` once another node using our host key answered.
+    pub fn handle_event(&mut self, event: &Event) -> Result<bool> {
+        if self.token != Some(event.token()) {
+            return Ok(false);
+        }
+        let _instance = logging::enter_instance(self.state.config().instance_name());
+
+        self.state.receive_datagrams(&mut self.buf)?;
+        self.state.drain_requests(&self.requests);
+        self.state.check_identity()?;
+
+        Ok(true)
+    }
+
+    /// Runs the periodic work when due and the requests queued by the `Cluster`.
+    /// Should be called after every poll.
+    pub fn tick(&mut self) {
+        let _instance = logging::enter_instance(self.state.config().instance_name());
+        let elapsed = self.state.now().saturating_duration_since(self.last_tick);
+        let interval = self.state.config().ping_interval;
+        if elapsed >= interval {
+            self.state.watch_tick_lag(elapsed - interval);
+            self.state.tick();
+            self.last_tick = self.state.now();
+        }
+
+        self.state.drain_requests(&self.requests);
+    }
+
+    /// Longest the reactor can block in a poll without delaying the protocol.
+    pub fn timeout(&self) -> Duration {
+        let elapsed = self.state.now().saturating_duration_since(self.last_tick);
+        let remaining = self
+            .state
+            .config()
+            .ping_interval
+            .checked_sub(elapsed)
+            .unwrap_or_default();
+        self.state.real_duration(remaining)
+    }
+
+    /// Turns false once the cluster was dropped or asked to exit, or on an identity conflict.
+    pub fn is_running(&self) -> bool {
+        self.state.is_running()
+    }
+
+    pub fn instance_name(&self) -> &str {
+        self.state.config().instance_name()
+    }
+
+    pub fn work_stats(&self) -> &WorkStats {
+        self.state.work_stats()
+    }
+
+    pub fn traffic_stats(&self) -> &TrafficStats {
+        self.state.traffic_stats()
+    }
+
+    pub fn relay_stats(&self) -> RelayStats {
+        self.state.relay_stats()
+    }
+
+    pub fn footprint(&self) -> StateFootprint {
+        self.state.footprint()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::prelude::*;
+    use mio::net::UdpSocket;
+    use mio::{Events, Interest};
+    use std::net::SocketAddr;
+    use uuid::Uuid;
+
+    /// Token of the sockets of the application sharing the poll.
+    const APP_TOKEN: usize = 3;
+
+    /// Runs the application loop around the driver until its cluster saw the member join.
+    fn run_until_joined(
+        poll: &mut Poll,
+        driver: &mut ClusterDriver,
+        cluster: &Cluster,
+        member: Uuid,
+    ) -> bool {
+        let mut events = Events::with_capacity(16);
+        let deadline = Instant::now() + Duration::from_secs(30);
+
+        while Instant::now() < deadline {
+            poll.poll(&mut events, Some(driver.timeout())).unwrap();
+            for event in events.iter() {
+                let handled = driver.handle_event(event).unwrap();
+                assert_eq!(handled, event.token() != Token(APP_TOKEN));
+            }
+            driver.tick();
+
+            if cluster.events.try_iter().any(|(_, event, _)| {
+                matches!(event, ArtilleryMemberEvent::Joined(m) if m.host_key() == member)
+            }) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    #[test]
+    fn runs_the_protocol_on_the_poll_of_the_application() {
+        let loopback = SocketAddr::from(([127, 0, 0, 1], 0));
+        let (seed_key, joiner_key) = (Uuid::new_v4(), Uuid::new_v4());
+        let config = ClusterConfig {
+            listen_addr: loopback,
+            ping_interval: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let (seed, _seed_handle) = Cluster::new_cluster(seed_key, config.clone()).unwrap();
+        let (joiner, mut driver) = ClusterDriver::new(
+            joiner_key,
+            ClusterConfig {
+                seeds: vec![PeerAddr::from(seed.local_addr())],
+                ..config
+            },
+        )
+        .unwrap();
+
+        let mut poll = Poll::new().unwrap();
+        driver.register(&mut poll, Token(1)).unwrap();
+        // Events of the application are left to it
+        let mut app_socket = UdpSocket::bind(loopback).unwrap();
+        poll.registry()
+            .register(&mut app_socket, Token(APP_TOKEN), Interest::READABLE)
+            .unwrap();
+        app_socket
+            .send_to(b"app", app_socket.local_addr().unwrap())
+            .unwrap();
+
+        assert!(run_until_joined(&mut poll, &mut driver, &joiner, seed_key));
+        assert!(driver.is_running());
+
+        drop(joiner);
+        driver.tick();
+        assert!(!driver.is_running());
+    }
+
+    #[test]
+    fn gossips_over_the_transport_it_was_given() {
+        let network = MemoryNetwork::new();
+        let seed_transport = network.bind("10.0.0.1:7946".parse().unwrap()).unwrap();
+        let seed_addr = seed_transport.local_addr().unwrap();
+        let (seed_key, joiner_key) = (Uuid::new_v4(), Uuid::new_v4());
+        let config = ClusterConfig {
+            ping_interval: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let (_seed, _seed_handle) =
+            Cluster::new_cluster_with_transport(seed_key, config.clone(), seed_transport).unwrap();
+        let (joiner, mut driver) = ClusterDriver::with_transport(
+            joiner_key,
+            ClusterConfig {
+                seeds: vec![PeerAddr::from(seed_addr)],
+                ..config
+            },
+            network.bind("10.0.0.2:0".parse().unwrap()).unwrap(),
+        )
+        .unwrap();
+        assert!(network.is_bound(&joiner.local_addr()));
+
+        let mut poll = Poll::new().unwrap();
+        driver.register(&mut poll, Token(1)).unwrap();
+        assert!(run_until_joined(&mut poll, &mut driver, &joiner, seed_key));
+    }
+}
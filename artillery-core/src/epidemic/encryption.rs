@@ -0,0 +1,239 @@
+use crate::errors::*;
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// First byte of an encrypted message, no plaintext message starts with it.
+pub const ENCRYPTION_MAGIC: u8 = 0xfe;
+
+/// Version of the encryption scheme, following the magic byte.
+const SCHEME_VERSION: u8 = 1;
+
+const NONCE_LEN: usize = 24;
+
+const TAG_LEN: usize = 16;
+
+/// Bytes the encryption adds to a message.
+pub const ENCRYPTION_OVERHEAD: usize = 2 + NONCE_LEN + TAG_LEN;
+
+/// Protection of the gossip, has to be rolled out cluster-wide in steps: `Permissive`
+/// on every member, then `Required`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encryption {
+    /// Messages in clear, as older releases send them. Anyone on the network can read
+    /// and forge membership updates.
+    Plaintext,
+    /// Encrypts the messages sent, still accepts the plaintext ones.
+    Permissive,
+    /// Encrypts the messages sent, drops the plaintext ones.
+    Required,
+}
+
+impl Encryption {
+    /// Bytes the encryption adds to every message sent.
+    pub fn overhead(self) -> usize {
+        match self {
+            Encryption::Plaintext => 0,
+            Encryption::Permissive | Encryption::Required => ENCRYPTION_OVERHEAD,
+        }
+    }
+}
+
+/// Keys of the gossip encryption. Messages are sealed with the primary key and opened
+/// with any key of the ring, so keys are rotated without downtime: a new key is installed
+/// on every member, then used by every member, and the old one removed.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Keyring {
+    primary: Vec<u8>,
+    secondary: Vec<Vec<u8>>,
+}
+
+impl Keyring {
+    pub fn new(primary: Vec<u8>) -> Self {
+        Keyring {
+            primary,
+            secondary: Vec::new(),
+        }
+    }
+
+    /// Also opens the messages sealed with the key.
+    pub fn with_secondary(mut self, key: Vec<u8>) -> Self {
+        if key != self.primary && !self.secondary.contains(&key) {
+            self.secondary.push(key);
+        }
+        self
+    }
+
+    pub fn primary(&self) -> &[u8] {
+        &self.primary
+    }
+
+    /// Keys opening the messages, the primary one first.
+    pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
+        std::iter::once(self.primary.as_slice()).chain(self.secondary.iter().map(Vec::as_slice))
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.keys().any(|known| known == key)
+    }
+
+    pub fn install(&mut self, key: Vec<u8>) -> Result<()> {
+        if key.is_empty() {
+            bail!(
+                ArtilleryError::InvalidArgument,
+                "encryption keys can't be empty"
+            );
+        }
+        if !self.contains(&key) {
+            self.secondary.push(key);
+        }
+
+        Ok(())
+    }
+
+    /// Seals with the installed `key` from now on, the previous primary key stays installed.
+    pub fn promote(&mut self, key: &[u8]) -> Result<()> {
+        if key == self.primary.as_slice() {
+            return Ok(());
+        }
+
+        let position = self
+            .secondary
+            .iter()
+            .position(|known| known == key)
+            .ok_or_else(|| {
+                ArtilleryError::InvalidArgument(
+                    "the key has to be installed before it's used".into(),
+                )
+            })?;
+        let promoted = self.secondary.remove(position);
+        let previous = std::mem::replace(&mut self.primary, promoted);
+        self.secondary.push(previous);
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Result<()> {
+        if key == self.primary.as_slice() {
+            bail!(
+                ArtilleryError::InvalidArgument,
+                "the primary key can't be removed, use another key first"
+            );
+        }
+        self.secondary.retain(|known| known != key);
+
+        Ok(())
+    }
+}
+
+/// Counts the keys, never prints them.
+impl fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Keyring")
+            .field("keys", &(1 + self.secondary.len()))
+            .finish()
+    }
+}
+
+/// XChaCha20-Poly1305 with a key derived from a cluster or keyring key, authenticating and
+/// encrypting every message as `[magic][version][nonce][ciphertext]`.
+pub struct GossipCipher {
+    cipher: XChaCha20Poly1305,
+}
+
+impl GossipCipher {
+    pub fn new(key: &[u8]) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(b"artillery gossip encryption v1");
+        hasher.update(key);
+        let derived = hasher.finalize();
+
+        GossipCipher {
+            cipher: XChaCha20Poly1305::new(&derived),
+        }
+    }
+
+    pub fn seal(&self, message: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0_u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher
+            .encrypt(&XNonce::from(nonce), message)
+            .map_err(|_| ArtilleryError::Unexpected("Message encryption failed".into()))?;
+
+        let mut sealed = Vec::with_capacity(ENCRYPTION_OVERHEAD + message.len());
+        sealed.extend_from_slice(&[ENCRYPTION_MAGIC, SCHEME_VERSION]);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+
+        Ok(sealed)
+    }
+
+    /// The message of a sealed datagram, failing if it was forged or tampered with.
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        match sealed {
+            [ENCRYPTION_MAGIC, SCHEME_VERSION, rest @ ..] if rest.len() >= NONCE_LEN + TAG_LEN => {
+                let (nonce_bytes, ciphertext) = rest.split_at(NONCE_LEN);
+                let nonce = <[u8; NONCE_LEN]>::try_from(nonce_bytes)
+                    .map_err(|_| ArtilleryError::ClusterMessageDecode("truncated nonce".into()))?;
+                self.cipher
+                    .decrypt(&XNonce::from(nonce), ciphertext)
+                    .map_err(|_| {
+                        ArtilleryError::ClusterMessageDecode("message authentication failed".into())
+                    })
+            }
+            _ => Err(ArtilleryError::ClusterMessageDecode(
+                "not an encrypted message of a known scheme".into(),
+            )),
+        }
+    }
+}
+
+pub fn is_sealed(datagram: &[u8]) -> bool {
+    datagram.first() == Some(&ENCRYPTION_MAGIC)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn seals_messages_for_the_same_cluster_key_only() {
+        let cipher = GossipCipher::new(b"cluster key");
+        let sealed = cipher.seal(b"{\"sender\":1}").unwrap();
+        assert_eq!(sealed.len(), 12 + ENCRYPTION_OVERHEAD);
+        assert!(is_sealed(&sealed));
+        assert_eq!(cipher.open(&sealed).unwrap(), b"{\"sender\":1}".to_vec());
+
+        // Fresh nonce per message
+        assert_ne!(cipher.seal(b"{\"sender\":1}").unwrap(), sealed);
+
+        let mut tampered = sealed.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(cipher.open(&tampered).is_err());
+        assert!(GossipCipher::new(b"other key").open(&sealed).is_err());
+        assert!(cipher.open(b"{\"sender\":1}").is_err());
+    }
+
+    #[test]
+    fn rotates_keys_through_the_keyring() {
+        let (old, new) = (b"old key".to_vec(), b"new key".to_vec());
+        let mut keyring = Keyring::new(old.clone());
+        assert!(keyring.promote(&new).is_err());
+        assert!(keyring.install(Vec::new()).is_err());
+
+        keyring.install(new.clone()).unwrap();
+        keyring.promote(&new).unwrap();
+        assert_eq!(keyring.primary(), new.as_slice());
+        assert!(keyring.contains(&old));
+        assert!(keyring.remove(&new).is_err());
+
+        keyring.remove(&old).unwrap();
+        assert_eq!(keyring.keys().count(), 1);
+        assert_eq!(format!("{:?}", keyring), "Keyring { keys: 1 }");
+    }
+}
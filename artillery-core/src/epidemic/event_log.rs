@@ -0,0 +1,101 @@
+use super::state::{ArtilleryEventStamp, ArtilleryMemberEvent};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::sync::{Arc, PoisonError, RwLock};
+
+pub type LoggedEvent = (ArtilleryMemberEvent, ArtilleryEventStamp);
+
+#[derive(Debug, Default)]
+struct EventRing {
+    capacity: usize,
+    events: VecDeque<LoggedEvent>,
+}
+
+/// Ring of the latest cluster events, read with a cursor at the pace of the consumer:
+///
+/// ```ignore
+/// let log = cluster.event_log();
+/// let mut next = 0;
+/// loop {
+///     for (event, stamp) in log.read_from(next, 100) {
+///         next = stamp.seq + 1;
+///         // ...
+///     }
+/// }
+/// ```
+///
+/// The state thread never waits for readers, the oldest events are overwritten once the ring
+/// is full. A reader fallen behind notices the gap in the sequence numbers.
+#[derive(Debug, Clone, Default)]
+pub struct EventLog(Arc<RwLock<EventRing>>);
+
+impl EventLog {
+    pub(crate) fn new(capacity: usize) -> Self {
+        EventLog(Arc::new(RwLock::new(EventRing {
+            capacity,
+            events: VecDeque::with_capacity(capacity),
+        })))
+    }
+
+    pub(crate) fn push(&self, event: &ArtilleryMemberEvent, stamp: ArtilleryEventStamp) {
+        let mut ring = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        if ring.capacity == 0 {
+            return;
+        }
+
+        if ring.events.len() >= ring.capacity {
+            ring.events.pop_front();
+        }
+        ring.events.push_back((event.clone(), stamp));
+    }
+
+    /// Up to `max` events from the sequence number `seq` on, or from the oldest one kept.
+    pub fn read_from(&self, seq: u64, max: usize) -> Vec<LoggedEvent> {
+        let ring = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        let oldest = match ring.events.front() {
+            Some((_, stamp)) => stamp.seq,
+            None => return Vec::new(),
+        };
+        // Sequence numbers of the ring are consecutive
+        let skip = usize::try_from(seq.saturating_sub(oldest)).unwrap_or(usize::MAX);
+
+        ring.events.iter().skip(skip).take(max).cloned().collect()
+    }
+
+    pub fn oldest_seq(&self) -> Option<u64> {
+        let ring = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        ring.events.front().map(|(_, stamp)| stamp.seq)
+    }
+
+    pub fn latest_seq(&self) -> Option<u64> {
+        let ring = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        ring.events.back().map(|(_, stamp)| stamp.seq)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::primitives::Timestamp;
+
+    #[test]
+    fn pages_through_the_latest_events() {
+        let log = EventLog::new(3);
+        for seq in 1..=5 {
+            let stamp = ArtilleryEventStamp {
+                seq,
+                timestamp: Timestamp::now(),
+            };
+            log.push(&ArtilleryMemberEvent::LocalConnectivityLost, stamp);
+        }
+
+        assert_eq!((log.oldest_seq(), log.latest_seq()), (Some(3), Some(5)));
+        let seqs = |events: Vec<LoggedEvent>| -> Vec<u64> {
+            events.into_iter().map(|(_, stamp)| stamp.seq).collect()
+        };
+        assert_eq!(seqs(log.read_from(0, 2)), vec![3, 4]);
+        assert_eq!(seqs(log.read_from(5, 2)), vec![5]);
+        assert!(log.read_from(6, 2).is_empty());
+        assert!(EventLog::new(0).read_from(0, 2).is_empty());
+    }
+}
@@ -0,0 +1,322 @@
+//!
+//! Member inventory in the JSON layout of [memberlist] nodes, for external tools and
+//! migrations from other gossip systems:
+//!
+//! ```json
+//! [{"Name": "9f6b2b5e-7f4e-4a4e-8d0e-6c52f8b9e2a1", "Addr": "10.0.0.1", "Port": 7946, "State": 0, "Incarnation": 3}]
+//! ```
+//!
+//! `Name` is the host key, `State` is 0 for alive, 1 suspect, 2 dead and 3 left.
+//! `Incarnation` is optional and other memberlist fields like `Meta` are ignored on import.
+//! The application ports of the members are exported as `Ports`, by service name.
+//!
+//! Huge clusters can be snapshotted in the compact binary [`MemberColumns`] instead.
+//!
+//! [memberlist]: https://github.com/hashicorp/memberlist
+use super::member::{ArtilleryMember, ArtilleryMemberState};
+use super::primitives::NodeId;
+use crate::errors::*;
+use serde::*;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use uuid::Uuid;
+
+const STATE_ALIVE: u8 = 0;
+const STATE_SUSPECT: u8 = 1;
+const STATE_DEAD: u8 = 2;
+const STATE_LEFT: u8 = 3;
+
+const COLUMNS_VERSION: u8 = 1;
+/// Version and member count.
+const COLUMNS_HEADER: usize = 5;
+/// Host key, IPv6 or IPv4-mapped address, port, state and incarnation.
+const COLUMNS_ROW: usize = 16 + 16 + 2 + 1 + 8;
+
+fn state_code(state: ArtilleryMemberState) -> u8 {
+    match state {
+        ArtilleryMemberState::Alive => STATE_ALIVE,
+        ArtilleryMemberState::Suspect => STATE_SUSPECT,
+        ArtilleryMemberState::Down => STATE_DEAD,
+        ArtilleryMemberState::Left => STATE_LEFT,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExportedMember {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Addr")]
+    pub addr: IpAddr,
+    #[serde(rename = "Port")]
+    pub port: u16,
+    #[serde(rename = "State", default)]
+    pub state: u8,
+    #[serde(rename = "Incarnation", default)]
+    pub incarnation: u64,
+    #[serde(rename = "Ports", default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub ports: BTreeMap<String, u16>,
+}
+
+impl ExportedMember {
+    fn from_member(member: &ArtilleryMember, local_addr: SocketAddr) -> Self {
+        let addr = member.remote_host().unwrap_or(local_addr);
+
+        ExportedMember {
+            name: member.host_key().to_string(),
+            addr: addr.ip(),
+            port: addr.port(),
+            state: state_code(member.state()),
+            incarnation: member.incarnation_number(),
+            ports: member.ports().clone(),
+        }
+    }
+}
+
+/// Exports the members, the current node is listed with the address it listens on.
+pub fn export_members(members: &[ArtilleryMember], local_addr: SocketAddr) -> Result<String> {
+    let exported: Vec<ExportedMember> = members
+        .iter()
+        .map(|m| ExportedMember::from_member(m, local_addr))
+        .collect();
+
+    Ok(serde_json::to_string_pretty(&exported)?)
+}
+
+/// Parses an inventory into seedable members, dead and left ones are skipped.
+pub fn import_members(json: &str) -> Result<Vec<(NodeId, SocketAddr)>> {
+    let exported: Vec<ExportedMember> = serde_json::from_str(json)?;
+    let mut members = Vec::with_capacity(exported.len());
+
+    for member in exported {
+        let host_key = Uuid::parse_str(&member.name).map_err(|e| {
+            ArtilleryError::InvalidArgument(format!(
+                "member name '{}' is not a host key: {}",
+                member.name, e
+            ))
+        })?;
+
+        match member.state {
+            STATE_ALIVE | STATE_SUSPECT => {
+                members.push((host_key.into(), SocketAddr::new(member.addr, member.port)))
+            }
+            STATE_DEAD | STATE_LEFT => {}
+            unknown => {
+                bail!(
+                    ArtilleryError::InvalidArgument,
+                    "member {} has an unknown state {}",
+                    host_key,
+                    unknown
+                );
+            }
+        }
+    }
+
+    Ok(members)
+}
+
+/// Columnar snapshot of the members, one array per field with the same state codes as
+/// the JSON export. Encodes to a fixed 43 bytes per member, without per member allocations.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemberColumns {
+    pub ids: Vec<NodeId>,
+    pub addrs: Vec<SocketAddr>,
+    pub states: Vec<u8>,
+    pub incarnations: Vec<u64>,
+}
+
+impl MemberColumns {
+    /// The current node is listed with the address it listens on.
+    pub fn of(members: &[ArtilleryMember], local_addr: SocketAddr) -> Self {
+        let mut columns = MemberColumns {
+            ids: Vec::with_capacity(members.len()),
+            addrs: Vec::with_capacity(members.len()),
+            states: Vec::with_capacity(members.len()),
+            incarnations: Vec::with_capacity(members.len()),
+        };
+
+        for member in members {
+            columns.ids.push(member.node_id());
+            columns
+                .addrs
+                .push(member.remote_host().unwrap_or(local_addr));
+            columns.states.push(state_code(member.state()));
+            columns.incarnations.push(member.incarnation_number());
+        }
+
+        columns
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Version byte, little endian `u32` count, then the columns one after the other.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let count = self.len();
+        if self.addrs.len() != count
+            || self.states.len() != count
+            || self.incarnations.len() != count
+        {
+            bail!(
+                ArtilleryError::InvalidArgument,
+                "Member columns have different lengths"
+            );
+        }
+
+        let mut buf = Vec::with_capacity(COLUMNS_HEADER + count * COLUMNS_ROW);
+        buf.push(COLUMNS_VERSION);
+        buf.extend_from_slice(&u32::try_from(count)?.to_le_bytes());
+        for id in &self.ids {
+            buf.extend_from_slice(id.as_bytes());
+        }
+        for addr in &self.addrs {
+            let ip = match addr.ip() {
+                IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                IpAddr::V6(ip) => ip,
+            };
+            buf.extend_from_slice(&ip.octets());
+        }
+        for addr in &self.addrs {
+            buf.extend_from_slice(&addr.port().to_le_bytes());
+        }
+        buf.extend_from_slice(&self.states);
+        for incarnation in &self.incarnations {
+            buf.extend_from_slice(&incarnation.to_le_bytes());
+        }
+
+        Ok(buf)
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.len() < COLUMNS_HEADER || buf[0] != COLUMNS_VERSION {
+            bail!(
+                ArtilleryError::InvalidArgument,
+                "Not a version {} member columns snapshot",
+                COLUMNS_VERSION
+            );
+        }
+
+        let mut count_bytes = [0_u8; 4];
+        count_bytes.copy_from_slice(&buf[1..COLUMNS_HEADER]);
+        let count = usize::try_from(u32::from_le_bytes(count_bytes))?;
+        let body = &buf[COLUMNS_HEADER..];
+        if count.checked_mul(COLUMNS_ROW) != Some(body.len()) {
+            bail!(
+                ArtilleryError::InvalidArgument,
+                "Member columns snapshot of {} bytes can't hold {} members",
+                buf.len(),
+                count
+            );
+        }
+
+        let (ids, after_ids) = body.split_at(count * 16);
+        let (ips, after_ips) = after_ids.split_at(count * 16);
+        let (ports, after_ports) = after_ips.split_at(count * 2);
+        let (states, incarnations) = after_ports.split_at(count);
+
+        if let Some(unknown) = states.iter().find(|s| **s > STATE_LEFT) {
+            bail!(
+                ArtilleryError::InvalidArgument,
+                "Member columns snapshot has an unknown state {}",
+                unknown
+            );
+        }
+
+        Ok(MemberColumns {
+            ids: ids
+                .chunks_exact(16)
+                .map(|id| {
+                    let mut bytes = [0_u8; 16];
+                    bytes.copy_from_slice(id);
+                    NodeId::from_bytes(bytes)
+                })
+                .collect(),
+            addrs: ips
+                .chunks_exact(16)
+                .zip(ports.chunks_exact(2))
+                .map(|(ip, port)| {
+                    let mut octets = [0_u8; 16];
+                    octets.copy_from_slice(ip);
+                    SocketAddr::new(
+                        unmap(Ipv6Addr::from(octets)),
+                        u16::from_le_bytes([port[0], port[1]]),
+                    )
+                })
+                .collect(),
+            states: states.to_vec(),
+            incarnations: incarnations
+                .chunks_exact(8)
+                .map(|incarnation| {
+                    let mut bytes = [0_u8; 8];
+                    bytes.copy_from_slice(incarnation);
+                    u64::from_le_bytes(bytes)
+                })
+                .collect(),
+        })
+    }
+}
+
+/// IPv4 addresses are stored mapped into IPv6.
+fn unmap(ip: Ipv6Addr) -> IpAddr {
+    let octets = ip.octets();
+    if octets[..10].iter().all(|o| *o == 0) && octets[10] == 0xff && octets[11] == 0xff {
+        IpAddr::from([octets[12], octets[13], octets[14], octets[15]])
+    } else {
+        IpAddr::V6(ip)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn import_skips_gone_members() {
+        let alive = Uuid::new_v4();
+        let json = format!(
+            r#"[
+                {{"Name": "{}", "Addr": "10.0.0.1", "Port": 7946, "Meta": null, "State": 0}},
+                {{"Name": "{}", "Addr": "10.0.0.2", "Port": 7946, "State": 2}}
+            ]"#,
+            alive,
+            Uuid::new_v4()
+        );
+
+        let members = import_members(&json).unwrap();
+        assert_eq!(
+            members,
+            vec![(alive.into(), "10.0.0.1:7946".parse().unwrap())]
+        );
+
+        let exported = export_members(
+            &[ArtilleryMember::current(alive)],
+            "10.0.0.1:7946".parse().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(import_members(&exported).unwrap(), members);
+    }
+
+    #[test]
+    fn member_columns_roundtrip() {
+        let v4: SocketAddr = "10.0.0.1:7946".parse().unwrap();
+        let v6: SocketAddr = "[2001:db8::1]:7947".parse().unwrap();
+        let members = vec![
+            ArtilleryMember::current(Uuid::new_v4()),
+            ArtilleryMember::new(Uuid::new_v4(), v6, 7, ArtilleryMemberState::Suspect),
+        ];
+
+        let columns = MemberColumns::of(&members, v4);
+        assert_eq!(columns.addrs, vec![v4, v6]);
+        assert_eq!(columns.states, vec![STATE_ALIVE, STATE_SUSPECT]);
+
+        let encoded = columns.encode().unwrap();
+        assert_eq!(encoded.len(), COLUMNS_HEADER + 2 * COLUMNS_ROW);
+        assert_eq!(MemberColumns::decode(&encoded).unwrap(), columns);
+        assert!(MemberColumns::decode(&encoded[..encoded.len() - 1]).is_err());
+    }
+}
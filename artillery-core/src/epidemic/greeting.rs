@@ -0,0 +1,110 @@
+use super::annotation::Annotation;
+use super::member::ArtilleryMember;
+use super::primitives::NodeId;
+use serde::*;
+use uuid::Uuid;
+
+/// Protocol features of this build, announced to the peers greeted.
+pub fn capabilities() -> Vec<String> {
+    let mut capabilities = vec![
+        "digest", "greeting", "locks", "pull", "reaping", "relay", "shutdown",
+    ];
+    if cfg!(feature = "compression") {
+        capabilities.push("compression");
+    }
+
+    capabilities.into_iter().map(String::from).collect()
+}
+
+/// Self-description a node sends along its first messages to a peer, until the peer
+/// acknowledged one. The member record, labels and capabilities of the node are known
+/// when the `Joined` event is emitted, instead of trickling in with the later gossip.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Greeting {
+    #[serde(rename = "n")]
+    name: String,
+    #[serde(rename = "m")]
+    member: ArtilleryMember,
+    #[serde(rename = "a", default)]
+    annotations: Vec<Annotation>,
+    #[serde(rename = "c", default)]
+    capabilities: Vec<String>,
+}
+
+impl Greeting {
+    pub(crate) fn new(name: String, member: ArtilleryMember, annotations: Vec<Annotation>) -> Self {
+        Greeting {
+            name,
+            member,
+            annotations,
+            capabilities: capabilities(),
+        }
+    }
+
+    pub fn member(&self) -> &ArtilleryMember {
+        &self.member
+    }
+
+    /// Annotations the node wrote about itself.
+    pub fn annotations(&self) -> &[Annotation] {
+        &self.annotations
+    }
+
+    pub fn capabilities(&self) -> &[String] {
+        &self.capabilities
+    }
+
+    /// The greeting without what doesn't belong to the sender, it only describes itself.
+    pub(crate) fn of_sender(mut self, sender: Uuid) -> Option<Self> {
+        if self.member.host_key() != sender {
+            return None;
+        }
+
+        let node = NodeId::from(sender);
+        self.annotations
+            .retain(|a| a.member() == node && a.author() == node);
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::capabilities;
+    use crate::epidemic::state::{ArtilleryMemberEvent, Request};
+    use crate::epidemic::test_kit::*;
+    use std::time::Duration;
+
+    #[test]
+    fn greets_new_peers_until_they_acknowledged() {
+        let a = TestPeer::new();
+        let mut tester = ProtocolTester::new();
+
+        tester
+            .recv(heartbeat_from(&a).with_greeting("eu-west", &[("role", "storage")]))
+            .expect_event("a joined with its zone", |e| {
+                if let ArtilleryMemberEvent::Joined(m) = e {
+                    m.host_key() == a.id() && m.zone() == Some("eu-west")
+                } else {
+                    false
+                }
+            })
+            .expect_sent(expectation(&a, "ack greeting a", |m| {
+                m.request == Request::Ack && m.hello.is_some()
+            }));
+
+        let view = tester.state().view();
+        let labels = view.read().labels(&a.id().into());
+        assert_eq!(labels.get("role").map(String::as_str), Some("storage"));
+        assert_eq!(labels.get("zone").map(String::as_str), Some("eu-west"));
+        let greeting = view.read().greeting(&a.id().into()).cloned().unwrap();
+        assert_eq!(greeting.name, a.id().to_string());
+        assert_eq!(view.capabilities(&a.id().into()), Some(capabilities()));
+
+        tester
+            .recv(ack_from(&a))
+            .tick(Duration::from_secs(1))
+            .expect_sent(expectation(&a, "heartbeat without greeting", |m| {
+                m.request == Request::Heartbeat && m.hello.is_none()
+            }));
+    }
+}
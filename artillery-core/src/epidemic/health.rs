@@ -0,0 +1,154 @@
+use super::member::{ArtilleryMember, ArtilleryMemberState};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+
+/// Scale of the per mille figures of the health.
+const PER_MILLE: u64 = 1000;
+
+/// Health of the cluster as seen by this node, one scalar for dashboards and readiness
+/// probes along the figures it is computed from. The score is the live fraction of the
+/// members, lowered by the flaps and the convergence lag within the `health_window`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClusterHealth {
+    /// Overall score in per mille, 1000 for a converged cluster of live, steady members.
+    pub score: u16,
+    /// Live members among the members, this node included, in per mille.
+    pub alive: u16,
+    /// Transitions of the members up, to suspect or down within the `health_window`.
+    pub flaps: usize,
+    /// For how long the changes gossiped by this node went unacknowledged, zero once
+    /// every change was acknowledged.
+    pub convergence_lag: Duration,
+}
+
+/// Flaps within the window and pending gossip of this node, sampled every tick.
+#[derive(Debug)]
+pub(crate) struct HealthTracker {
+    window: Duration,
+    flaps: VecDeque<Instant>,
+    pending_since: Option<Instant>,
+}
+
+impl HealthTracker {
+    pub(crate) fn new(window: Duration) -> Self {
+        HealthTracker {
+            window,
+            flaps: VecDeque::new(),
+            pending_since: None,
+        }
+    }
+
+    pub(crate) fn record_flap(&mut self, now: Instant) {
+        self.flaps.push_back(now);
+    }
+
+    pub(crate) fn observe(&mut self, converged: bool, now: Instant) {
+        if converged {
+            self.pending_since = None;
+        } else {
+            self.pending_since.get_or_insert(now);
+        }
+
+        let window = self.window;
+        while self
+            .flaps
+            .front()
+            .is_some_and(|&at| now.saturating_duration_since(at) > window)
+        {
+            self.flaps.pop_front();
+        }
+    }
+
+    pub(crate) fn health(&self, members: &[ArtilleryMember], now: Instant) -> ClusterHealth {
+        let alive = members
+            .iter()
+            .filter(|m| m.state() == ArtilleryMemberState::Alive)
+            .count();
+        let flaps = self
+            .flaps
+            .iter()
+            .filter(|&&at| now.saturating_duration_since(at) <= self.window)
+            .count();
+        let convergence_lag = self.pending_since.map_or_else(Duration::default, |since| {
+            now.saturating_duration_since(since)
+        });
+
+        let alive_per_mille = per_mille(to_u64(alive), to_u64(members.len()));
+        let flap_penalty = per_mille(to_u64(flaps), to_u64(members.len()));
+        let lag_penalty = per_mille(millis(convergence_lag), millis(self.window));
+        let score = alive_per_mille * (PER_MILLE - flap_penalty) / PER_MILLE
+            * (PER_MILLE - lag_penalty)
+            / PER_MILLE;
+
+        ClusterHealth {
+            score: to_u16(score),
+            alive: to_u16(alive_per_mille),
+            flaps,
+            convergence_lag,
+        }
+    }
+}
+
+/// `part` of `whole` in per mille, at most 1000. Nothing of nothing is all of it.
+fn per_mille(part: u64, whole: u64) -> u64 {
+    if whole == 0 {
+        return PER_MILLE;
+    }
+
+    part.saturating_mul(PER_MILLE)
+        .checked_div(whole)
+        .unwrap_or(PER_MILLE)
+        .min(PER_MILLE)
+}
+
+fn to_u64(count: usize) -> u64 {
+    u64::try_from(count).unwrap_or(u64::MAX)
+}
+
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+fn to_u16(per_mille: u64) -> u16 {
+    u16::try_from(per_mille).unwrap_or(u16::MAX)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::SocketAddr;
+    use uuid::Uuid;
+
+    fn member(state: ArtilleryMemberState) -> ArtilleryMember {
+        let addr: SocketAddr = "127.0.0.1:7946".parse().unwrap();
+        ArtilleryMember::new(Uuid::new_v4(), addr, 0, state)
+    }
+
+    #[test]
+    fn scores_liveness_flaps_and_convergence() {
+        let window = Duration::from_secs(60);
+        let start = Instant::now();
+        let mut tracker = HealthTracker::new(window);
+        let mut members = vec![member(ArtilleryMemberState::Alive); 3];
+        members.push(member(ArtilleryMemberState::Down));
+
+        tracker.observe(true, start);
+        let health = tracker.health(&members, start);
+        assert_eq!(health.alive, 750);
+        assert_eq!(health.score, 750);
+
+        tracker.record_flap(start);
+        tracker.observe(false, start);
+        let later = start + Duration::from_secs(30);
+        let health = tracker.health(&members, later);
+        assert_eq!(health.flaps, 1);
+        assert_eq!(health.convergence_lag, Duration::from_secs(30));
+        // 750 * (1 - 1/4) * (1 - 30s/60s)
+        assert_eq!(health.score, 281);
+
+        let much_later = start + Duration::from_secs(120);
+        tracker.observe(true, much_later);
+        assert_eq!(tracker.health(&members, much_later).score, 750);
+    }
+}
@@ -0,0 +1,166 @@
+use crate::epidemic::primitives::NodeId;
+use crate::errors::*;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Namespace of the host keys derived from a machine id.
+const MACHINE_ID_NAMESPACE: Uuid = Uuid::from_bytes([
+    0x3d, 0x6e, 0x1a, 0x52, 0x8c, 0x0b, 0x4f, 0x27, 0x9e, 0x61, 0x0d, 0x4a, 0xb7, 0x35, 0xc2, 0x18,
+]);
+
+/// Machine id locations of systemd and D-Bus.
+const MACHINE_ID_PATHS: [&str; 2] = ["/etc/machine-id", "/var/lib/dbus/machine-id"];
+
+/// Where the host key of a node comes from the first time it starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdentitySource {
+    /// Random key.
+    Random,
+    /// Derived from the machine id, the same machine gets the same key even if the
+    /// identity file is lost. Only available where a `machine-id` file exists.
+    MachineId,
+    /// Derived from the machine id and a discriminator, to run several nodes per machine.
+    MachineIdWith(String),
+}
+
+/// Host key of this node, persisted to a file so that the node keeps its identity
+/// across restarts instead of rejoining as a new member.
+#[derive(Debug, Clone)]
+pub struct NodeIdentity {
+    host_key: Uuid,
+    path: PathBuf,
+    created: bool,
+}
+
+impl NodeIdentity {
+    /// Loads the host key stored at `path`, or persists a new random one.
+    pub fn load_or_create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::load_or_create_with(path, &IdentitySource::Random)
+    }
+
+    /// Loads the host key stored at `path`, or persists a new one from `source`.
+    /// A corrupt file is an error rather than silently becoming a new identity.
+    pub fn load_or_create_with<P: AsRef<Path>>(
+        identity_file: P,
+        source: &IdentitySource,
+    ) -> Result<Self> {
+        let path = identity_file.as_ref().to_path_buf();
+
+        if path.exists() {
+            let stored = fs::read_to_string(&path)?;
+            let host_key = Uuid::parse_str(stored.trim()).map_err(|e| {
+                ArtilleryError::Decoding(format!(
+                    "Identity file {} is corrupt: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+
+            return Ok(NodeIdentity {
+                host_key,
+                path,
+                created: false,
+            });
+        }
+
+        let host_key = match source {
+            IdentitySource::Random => Uuid::new_v4(),
+            IdentitySource::MachineId => derive_host_key(&read_machine_id()?, ""),
+            IdentitySource::MachineIdWith(discriminator) => {
+                derive_host_key(&read_machine_id()?, discriminator)
+            }
+        };
+        persist(&path, host_key)?;
+
+        Ok(NodeIdentity {
+            host_key,
+            path,
+            created: true,
+        })
+    }
+
+    pub fn host_key(&self) -> NodeId {
+        self.host_key.into()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the identity was created by this run.
+    pub fn is_new(&self) -> bool {
+        self.created
+    }
+}
+
+/// The machine id is hashed into the key, it's not exposed to the cluster.
+fn derive_host_key(machine_id: &str, discriminator: &str) -> Uuid {
+    let name = format!("{}/{}", machine_id, discriminator);
+    Uuid::new_v5(&MACHINE_ID_NAMESPACE, name.as_bytes())
+}
+
+fn read_machine_id() -> Result<String> {
+    for machine_id_path in &MACHINE_ID_PATHS {
+        if let Ok(contents) = fs::read_to_string(machine_id_path) {
+            let machine_id = contents.trim();
+            if !machine_id.is_empty() {
+                return Ok(machine_id.to_string());
+            }
+        }
+    }
+
+    Err(ArtilleryError::InvalidConfiguration(String::from(
+        "No machine id available to derive the host key from",
+    )))
+}
+
+/// Written to a temporary file and renamed over, a crash never leaves a partial identity.
+fn persist(path: &Path, host_key: Uuid) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut staging_name = path.as_os_str().to_os_string();
+    staging_name.push(".tmp");
+    let staging = PathBuf::from(staging_name);
+
+    let mut file = fs::File::create(&staging)?;
+    file.write_all(host_key.to_hyphenated().to_string().as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&staging, path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn identity_is_stable_across_loads() {
+        let dir = env::temp_dir().join(format!("artillery-identity-{}", Uuid::new_v4()));
+        let path = dir.join("node.id");
+
+        let created = NodeIdentity::load_or_create(&path).unwrap();
+        let loaded = NodeIdentity::load_or_create(&path).unwrap();
+
+        assert!(created.is_new());
+        assert!(!loaded.is_new());
+        assert_eq!(created.host_key(), loaded.host_key());
+
+        fs::write(&path, "not a uuid").unwrap();
+        assert!(NodeIdentity::load_or_create(&path).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn derived_keys_depend_on_discriminator() {
+        assert_eq!(derive_host_key("abc", "a"), derive_host_key("abc", "a"));
+        assert_ne!(derive_host_key("abc", "a"), derive_host_key("abc", "b"));
+        assert_ne!(derive_host_key("abc", "a"), derive_host_key("abd", "a"));
+    }
+}
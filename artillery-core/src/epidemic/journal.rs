@@ -0,0 +1,186 @@
+use super::member::{ArtilleryMember, ArtilleryMemberState};
+use crate::errors::*;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Durable storage of the `Down` declarations made by this node, written before they are
+/// gossiped and read back on restart, until every live member acknowledged them. A crash
+/// right after a failure was detected doesn't lose it.
+///
+/// The `Left` of this node isn't journaled, a restarted node rejoins as a new run which
+/// the peers tell apart from the run that left.
+pub trait ChangeStore: Debug + Send + Sync {
+    /// Replaces the stored changes.
+    fn store(&self, changes: &[ArtilleryMember]) -> Result<()>;
+    /// Stored changes, none when nothing was stored yet.
+    fn load(&self) -> Result<Vec<ArtilleryMember>>;
+}
+
+/// Keeps the changes as JSON in a file, replaced through a rename so that a crash while
+/// writing leaves the previous content.
+#[derive(Debug, Clone)]
+pub struct FileChangeStore {
+    path: PathBuf,
+}
+
+impl FileChangeStore {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        FileChangeStore { path: path.into() }
+    }
+}
+
+impl ChangeStore for FileChangeStore {
+    fn store(&self, changes: &[ArtilleryMember]) -> Result<()> {
+        let staged = self.path.with_extension("tmp");
+        let mut file = File::create(&staged)?;
+        file.write_all(&serde_json::to_vec(changes)?)?;
+        file.sync_all()?;
+        fs::rename(&staged, &self.path)?;
+
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Vec<ArtilleryMember>> {
+        match fs::read(&self.path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+fn same_change(a: &ArtilleryMember, b: &ArtilleryMember) -> bool {
+    a.host_key() == b.host_key()
+        && a.incarnation_number() == b.incarnation_number()
+        && a.state() == b.state()
+}
+
+/// Journaled changes waiting for the acknowledgement of every live member.
+#[derive(Debug)]
+pub(crate) struct ChangeJournal {
+    store: Arc<dyn ChangeStore>,
+    pending: Vec<(ArtilleryMember, HashSet<Uuid>)>,
+}
+
+impl ChangeJournal {
+    /// Journal of the store, with the changes left pending by the previous run.
+    pub fn open(store: Arc<dyn ChangeStore>) -> Result<Self> {
+        let pending = store
+            .load()?
+            .into_iter()
+            .map(|member| (member, HashSet::new()))
+            .collect();
+
+        Ok(ChangeJournal { store, pending })
+    }
+
+    pub fn pending(&self) -> Vec<ArtilleryMember> {
+        self.pending.iter().map(|(m, _)| m.clone()).collect()
+    }
+
+    pub fn is_pending(&self, member: &ArtilleryMember) -> bool {
+        self.pending.iter().any(|(m, _)| same_change(m, member))
+    }
+
+    /// Stores the change before it is gossiped, only `Down` changes are journaled.
+    pub fn record(&mut self, member: &ArtilleryMember) -> Result<()> {
+        if member.state() != ArtilleryMemberState::Down || self.is_pending(member) {
+            return Ok(());
+        }
+
+        self.pending
+            .retain(|(m, _)| m.host_key() != member.host_key());
+        self.pending.push((member.clone(), HashSet::new()));
+        self.store.store(&self.pending())
+    }
+
+    pub fn acked(&mut self, member: &ArtilleryMember, by: Uuid) {
+        for (pending, acks) in &mut self.pending {
+            if same_change(pending, member) {
+                acks.insert(by);
+            }
+        }
+    }
+
+    /// Forgets the changes acknowledged by all the `live` members, or superseded as told by
+    /// `is_current`. Nothing is settled while no member is live to acknowledge.
+    pub fn settle<F>(&mut self, live: &[Uuid], is_current: F) -> Result<()>
+    where
+        F: Fn(&ArtilleryMember) -> bool,
+    {
+        let before = self.pending.len();
+        self.pending.retain(|(member, acks)| {
+            let waiting = live
+                .iter()
+                .any(|id| *id != member.host_key() && !acks.contains(id));
+            is_current(member) && (live.is_empty() || waiting)
+        });
+
+        if self.pending.len() == before {
+            return Ok(());
+        }
+        self.store.store(&self.pending())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    struct MemoryStore(Mutex<Vec<ArtilleryMember>>);
+
+    impl ChangeStore for MemoryStore {
+        fn store(&self, changes: &[ArtilleryMember]) -> Result<()> {
+            *self.0.lock().unwrap() = changes.to_vec();
+            Ok(())
+        }
+
+        fn load(&self) -> Result<Vec<ArtilleryMember>> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+    }
+
+    #[test]
+    fn keeps_down_changes_until_every_live_member_acked() {
+        let store = Arc::new(MemoryStore::default());
+        let mut journal = ChangeJournal::open(store.clone()).unwrap();
+        let addr = "127.0.0.1:40001".parse().unwrap();
+        let down = ArtilleryMember::new(Uuid::new_v4(), addr, 2, ArtilleryMemberState::Down);
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+
+        journal
+            .record(&ArtilleryMember::new(
+                Uuid::new_v4(),
+                addr,
+                0,
+                ArtilleryMemberState::Suspect,
+            ))
+            .unwrap();
+        journal.record(&down).unwrap();
+        assert_eq!(store.load().unwrap(), vec![down.clone()]);
+
+        // A restart reads the pending changes back
+        let mut restarted = ChangeJournal::open(store.clone()).unwrap();
+        assert!(restarted.is_pending(&down));
+        restarted.settle(&[], |_| true).unwrap();
+        assert!(restarted.is_pending(&down));
+
+        restarted.acked(&down, first);
+        restarted.settle(&[first, second], |_| true).unwrap();
+        assert!(restarted.is_pending(&down));
+        restarted.acked(&down, second);
+        restarted.settle(&[first, second], |_| true).unwrap();
+        assert!(store.load().unwrap().is_empty());
+
+        restarted.record(&down).unwrap();
+        restarted.settle(&[first], |_| false).unwrap();
+        assert!(!restarted.is_pending(&down));
+    }
+}
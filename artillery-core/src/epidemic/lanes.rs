@@ -0,0 +1,126 @@
+use super::cluster_config::ClusterConfig;
+use super::state::{classify_work, ArtilleryClusterRequest, WorkClass};
+use std::sync::mpsc::{sync_channel, Receiver, SendError, SyncSender, TryRecvError, TrySendError};
+
+/// Bounded request queues from the `Cluster` to the event loop, one lane for the control
+/// requests and one for the bulk payloads. A flood of payloads fills its own lane only,
+/// `LeaveCluster` or `Exit` are never queued behind it.
+pub fn request_lanes(config: &ClusterConfig) -> (RequestSender, RequestReceiver) {
+    let (control_tx, control_rx) = sync_channel(config.control_queue_capacity);
+    let (data_tx, data_rx) = sync_channel(config.data_queue_capacity);
+
+    (
+        RequestSender {
+            control: control_tx,
+            data: data_tx,
+        },
+        RequestReceiver {
+            control: control_rx,
+            data: data_rx,
+        },
+    )
+}
+
+#[derive(Clone)]
+pub struct RequestSender {
+    control: SyncSender<ArtilleryClusterRequest>,
+    data: SyncSender<ArtilleryClusterRequest>,
+}
+
+impl RequestSender {
+    /// Queues the request on its lane, waits while the lane is full.
+    /// Fails once the event loop is gone.
+    // The error hands the request back, as the channels do.
+    #[allow(clippy::result_large_err)]
+    pub fn send(
+        &self,
+        request: ArtilleryClusterRequest,
+    ) -> Result<(), SendError<ArtilleryClusterRequest>> {
+        match classify_work(&request) {
+            WorkClass::Critical => self.control.send(request),
+            WorkClass::Bulk => self.data.send(request),
+        }
+    }
+
+    /// Queues the request on its lane without waiting, fails while the lane is full.
+    #[allow(clippy::result_large_err)]
+    pub fn try_send(
+        &self,
+        request: ArtilleryClusterRequest,
+    ) -> Result<(), TrySendError<ArtilleryClusterRequest>> {
+        match classify_work(&request) {
+            WorkClass::Critical => self.control.try_send(request),
+            WorkClass::Bulk => self.data.try_send(request),
+        }
+    }
+}
+
+pub struct RequestReceiver {
+    control: Receiver<ArtilleryClusterRequest>,
+    data: Receiver<ArtilleryClusterRequest>,
+}
+
+impl RequestReceiver {
+    pub fn try_recv_control(&self) -> Result<ArtilleryClusterRequest, TryRecvError> {
+        self.control.try_recv()
+    }
+
+    pub fn try_recv_data(&self) -> Result<ArtilleryClusterRequest, TryRecvError> {
+        self.data.try_recv()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc::channel;
+    use uuid::Uuid;
+
+    #[test]
+    fn control_requests_pass_a_full_data_lane() {
+        let (sender, receiver) = request_lanes(&ClusterConfig {
+            data_queue_capacity: 2,
+            ..Default::default()
+        });
+        for _ in 0..2 {
+            sender
+                .send(ArtilleryClusterRequest::Payload(
+                    Uuid::new_v4(),
+                    "bulk".into(),
+                ))
+                .unwrap();
+        }
+        let (exit_tx, _exit_rx) = channel();
+        sender.send(ArtilleryClusterRequest::Exit(exit_tx)).unwrap();
+
+        assert!(matches!(
+            receiver.try_recv_control(),
+            Ok(ArtilleryClusterRequest::Exit(_))
+        ));
+        assert!(matches!(
+            receiver.try_recv_data(),
+            Ok(ArtilleryClusterRequest::Payload(..))
+        ));
+    }
+
+    #[test]
+    fn try_send_fails_on_a_full_lane() {
+        let (sender, receiver) = request_lanes(&ClusterConfig {
+            data_queue_capacity: 1,
+            ..Default::default()
+        });
+        let payload = || ArtilleryClusterRequest::Payload(Uuid::new_v4(), "bulk".into());
+
+        sender.try_send(payload()).unwrap();
+        assert!(matches!(
+            sender.try_send(payload()),
+            Err(TrySendError::Full(_))
+        ));
+
+        drop(receiver);
+        assert!(matches!(
+            sender.try_send(payload()),
+            Err(TrySendError::Disconnected(_))
+        ));
+    }
+}
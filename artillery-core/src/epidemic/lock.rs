@@ -0,0 +1,299 @@
+use super::broadcast::BroadcastQueue;
+use super::primitives::{NodeId, Timestamp};
+use crate::constants::CONST_LOCK_RETENTION;
+use chrono::{DateTime, Duration, Utc};
+use serde::*;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Gossiped lease on a named lock. Locks are advisory only: leases expire by the wall
+/// clock of every node, moved along with its protocol clock, and two nodes not hearing
+/// each other both get the lock. Once they do, the lease confirmed by the arbiter wins,
+/// otherwise the earliest one.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    #[serde(rename = "n")]
+    name: String,
+    #[serde(rename = "h")]
+    holder: Uuid,
+    #[serde(rename = "a")]
+    acquired_at: DateTime<Utc>,
+    #[serde(rename = "e")]
+    expires_at: DateTime<Utc>,
+    #[serde(rename = "u")]
+    updated_at: DateTime<Utc>,
+    #[serde(rename = "c", default)]
+    confirmed_by: Option<Uuid>,
+}
+
+impl Lease {
+    pub(crate) fn new(
+        name: String,
+        holder: Uuid,
+        ttl: std::time::Duration,
+        now: DateTime<Utc>,
+    ) -> Self {
+        Lease {
+            name,
+            holder,
+            acquired_at: now,
+            expires_at: Duration::from_std(ttl)
+                .ok()
+                .and_then(|span| now.checked_add_signed(span))
+                .unwrap_or(DateTime::<Utc>::MAX_UTC),
+            updated_at: now,
+            confirmed_by: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn holder(&self) -> NodeId {
+        self.holder.into()
+    }
+
+    pub fn acquired_at(&self) -> Timestamp {
+        self.acquired_at.into()
+    }
+
+    pub fn expires_at(&self) -> Timestamp {
+        self.expires_at.into()
+    }
+
+    /// The arbiter which confirmed the lease, if any did.
+    pub fn confirmed_by(&self) -> Option<NodeId> {
+        self.confirmed_by.map(NodeId::from)
+    }
+
+    pub(crate) fn is_live(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at > now
+    }
+
+    fn same_lease(&self, other: &Lease) -> bool {
+        self.holder == other.holder && self.acquired_at == other.acquired_at
+    }
+
+    /// Updates of a lease go by their time, a release beating a renewal of the same instant.
+    /// Between two live leases the confirmed one wins, then the earliest one, so that every
+    /// node converges on the same holder.
+    fn supersedes(&self, other: &Lease, now: DateTime<Utc>) -> bool {
+        if self.same_lease(other) {
+            return (
+                self.updated_at,
+                self.confirmed_by.is_some(),
+                Reverse(self.expires_at),
+            ) > (
+                other.updated_at,
+                other.confirmed_by.is_some(),
+                Reverse(other.expires_at),
+            );
+        }
+
+        match (self.is_live(now), other.is_live(now)) {
+            (true, true) => {
+                (other.confirmed_by.is_some(), self.acquired_at, self.holder)
+                    < (self.confirmed_by.is_some(), other.acquired_at, other.holder)
+            }
+            (true, false) => true,
+            (false, true) => false,
+            (false, false) => self.acquired_at > other.acquired_at,
+        }
+    }
+}
+
+/// Latest lease of every lock known by this node and their dissemination queue.
+#[derive(Debug)]
+pub(crate) struct Locks {
+    table: HashMap<String, Lease>,
+    queue: BroadcastQueue<Lease>,
+}
+
+impl Locks {
+    pub fn new(retransmits: usize) -> Self {
+        Locks {
+            table: HashMap::new(),
+            queue: BroadcastQueue::new(retransmits),
+        }
+    }
+
+    /// Merges the lease. Returns the live lease of another holder it overruled, if any.
+    pub fn apply(&mut self, lease: Lease, now: DateTime<Utc>) -> Option<Lease> {
+        let overruled = match self.table.get(&lease.name) {
+            Some(known) if !lease.supersedes(known, now) => return None,
+            Some(known) if known.is_live(now) && !known.same_lease(&lease) => Some(known.clone()),
+            Some(_) | None => None,
+        };
+
+        let name = lease.name.clone();
+        self.table.insert(name.clone(), lease.clone());
+        self.queue
+            .push_superseding(lease, |pending| pending.name == name);
+
+        overruled
+    }
+
+    /// Takes or renews the lock for `holder`, unless another holder has a live lease on it.
+    pub fn try_lock(
+        &mut self,
+        name: &str,
+        holder: Uuid,
+        ttl: std::time::Duration,
+        now: DateTime<Utc>,
+    ) -> Option<Lease> {
+        let lease = match self.table.get(name) {
+            Some(known) if known.is_live(now) && known.holder != holder => return None,
+            Some(known) if known.is_live(now) => Lease {
+                expires_at: Lease::new(name.to_string(), holder, ttl, now).expires_at,
+                updated_at: now,
+                ..known.clone()
+            },
+            Some(_) | None => Lease::new(name.to_string(), holder, ttl, now),
+        };
+
+        self.apply(lease.clone(), now);
+        Some(lease)
+    }
+
+    /// Expires the live lease of `holder` on the lock right away.
+    pub fn unlock(&mut self, name: &str, holder: Uuid, now: DateTime<Utc>) -> bool {
+        let released = match self.table.get(name) {
+            Some(known) if known.is_live(now) && known.holder == holder => Lease {
+                expires_at: now,
+                updated_at: now,
+                ..known.clone()
+            },
+            Some(_) | None => return false,
+        };
+
+        self.apply(released, now);
+        true
+    }
+
+    /// The live lease on the lock as known by this node.
+    pub fn holder(&self, name: &str, now: DateTime<Utc>) -> Option<Lease> {
+        self.table
+            .get(name)
+            .filter(|lease| lease.is_live(now))
+            .cloned()
+    }
+
+    /// Confirms the live leases as the arbiter, returns how many were confirmed.
+    pub fn arbitrate(&mut self, arbiter: Uuid, now: DateTime<Utc>) -> usize {
+        let unconfirmed: Vec<Lease> = self
+            .table
+            .values()
+            .filter(|lease| lease.is_live(now) && lease.confirmed_by != Some(arbiter))
+            .cloned()
+            .collect();
+        let count = unconfirmed.len();
+
+        for lease in unconfirmed {
+            self.apply(
+                Lease {
+                    confirmed_by: Some(arbiter),
+                    updated_at: now.max(lease.updated_at),
+                    ..lease
+                },
+                now,
+            );
+        }
+
+        count
+    }
+
+    /// Forgets the leases expired for longer than the retention.
+    pub fn prune(&mut self, now: DateTime<Utc>) {
+        let before =
+            now - Duration::from_std(CONST_LOCK_RETENTION).unwrap_or_else(|_| Duration::zero());
+        self.table.retain(|_, lease| lease.expires_at >= before);
+    }
+
+    pub fn next_batch(&mut self, max: usize) -> Vec<Lease> {
+        self.queue.next_batch(max)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::state::ArtilleryClusterRequest;
+    use crate::epidemic::test_kit::ProtocolTester;
+    use std::sync::mpsc::channel;
+
+    const TTL: std::time::Duration = std::time::Duration::from_secs(30);
+
+    #[test]
+    fn denies_live_locks_and_releases() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let now = Utc::now();
+        let mut locks = Locks::new(3);
+
+        let lease = locks.try_lock("migration", a, TTL, now).unwrap();
+        assert!(locks.try_lock("migration", b, TTL, now).is_none());
+
+        let later = now + Duration::seconds(1);
+        let renewed = locks.try_lock("migration", a, TTL, later).unwrap();
+        assert_eq!(renewed.acquired_at(), lease.acquired_at());
+        assert!(renewed.expires_at() > lease.expires_at());
+
+        assert!(!locks.unlock("migration", b, later));
+        assert!(locks.unlock("migration", a, later));
+        assert!(locks.holder("migration", later).is_none());
+        assert_eq!(
+            locks.try_lock("migration", b, TTL, later).unwrap().holder(),
+            b.into()
+        );
+    }
+
+    #[test]
+    fn conflicts_resolve_to_the_confirmed_lease() {
+        let (arbiter, a, b) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let now = Utc::now();
+        let mut locks = Locks::new(3);
+
+        let late = Lease::new("migration".into(), b, TTL, now + Duration::seconds(1));
+        let early = Lease::new("migration".into(), a, TTL, now);
+        assert!(locks.apply(late.clone(), now).is_none());
+        assert_eq!(locks.apply(early.clone(), now), Some(late.clone()));
+        assert!(locks.apply(late.clone(), now).is_none());
+
+        let mut other = Locks::new(3);
+        other.apply(late.clone(), now);
+        assert_eq!(other.arbitrate(arbiter, now), 1);
+        let confirmed = other.holder("migration", now).unwrap();
+        assert_eq!(confirmed.confirmed_by(), Some(arbiter.into()));
+
+        assert_eq!(locks.apply(confirmed, now), Some(early.clone()));
+        assert!(locks.apply(early, now).is_none());
+        assert_eq!(locks.holder("migration", now).unwrap().holder(), b.into());
+    }
+
+    #[test]
+    fn leases_expire_on_the_protocol_clock() {
+        let mut tester = ProtocolTester::new();
+        let (tx, rx) = channel();
+        tester.request(ArtilleryClusterRequest::TryLock(
+            "migration".into(),
+            TTL,
+            tx,
+        ));
+        assert!(rx.recv().unwrap().is_some());
+
+        let holder = |tester: &mut ProtocolTester| {
+            let (tx, rx) = channel();
+            tester.request(ArtilleryClusterRequest::LockHolder("migration".into(), tx));
+            rx.recv().unwrap()
+        };
+        tester.tick(TTL / 2);
+        assert_eq!(
+            holder(&mut tester).map(|l| l.holder()),
+            Some(tester.host_key().into())
+        );
+
+        tester.tick(TTL);
+        assert!(holder(&mut tester).is_none());
+    }
+}
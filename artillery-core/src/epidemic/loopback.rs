@@ -0,0 +1,443 @@
+use super::transport::Transport;
+use mio::{Registry, Token, Waker};
+use rand::Rng;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Datagrams queued for an endpoint beyond this are dropped, like a full socket buffer.
+const MAILBOX_CAPACITY: usize = 4096;
+/// How often the idle delay line checks whether the network was dropped.
+const DELAY_LINE_IDLE: Duration = Duration::from_millis(100);
+
+#[derive(Default)]
+struct Mailbox {
+    datagrams: Mutex<VecDeque<(SocketAddr, Vec<u8>)>>,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Mailbox {
+    fn deliver(&self, from: SocketAddr, buf: &[u8]) {
+        {
+            let mut datagrams = lock(&self.datagrams);
+            if datagrams.len() >= MAILBOX_CAPACITY {
+                return;
+            }
+            datagrams.push_back((from, buf.to_vec()));
+        }
+
+        if let Some(waker) = lock(&self.waker).as_ref() {
+            let _ = waker.wake();
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Faults of every link of a [`MemoryNetwork`], applied to each datagram sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkFaults {
+    /// Share of the datagrams lost, in percent.
+    pub loss_percent: u8,
+    /// Delay of every datagram.
+    pub latency: Duration,
+    /// Extra delay up to this, random for each datagram, which reorders them.
+    pub jitter: Duration,
+}
+
+impl LinkFaults {
+    fn is_lost(&self) -> bool {
+        self.loss_percent > 0 && rand::thread_rng().gen_range(0, 100) < self.loss_percent
+    }
+
+    fn delay(&self) -> Duration {
+        let jitter = u64::try_from(self.jitter.as_millis()).unwrap_or(u64::MAX);
+        if jitter == 0 {
+            return self.latency;
+        }
+
+        let extra = rand::thread_rng().gen_range(0, jitter.saturating_add(1));
+        self.latency + Duration::from_millis(extra)
+    }
+}
+
+type DelayedDatagram = (Instant, SocketAddr, Arc<Mailbox>, Vec<u8>);
+
+/// Datagrams held back by the latency of the links, delivered by a thread once due.
+#[derive(Default)]
+struct DelayLine {
+    datagrams: Mutex<Vec<DelayedDatagram>>,
+    queued: Condvar,
+    running: AtomicBool,
+}
+
+impl DelayLine {
+    fn push(line: &Arc<Self>, datagram: DelayedDatagram) {
+        if !line.running.swap(true, Ordering::AcqRel) {
+            let weak = Arc::downgrade(line);
+            let _ = thread::Builder::new()
+                .name("artillery-delay-line".to_string())
+                .spawn(move || deliver_delayed(&weak));
+        }
+
+        lock(&line.datagrams).push(datagram);
+        line.queued.notify_one();
+    }
+}
+
+/// Runs until the network is dropped.
+fn deliver_delayed(weak: &Weak<DelayLine>) {
+    while let Some(line) = weak.upgrade() {
+        let now = Instant::now();
+        let (due, held): (Vec<_>, Vec<_>) = lock(&line.datagrams)
+            .drain(..)
+            .partition(|(at, ..)| *at <= now);
+        for (_, from, mailbox, datagram) in due {
+            mailbox.deliver(from, &datagram);
+        }
+
+        let mut datagrams = lock(&line.datagrams);
+        datagrams.extend(held);
+        let wait = datagrams
+            .iter()
+            .map(|(at, ..)| at.saturating_duration_since(now))
+            .min()
+            .unwrap_or(DELAY_LINE_IDLE)
+            .min(DELAY_LINE_IDLE);
+        let _ = line.queued.wait_timeout(datagrams, wait);
+    }
+}
+
+/// In-process datagram network, endpoints are keyed by fake addresses so that any number
+/// of clusters run in one process without binding ports. Datagrams to unbound addresses
+/// are lost, as with UDP, and so are the datagrams from or to an isolated endpoint. The
+/// [`LinkFaults`] inject loss and latency into all the links.
+///
+/// ```ignore
+/// let network = MemoryNetwork::new();
+/// let transport = network.bind("10.0.0.1:0".parse()?)?;
+/// let (cluster, handle) = Cluster::new_cluster_with_transport(host_key, config, transport)?;
+/// // The node fails as seen by the others
+/// network.isolate(cluster.local_addr());
+/// ```
+#[derive(Clone, Default)]
+pub struct MemoryNetwork {
+    endpoints: Arc<Mutex<HashMap<SocketAddr, Arc<Mailbox>>>>,
+    isolated: Arc<Mutex<HashSet<SocketAddr>>>,
+    faults: Arc<Mutex<LinkFaults>>,
+    delay_line: Arc<DelayLine>,
+}
+
+impl MemoryNetwork {
+    pub fn new() -> Self {
+        MemoryNetwork::default()
+    }
+
+    /// Endpoint at the address, port `0` picks a free port of that IP.
+    pub fn bind(&self, addr: SocketAddr) -> io::Result<MemoryTransport> {
+        let mut endpoints = lock(&self.endpoints);
+        let local_addr = if addr.port() == 0 {
+            (1..=u16::MAX)
+                .map(|port| SocketAddr::new(addr.ip(), port))
+                .find(|candidate| !endpoints.contains_key(candidate))
+                .ok_or_else(|| io::Error::from(io::ErrorKind::AddrNotAvailable))?
+        } else if endpoints.contains_key(&addr) {
+            return Err(io::Error::from(io::ErrorKind::AddrInUse));
+        } else {
+            addr
+        };
+
+        let mailbox = Arc::new(Mailbox::default());
+        endpoints.insert(local_addr, mailbox.clone());
+
+        Ok(MemoryTransport {
+            network: self.clone(),
+            local_addr,
+            mailbox,
+        })
+    }
+
+    pub fn is_bound(&self, addr: &SocketAddr) -> bool {
+        lock(&self.endpoints).contains_key(addr)
+    }
+
+    /// Cuts the endpoint off the network until it is reconnected, it stays bound.
+    pub fn isolate(&self, addr: SocketAddr) {
+        lock(&self.isolated).insert(addr);
+    }
+
+    pub fn reconnect(&self, addr: &SocketAddr) {
+        lock(&self.isolated).remove(addr);
+    }
+
+    /// Applies to the datagrams sent from now on, the default faults are none.
+    pub fn set_faults(&self, faults: LinkFaults) {
+        *lock(&self.faults) = faults;
+    }
+
+    fn links(&self, from: &SocketAddr, to: &SocketAddr) -> bool {
+        let isolated = lock(&self.isolated);
+        !isolated.contains(from) && !isolated.contains(to)
+    }
+}
+
+/// Endpoint of a [`MemoryNetwork`], unbound when dropped.
+pub struct MemoryTransport {
+    network: MemoryNetwork,
+    local_addr: SocketAddr,
+    mailbox: Arc<Mailbox>,
+}
+
+impl Transport for MemoryTransport {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    fn send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        let faults = *lock(&self.network.faults);
+        if !self.network.links(&self.local_addr, &target) || faults.is_lost() {
+            return Ok(buf.len());
+        }
+
+        let endpoint = lock(&self.network.endpoints).get(&target).cloned();
+        if let Some(mailbox) = endpoint {
+            let delay = faults.delay();
+            if delay == Duration::from_secs(0) {
+                mailbox.deliver(self.local_addr, buf);
+            } else {
+                DelayLine::push(
+                    &self.network.delay_line,
+                    (
+                        Instant::now() + delay,
+                        self.local_addr,
+                        mailbox,
+                        buf.to_vec(),
+                    ),
+                );
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    /// Longer datagrams are truncated to the buffer, as with UDP.
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let (from, datagram) = lock(&self.mailbox.datagrams)
+            .pop_front()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::WouldBlock))?;
+        let len = datagram.len().min(buf.len());
+        buf[..len].copy_from_slice(&datagram[..len]);
+
+        Ok((len, from))
+    }
+
+    fn register(&mut self, registry: &Registry, token: Token) -> io::Result<()> {
+        let waker = Waker::new(registry, token)?;
+        // Datagrams delivered before the registration
+        if !lock(&self.mailbox.datagrams).is_empty() {
+            waker.wake()?;
+        }
+        *lock(&self.mailbox.waker) = Some(waker);
+
+        Ok(())
+    }
+
+    fn deregister(&mut self, _registry: &Registry) -> io::Result<()> {
+        *lock(&self.mailbox.waker) = None;
+        Ok(())
+    }
+}
+
+impl Drop for MemoryTransport {
+    fn drop(&mut self) {
+        lock(&self.network.endpoints).remove(&self.local_addr);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::prelude::*;
+    use mio::{Events, Poll};
+    use std::net::IpAddr;
+    use uuid::Uuid;
+
+    /// Waits up to 30 seconds for an event of the cluster matching the predicate.
+    fn saw_event<F>(cluster: &Cluster, predicate: F) -> bool
+    where
+        F: Fn(&ArtilleryMemberEvent) -> bool,
+    {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        std::iter::from_fn(|| {
+            let left = deadline.checked_duration_since(Instant::now())?;
+            cluster.events.recv_timeout(left).ok()
+        })
+        .any(|(_, event, _)| predicate(&event))
+    }
+
+    #[test]
+    fn delivers_datagrams_between_endpoints() {
+        let network = MemoryNetwork::new();
+        let a = network.bind("10.0.0.1:0".parse().unwrap()).unwrap();
+        let mut b = network.bind("10.0.0.1:0".parse().unwrap()).unwrap();
+        let b_addr = b.local_addr().unwrap();
+        assert_ne!(a.local_addr().unwrap(), b_addr);
+        assert!(network.bind(b_addr).is_err());
+
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(4);
+        b.register(poll.registry(), Token(3)).unwrap();
+        a.send_to(b"hello", b_addr).unwrap();
+        poll.poll(&mut events, Some(Duration::from_secs(5)))
+            .unwrap();
+        assert!(events.iter().any(|e| e.token() == Token(3)));
+
+        let mut buf = [0_u8; 16];
+        let (len, from) = b.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"hello");
+        assert_eq!(from, a.local_addr().unwrap());
+        assert_eq!(
+            b.recv_from(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+
+        network.set_faults(LinkFaults {
+            latency: Duration::from_millis(50),
+            ..Default::default()
+        });
+        let sent = Instant::now();
+        a.send_to(b"late", b_addr).unwrap();
+        poll.poll(&mut events, Some(Duration::from_secs(5)))
+            .unwrap();
+        let (len, _) = b.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"late");
+        assert!(sent.elapsed() >= Duration::from_millis(50));
+
+        network.set_faults(LinkFaults {
+            loss_percent: 100,
+            ..Default::default()
+        });
+        a.send_to(b"lost", b_addr).unwrap();
+        assert_eq!(
+            b.recv_from(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+
+        drop(b);
+        assert!(!network.is_bound(&b_addr));
+        assert_eq!(a.send_to(b"lost", b_addr).unwrap(), 4);
+    }
+
+    #[test]
+    fn clusters_join_over_the_network() {
+        let network = MemoryNetwork::new();
+        let seed = network.bind("10.0.0.1:7946".parse().unwrap()).unwrap();
+        let seed_addr = seed.local_addr().unwrap();
+        let (seed_key, joiner_key) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let (_seed, _seed_handle) =
+            Cluster::new_cluster_with_transport(seed_key, ClusterConfig::default(), seed).unwrap();
+        let (joiner, _joiner_handle) = Cluster::new_cluster_with_transport(
+            joiner_key,
+            ClusterConfig {
+                seeds: vec![PeerAddr::from(seed_addr)],
+                ..Default::default()
+            },
+            network.bind("10.0.0.2:0".parse().unwrap()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            joiner.local_addr().ip(),
+            "10.0.0.2".parse::<IpAddr>().unwrap()
+        );
+        assert!(network.is_bound(&joiner.local_addr()));
+        let myself = joiner.local_member().unwrap();
+        assert_eq!(myself.host_key(), joiner_key);
+        assert!(myself.is_current());
+
+        assert!(saw_event(&joiner, |event| {
+            matches!(event, ArtilleryMemberEvent::Joined(m) if m.host_key() == seed_key)
+        }));
+    }
+
+    #[test]
+    fn clusters_declare_an_isolated_member_down() {
+        let network = MemoryNetwork::new();
+        let seed = network.bind("10.0.0.1:7946".parse().unwrap()).unwrap();
+        let seed_addr = seed.local_addr().unwrap();
+        let (seed_key, joiner_key) = (Uuid::new_v4(), Uuid::new_v4());
+        let config = ClusterConfig {
+            ping_interval: Duration::from_millis(100),
+            ping_timeout: Duration::from_millis(200),
+            suspicion_timeout: Duration::from_millis(500),
+            ..Default::default()
+        };
+
+        let (seed_cluster, _seed_handle) =
+            Cluster::new_cluster_with_transport(seed_key, config.clone(), seed).unwrap();
+        let (joiner, _joiner_handle) = Cluster::new_cluster_with_transport(
+            joiner_key,
+            ClusterConfig {
+                seeds: vec![PeerAddr::from(seed_addr)],
+                ..config
+            },
+            network.bind("10.0.0.2:0".parse().unwrap()).unwrap(),
+        )
+        .unwrap();
+        let is_joiner = |m: &ArtilleryMember| m.host_key() == joiner_key;
+        assert!(saw_event(&seed_cluster, |event| {
+            matches!(event, ArtilleryMemberEvent::Joined(m) if is_joiner(m))
+        }));
+
+        network.isolate(joiner.local_addr());
+        assert!(saw_event(&seed_cluster, |event| {
+            matches!(event, ArtilleryMemberEvent::SuspectedDown(m, _) if is_joiner(m))
+        }));
+        assert!(saw_event(&seed_cluster, |event| {
+            matches!(event, ArtilleryMemberEvent::WentDown(m, _) if is_joiner(m))
+        }));
+    }
+
+    #[test]
+    fn accelerated_clusters_converge_in_protocol_time() {
+        let network = MemoryNetwork::new();
+        let seed = network.bind("10.0.0.1:7946".parse().unwrap()).unwrap();
+        let seed_addr = seed.local_addr().unwrap();
+        let (seed_key, joiner_key) = (Uuid::new_v4(), Uuid::new_v4());
+        // Default intervals, seconds of protocol time to detect a failure
+        let config = ClusterConfig {
+            clock: Some(Arc::new(AcceleratedClock::new(100))),
+            ..Default::default()
+        };
+
+        let start = Instant::now();
+        let (seed_cluster, _seed_handle) =
+            Cluster::new_cluster_with_transport(seed_key, config.clone(), seed).unwrap();
+        let (joiner, _joiner_handle) = Cluster::new_cluster_with_transport(
+            joiner_key,
+            ClusterConfig {
+                seeds: vec![PeerAddr::from(seed_addr)],
+                ..config
+            },
+            network.bind("10.0.0.2:0".parse().unwrap()).unwrap(),
+        )
+        .unwrap();
+        let is_joiner = |m: &ArtilleryMember| m.host_key() == joiner_key;
+        assert!(saw_event(&seed_cluster, |event| {
+            matches!(event, ArtilleryMemberEvent::Joined(m) if is_joiner(m))
+        }));
+
+        network.isolate(joiner.local_addr());
+        assert!(saw_event(&seed_cluster, |event| {
+            matches!(event, ArtilleryMemberEvent::WentDown(m, _) if is_joiner(m))
+        }));
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+}
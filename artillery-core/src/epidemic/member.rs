@@ -1,9 +1,11 @@
 use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::fmt::{Debug, Formatter};
 use std::net::SocketAddr;
 
-use chrono::{DateTime, Duration, Utc};
+use super::primitives::{NodeId, Timestamp};
+use chrono::{DateTime, Utc};
 use serde::*;
 use uuid::Uuid;
 
@@ -23,6 +25,22 @@ pub enum ArtilleryMemberState {
     Left,
 }
 
+/// Why a member was suspected or declared down, carried by the `SuspectedDown` and
+/// `WentDown` events.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransitionReason {
+    /// The member didn't answer the ping of this node in time.
+    DirectTimeout,
+    /// The members asked to ping the suspect didn't get an answer either.
+    IndirectFailure,
+    /// Learned from the gossip of this member.
+    Gossip(NodeId),
+    /// Confirmed down by the application through `Cluster::confirm_down`.
+    Confirmed,
+    /// Reported failed by an external system through `Cluster::report_external_failure`.
+    External,
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct ArtilleryMember {
     #[serde(rename = "h")]
@@ -35,43 +53,156 @@ pub struct ArtilleryMember {
     member_state: ArtilleryMemberState,
     #[serde(rename = "t")]
     last_state_change: DateTime<Utc>,
+    #[serde(rename = "c", default)]
+    confirmed_down: bool,
+    #[serde(rename = "z", default)]
+    zone: Option<String>,
+    /// Random per-process id, differs between the runs of the same `host_key`.
+    #[serde(rename = "u", default)]
+    run_id: Option<Uuid>,
+    /// Set by the member itself while it can't reach the network.
+    #[serde(rename = "g", default)]
+    degraded: bool,
+    /// Planned downtime announced by the member itself, until the ETA if one is given.
+    #[serde(rename = "w", default)]
+    maintenance: bool,
+    #[serde(rename = "e", default)]
+    maintenance_eta: Option<DateTime<Utc>>,
+    /// Application ports of the member by service name, e.g. `http` or `grpc`.
+    #[serde(rename = "p", default)]
+    ports: BTreeMap<String, u16>,
+    /// Application metadata set by the member itself, e.g. its role or shard id.
+    #[serde(rename = "k", default)]
+    metadata: HashMap<String, Vec<u8>>,
+    /// Bumped by the member on every metadata update.
+    #[serde(rename = "v", default)]
+    metadata_version: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialOrd, Ord, PartialEq, Eq)]
 pub struct ArtilleryStateChange {
     member: ArtilleryMember,
+    /// Local version of the change, so acks retire exactly what they covered. Not gossiped.
+    #[serde(skip)]
+    id: u64,
+    /// Outgoing messages which carried this change. Not gossiped.
+    #[serde(skip)]
+    transmissions: usize,
 }
 
 impl ArtilleryMember {
-    pub fn new(
-        host_key: Uuid,
+    pub fn new<I: Into<NodeId>>(
+        host_key: I,
         remote_host: SocketAddr,
         incarnation_number: u64,
         known_state: ArtilleryMemberState,
     ) -> Self {
         ArtilleryMember {
-            host_key,
+            host_key: host_key.into().into(),
             remote_host: Some(remote_host),
             incarnation_number,
             member_state: known_state,
             last_state_change: Utc::now(),
+            confirmed_down: false,
+            zone: None,
+            run_id: None,
+            degraded: false,
+            maintenance: false,
+            maintenance_eta: None,
+            ports: BTreeMap::new(),
+            metadata: HashMap::new(),
+            metadata_version: 0,
         }
     }
 
-    pub fn current(host_key: Uuid) -> Self {
+    pub fn current<I: Into<NodeId>>(host_key: I) -> Self {
         ArtilleryMember {
-            host_key,
+            host_key: host_key.into().into(),
             remote_host: None,
             incarnation_number: 0,
             member_state: ArtilleryMemberState::Alive,
             last_state_change: Utc::now(),
+            confirmed_down: false,
+            zone: None,
+            run_id: Some(Uuid::new_v4()),
+            degraded: false,
+            maintenance: false,
+            maintenance_eta: None,
+            ports: BTreeMap::new(),
+            metadata: HashMap::new(),
+            metadata_version: 0,
         }
     }
 
-    pub fn host_key(&self) -> Uuid {
+    /// Places the member in the given availability zone.
+    pub fn with_zone(self, zone: Option<String>) -> Self {
+        ArtilleryMember { zone, ..self }
+    }
+
+    pub fn zone(&self) -> Option<&str> {
+        self.zone.as_deref()
+    }
+
+    /// Announces the application ports of the member by service name.
+    pub fn with_ports(self, ports: BTreeMap<String, u16>) -> Self {
+        ArtilleryMember { ports, ..self }
+    }
+
+    pub fn ports(&self) -> &BTreeMap<String, u16> {
+        &self.ports
+    }
+
+    pub fn port(&self, service: &str) -> Option<u16> {
+        self.ports.get(service).copied()
+    }
+
+    /// Starts with the given metadata, at version 0.
+    pub fn with_metadata(self, metadata: HashMap<String, Vec<u8>>) -> Self {
+        ArtilleryMember { metadata, ..self }
+    }
+
+    pub fn metadata(&self) -> &HashMap<String, Vec<u8>> {
+        &self.metadata
+    }
+
+    pub fn metadata_version(&self) -> u64 {
+        self.metadata_version
+    }
+
+    pub fn set_metadata(&mut self, metadata: HashMap<String, Vec<u8>>) {
+        self.metadata = metadata;
+        self.metadata_version += 1;
+    }
+
+    /// Endpoint of the named service on the member, `None` for the current node which has
+    /// no remote address.
+    pub fn service_addr(&self, service: &str) -> Option<SocketAddr> {
+        let host = self.remote_host?;
+        self.port(service)
+            .map(|port| SocketAddr::new(host.ip(), port))
+    }
+
+    pub(crate) fn host_key(&self) -> Uuid {
         self.host_key
     }
 
+    pub fn node_id(&self) -> NodeId {
+        NodeId::from(self.host_key)
+    }
+
+    /// Id of the process run this record originates from, `None` for nodes not gossiping it.
+    pub fn run_id(&self) -> Option<NodeId> {
+        self.run_id.map(NodeId::from)
+    }
+
+    /// Whether this record comes from a newer run of the known member, after a restart.
+    pub fn is_restart_of(&self, known: &ArtilleryMember) -> bool {
+        self.host_key == known.host_key
+            && self.run_id.is_some()
+            && known.run_id.is_some()
+            && self.run_id != known.run_id
+    }
+
     pub fn remote_host(&self) -> Option<SocketAddr> {
         self.remote_host
     }
@@ -84,8 +215,16 @@ impl ArtilleryMember {
         self.remote_host.is_none()
     }
 
-    pub fn state_change_older_than(&self, duration: Duration) -> bool {
-        self.last_state_change + duration < Utc::now()
+    pub fn state_change_older_than(&self, duration: std::time::Duration) -> bool {
+        chrono::Duration::from_std(duration)
+            .ok()
+            .and_then(|d| self.last_state_change.checked_add_signed(d))
+            .is_some_and(|deadline| deadline < Utc::now())
+    }
+
+    /// When this node saw the member change state last.
+    pub fn last_state_change(&self) -> Timestamp {
+        Timestamp::from(self.last_state_change)
     }
 
     pub fn state(&self) -> ArtilleryMemberState {
@@ -99,6 +238,26 @@ impl ArtilleryMember {
         }
     }
 
+    /// Whether an operator asserted this member dead via `Cluster::confirm_down`.
+    pub fn is_confirmed_down(&self) -> bool {
+        self.confirmed_down
+    }
+
+    /// Moves the member to `Down` skipping the suspicion window.
+    /// Confirmed members are not revived by acks of the same incarnation.
+    pub fn confirm_down(&mut self) {
+        self.set_state(ArtilleryMemberState::Down);
+        self.confirmed_down = true;
+    }
+
+    /// Brings back a confirmed `Down` member answering from a new run, the confirmation was
+    /// about the old one.
+    pub fn revive(&mut self, run: Uuid) {
+        self.set_state(ArtilleryMemberState::Alive);
+        self.confirmed_down = false;
+        self.run_id = Some(run);
+    }
+
     pub fn member_by_changing_host(&self, remote_host: SocketAddr) -> ArtilleryMember {
         ArtilleryMember {
             remote_host: Some(remote_host),
@@ -106,6 +265,45 @@ impl ArtilleryMember {
         }
     }
 
+    pub fn incarnation_number(&self) -> u64 {
+        self.incarnation_number
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.degraded
+    }
+
+    pub fn set_degraded(&mut self, degraded: bool) {
+        self.degraded = degraded;
+    }
+
+    pub fn is_in_maintenance(&self) -> bool {
+        self.maintenance
+    }
+
+    pub fn maintenance_eta(&self) -> Option<Timestamp> {
+        self.maintenance_eta.map(Timestamp::from)
+    }
+
+    /// Whether the maintenance still keeps the member from being declared down.
+    pub fn is_under_maintenance(&self, now: Timestamp) -> bool {
+        self.maintenance && self.maintenance_eta.is_none_or(|eta| eta > now.into())
+    }
+
+    pub fn set_maintenance(&mut self, maintenance: bool, eta: Option<Timestamp>) {
+        self.maintenance = maintenance;
+        self.maintenance_eta = eta.filter(|_| maintenance).map(DateTime::from);
+    }
+
+    /// Makes the record look like a fresh process run, others see it as a restart.
+    pub fn start_new_run(&mut self) {
+        self.set_state(ArtilleryMemberState::Alive);
+        self.run_id = Some(Uuid::new_v4());
+        self.degraded = false;
+        self.set_maintenance(false, None);
+        self.reincarnate();
+    }
+
     pub fn reincarnate(&mut self) {
         self.incarnation_number += 1
     }
@@ -113,7 +311,31 @@ impl ArtilleryMember {
 
 impl ArtilleryStateChange {
     pub fn new(member: ArtilleryMember) -> ArtilleryStateChange {
-        ArtilleryStateChange { member }
+        ArtilleryStateChange {
+            member,
+            id: 0,
+            transmissions: 0,
+        }
+    }
+
+    pub(crate) fn with_id(member: ArtilleryMember, id: u64) -> ArtilleryStateChange {
+        ArtilleryStateChange {
+            member,
+            id,
+            transmissions: 0,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn transmissions(&self) -> usize {
+        self.transmissions
+    }
+
+    pub(crate) fn record_transmission(&mut self) {
+        self.transmissions += 1
     }
 
     pub fn member(&self) -> &ArtilleryMember {
@@ -157,6 +379,12 @@ impl Debug for ArtilleryMember {
             .field("incarnation_number", &self.incarnation_number)
             .field("host", &self.host_key)
             .field("state", &self.member_state)
+            .field("run", &self.run_id)
+            .field("degraded", &self.degraded)
+            .field("maintenance", &self.maintenance)
+            .field("maintenance_eta", &self.maintenance_eta)
+            .field("ports", &self.ports)
+            .field("metadata_version", &self.metadata_version)
             .field(
                 "drift_time_ms",
                 &(Utc::now() - self.last_state_change).num_milliseconds(),
@@ -172,6 +400,14 @@ impl Debug for ArtilleryMember {
     }
 }
 
+/// Bytes of the metadata keys and values, bounded by `CONST_METADATA_LIMIT`.
+pub fn metadata_size(metadata: &HashMap<String, Vec<u8>>) -> usize {
+    metadata
+        .iter()
+        .map(|(key, value)| key.len() + value.len())
+        .sum()
+}
+
 pub fn most_uptodate_member_data<'a>(
     lhs: &'a ArtilleryMember,
     rhs: &'a ArtilleryMember,
@@ -180,6 +416,26 @@ pub fn most_uptodate_member_data<'a>(
     // It's important bit otherwise we won't understand.
     #![allow(clippy::match_same_arms)]
 
+    // Operator confirmation is about one incarnation, a member refuting it or coming
+    // back with a higher one wins. At the same incarnation the confirmation wins against
+    // an unconfirmed `Down` so that the flag itself gets disseminated.
+    if lhs.confirmed_down || rhs.confirmed_down {
+        if lhs.incarnation_number != rhs.incarnation_number {
+            return if lhs.incarnation_number > rhs.incarnation_number {
+                lhs
+            } else {
+                rhs
+            };
+        }
+
+        if lhs.member_state == ArtilleryMemberState::Down
+            && rhs.member_state == ArtilleryMemberState::Down
+            && lhs.confirmed_down != rhs.confirmed_down
+        {
+            return if lhs.confirmed_down { lhs } else { rhs };
+        }
+    }
+
     let lhs_overrides = match (
         lhs.member_state,
         lhs.incarnation_number,
@@ -207,8 +463,15 @@ pub fn most_uptodate_member_data<'a>(
 mod test {
     use std::str::FromStr;
 
-    use super::{ArtilleryMember, ArtilleryMemberState};
+    use super::{
+        most_uptodate_member_data, ArtilleryMember, ArtilleryMemberState, ArtilleryStateChange,
+    };
+    use crate::epidemic::state::ArtilleryClusterRequest;
+    use crate::epidemic::test_kit::*;
     use chrono::{Duration, Utc};
+    use std::collections::BTreeMap;
+    use std::net::SocketAddr;
+    use std::sync::mpsc::channel;
 
     use uuid;
 
@@ -220,7 +483,23 @@ mod test {
             incarnation_number: 123,
             member_state: ArtilleryMemberState::Alive,
             last_state_change: Utc::now() - Duration::days(1),
+            confirmed_down: false,
+            zone: None,
+            run_id: None,
+            degraded: false,
+            maintenance: false,
+            maintenance_eta: None,
+            ports: vec![("http".to_string(), 8080)].into_iter().collect(),
+            metadata: vec![("role".to_string(), b"storage".to_vec())]
+                .into_iter()
+                .collect(),
+            metadata_version: 1,
         };
+        assert_eq!(
+            member.service_addr("http"),
+            Some(FromStr::from_str("127.0.0.1:8080").unwrap())
+        );
+        assert_eq!(member.service_addr("grpc"), None);
 
         let encoded = bincode::serialize(&member).unwrap();
         dbg!(encoded.len());
@@ -232,4 +511,55 @@ mod test {
 
         assert_eq!(decoded, member);
     }
+
+    #[test]
+    fn incarnation_goes_before_the_down_confirmation() {
+        let id = uuid::Uuid::new_v4();
+        let addr = FromStr::from_str("127.0.0.1:1337").unwrap();
+        let mut confirmed = ArtilleryMember::new(id, addr, 3, ArtilleryMemberState::Alive);
+        confirmed.confirm_down();
+        let down = ArtilleryMember::new(id, addr, 3, ArtilleryMemberState::Down);
+        let refuted = ArtilleryMember::new(id, addr, 4, ArtilleryMemberState::Alive);
+        let stale = ArtilleryMember::new(id, addr, 2, ArtilleryMemberState::Alive);
+
+        // Same incarnation, the confirmation breaks the tie
+        assert!(most_uptodate_member_data(&confirmed, &down).is_confirmed_down());
+        assert!(most_uptodate_member_data(&down, &confirmed).is_confirmed_down());
+
+        // A higher incarnation wins whatever side it is on
+        assert_eq!(most_uptodate_member_data(&confirmed, &refuted), &refuted);
+        assert_eq!(most_uptodate_member_data(&refuted, &confirmed), &refuted);
+        assert_eq!(most_uptodate_member_data(&confirmed, &stale), &confirmed);
+        assert_eq!(most_uptodate_member_data(&stale, &confirmed), &confirmed);
+    }
+
+    #[test]
+    fn gossips_the_named_ports_of_the_members() {
+        let (a, b) = (TestPeer::new(), TestPeer::new());
+        let grpc: BTreeMap<String, u16> = vec![("grpc".to_string(), 9090)].into_iter().collect();
+        let mut tester = tester_with(|config| {
+            config.ports = grpc.clone();
+        });
+
+        tester.recv(heartbeat_from(&a).with_state_changes(vec![b
+                .member(ArtilleryMemberState::Alive)
+                .with_ports(grpc.clone())]));
+        let sent = tester.take_sent(&a);
+        let myself = sent
+            .iter()
+            .flat_map(|m| &m.state_changes)
+            .map(ArtilleryStateChange::member)
+            .find(|m| m.host_key() == tester.host_key())
+            .expect("Own record not gossiped");
+        assert_eq!(myself.port("grpc"), Some(9090));
+
+        let (tx, rx) = channel();
+        tester.request(ArtilleryClusterRequest::GetMembers(tx));
+        let members = rx.recv().unwrap();
+        let b_record = members.iter().find(|m| m.host_key() == b.id()).unwrap();
+        assert_eq!(
+            b_record.service_addr("grpc"),
+            Some(SocketAddr::new(b.addr().ip(), 9090))
+        );
+    }
 }
@@ -2,18 +2,43 @@ use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
 
-use chrono::Duration;
 use uuid::Uuid;
 
+use super::cluster_config::IndirectPingStrategy;
 use super::member::{ArtilleryMember, ArtilleryMemberState, ArtilleryStateChange};
+use super::primitives::{NodeId, Timestamp};
 use crate::epidemic::member;
 use bastion_utils::math;
+use chrono::{DateTime, Utc};
+use rand::Rng;
 
 use kaos::flunk;
 
+/// Outcome of merging gossiped member records.
+#[derive(Debug, Default)]
+pub struct MembershipChanges {
+    pub new: Vec<ArtilleryMember>,
+    pub changed: Vec<ArtilleryMember>,
+    pub restarted: Vec<ArtilleryMember>,
+    /// Newer incarnations in the same state, only the member metadata changed.
+    pub refreshed: Vec<ArtilleryMember>,
+    /// Known members whose merged record brought other application metadata.
+    pub metadata: Vec<ArtilleryMember>,
+}
+
+/// Page of the available members by host key, see [`ArtilleryMemberList::page`].
+#[derive(Debug, Clone, Default)]
+pub struct MemberPage {
+    pub members: Vec<ArtilleryMember>,
+    /// Cursor of the next page, `None` on the last one.
+    pub next: Option<NodeId>,
+}
+
 pub struct ArtilleryMemberList {
     members: Vec<ArtilleryMember>,
     periodic_index: usize,
+    /// Run ids superseded by a restart, per member.
+    retired_runs: HashMap<Uuid, HashSet<Uuid>>,
 }
 
 impl ArtilleryMemberList {
@@ -21,6 +46,7 @@ impl ArtilleryMemberList {
         ArtilleryMemberList {
             members: vec![current],
             periodic_index: 0,
+            retired_runs: HashMap::new(),
         }
     }
 
@@ -32,6 +58,33 @@ impl ArtilleryMemberList {
             .collect()
     }
 
+    /// Available members with a host key after the cursor, by host key. Only the members
+    /// of the page are cloned.
+    pub fn page(&self, after: Option<Uuid>, limit: usize) -> MemberPage {
+        let mut members: Vec<&ArtilleryMember> = self
+            .members
+            .iter()
+            .filter(|m| {
+                m.state() != ArtilleryMemberState::Left
+                    && after.is_none_or(|cursor| m.host_key() > cursor)
+            })
+            .collect();
+        members.sort_unstable_by_key(|m| m.host_key());
+
+        let size = limit.max(1);
+        let more = members.len() > size;
+        members.truncate(size);
+
+        MemberPage {
+            next: members.last().filter(|_| more).map(|m| m.node_id()),
+            members: members.into_iter().cloned().collect(),
+        }
+    }
+
+    pub fn all_members(&self) -> Vec<ArtilleryMember> {
+        self.members.clone()
+    }
+
     pub fn to_map(&self) -> HashMap<Uuid, ArtilleryMember> {
         self.members
             .iter()
@@ -49,6 +102,17 @@ impl ArtilleryMemberList {
         panic!("Could not find this instance as registered member");
     }
 
+    pub fn myself(&self) -> Option<&ArtilleryMember> {
+        self.members.iter().find(|m| m.is_current())
+    }
+
+    /// Run id of this node, it changes on every rejoin.
+    pub fn current_run_id(&self) -> Option<Uuid> {
+        self.myself()
+            .and_then(ArtilleryMember::run_id)
+            .map(Uuid::from)
+    }
+
     pub fn reincarnate_self(&mut self) -> ArtilleryMember {
         let myself = self.mut_myself();
         myself.reincarnate();
@@ -56,6 +120,43 @@ impl ArtilleryMemberList {
         myself.clone()
     }
 
+    /// Flags this node degraded or recovered, with a new incarnation so the flag gets gossiped.
+    pub fn set_self_degraded(&mut self, degraded: bool) -> ArtilleryMember {
+        let myself = self.mut_myself();
+        myself.set_degraded(degraded);
+        myself.reincarnate();
+
+        myself.clone()
+    }
+
+    /// Starts or ends the maintenance of this node, with a new incarnation so it gets gossiped.
+    pub fn set_self_maintenance(
+        &mut self,
+        maintenance: bool,
+        eta: Option<DateTime<Utc>>,
+    ) -> ArtilleryMember {
+        let myself = self.mut_myself();
+        myself.set_maintenance(maintenance, eta.map(Timestamp::from));
+        myself.reincarnate();
+
+        myself.clone()
+    }
+
+    /// Replaces the metadata of this node, with a new incarnation so it gets gossiped.
+    pub fn set_self_metadata(&mut self, metadata: HashMap<String, Vec<u8>>) -> ArtilleryMember {
+        let myself = self.mut_myself();
+        myself.set_metadata(metadata);
+        myself.reincarnate();
+
+        myself.clone()
+    }
+
+    pub fn has_alive_remote_members(&self) -> bool {
+        self.members
+            .iter()
+            .any(|m| m.is_remote() && m.state() == ArtilleryMemberState::Alive)
+    }
+
     pub fn leave(&mut self) -> ArtilleryMember {
         let myself = self.mut_myself();
         myself.set_state(ArtilleryMemberState::Left);
@@ -64,6 +165,34 @@ impl ArtilleryMemberList {
         myself.clone()
     }
 
+    pub fn has_left(&self) -> bool {
+        self.members
+            .iter()
+            .any(|m| m.is_current() && m.state() == ArtilleryMemberState::Left)
+    }
+
+    /// Comes back as a new run of this node, with a higher incarnation.
+    pub fn rejoin(&mut self) -> ArtilleryMember {
+        let myself = self.mut_myself();
+        myself.start_new_run();
+
+        myself.clone()
+    }
+
+    /// Removes the member if it is still down or gone at the given incarnation at most.
+    pub fn reap(&mut self, host_key: &Uuid, incarnation: u64) -> Option<ArtilleryMember> {
+        let position = self.members.iter().position(|m| {
+            m.is_remote()
+                && m.host_key() == *host_key
+                && m.incarnation_number() <= incarnation
+                && (m.state() == ArtilleryMemberState::Down
+                    || m.state() == ArtilleryMemberState::Left)
+        })?;
+
+        self.periodic_index = 0;
+        Some(self.members.remove(position))
+    }
+
     pub fn next_random_member(&mut self) -> Option<ArtilleryMember> {
         if self.periodic_index == 0 {
             math::shuffle_linear(&mut self.members);
@@ -80,50 +209,138 @@ impl ArtilleryMemberList {
         }
     }
 
-    pub fn time_out_nodes(
-        &mut self,
-        expired_hosts: &HashSet<SocketAddr>,
-    ) -> (Vec<ArtilleryMember>, Vec<ArtilleryMember>) {
+    /// Reservoir sampling over the live remote members, picks `k` of the ones
+    /// passing the `filter` uniformly at random in a single pass.
+    pub fn sample_members<F>(&self, k: usize, filter: F) -> Vec<ArtilleryMember>
+    where
+        F: Fn(&ArtilleryMember) -> bool,
+    {
+        self.sample_members_with(k, filter, &mut rand::thread_rng())
+    }
+
+    fn sample_members_with<F, R>(&self, k: usize, filter: F, rng: &mut R) -> Vec<ArtilleryMember>
+    where
+        F: Fn(&ArtilleryMember) -> bool,
+        R: Rng,
+    {
+        let mut reservoir: Vec<ArtilleryMember> = Vec::with_capacity(k);
+        if k == 0 {
+            return reservoir;
+        }
+
+        let live = self
+            .members
+            .iter()
+            .filter(|&m| m.is_remote() && m.state() == ArtilleryMemberState::Alive && filter(m));
+
+        for (seen, member) in live.enumerate() {
+            if reservoir.len() < k {
+                reservoir.push(member.clone());
+            } else {
+                let slot = rng.gen_range(0, seen + 1);
+                if slot < k {
+                    reservoir[slot] = member.clone();
+                }
+            }
+        }
+
+        reservoir
+    }
+
+    pub fn suspected_members(&self) -> Vec<ArtilleryMember> {
+        self.members
+            .iter()
+            .filter(|m| m.is_remote() && m.state() == ArtilleryMemberState::Suspect)
+            .cloned()
+            .collect()
+    }
+
+    /// Turns the alive members with expired probes into suspects, returns them.
+    pub fn time_out_nodes(&mut self, expired_hosts: &HashSet<SocketAddr>) -> Vec<ArtilleryMember> {
         let mut suspect_members = Vec::new();
-        let mut down_members = Vec::new();
 
         for member in &mut self.members {
             if let Some(remote_host) = member.remote_host() {
-                if !expired_hosts.contains(&remote_host) {
-                    continue;
+                if expired_hosts.contains(&remote_host)
+                    && member.state() == ArtilleryMemberState::Alive
+                {
+                    member.set_state(ArtilleryMemberState::Suspect);
+                    suspect_members.push(member.clone());
                 }
+            }
+        }
 
-                match member.state() {
-                    ArtilleryMemberState::Alive => {
-                        member.set_state(ArtilleryMemberState::Suspect);
-                        suspect_members.push(member.clone());
-                    }
-                    // TODO: Config suspect timeout
-                    ArtilleryMemberState::Suspect
-                        if member.state_change_older_than(Duration::seconds(3)) =>
-                    {
-                        member.set_state(ArtilleryMemberState::Down);
-                        down_members.push(member.clone());
-                    }
-                    ArtilleryMemberState::Suspect
-                    | ArtilleryMemberState::Down
-                    | ArtilleryMemberState::Left => {}
-                }
+        suspect_members
+    }
+
+    /// Declares a suspect down, other members are left as they are.
+    pub fn mark_down(&mut self, addr: &SocketAddr) -> Option<ArtilleryMember> {
+        for member in &mut self.members {
+            if member.remote_host() == Some(*addr)
+                && member.state() == ArtilleryMemberState::Suspect
+            {
+                member.set_state(ArtilleryMemberState::Down);
+                return Some(member.clone());
             }
         }
 
-        (suspect_members, down_members)
+        None
+    }
+
+    pub fn is_suspect(&self, addr: &SocketAddr) -> bool {
+        self.members
+            .iter()
+            .any(|m| m.remote_host() == Some(*addr) && m.state() == ArtilleryMemberState::Suspect)
     }
 
-    pub fn mark_node_alive(&mut self, src_addr: &SocketAddr) -> Option<ArtilleryMember> {
+    /// Marks the member at `src_addr` alive. A confirmed `Down` member only comes back when
+    /// the sender tells a run other than the confirmed one, i.e. after a restart.
+    pub fn mark_node_alive(
+        &mut self,
+        src_addr: &SocketAddr,
+        run: Option<Uuid>,
+    ) -> Option<ArtilleryMember> {
         for member in &mut self.members {
-            if member.remote_host() == Some(*src_addr)
-                && member.state() != ArtilleryMemberState::Alive
+            if member.remote_host() != Some(*src_addr)
+                || member.state() == ArtilleryMemberState::Alive
             {
-                member.set_state(ArtilleryMemberState::Alive);
+                continue;
+            }
 
+            if !member.is_confirmed_down() {
+                member.set_state(ArtilleryMemberState::Alive);
                 return Some(member.clone());
             }
+
+            return match (member.run_id().map(Uuid::from), run) {
+                (Some(confirmed), Some(restarted)) if confirmed != restarted => {
+                    self.retired_runs
+                        .entry(member.host_key())
+                        .or_default()
+                        .insert(confirmed);
+                    member.revive(restarted);
+                    Some(member.clone())
+                }
+                _ => None,
+            };
+        }
+
+        None
+    }
+
+    /// Sticky transition to `Down` asserted from the outside, skips the suspicion window.
+    pub fn confirm_down(&mut self, id: &Uuid) -> Option<ArtilleryMember> {
+        for member in &mut self.members {
+            if member.host_key() != *id || !member.is_remote() {
+                continue;
+            }
+
+            if member.is_confirmed_down() || member.state() == ArtilleryMemberState::Left {
+                return None;
+            }
+
+            member.confirm_down();
+            return Some(member.clone());
         }
 
         None
@@ -133,11 +350,9 @@ impl ArtilleryMemberList {
         &mut self,
         state_changes: Vec<ArtilleryStateChange>,
         from: &SocketAddr,
-    ) -> (Vec<ArtilleryMember>, Vec<ArtilleryMember>) {
+    ) -> MembershipChanges {
         let mut current_members = self.to_map();
-
-        let mut changed_nodes = Vec::new();
-        let mut new_nodes = Vec::new();
+        let mut changes = MembershipChanges::default();
 
         let my_host_key = self.mut_myself().host_key();
 
@@ -148,11 +363,38 @@ impl ArtilleryMemberList {
             if new_member_data.host_key() == my_host_key {
                 if new_member_data.state() != ArtilleryMemberState::Alive {
                     let myself = self.reincarnate_self();
-                    changed_nodes.push(myself.clone());
+                    changes.changed.push(myself.clone());
                 }
             } else {
                 match old_member_data {
                     Entry::Occupied(mut entry) => {
+                        if self.is_retired_run(new_member_data) {
+                            // Late gossip about a run we already saw restarting
+                            continue;
+                        }
+
+                        if new_member_data.is_restart_of(entry.get()) {
+                            if let Some(old_run) = entry.get().run_id() {
+                                self.retired_runs
+                                    .entry(new_member_data.host_key())
+                                    .or_default()
+                                    .insert(old_run.into());
+                            }
+
+                            let new_host = new_member_data
+                                .remote_host()
+                                .or_else(|| entry.get().remote_host())
+                                .unwrap_or(*from);
+                            let restarted = new_member_data.member_by_changing_host(new_host);
+
+                            if restarted.metadata() != entry.get().metadata() {
+                                changes.metadata.push(restarted.clone());
+                            }
+                            entry.insert(restarted.clone());
+                            changes.restarted.push(restarted);
+                            continue;
+                        }
+
                         let new_member =
                             member::most_uptodate_member_data(new_member_data, entry.get()).clone();
                         let new_host = new_member
@@ -161,9 +403,23 @@ impl ArtilleryMemberList {
                             .unwrap();
                         let new_member = new_member.member_by_changing_host(new_host);
 
-                        if new_member.state() != entry.get().state() {
+                        let state_changed = new_member.state() != entry.get().state();
+                        let refreshed = !state_changed
+                            && new_member.incarnation_number() > entry.get().incarnation_number();
+                        if (state_changed || refreshed)
+                            && new_member.metadata() != entry.get().metadata()
+                        {
+                            changes.metadata.push(new_member.clone());
+                        }
+
+                        if state_changed {
+                            entry.insert(new_member.clone());
+                            changes.changed.push(new_member);
+                        } else if refreshed {
                             entry.insert(new_member.clone());
-                            changed_nodes.push(new_member);
+                            changes.refreshed.push(new_member);
+                        } else {
+                            // Same or older knowledge, nothing to merge
                         }
                     }
                     Entry::Vacant(entry) => {
@@ -171,7 +427,7 @@ impl ArtilleryMemberList {
                         let new_member = new_member_data.member_by_changing_host(new_host);
 
                         entry.insert(new_member.clone());
-                        new_nodes.push(new_member);
+                        changes.new.push(new_member);
                     }
                 };
             }
@@ -179,35 +435,69 @@ impl ArtilleryMemberList {
 
         self.members = current_members.values().cloned().collect();
 
-        (new_nodes, changed_nodes)
+        changes
+    }
+
+    /// Merges the full member list of a peer, from a push/pull state sync. The peer keeps
+    /// no address for itself, its own record is merged only when its address is known.
+    pub fn merge_remote_state(
+        &mut self,
+        mut remote: Vec<ArtilleryStateChange>,
+        from: Option<SocketAddr>,
+    ) -> MembershipChanges {
+        if from.is_none() {
+            remote.retain(|change| change.member().is_remote());
+        }
+        // Only given to the records without an address, none is left without `from`
+        let addr = from.unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], 0)));
+
+        self.apply_state_changes(remote, &addr)
+    }
+
+    fn is_retired_run(&self, member: &ArtilleryMember) -> bool {
+        match (member.run_id(), self.retired_runs.get(&member.host_key())) {
+            (Some(run), Some(retired)) => retired.contains(&run.into()),
+            (None, _) | (_, None) => false,
+        }
     }
 
     ///
     ///
-    /// Random ping enqueuing
+    /// Random ping enqueuing, optionally preferring relays from the zone of the target
     pub fn hosts_for_indirect_ping(
         &self,
         host_count: usize,
         target: &SocketAddr,
+        strategy: IndirectPingStrategy,
     ) -> Vec<SocketAddr> {
         let mut possible_members: Vec<_> = self
             .members
             .iter()
-            .filter_map(|m| {
-                if m.state() == ArtilleryMemberState::Alive
+            .filter(|m| {
+                m.state() == ArtilleryMemberState::Alive
                     && m.is_remote()
                     && m.remote_host() != Some(*target)
-                {
-                    m.remote_host()
-                } else {
-                    None
-                }
             })
             .collect();
 
         math::shuffle_linear(&mut possible_members);
 
-        possible_members.iter().take(host_count).cloned().collect()
+        let target_zone = self
+            .members
+            .iter()
+            .find(|m| m.remote_host() == Some(*target))
+            .and_then(ArtilleryMember::zone);
+
+        if strategy == IndirectPingStrategy::SameZone && target_zone.is_some() {
+            // Stable sort keeps the shuffled order within the groups.
+            possible_members.sort_by_key(|m| m.zone() != target_zone);
+        }
+
+        possible_members
+            .iter()
+            .take(host_count)
+            .filter_map(|m| m.remote_host())
+            .collect()
     }
 
     pub fn has_member(&self, remote_host: &SocketAddr) -> bool {
@@ -216,6 +506,13 @@ impl ArtilleryMemberList {
             .any(|m| m.remote_host() == Some(*remote_host))
     }
 
+    pub fn member_at(&self, remote_host: &SocketAddr) -> Option<ArtilleryMember> {
+        self.members
+            .iter()
+            .find(|m| m.remote_host() == Some(*remote_host))
+            .cloned()
+    }
+
     pub fn add_member(&mut self, member: ArtilleryMember) {
         self.members.push(member)
     }
@@ -236,3 +533,156 @@ impl ArtilleryMemberList {
         Some(member[0].clone())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::state::{
+        ArtilleryClusterRequest, ArtilleryMemberEvent, ArtilleryMessage, EncSocketAddr, Request,
+    };
+    use crate::epidemic::test_kit::*;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    #[test]
+    fn pages_the_members_by_host_key() {
+        let a = TestPeer::new();
+        let b = TestPeer::new();
+        let mut tester = ProtocolTester::new();
+        tester.recv(heartbeat_from(&a)).recv(heartbeat_from(&b));
+
+        let mut host_keys = vec![tester.host_key(), a.id(), b.id()];
+        host_keys.sort();
+        let mut paged = Vec::new();
+        let mut cursor = None;
+        loop {
+            let (tx, rx) = channel();
+            tester.request(ArtilleryClusterRequest::GetMembersPage(cursor, 2, tx));
+            let page = rx.recv().unwrap();
+            assert!(page.members.len() <= 2);
+            paged.extend(page.members.iter().map(ArtilleryMember::host_key));
+            if page.next.is_none() {
+                break;
+            }
+            cursor = page.next.map(Uuid::from);
+        }
+
+        assert_eq!(paged, host_keys);
+    }
+
+    #[test]
+    fn revives_a_confirmed_member_only_from_a_new_run() {
+        let addr: SocketAddr = "127.0.0.1:1337".parse().unwrap();
+        let peer = ArtilleryMember::current(Uuid::new_v4()).member_by_changing_host(addr);
+        let old_run = peer.run_id().map(Uuid::from);
+        let mut members = ArtilleryMemberList::new(ArtilleryMember::current(Uuid::new_v4()));
+        members.apply_state_changes(vec![ArtilleryStateChange::new(peer.clone())], &addr);
+        members.confirm_down(&peer.host_key()).unwrap();
+
+        assert!(members.mark_node_alive(&addr, None).is_none());
+        assert!(members.mark_node_alive(&addr, old_run).is_none());
+
+        let new_run = Uuid::new_v4();
+        let revived = members.mark_node_alive(&addr, Some(new_run)).unwrap();
+        assert_eq!(revived.state(), ArtilleryMemberState::Alive);
+        assert!(!revived.is_confirmed_down());
+        assert_eq!(revived.run_id().map(Uuid::from), Some(new_run));
+        assert!(members.is_retired_run(&peer));
+    }
+
+    #[test]
+    fn samples_every_live_member_alike() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let mut members = ArtilleryMemberList::new(ArtilleryMember::current(Uuid::new_v4()));
+        let changes: Vec<ArtilleryStateChange> = (0..5_u16)
+            .map(|port| {
+                let addr = SocketAddr::from(([127, 0, 0, 1], 7000 + port));
+                let state = if port == 0 {
+                    ArtilleryMemberState::Down
+                } else {
+                    ArtilleryMemberState::Alive
+                };
+                ArtilleryStateChange::new(ArtilleryMember::new(Uuid::new_v4(), addr, 0, state))
+            })
+            .collect();
+        let from = SocketAddr::from(([127, 0, 0, 1], 7000));
+        members.apply_state_changes(changes, &from);
+        let live: HashSet<Uuid> = members
+            .available_nodes()
+            .iter()
+            .filter(|m| m.is_remote() && m.state() == ArtilleryMemberState::Alive)
+            .map(ArtilleryMember::host_key)
+            .collect();
+        assert_eq!(live.len(), 4);
+
+        let mut rng = StdRng::seed_from_u64(929);
+        let mut picks: HashMap<Uuid, usize> = HashMap::new();
+        for _ in 0..400 {
+            let sample = members.sample_members_with(2, |_| true, &mut rng);
+            let distinct: HashSet<Uuid> = sample.iter().map(ArtilleryMember::host_key).collect();
+            assert_eq!(distinct.len(), 2);
+            assert!(distinct.is_subset(&live));
+            for id in distinct {
+                *picks.entry(id).or_default() += 1;
+            }
+        }
+
+        // Each of the 4 members is expected in half of the 400 samples
+        assert_eq!(picks.len(), 4);
+        assert!(picks.values().all(|&n| n > 150 && n < 250), "{:?}", picks);
+        assert_eq!(members.sample_members_with(8, |_| true, &mut rng).len(), 4);
+        assert!(members
+            .sample_members_with(0, |_| true, &mut rng)
+            .is_empty());
+    }
+
+    #[test]
+    fn asks_the_relays_in_the_zone_of_the_target_first() {
+        let (east, west, target) = (TestPeer::new(), TestPeer::new(), TestPeer::new());
+        let in_zone = |peer: &TestPeer, state, zone: &str| {
+            peer.member(state).with_zone(Some(zone.to_string()))
+        };
+        let mut tester = tester_with(|config| {
+            config.ping_request_host_count = 1;
+            config.indirect_ping_strategy = IndirectPingStrategy::SameZone;
+        });
+        tester.recv(heartbeat_from(&east).with_state_changes(vec![
+            in_zone(&east, ArtilleryMemberState::Alive, "east"),
+            in_zone(&west, ArtilleryMemberState::Alive, "west"),
+            in_zone(&target, ArtilleryMemberState::Suspect, "west"),
+        ]));
+        tester.take_sent(&east);
+        tester.take_sent(&west);
+
+        let asked_for_target = |sent: Vec<ArtilleryMessage>| {
+            sent.iter()
+                .any(|m| m.request == Request::Ping(EncSocketAddr(target.addr())))
+        };
+        for _ in 0..5 {
+            tester.tick(Duration::from_millis(200));
+            assert!(asked_for_target(tester.take_sent(&west)));
+            assert!(!asked_for_target(tester.take_sent(&east)));
+        }
+    }
+
+    #[test]
+    fn reports_a_member_gossiped_from_a_new_run_as_restarted() {
+        let (a, b) = (TestPeer::new(), TestPeer::new());
+        let first_run = ArtilleryMember::current(b.id()).member_by_changing_host(b.addr());
+        let mut second_run = first_run.clone();
+        second_run.start_new_run();
+        let restarted = |e: &ArtilleryMemberEvent| matches!(e, ArtilleryMemberEvent::Restarted(m) if m.host_key() == b.id());
+
+        ProtocolTester::new()
+            .recv(heartbeat_from(&a).with_state_changes(vec![first_run.clone()]))
+            .expect_no_event("b restarted on first sight", restarted)
+            .recv(heartbeat_from(&a).with_state_changes(vec![second_run.clone()]))
+            .expect_event("b restarted", restarted)
+            .recv(heartbeat_from(&a).with_state_changes(vec![first_run]))
+            .expect_no_event("b restarted from late gossip", restarted)
+            .recv(heartbeat_from(&a).with_state_changes(vec![second_run]))
+            .expect_no_event("b restarted from the same run", restarted);
+    }
+}
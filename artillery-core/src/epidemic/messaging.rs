@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Application message to a single member, sent again until it is acknowledged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct OutgoingMessage {
+    pub(crate) id: Uuid,
+    pub(crate) member: Uuid,
+    pub(crate) data: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct PendingMessage {
+    message: OutgoingMessage,
+    attempts: usize,
+    next_attempt: Instant,
+}
+
+/// Messages of this node awaiting their acknowledgement, and the messages delivered to
+/// it lately, so that the retries of their senders are delivered once.
+#[derive(Debug)]
+pub(crate) struct Mailbox {
+    retries: usize,
+    retry_interval: Duration,
+    pending: HashMap<Uuid, PendingMessage>,
+    delivered: HashMap<Uuid, Instant>,
+}
+
+impl Mailbox {
+    pub(crate) fn new(retries: usize, retry_interval: Duration) -> Self {
+        Mailbox {
+            retries,
+            retry_interval,
+            pending: HashMap::new(),
+            delivered: HashMap::new(),
+        }
+    }
+
+    /// Awaits the acknowledgement of the message, sent once already.
+    pub(crate) fn sent(&mut self, message: OutgoingMessage, now: Instant) {
+        let pending = PendingMessage {
+            message,
+            attempts: 1,
+            next_attempt: now + self.retry_interval,
+        };
+        self.pending.insert(pending.message.id, pending);
+    }
+
+    pub(crate) fn acknowledged(&mut self, id: Uuid) -> bool {
+        self.pending.remove(&id).is_some()
+    }
+
+    /// Messages to send again, and the unacknowledged ones out of retries.
+    pub(crate) fn due(&mut self, now: Instant) -> (Vec<OutgoingMessage>, Vec<OutgoingMessage>) {
+        let mut retries = Vec::new();
+        let mut exhausted = Vec::new();
+        for (id, pending) in &mut self.pending {
+            if pending.next_attempt > now {
+                continue;
+            }
+
+            if pending.attempts > self.retries {
+                exhausted.push(*id);
+            } else {
+                pending.attempts += 1;
+                pending.next_attempt = now + self.retry_interval;
+                retries.push(pending.message.clone());
+            }
+        }
+
+        let mut failed = Vec::new();
+        for id in exhausted {
+            if let Some(pending) = self.pending.remove(&id) {
+                failed.push(pending.message);
+            }
+        }
+
+        (retries, failed)
+    }
+
+    /// Whether the message is delivered to this node for the first time.
+    pub(crate) fn delivered(&mut self, id: Uuid, now: Instant) -> bool {
+        if self.delivered.contains_key(&id) {
+            return false;
+        }
+
+        self.delivered.insert(id, now);
+        true
+    }
+
+    /// Forgets the delivered messages their senders gave up on.
+    pub(crate) fn prune(&mut self, now: Instant) {
+        let attempts = u32::try_from(self.retries + 2).unwrap_or(u32::MAX);
+        let retention = self
+            .retry_interval
+            .checked_mul(attempts)
+            .unwrap_or(self.retry_interval);
+        self.delivered
+            .retain(|_, &mut at| now.saturating_duration_since(at) <= retention);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::state::{ArtilleryClusterRequest, ArtilleryMemberEvent};
+    use crate::epidemic::test_kit::*;
+
+    #[test]
+    fn retries_until_acknowledged_or_out_of_retries() {
+        let interval = Duration::from_secs(1);
+        let start = Instant::now();
+        let mut mailbox = Mailbox::new(1, interval);
+        let message = |data: &[u8]| OutgoingMessage {
+            id: Uuid::new_v4(),
+            member: Uuid::new_v4(),
+            data: data.to_vec(),
+        };
+        let acked = message(b"acked");
+        let lost = message(b"lost");
+        mailbox.sent(acked.clone(), start);
+        mailbox.sent(lost.clone(), start);
+
+        assert_eq!(mailbox.due(start), (Vec::new(), Vec::new()));
+        let (mut retries, failed) = mailbox.due(start + interval);
+        retries.sort_by_key(|m| m.data.clone());
+        assert_eq!(retries, vec![acked.clone(), lost.clone()]);
+        assert!(failed.is_empty());
+
+        assert!(mailbox.acknowledged(acked.id));
+        assert!(!mailbox.acknowledged(acked.id));
+        assert_eq!(mailbox.due(start + interval * 2), (Vec::new(), vec![lost]));
+        assert!(mailbox.pending.is_empty());
+    }
+
+    #[test]
+    fn delivers_the_retries_once() {
+        let start = Instant::now();
+        let mut mailbox = Mailbox::new(1, Duration::from_secs(1));
+        let id = Uuid::new_v4();
+
+        assert!(mailbox.delivered(id, start));
+        assert!(!mailbox.delivered(id, start));
+        mailbox.prune(start + Duration::from_secs(4));
+        assert!(mailbox.delivered(id, start));
+    }
+
+    #[test]
+    fn acknowledges_every_message_and_delivers_it_once() {
+        let a = TestPeer::new();
+        let id = Uuid::new_v4();
+
+        ProtocolTester::new()
+            .recv(message_from(&a, id, b"hello"))
+            .expect_sent(message_ack_to(&a))
+            .expect_event("message of a", |e| {
+                if let ArtilleryMemberEvent::MemberMessage(m, data) = e {
+                    m.host_key() == a.id() && data == b"hello"
+                } else {
+                    false
+                }
+            })
+            .recv(message_from(&a, id, b"hello"))
+            .expect_sent(message_ack_to(&a))
+            .expect_no_event("message of a again", |e| {
+                matches!(e, ArtilleryMemberEvent::MemberMessage(..))
+            });
+    }
+
+    #[test]
+    fn reports_the_messages_never_acknowledged() {
+        let a = TestPeer::new();
+        let message = Uuid::new_v4();
+        let mut tester = tester_with(|config| {
+            config.message_retries = 1;
+        });
+
+        tester
+            .recv(heartbeat_from(&a))
+            .request(ArtilleryClusterRequest::SendMessage(
+                message,
+                a.id(),
+                b"hello".to_vec(),
+            ))
+            .expect_sent(message_to(&a))
+            .tick(Duration::from_secs(1))
+            .expect_sent(message_to(&a))
+            .tick(Duration::from_secs(1))
+            .expect_event("message undelivered", |e| {
+                matches!(e, ArtilleryMemberEvent::MessageUndelivered(id, member)
+                    if *id == message.into() && *member == a.id().into())
+            });
+    }
+}
@@ -1,16 +1,159 @@
 // As you swim lazily through the milieu,
 // The secrets of the world will infect you.
 
+#[cfg(feature = "internals")]
+pub mod address;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod address;
+#[cfg(feature = "internals")]
+pub mod admin;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod admin;
+#[cfg(feature = "internals")]
+pub mod annotation;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod annotation;
+#[cfg(unix)]
+pub mod bridge;
+#[cfg(feature = "internals")]
+pub mod broadcast;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod broadcast;
+pub mod clock;
 pub mod cluster;
 pub mod cluster_config;
+#[cfg(feature = "internals")]
+pub mod codec;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod codec;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod diagnostics;
+#[cfg(feature = "internals")]
+pub mod digest;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod digest;
+pub mod driver;
+pub mod encryption;
+pub mod event_log;
+pub mod export;
+#[cfg(feature = "internals")]
+pub mod greeting;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod greeting;
+pub mod health;
+pub mod identity;
+pub mod journal;
+#[cfg(feature = "internals")]
+pub mod lanes;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod lanes;
+pub mod lock;
+#[cfg(any(test, feature = "test-kit"))]
+pub mod loopback;
 pub mod member;
+#[cfg(feature = "internals")]
 pub mod membership;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod membership;
+mod messaging;
+#[cfg(feature = "internals")]
+pub mod nat;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod nat;
+pub mod primitives;
+#[cfg(feature = "internals")]
+pub mod reachability;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod reachability;
+#[cfg(feature = "internals")]
+pub mod reaping;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod reaping;
+#[cfg(any(test, feature = "test-kit"))]
+pub mod replay;
+pub mod resolver;
+pub mod rpc;
+pub mod selector;
+mod shutdown;
+#[cfg(feature = "internals")]
 pub mod state;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod state;
+pub mod std_transport;
+pub mod summary;
+#[cfg(feature = "internals")]
+pub mod suspicion;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod suspicion;
+#[cfg(feature = "internals")]
+pub mod tap;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod tap;
+mod tcp_channel;
+#[cfg(any(test, feature = "test-kit"))]
+pub mod test_kit;
+#[cfg(feature = "internals")]
+pub mod traffic;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod traffic;
+#[cfg(feature = "internals")]
+pub mod transaction;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod transaction;
+pub mod transport;
+#[cfg(feature = "internals")]
+pub mod user_payload;
+#[cfg(not(feature = "internals"))]
+pub(crate) mod user_payload;
+pub mod view;
+pub mod wireguard;
+
+#[cfg(test)]
+mod wire_compat;
 
 pub mod prelude {
+    pub use super::address::{PeerAddr, SeedContact};
+    pub use super::annotation::{Annotation, AnnotationPolicy, AnnotationScope};
+    #[cfg(unix)]
+    pub use super::bridge::*;
+    #[cfg(any(test, feature = "test-kit"))]
+    pub use super::clock::AcceleratedClock;
+    pub use super::clock::{Clock, SystemClock};
     pub use super::cluster::*;
     pub use super::cluster_config::*;
+    pub use super::codec::MessageCodec;
+    #[cfg(feature = "compression")]
+    pub use super::compression::*;
+    pub use super::diagnostics::*;
+    pub use super::driver::*;
+    pub use super::encryption::{Encryption, Keyring};
+    pub use super::event_log::*;
+    pub use super::health::ClusterHealth;
+    pub use super::identity::*;
+    pub use super::journal::{ChangeStore, FileChangeStore};
+    pub use super::lock::Lease;
+    #[cfg(any(test, feature = "test-kit"))]
+    pub use super::loopback::*;
     pub use super::member::*;
-    pub use super::membership::*;
-    pub use super::state::*;
+    pub use super::membership::MemberPage;
+    pub use super::nat::RelayStats;
+    pub use super::primitives::{NodeId, OperationId, Timestamp};
+    pub use super::resolver::{Resolver, SystemResolver};
+    pub use super::rpc::RpcHandler;
+    pub use super::selector::*;
+    pub use super::state::{
+        ArtilleryClusterEvent, ArtilleryEventStamp, ArtilleryMemberEvent, HeartbeatCallback,
+        MemberFilter, StateFootprint, WorkStats,
+    };
+    pub use super::std_transport::StdUdpTransport;
+    pub use super::summary::ProtocolRoundSummary;
+    pub use super::tap::{MessageTap, TapDirection, TapRecord};
+    pub use super::traffic::TrafficStats;
+    pub use super::transaction::{RemovalTransaction, RemovalTransactionStatus};
+    pub use super::transport::{DefaultTransport, Transport};
+    pub use super::view::*;
+    pub use super::wireguard::*;
+    #[cfg(feature = "internals")]
+    pub use super::{lanes::*, membership::*, nat::*, state::*, suspicion::*, traffic::*};
 }
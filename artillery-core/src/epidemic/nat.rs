@@ -0,0 +1,267 @@
+use crate::constants::CONST_RELAY_UPGRADE_ROUNDS;
+use serde::*;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Header of a message going through a relay member. The sender only fills `to`, the
+/// relay adds the address it saw the message coming from and forwards it. A message
+/// with `from` set is never relayed again, so messages can't loop between relays.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RelayEnvelope {
+    #[serde(rename = "to")]
+    pub(crate) to: SocketAddr,
+    #[serde(rename = "from", default)]
+    pub(crate) from: Option<SocketAddr>,
+}
+
+impl RelayEnvelope {
+    pub fn to(target: SocketAddr) -> Self {
+        RelayEnvelope {
+            to: target,
+            from: None,
+        }
+    }
+}
+
+/// Snapshot of the relaying done by this node.
+#[derive(Debug, Clone, Default)]
+pub struct RelayStats {
+    /// Members this node reaches through a relay.
+    pub relayed_peers: usize,
+    /// Messages forwarded for other members since the start.
+    pub forwarded: u64,
+    /// Bytes forwarded for other members since the start.
+    pub forwarded_bytes: u64,
+    /// Messages not forwarded because of the cap.
+    pub dropped: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RelayRoute {
+    via: SocketAddr,
+    rounds: usize,
+}
+
+/// Routes to the peers unreachable directly, e.g. behind a NAT. Such a peer is introduced
+/// through a rendezvous member, both sides then send to each other directly to punch
+/// their NATs. Messages go through the rendezvous until a direct message arrives.
+/// Members only answering indirect probes are relayed the same way, through the member
+/// that reached them.
+#[derive(Debug)]
+pub struct NatTraversal {
+    rendezvous: Vec<SocketAddr>,
+    punch_attempts: usize,
+    routes: HashMap<SocketAddr, RelayRoute>,
+    next_rendezvous: usize,
+    /// Indirect probes answered in a row by peers failing the direct ones.
+    indirect_only: HashMap<SocketAddr, usize>,
+    forward_cap: Option<usize>,
+    window_start: Instant,
+    window_forwarded: usize,
+    stats: RelayStats,
+}
+
+impl NatTraversal {
+    pub fn new(
+        rendezvous: Vec<SocketAddr>,
+        punch_attempts: usize,
+        forward_cap: Option<usize>,
+    ) -> Self {
+        NatTraversal {
+            rendezvous,
+            punch_attempts,
+            routes: HashMap::new(),
+            next_rendezvous: 0,
+            indirect_only: HashMap::new(),
+            forward_cap,
+            window_start: Instant::now(),
+            window_forwarded: 0,
+            stats: RelayStats::default(),
+        }
+    }
+
+    /// Starts relaying to a peer that didn't answer, returns the rendezvous introducing
+    /// us. Nothing starts without rendezvous members, or for a rendezvous itself.
+    pub fn start(&mut self, peer: SocketAddr) -> Option<SocketAddr> {
+        if self.rendezvous.is_empty()
+            || self.rendezvous.contains(&peer)
+            || self.routes.contains_key(&peer)
+        {
+            return None;
+        }
+
+        let via = self.rendezvous[self.next_rendezvous % self.rendezvous.len()];
+        self.next_rendezvous += 1;
+        self.routes.insert(peer, RelayRoute { via, rounds: 0 });
+
+        Some(via)
+    }
+
+    /// Peers to punch through to this round, with their rendezvous. Every round for the
+    /// first attempts, then only once in a while to upgrade the route to a direct one.
+    pub fn punch_round(&mut self) -> Vec<(SocketAddr, SocketAddr)> {
+        let attempts = self.punch_attempts;
+
+        self.routes
+            .iter_mut()
+            .filter_map(|(peer, route)| {
+                route.rounds += 1;
+                let punch =
+                    route.rounds <= attempts || route.rounds % CONST_RELAY_UPGRADE_ROUNDS == 0;
+                if punch {
+                    Some((*peer, route.via))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// The peer answered an indirect probe through `via` only. After `threshold` times in a
+    /// row it is reached through `via`, returns whether that route starts now. Relays we
+    /// only reach through another relay aren't used.
+    pub fn indirect_ack(&mut self, peer: SocketAddr, via: SocketAddr, threshold: usize) -> bool {
+        if peer == via || self.routes.contains_key(&peer) || self.routes.contains_key(&via) {
+            return false;
+        }
+
+        let count = self.indirect_only.entry(peer).or_insert(0);
+        *count += 1;
+        if *count < threshold {
+            return false;
+        }
+
+        self.indirect_only.remove(&peer);
+        self.routes.insert(peer, RelayRoute { via, rounds: 0 });
+        true
+    }
+
+    /// The relay of the messages to the peer, if it isn't reachable directly.
+    pub fn relay_for(&self, peer: &SocketAddr) -> Option<SocketAddr> {
+        self.routes.get(peer).map(|route| route.via)
+    }
+
+    /// The peer reached us through a relay, the answers take the same way back.
+    pub fn relayed_from(&mut self, peer: SocketAddr, via: SocketAddr) {
+        self.routes
+            .entry(peer)
+            .or_insert(RelayRoute { via, rounds: 0 })
+            .via = via;
+    }
+
+    /// A message came directly from the peer, returns whether it was relayed so far.
+    pub fn direct(&mut self, peer: &SocketAddr) -> bool {
+        self.indirect_only.remove(peer);
+        self.routes.remove(peer).is_some()
+    }
+
+    /// Accounts a message to forward for another member, false if over the cap.
+    pub fn admit_forward(&mut self, bytes: usize) -> bool {
+        if self.window_start.elapsed() >= Duration::from_secs(1) {
+            self.window_start = Instant::now();
+            self.window_forwarded = 0;
+        }
+
+        if self
+            .forward_cap
+            .is_some_and(|cap| self.window_forwarded >= cap)
+        {
+            self.stats.dropped += 1;
+            return false;
+        }
+
+        self.window_forwarded += 1;
+        self.stats.forwarded += 1;
+        self.stats.forwarded_bytes += bytes as u64;
+        true
+    }
+
+    pub fn stats(&self) -> RelayStats {
+        RelayStats {
+            relayed_peers: self.routes.len(),
+            ..self.stats.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::member::ArtilleryMemberState;
+    use crate::epidemic::test_kit::*;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn relays_until_the_peer_is_reached_directly() {
+        let rendezvous = addr(7000);
+        let peer = addr(7001);
+        let mut nat = NatTraversal::new(vec![rendezvous], 2, None);
+
+        assert_eq!(nat.start(rendezvous), None);
+        assert_eq!(nat.start(peer), Some(rendezvous));
+        assert_eq!(nat.start(peer), None);
+        assert_eq!(nat.relay_for(&peer), Some(rendezvous));
+
+        assert_eq!(nat.punch_round(), vec![(peer, rendezvous)]);
+        assert_eq!(nat.punch_round(), vec![(peer, rendezvous)]);
+        assert!(nat.punch_round().is_empty());
+        assert_eq!(nat.relay_for(&peer), Some(rendezvous));
+
+        assert!(nat.direct(&peer));
+        assert!(!nat.direct(&peer));
+        assert_eq!(nat.relay_for(&peer), None);
+    }
+
+    #[test]
+    fn answers_through_the_relay_of_the_peer() {
+        let mut nat = NatTraversal::new(Vec::new(), 2, None);
+
+        assert_eq!(nat.start(addr(7001)), None);
+        nat.relayed_from(addr(7001), addr(7000));
+        assert_eq!(nat.relay_for(&addr(7001)), Some(addr(7000)));
+    }
+
+    #[test]
+    fn relays_after_consistent_indirect_acks_only() {
+        let relay = addr(7000);
+        let peer = addr(7001);
+        let mut nat = NatTraversal::new(Vec::new(), 2, Some(1));
+
+        assert!(!nat.indirect_ack(peer, relay, 2));
+        nat.direct(&peer);
+        assert!(!nat.indirect_ack(peer, relay, 2));
+        assert!(nat.indirect_ack(peer, relay, 2));
+        assert_eq!(nat.relay_for(&peer), Some(relay));
+
+        // No route through a member reached through a relay itself
+        assert!(!nat.indirect_ack(addr(7002), peer, 1));
+        assert!(!nat.indirect_ack(relay, relay, 1));
+
+        assert!(nat.admit_forward(100));
+        assert!(!nat.admit_forward(100));
+        let stats = nat.stats();
+        assert_eq!(
+            (stats.forwarded, stats.dropped, stats.relayed_peers),
+            (1, 1, 1)
+        );
+    }
+
+    #[test]
+    fn relays_and_introduces_members() {
+        let a = TestPeer::new();
+        let b = TestPeer::new();
+
+        ProtocolTester::new()
+            .recv(
+                heartbeat_from(&a).with_state_changes(vec![b.member(ArtilleryMemberState::Alive)]),
+            )
+            .recv(heartbeat_from(&a).relayed_to(&b))
+            .expect_sent(relayed_message_to(&b))
+            .recv(introduce_from(&a, &b))
+            .expect_sent(punch_request_to(&b));
+    }
+}
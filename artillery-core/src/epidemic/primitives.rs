@@ -0,0 +1,147 @@
+use crate::errors::*;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::*;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Identity of a cluster member, the host key. Wraps the key so that applications don't
+/// have to depend on the `uuid` version used by this crate, conversions exist for those who do.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(transparent)]
+pub struct NodeId(Uuid);
+
+impl NodeId {
+    pub fn random() -> Self {
+        NodeId(Uuid::new_v4())
+    }
+
+    pub fn from_bytes(bytes: [u8; 16]) -> Self {
+        NodeId(Uuid::from_bytes(bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        self.0.as_bytes()
+    }
+}
+
+impl From<Uuid> for NodeId {
+    fn from(key: Uuid) -> Self {
+        NodeId(key)
+    }
+}
+
+impl From<NodeId> for Uuid {
+    fn from(id: NodeId) -> Self {
+        id.0
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl FromStr for NodeId {
+    type Err = ArtilleryError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Uuid::parse_str(s.trim()).map(NodeId).map_err(|e| {
+            ArtilleryError::InvalidArgument(format!("Node id '{}' isn't valid: {}", s, e))
+        })
+    }
+}
+
+/// Id of an operation started through the API: a message, a removal transaction, a shutdown
+/// order. Wraps the key like [`NodeId`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(transparent)]
+pub struct OperationId(Uuid);
+
+impl OperationId {
+    pub(crate) fn random() -> Self {
+        OperationId(Uuid::new_v4())
+    }
+}
+
+impl From<Uuid> for OperationId {
+    fn from(key: Uuid) -> Self {
+        OperationId(key)
+    }
+}
+
+impl From<OperationId> for Uuid {
+    fn from(id: OperationId) -> Self {
+        id.0
+    }
+}
+
+impl fmt::Display for OperationId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// Wall clock time gossiped by the protocol, in UTC. Wraps the `chrono` type like
+/// [`NodeId`] wraps the key.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(transparent)]
+pub struct Timestamp(DateTime<Utc>);
+
+impl Timestamp {
+    pub fn now() -> Self {
+        Timestamp(Utc::now())
+    }
+
+    /// `None` when out of the representable range.
+    pub fn from_unix_millis(millis: i64) -> Option<Self> {
+        Utc.timestamp_millis_opt(millis).single().map(Timestamp)
+    }
+
+    pub fn unix_millis(&self) -> i64 {
+        self.0.timestamp_millis()
+    }
+}
+
+impl From<DateTime<Utc>> for Timestamp {
+    fn from(time: DateTime<Utc>) -> Self {
+        Timestamp(time)
+    }
+}
+
+impl From<Timestamp> for DateTime<Utc> {
+    fn from(time: Timestamp) -> Self {
+        time.0
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0.to_rfc3339())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn converts_to_and_from_the_wrapped_types() {
+        let key = Uuid::new_v4();
+        let id = NodeId::from(key);
+        assert_eq!(Uuid::from(id), key);
+        assert_eq!(id.to_string().parse::<NodeId>().unwrap(), id);
+        assert!("not-a-key".parse::<NodeId>().is_err());
+        assert_eq!(
+            serde_json::to_string(&id).unwrap(),
+            serde_json::to_string(&key).unwrap()
+        );
+
+        let time = Timestamp::from_unix_millis(1_600_000_000_123).unwrap();
+        assert_eq!(time.unix_millis(), 1_600_000_000_123);
+        assert_eq!(Timestamp::from(DateTime::<Utc>::from(time)), time);
+    }
+}
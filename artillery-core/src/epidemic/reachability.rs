@@ -0,0 +1,235 @@
+use super::broadcast::BroadcastQueue;
+use super::member::{ArtilleryMember, ArtilleryMemberState};
+use chrono::{DateTime, Duration, Utc};
+use serde::*;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// Members an observer can't reach directly as of `observed_at`. An empty list withdraws
+/// the previous report of the observer.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReachabilityReport {
+    #[serde(rename = "o")]
+    observer: Uuid,
+    #[serde(rename = "u")]
+    unreachable: Vec<Uuid>,
+    #[serde(rename = "t")]
+    observed_at: DateTime<Utc>,
+}
+
+impl ReachabilityReport {
+    pub(crate) fn new(observer: Uuid, unreachable: Vec<Uuid>, observed_at: DateTime<Utc>) -> Self {
+        ReachabilityReport {
+            observer,
+            unreachable,
+            observed_at,
+        }
+    }
+}
+
+/// Change of how a member is reached across the cluster.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ReachabilityChange {
+    /// The member is reached by live members, but not by these observers.
+    Partial(Uuid, Vec<Uuid>),
+    /// Every live member reaches the member again. Not reported when nobody reaches it
+    /// anymore, the failure detection takes over then.
+    Restored(Uuid),
+}
+
+/// Latest report of every observer and their dissemination queue. Tells a member
+/// unreachable from part of the cluster only apart from a member nobody reaches.
+#[derive(Debug)]
+pub(crate) struct Reachability {
+    reports: HashMap<Uuid, ReachabilityReport>,
+    queue: BroadcastQueue<ReachabilityReport>,
+    refresh: Duration,
+    partial: HashMap<Uuid, Vec<Uuid>>,
+}
+
+impl Reachability {
+    pub fn new(retransmits: usize, refresh: std::time::Duration) -> Self {
+        Reachability {
+            reports: HashMap::new(),
+            queue: BroadcastQueue::new(retransmits),
+            refresh: Duration::from_std(refresh).unwrap_or(Duration::MAX),
+            partial: HashMap::new(),
+        }
+    }
+
+    /// Merges the report, returns whether it is newer than the known one of the observer.
+    pub fn apply(&mut self, report: ReachabilityReport) -> bool {
+        if let Some(known) = self.reports.get(&report.observer) {
+            if report.observed_at <= known.observed_at {
+                return false;
+            }
+        }
+
+        let observer = report.observer;
+        self.reports.insert(observer, report.clone());
+        self.queue
+            .push_superseding(report, |pending| pending.observer == observer);
+
+        true
+    }
+
+    /// Publishes the observation of this node when it changed, and again once in a while
+    /// as long as it isn't empty, for the members joining later.
+    pub fn observe(&mut self, myself: Uuid, mut unreachable: Vec<Uuid>, now: DateTime<Utc>) {
+        unreachable.sort();
+        unreachable.dedup();
+
+        let publish = match self.reports.get(&myself) {
+            Some(own) => {
+                own.unreachable != unreachable
+                    || (!unreachable.is_empty() && now - own.observed_at >= self.refresh)
+            }
+            None => !unreachable.is_empty(),
+        };
+
+        if publish {
+            self.apply(ReachabilityReport::new(myself, unreachable, now));
+        }
+    }
+
+    /// Members unreachable from some of the live members only, compared to the last call.
+    /// Reports of observers which aren't members anymore are forgotten.
+    pub fn changes(&mut self, members: &[ArtilleryMember]) -> Vec<ReachabilityChange> {
+        let known: HashSet<Uuid> = members.iter().map(ArtilleryMember::host_key).collect();
+        self.reports.retain(|observer, _| known.contains(observer));
+
+        let live: Vec<Uuid> = members
+            .iter()
+            .filter_map(|m| match m.state() {
+                ArtilleryMemberState::Alive | ArtilleryMemberState::Suspect => Some(m.host_key()),
+                ArtilleryMemberState::Down | ArtilleryMemberState::Left => None,
+            })
+            .collect();
+
+        let mut partial = HashMap::new();
+        let mut unreachable = HashSet::new();
+        for target in &live {
+            let observers: Vec<Uuid> = live
+                .iter()
+                .filter(|observer| {
+                    *observer != target
+                        && self
+                            .reports
+                            .get(*observer)
+                            .is_some_and(|r| r.unreachable.contains(target))
+                })
+                .copied()
+                .collect();
+
+            if observers.is_empty() {
+                continue;
+            }
+            unreachable.insert(*target);
+            if observers.len() + 1 < live.len() {
+                partial.insert(*target, observers);
+            }
+        }
+
+        let mut changes: Vec<ReachabilityChange> = partial
+            .iter()
+            .filter(|&(target, observers)| self.partial.get(target) != Some(observers))
+            .map(|(target, observers)| ReachabilityChange::Partial(*target, observers.clone()))
+            .collect();
+        changes.extend(
+            self.partial
+                .keys()
+                .filter(|&target| live.contains(target) && !unreachable.contains(target))
+                .map(|target| ReachabilityChange::Restored(*target)),
+        );
+
+        self.partial = partial;
+        changes
+    }
+
+    pub fn next_batch(&mut self, max: usize) -> Vec<ReachabilityReport> {
+        self.queue.next_batch(max)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::state::ArtilleryMemberEvent;
+    use crate::epidemic::test_kit::*;
+    use std::net::SocketAddr;
+
+    fn alive(host_key: Uuid, port: u16) -> ArtilleryMember {
+        ArtilleryMember::new(
+            host_key,
+            SocketAddr::from(([127, 0, 0, 1], port)),
+            0,
+            ArtilleryMemberState::Alive,
+        )
+    }
+
+    #[test]
+    fn tells_partial_unreachability_from_failure() {
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let members = vec![alive(a, 7001), alive(b, 7002), alive(c, 7003)];
+        let mut reachability = Reachability::new(3, std::time::Duration::from_secs(30));
+        let now = Utc::now();
+
+        reachability.observe(a, vec![c], now);
+        assert_eq!(
+            reachability.changes(&members),
+            vec![ReachabilityChange::Partial(c, vec![a])]
+        );
+        assert!(reachability.changes(&members).is_empty());
+
+        // Nobody reaches c, that is an ordinary failure
+        assert!(reachability.apply(ReachabilityReport::new(b, vec![c], now)));
+        assert!(reachability.changes(&members).is_empty());
+
+        reachability.observe(a, Vec::new(), now + Duration::seconds(1));
+        assert_eq!(
+            reachability.changes(&members),
+            vec![ReachabilityChange::Partial(c, vec![b])]
+        );
+        assert!(!reachability.apply(ReachabilityReport::new(b, Vec::new(), now)));
+        assert!(reachability.apply(ReachabilityReport::new(
+            b,
+            Vec::new(),
+            now + Duration::seconds(1)
+        )));
+        assert_eq!(
+            reachability.changes(&members),
+            vec![ReachabilityChange::Restored(c)]
+        );
+        assert_eq!(reachability.next_batch(8).len(), 2);
+    }
+
+    #[test]
+    fn reports_the_members_unreachable_from_some_peers() {
+        let (a, b, c) = (TestPeer::new(), TestPeer::new(), TestPeer::new());
+        let mut tester = tester_with(|config| {
+            config.track_reachability = true;
+        });
+        tester
+            .recv(heartbeat_from(&a))
+            .recv(heartbeat_from(&b))
+            .recv(heartbeat_from(&c))
+            .drain_events();
+
+        let (b_id, a_id) = (b.id(), a.id());
+        tester
+            .recv(heartbeat_from(&a).with_reachability(&[&b]))
+            .tick(std::time::Duration::from_secs(1))
+            .expect_event("b unreachable from a", move |e| {
+                matches!(e, ArtilleryMemberEvent::PartiallyUnreachable(m, observers)
+                    if m.host_key() == b_id
+                        && observers.iter().map(ArtilleryMember::host_key).eq(vec![a_id]))
+            });
+
+        tester
+            .recv(heartbeat_from(&a).with_reachability(&[]))
+            .tick(std::time::Duration::from_secs(1))
+            .expect_event("b reached again", move |e| {
+                matches!(e, ArtilleryMemberEvent::ReachabilityRestored(m) if m.host_key() == b_id)
+            });
+    }
+}
@@ -0,0 +1,173 @@
+use super::broadcast::BroadcastQueue;
+use super::member::ArtilleryMember;
+use super::primitives::NodeId;
+use chrono::{DateTime, Duration, Utc};
+use serde::*;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Announcement that a down or left member is removed from the member lists at `reap_at`,
+/// so that every node forgets it at about the same time.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReapNotice {
+    #[serde(rename = "m")]
+    member: Uuid,
+    /// Latest incarnation of the member covered by the removal.
+    #[serde(rename = "i")]
+    incarnation: u64,
+    #[serde(rename = "t")]
+    reap_at: DateTime<Utc>,
+}
+
+impl ReapNotice {
+    pub(crate) fn new(member: &ArtilleryMember, reap_at: DateTime<Utc>) -> Self {
+        ReapNotice {
+            member: member.host_key(),
+            incarnation: member.incarnation_number(),
+            reap_at,
+        }
+    }
+
+    pub fn member(&self) -> NodeId {
+        self.member.into()
+    }
+
+    pub fn incarnation(&self) -> u64 {
+        self.incarnation
+    }
+}
+
+/// Scheduled removals and the tombstones of the reaped members. Tombstones keep stale
+/// gossip from resurrecting a member after it was removed.
+#[derive(Debug)]
+pub(crate) struct Reaper {
+    pending: HashMap<Uuid, ReapNotice>,
+    tombstones: HashMap<Uuid, (ArtilleryMember, DateTime<Utc>)>,
+    queue: BroadcastQueue<ReapNotice>,
+}
+
+impl Reaper {
+    pub fn new(retransmits: usize) -> Self {
+        Reaper {
+            pending: HashMap::new(),
+            tombstones: HashMap::new(),
+            queue: BroadcastQueue::new(retransmits),
+        }
+    }
+
+    pub fn is_scheduled(&self, member: &Uuid) -> bool {
+        self.pending.contains_key(member)
+    }
+
+    /// Merges the notice, returns whether it changed the schedule. Covering a newer
+    /// incarnation wins, otherwise the earliest removal time, so all nodes converge.
+    pub fn apply(&mut self, notice: ReapNotice) -> bool {
+        if let Some(known) = self.pending.get(&notice.member) {
+            let supersedes = notice.incarnation > known.incarnation
+                || (notice.incarnation == known.incarnation && notice.reap_at < known.reap_at);
+            if !supersedes {
+                return false;
+            }
+        }
+
+        let member = notice.member;
+        self.pending.insert(member, notice.clone());
+        self.queue
+            .push_superseding(notice, |pending| pending.member == member);
+
+        true
+    }
+
+    /// Removals due at `now`, they are not scheduled anymore.
+    pub fn due(&mut self, now: DateTime<Utc>) -> Vec<ReapNotice> {
+        let due: Vec<ReapNotice> = self
+            .pending
+            .values()
+            .filter(|n| n.reap_at <= now)
+            .cloned()
+            .collect();
+
+        for notice in &due {
+            self.pending.remove(&notice.member);
+        }
+
+        due
+    }
+
+    pub fn bury(&mut self, member: ArtilleryMember, until: DateTime<Utc>) {
+        self.tombstones.insert(member.host_key(), (member, until));
+    }
+
+    /// Whether the record is gossip from before the removal of the member. A newer
+    /// incarnation or a new run of the member is genuine.
+    pub fn is_buried(&self, member: &ArtilleryMember) -> bool {
+        self.tombstones
+            .get(&member.host_key())
+            .is_some_and(|(reaped, _)| {
+                member.incarnation_number() <= reaped.incarnation_number()
+                    && !member.is_restart_of(reaped)
+            })
+    }
+
+    pub fn prune(&mut self, now: DateTime<Utc>) {
+        self.tombstones.retain(|_, (_, until)| *until > now);
+    }
+
+    pub fn next_batch(&mut self, max: usize) -> Vec<ReapNotice> {
+        self.queue.next_batch(max)
+    }
+}
+
+/// Retention of a tombstone as a chrono duration, saturating on overflow.
+pub(crate) fn tombstone_until(now: DateTime<Utc>, retention: std::time::Duration) -> DateTime<Utc> {
+    Duration::from_std(retention)
+        .ok()
+        .and_then(|span| now.checked_add_signed(span))
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::member::ArtilleryMemberState;
+
+    fn down_member(incarnation: u64) -> ArtilleryMember {
+        ArtilleryMember::new(
+            Uuid::nil(),
+            "127.0.0.1:40001".parse().unwrap(),
+            incarnation,
+            ArtilleryMemberState::Down,
+        )
+    }
+
+    #[test]
+    fn earliest_removal_wins() {
+        let now = Utc::now();
+        let member = down_member(2);
+        let mut reaper = Reaper::new(3);
+
+        assert!(reaper.apply(ReapNotice::new(&member, now + Duration::seconds(30))));
+        assert!(reaper.apply(ReapNotice::new(&member, now + Duration::seconds(10))));
+        assert!(!reaper.apply(ReapNotice::new(&member, now + Duration::seconds(20))));
+
+        assert!(reaper.due(now + Duration::seconds(5)).is_empty());
+        let due = reaper.due(now + Duration::seconds(10));
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].reap_at, now + Duration::seconds(10));
+        assert!(!reaper.is_scheduled(&member.host_key()));
+    }
+
+    #[test]
+    fn tombstones_reject_stale_gossip_only() {
+        let now = Utc::now();
+        let mut reaper = Reaper::new(3);
+        reaper.bury(down_member(2), now + Duration::seconds(60));
+
+        assert!(reaper.is_buried(&down_member(2)));
+        assert!(reaper.is_buried(&down_member(1)));
+        assert!(!reaper.is_buried(&down_member(3)));
+
+        reaper.prune(now + Duration::seconds(61));
+        assert!(!reaper.is_buried(&down_member(2)));
+    }
+}
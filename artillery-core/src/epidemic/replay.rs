@@ -0,0 +1,189 @@
+//!
+//! Replay of recorded traffic into a fresh cluster state machine.
+//!
+//! Inbound records of a [`MessageTap`] trace are fed in order, with the protocol clock
+//! following the recorded timestamps, so a production incident can be reproduced
+//! and turned into a test. Nothing goes out to the network, sent messages are collected
+//! into the report instead. Random member selection isn't part of a trace, so probe
+//! targets might differ from the recorded run.
+//!
+//! [`MessageTap`]: super::tap::MessageTap
+use super::cluster_config::ClusterConfig;
+use super::lanes::{request_lanes, RequestReceiver};
+use super::primitives::{NodeId, Timestamp};
+use super::state::*;
+use super::tap::{TapDirection, TapRecord};
+use super::transport::DefaultTransport;
+use crate::errors::*;
+use chrono::{DateTime, Utc};
+use mio::Poll;
+use std::net::SocketAddr;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+/// Outcome of a replay.
+pub struct ReplayReport {
+    pub events: Vec<ArtilleryClusterEvent>,
+    pub sent: Vec<TapRecord>,
+}
+
+pub struct TrafficReplay {
+    state: ArtilleryEpidemic,
+    config: ClusterConfig,
+    requests: RequestReceiver,
+    events: Receiver<ArtilleryClusterEvent>,
+    sent: Receiver<TapRecord>,
+    last_timestamp: Option<DateTime<Utc>>,
+    since_tick: Duration,
+    _poll: Poll,
+}
+
+impl TrafficReplay {
+    /// Instance standing in for the recorded node `host_key`, configured like it.
+    pub fn new<I: Into<NodeId>>(host_key: I, recorded: ClusterConfig) -> Result<Self> {
+        let config = ClusterConfig {
+            listen_addr: SocketAddr::from(([127, 0, 0, 1], 0)),
+            message_tap: None,
+            // Gaps of the recording are skipped over, not a suspended host
+            suspend_detection_threshold: None,
+            ..recorded
+        };
+        let (event_tx, events) = channel();
+        let (internal_tx, requests) = request_lanes(&config);
+        let (sent_tx, sent) = channel();
+
+        let socket = DefaultTransport::bind(config.listen_addr)?;
+        let (poll, mut state) = ArtilleryEpidemic::with_transport(
+            host_key.into().into(),
+            config.clone(),
+            Box::new(socket),
+            event_tx,
+            internal_tx,
+        )?;
+        state.divert_outbound(sent_tx);
+
+        Ok(TrafficReplay {
+            state,
+            config,
+            requests,
+            events,
+            sent,
+            last_timestamp: None,
+            since_tick: Duration::from_secs(0),
+            _poll: poll,
+        })
+    }
+
+    /// Feeds one record, outbound records are only used to move the clock.
+    pub fn feed(&mut self, record: &TapRecord) -> Result<()> {
+        self.advance_to(record.timestamp);
+
+        match record.direction {
+            TapDirection::Inbound => {
+                let message = decode_datagram(&self.config, &record.bytes)?;
+                self.state.respond_to_message(record.peer, message);
+                self.state.drain_requests(&self.requests);
+            }
+            TapDirection::Outbound => {}
+        }
+
+        Ok(())
+    }
+
+    pub fn run(mut self, records: &[TapRecord]) -> Result<ReplayReport> {
+        for record in records {
+            self.feed(record)?;
+        }
+
+        Ok(self.finish())
+    }
+
+    pub fn finish(self) -> ReplayReport {
+        ReplayReport {
+            events: self.events.try_iter().collect(),
+            sent: self.sent.try_iter().collect(),
+        }
+    }
+
+    fn advance_to(&mut self, timestamp: Timestamp) {
+        let at = DateTime::<Utc>::from(timestamp);
+        let elapsed = self
+            .last_timestamp
+            .and_then(|last| at.signed_duration_since(last).to_std().ok())
+            .unwrap_or_default();
+        self.last_timestamp = Some(at);
+
+        self.state.advance_clock(elapsed);
+        self.since_tick += elapsed;
+
+        while self.since_tick >= self.config.ping_interval {
+            self.since_tick -= self.config.ping_interval;
+            self.state.tick();
+            self.state.drain_requests(&self.requests);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::codec::MessageCodec;
+    use crate::epidemic::member::{ArtilleryMember, ArtilleryMemberState, ArtilleryStateChange};
+    use chrono::Duration as ChronoDuration;
+    use uuid::Uuid;
+
+    #[test]
+    fn replays_inbound_heartbeats() {
+        let config = ClusterConfig::default();
+        let peer = "127.0.0.1:40002".parse().unwrap();
+        let peer_id = Uuid::new_v4();
+        let message = ArtilleryMessage {
+            sender: peer_id,
+            cluster_name: config.cluster_name.clone(),
+            cluster_key: config.cluster_key.clone(),
+            request: Request::Heartbeat,
+            state_changes: vec![ArtilleryStateChange::new(ArtilleryMember::new(
+                peer_id,
+                peer,
+                0,
+                ArtilleryMemberState::Alive,
+            ))],
+            heartbeat: None,
+            transactions: Vec::new(),
+            annotations: Vec::new(),
+            reaps: Vec::new(),
+            reachability: Vec::new(),
+            relay: None,
+            locks: Vec::new(),
+            run: None,
+            digest: None,
+            shutdown: Vec::new(),
+            hello: None,
+            payloads: Vec::new(),
+        };
+        let start = Utc::now();
+        let records: Vec<TapRecord> = (0..3)
+            .map(|i| TapRecord {
+                direction: TapDirection::Inbound,
+                peer,
+                timestamp: (start + ChronoDuration::seconds(i * 2)).into(),
+                bytes: MessageCodec::Json.encode(&message).unwrap(),
+            })
+            .collect();
+
+        let report = TrafficReplay::new(Uuid::new_v4(), config)
+            .unwrap()
+            .run(&records)
+            .unwrap();
+
+        let mut acks = 0;
+        for record in report.sent.iter().filter(|r| r.peer == peer) {
+            let sent: ArtilleryMessage = MessageCodec::Json.decode(&record.bytes).unwrap();
+            if sent.request == Request::Ack {
+                acks += 1;
+            }
+        }
+        assert_eq!(acks, 3);
+        assert!(!report.events.is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+use super::address::PeerAddr;
+use super::lanes::RequestSender;
+use super::state::ArtilleryClusterRequest;
+use crate::errors::*;
+use crate::logging;
+use std::fmt::Debug;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+use std::thread;
+
+/// Name resolution of the peers given by host name, e.g. through a service registry
+/// instead of the system DNS. Called off the event loop, it may block.
+pub trait Resolver: Debug + Send + Sync {
+    /// Every address of the host, empty when it has none.
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>>;
+}
+
+/// Resolves through the system resolver, as configured for the host.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+        Ok((host, port).to_socket_addrs()?.collect())
+    }
+}
+
+/// Thread resolving on behalf of the event loop, the addresses come back as a
+/// `SeedResolved` request. Exits once the worker is dropped.
+pub(crate) struct ResolverWorker {
+    jobs: Sender<PeerAddr>,
+}
+
+impl ResolverWorker {
+    pub(crate) fn spawn(
+        resolver: Arc<dyn Resolver>,
+        instance_name: String,
+        requests: RequestSender,
+    ) -> io::Result<Self> {
+        let (jobs, pending) = channel::<PeerAddr>();
+
+        thread::Builder::new()
+            .name(format!("{}-resolver", instance_name))
+            .spawn(move || {
+                let _instance = logging::enter_instance(instance_name);
+                for addr in pending {
+                    let addrs = addr.resolve_with(resolver.as_ref()).unwrap_or_else(|e| {
+                        log_transport!(Warn, "Unable to resolve seed {}: {}", addr, e);
+                        Vec::new()
+                    });
+                    if requests
+                        .send(ArtilleryClusterRequest::SeedResolved(addr, addrs))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            })?;
+
+        Ok(ResolverWorker { jobs })
+    }
+
+    /// Queues the resolution, returns false if the worker is gone.
+    pub(crate) fn resolve(&self, addr: PeerAddr) -> bool {
+        self.jobs.send(addr).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::cluster_config::ClusterConfig;
+    use crate::epidemic::lanes::request_lanes;
+    use std::time::{Duration, Instant};
+
+    #[derive(Debug)]
+    struct FixedResolver(SocketAddr);
+
+    impl Resolver for FixedResolver {
+        fn resolve(&self, _host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+            Ok(vec![SocketAddr::new(self.0.ip(), port)])
+        }
+    }
+
+    #[test]
+    fn delivers_the_addresses_as_requests() {
+        let (requests, resolved) = request_lanes(&ClusterConfig::default());
+        let fixed = "10.0.0.7:0".parse::<SocketAddr>().unwrap();
+        let worker =
+            ResolverWorker::spawn(Arc::new(FixedResolver(fixed)), "test".into(), requests).unwrap();
+
+        let seed = PeerAddr::host("seed.internal", 7946);
+        assert!(worker.resolve(seed.clone()));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let request = loop {
+            if let Ok(request) = resolved.try_recv_control() {
+                break request;
+            }
+            assert!(
+                Instant::now() < deadline,
+                "Expected the seed to be resolved"
+            );
+            thread::sleep(Duration::from_millis(10));
+        };
+        if let ArtilleryClusterRequest::SeedResolved(addr, addrs) = request {
+            assert_eq!(addr, seed);
+            assert_eq!(addrs, vec!["10.0.0.7:7946".parse::<SocketAddr>().unwrap()]);
+        } else {
+            panic!("Expected the seed to be resolved");
+        }
+    }
+}
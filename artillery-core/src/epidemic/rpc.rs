@@ -0,0 +1,204 @@
+use super::member::ArtilleryMember;
+use crate::errors::*;
+use std::collections::HashMap;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::Instant;
+use uuid::Uuid;
+
+/// Handler of the calls of a method made with `Cluster::call`, run on the cluster thread
+/// with the calling member and the request body. Answers with the response body, or a
+/// failure reported to the caller. Keep it lightweight, it runs inside the event loop.
+pub type RpcHandler =
+    Arc<dyn Fn(&ArtilleryMember, &[u8]) -> std::result::Result<Vec<u8>, String> + Send + Sync>;
+
+/// Response body, or the failure of the callee, carried back to the caller.
+pub(crate) type RpcResponse = std::result::Result<Vec<u8>, String>;
+
+struct PendingCall {
+    member: Uuid,
+    reply: Sender<Result<Vec<u8>>>,
+    deadline: Instant,
+}
+
+/// Handlers of this node by method, and the routes of the responses to the calls it
+/// made by correlation id.
+#[derive(Default)]
+pub(crate) struct RpcRouter {
+    handlers: HashMap<String, RpcHandler>,
+    pending: HashMap<Uuid, PendingCall>,
+}
+
+impl RpcRouter {
+    /// Replaces the handler of the method, `None` removes it.
+    pub(crate) fn register(&mut self, method: String, handler: Option<RpcHandler>) {
+        match handler {
+            Some(registered) => self.handlers.insert(method, registered),
+            None => self.handlers.remove(&method),
+        };
+    }
+
+    pub(crate) fn handle(
+        &self,
+        method: &str,
+        caller: &ArtilleryMember,
+        body: &[u8],
+    ) -> RpcResponse {
+        match self.handlers.get(method) {
+            Some(handler) => handler(caller, body),
+            None => Err(format!("no handler for the method '{}'", method)),
+        }
+    }
+
+    pub(crate) fn await_response(
+        &mut self,
+        id: Uuid,
+        member: Uuid,
+        reply: Sender<Result<Vec<u8>>>,
+        deadline: Instant,
+    ) {
+        self.pending.insert(
+            id,
+            PendingCall {
+                member,
+                reply,
+                deadline,
+            },
+        );
+    }
+
+    /// Hands the response to the caller, if it comes from the member called and in time.
+    pub(crate) fn respond(&mut self, id: Uuid, from: Uuid, response: RpcResponse) -> bool {
+        if self.pending.get(&id).is_none_or(|call| call.member != from) {
+            return false;
+        }
+
+        if let Some(call) = self.pending.remove(&id) {
+            let _ = call.reply.send(response.map_err(ArtilleryError::Rpc));
+        }
+        true
+    }
+
+    /// Fails the calls which were not answered in time.
+    pub(crate) fn expire(&mut self, now: Instant) {
+        let expired: Vec<Uuid> = self
+            .pending
+            .iter()
+            .filter(|(_, call)| call.deadline <= now)
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in expired {
+            if let Some(call) = self.pending.remove(&id) {
+                let _ = call.reply.send(Err(ArtilleryError::RpcTimeout(format!(
+                    "call {} to {} unanswered",
+                    id, call.member
+                ))));
+            }
+        }
+    }
+
+    /// Fails the pending calls to the member, e.g. once it is down.
+    pub(crate) fn fail_calls_to(&mut self, member: Uuid, reason: &str) {
+        let failed: Vec<Uuid> = self
+            .pending
+            .iter()
+            .filter(|(_, call)| call.member == member)
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in failed {
+            if let Some(call) = self.pending.remove(&id) {
+                let _ = call.reply.send(Err(ArtilleryError::Rpc(format!(
+                    "call {} to {} failed: {}",
+                    id, member, reason
+                ))));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::state::ArtilleryClusterRequest;
+    use crate::epidemic::test_kit::*;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    #[test]
+    fn routes_the_responses_to_their_callers() {
+        let now = Instant::now();
+        let callee = Uuid::new_v4();
+        let mut router = RpcRouter::default();
+        router.register(
+            "echo".to_string(),
+            Some(Arc::new(|_: &ArtilleryMember, body: &[u8]| {
+                Ok(body.to_vec())
+            })),
+        );
+
+        let caller = ArtilleryMember::current(Uuid::new_v4());
+        assert_eq!(router.handle("echo", &caller, b"hi"), Ok(b"hi".to_vec()));
+        assert!(router.handle("missing", &caller, b"hi").is_err());
+
+        let (tx, rx) = channel();
+        let answered = Uuid::new_v4();
+        router.await_response(answered, callee, tx.clone(), now + Duration::from_secs(1));
+        assert!(!router.respond(answered, Uuid::new_v4(), Ok(Vec::new())));
+        assert!(router.respond(answered, callee, Ok(b"hi".to_vec())));
+        assert_eq!(rx.recv().unwrap().unwrap(), b"hi".to_vec());
+
+        router.await_response(Uuid::new_v4(), callee, tx, now + Duration::from_secs(1));
+        router.expire(now);
+        assert!(rx.try_recv().is_err());
+        router.expire(now + Duration::from_secs(1));
+        assert!(matches!(
+            rx.recv().unwrap(),
+            Err(ArtilleryError::RpcTimeout(_))
+        ));
+    }
+
+    #[test]
+    fn answers_the_calls_of_registered_methods() {
+        let a = TestPeer::new();
+        let upper: RpcHandler =
+            Arc::new(|_: &ArtilleryMember, body: &[u8]| Ok(body.to_ascii_uppercase()));
+
+        ProtocolTester::new()
+            .request(ArtilleryClusterRequest::OnRpc(
+                "upper".to_string(),
+                Some(upper),
+            ))
+            .recv(rpc_call_from(&a, Uuid::new_v4(), "upper", b"hello"))
+            .expect_sent(rpc_reply_to(&a))
+            .recv(rpc_call_from(&a, Uuid::new_v4(), "lower", b"HELLO"))
+            .expect_sent(rpc_failure_to(&a));
+    }
+
+    #[test]
+    fn times_out_the_unanswered_calls() {
+        let a = TestPeer::new();
+        let (tx, rx) = channel();
+        let mut tester = ProtocolTester::new();
+
+        tester
+            .recv(heartbeat_from(&a))
+            .request(ArtilleryClusterRequest::Call(
+                a.id(),
+                "upper".to_string(),
+                b"hello".to_vec(),
+                Duration::from_secs(2),
+                tx,
+            ))
+            .expect_sent(rpc_call_to(&a))
+            .tick(Duration::from_secs(1));
+        assert!(rx.try_recv().is_err());
+
+        tester.tick(Duration::from_secs(1));
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(Err(ArtilleryError::RpcTimeout(_)))
+        ));
+    }
+}
@@ -0,0 +1,182 @@
+use crate::errors::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Label of a member carrying its zone, next to its annotations.
+pub const ZONE_LABEL: &str = "zone";
+
+/// Conjunction of `key=value` label terms, e.g. `role=storage,zone=eu-west`. The labels of
+/// a member are its annotations and its zone. An empty selector matches every member.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selector {
+    terms: Vec<(String, String)>,
+}
+
+impl Selector {
+    pub fn terms(&self) -> &[(String, String)] {
+        &self.terms
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.terms
+            .iter()
+            .all(|(key, value)| labels.get(key) == Some(value))
+    }
+}
+
+impl FromStr for Selector {
+    type Err = ArtilleryError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut terms = Vec::new();
+
+        for term in s.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            let mut parts = term.splitn(2, '=');
+            let key = parts.next().unwrap_or_default().trim();
+
+            match parts.next().map(str::trim) {
+                Some(value) if !key.is_empty() && !value.is_empty() => {
+                    terms.push((key.to_string(), value.to_string()))
+                }
+                Some(_) | None => {
+                    return Err(ArtilleryError::InvalidArgument(format!(
+                        "Selector term '{}' isn't key=value",
+                        term
+                    )))
+                }
+            }
+        }
+
+        Ok(Selector { terms })
+    }
+}
+
+/// Members by label, updated member by member so that selections don't scan the
+/// whole member list.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LabelIndex {
+    labels: HashMap<Uuid, BTreeMap<String, String>>,
+    index: HashMap<(String, String), HashSet<Uuid>>,
+}
+
+impl LabelIndex {
+    /// Replaces the labels of the member, only the changed ones are reindexed.
+    pub fn set(&mut self, member: Uuid, labels: BTreeMap<String, String>) {
+        let previous = self.labels.remove(&member).unwrap_or_default();
+
+        for (key, value) in &previous {
+            if labels.get(key) == Some(value) {
+                continue;
+            }
+
+            let label = (key.clone(), value.clone());
+            if let Some(members) = self.index.get_mut(&label) {
+                members.remove(&member);
+                if members.is_empty() {
+                    self.index.remove(&label);
+                }
+            }
+        }
+
+        for (key, value) in &labels {
+            if previous.get(key) != Some(value) {
+                self.index
+                    .entry((key.clone(), value.clone()))
+                    .or_default()
+                    .insert(member);
+            }
+        }
+
+        if !labels.is_empty() {
+            self.labels.insert(member, labels);
+        }
+    }
+
+    pub fn remove(&mut self, member: &Uuid) {
+        self.set(*member, BTreeMap::new());
+    }
+
+    pub fn labels(&self, member: &Uuid) -> Option<&BTreeMap<String, String>> {
+        self.labels.get(member)
+    }
+
+    /// Members carrying every label of a non-empty selector.
+    pub fn select(&self, selector: &Selector) -> HashSet<Uuid> {
+        let mut candidates = Vec::with_capacity(selector.terms.len());
+        for (key, value) in &selector.terms {
+            match self.index.get(&(key.clone(), value.clone())) {
+                Some(members) => candidates.push(members),
+                None => return HashSet::new(),
+            }
+        }
+        candidates.sort_by_key(|members| members.len());
+
+        let mut remaining = candidates.iter();
+        let smallest = match remaining.next() {
+            Some(smallest) => smallest,
+            None => return HashSet::new(),
+        };
+        let others: Vec<_> = remaining.collect();
+
+        smallest
+            .iter()
+            .filter(|member| others.iter().all(|members| members.contains(*member)))
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_selectors() {
+        let selector: Selector = " role=storage, zone=eu-west ".parse().unwrap();
+
+        assert_eq!(selector.terms().len(), 2);
+        assert!(selector.matches(&labels(&[
+            ("role", "storage"),
+            ("zone", "eu-west"),
+            ("tier", "hot")
+        ])));
+        assert!(!selector.matches(&labels(&[("role", "storage")])));
+        assert!("".parse::<Selector>().unwrap().is_empty());
+
+        assert!("role".parse::<Selector>().is_err());
+        assert!("role=".parse::<Selector>().is_err());
+        assert!("=storage".parse::<Selector>().is_err());
+    }
+
+    #[test]
+    fn index_follows_label_changes() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let storage_eu: Selector = "role=storage,zone=eu-west".parse().unwrap();
+        let mut index = LabelIndex::default();
+
+        index.set(a, labels(&[("role", "storage"), ("zone", "eu-west")]));
+        index.set(b, labels(&[("role", "storage"), ("zone", "us-east")]));
+        assert_eq!(index.select(&storage_eu), vec![a].into_iter().collect());
+
+        index.set(b, labels(&[("role", "storage"), ("zone", "eu-west")]));
+        assert_eq!(index.select(&storage_eu).len(), 2);
+
+        index.remove(&a);
+        index.set(b, labels(&[("role", "compute"), ("zone", "eu-west")]));
+        assert!(index.select(&storage_eu).is_empty());
+        assert!(index.labels(&a).is_none());
+        assert!(!index.index.contains_key(&("role".into(), "storage".into())));
+    }
+}
@@ -0,0 +1,165 @@
+//!
+//! Exit handshake between the cluster handle and the event loop.
+//!
+//! Primitives come from `loom` when built with `--cfg loom`, so the handshake can be model
+//! checked: `RUSTFLAGS="--cfg loom" cargo test --release shutdown`.
+
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicBool, Ordering};
+#[cfg(not(loom))]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+
+/// Replying side of an exit request.
+pub(crate) trait ExitNotifier {
+    /// Never fails, embedded clusters don't wait for the reply.
+    fn notify(&self);
+}
+
+impl ExitNotifier for Sender<()> {
+    fn notify(&self) {
+        let _ = self.send(());
+    }
+}
+
+#[cfg(loom)]
+impl ExitNotifier for loom::sync::mpsc::Sender<()> {
+    fn notify(&self) {
+        let _ = self.send(());
+    }
+}
+
+/// Running flag of the event loop.
+#[derive(Debug)]
+pub(crate) struct ShutdownLatch {
+    running: AtomicBool,
+}
+
+impl ShutdownLatch {
+    pub(crate) fn new() -> Self {
+        ShutdownLatch {
+            running: AtomicBool::new(true),
+        }
+    }
+
+    pub(crate) fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Stops the event loop without a requester, returns whether it was running.
+    pub(crate) fn stop(&self) -> bool {
+        self.running.swap(false, Ordering::SeqCst)
+    }
+
+    /// Stops the event loop and replies to the requester. Every request is answered,
+    /// returns whether this one was the first to stop the loop.
+    pub(crate) fn acknowledge_exit<N: ExitNotifier>(&self, notifier: &N) -> bool {
+        let first = self.running.swap(false, Ordering::SeqCst);
+        notifier.notify();
+        first
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn only_first_exit_stops_the_loop() {
+        let latch = ShutdownLatch::new();
+        let (tx, rx) = channel();
+
+        assert!(latch.acknowledge_exit(&tx));
+        assert!(!latch.acknowledge_exit(&tx));
+        assert!(!latch.is_running());
+        assert_eq!(rx.try_iter().count(), 2);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_test {
+    use super::*;
+    use loom::sync::mpsc::{channel, Receiver, Sender};
+    use loom::sync::Arc;
+    use loom::thread;
+
+    enum Request {
+        Work,
+        Exit(Sender<()>),
+    }
+
+    /// Event loop reduced to the request handling, returns the number of loops stopped.
+    fn event_loop(latch: &ShutdownLatch, requests: &Receiver<Request>) -> usize {
+        let mut stopped = 0;
+        while latch.is_running() {
+            match requests.recv() {
+                Ok(Request::Work) => {}
+                Ok(Request::Exit(tx)) => {
+                    if latch.acknowledge_exit(&tx) {
+                        stopped += 1;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        stopped
+    }
+
+    #[test]
+    fn exit_reply_is_never_lost() {
+        loom::model(|| {
+            let latch = Arc::new(ShutdownLatch::new());
+            let (comm, requests) = channel();
+
+            let worker = comm.clone();
+            let sender = thread::spawn(move || {
+                let _ = worker.send(Request::Work);
+            });
+
+            let event_loop_latch = latch.clone();
+            let event_loop = thread::spawn(move || event_loop(&event_loop_latch, &requests));
+
+            let (tx, rx) = channel();
+            comm.send(Request::Exit(tx)).unwrap();
+            rx.recv().unwrap();
+            assert!(!latch.is_running());
+
+            sender.join().unwrap();
+            assert_eq!(event_loop.join().unwrap(), 1);
+        });
+    }
+
+    #[test]
+    fn concurrent_exits_stop_once() {
+        loom::model(|| {
+            let latch = ShutdownLatch::new();
+            let (comm, requests) = channel();
+
+            let handles: Vec<_> = (0..2)
+                .map(|_| {
+                    let handle_comm = comm.clone();
+                    thread::spawn(move || {
+                        let (tx, rx) = channel();
+                        handle_comm.send(Request::Exit(tx)).unwrap();
+                        rx.recv().unwrap();
+                    })
+                })
+                .collect();
+
+            let mut stopped = 0;
+            for _ in 0..2 {
+                if let Request::Exit(tx) = requests.recv().unwrap() {
+                    if latch.acknowledge_exit(&tx) {
+                        stopped += 1;
+                    }
+                }
+            }
+            assert_eq!(stopped, 1);
+
+            for handle in handles {
+                handle.join().unwrap();
+            }
+        });
+    }
+}
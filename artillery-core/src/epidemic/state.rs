@@ -1,76 +1,279 @@
-use super::cluster_config::ClusterConfig;
-use super::membership::ArtilleryMemberList;
-use crate::epidemic::member::{ArtilleryMember, ArtilleryMemberState, ArtilleryStateChange};
+use super::address::{peer_addr_for, HostSeed, PeerAddr, SeedContact, SeedTracker};
+use super::admin::{ShutdownOrder, ShutdownOrders};
+use super::annotation::{Annotation, Annotations};
+#[cfg(unix)]
+use super::bridge::EventBridge;
+use super::clock::{Clock, SystemClock};
+use super::cluster_config::{
+    ClusterConfig, EventConsumerPolicy, ExternalSignals, InitialMemberState, StateChangeOverflow,
+};
+use super::codec::{self, MessageCodec, PADDING_HEADER};
+use super::diagnostics::ClusterDiagnostic;
+use super::digest::MemberDigest;
+use super::encryption::{self, Encryption, GossipCipher, Keyring};
+use super::event_log::EventLog;
+use super::greeting::Greeting;
+use super::health::{ClusterHealth, HealthTracker};
+use super::journal::ChangeJournal;
+use super::lanes::{RequestReceiver, RequestSender};
+use super::lock::{Lease, Locks};
+use super::membership::{ArtilleryMemberList, MemberPage, MembershipChanges};
+use super::messaging::{Mailbox, OutgoingMessage};
+use super::nat::{NatTraversal, RelayEnvelope, RelayStats};
+use super::primitives::{NodeId, OperationId, Timestamp};
+use super::reachability::{Reachability, ReachabilityChange, ReachabilityReport};
+use super::reaping::{self, ReapNotice, Reaper};
+use super::resolver::{ResolverWorker, SystemResolver};
+use super::rpc::{RpcHandler, RpcResponse, RpcRouter};
+use super::selector::Selector;
+use super::shutdown::ShutdownLatch;
+use super::summary::{ProtocolRoundSummary, RoundTracker};
+use super::suspicion::{SuspicionPolicy, SuspicionTracker};
+use super::tap::{TapDirection, TapRecord};
+use super::tcp_channel::{TcpExchange, TcpProgress};
+use super::traffic::{TrafficAccount, TrafficStats};
+use super::transaction::{RemovalTransaction, RemovalTransactionStatus, RemovalTransactions};
+use super::transport::Transport;
+use super::user_payload::{UserPayload, UserPayloads};
+use super::view::SharedMembershipView;
+use super::wireguard;
+use crate::epidemic::member::{
+    ArtilleryMember, ArtilleryMemberState, ArtilleryStateChange, TransitionReason,
+};
 use crate::errors::*;
+use bastion_utils::math;
 use chrono::{DateTime, Utc};
 use cuneiform_fields::prelude::*;
-use mio::net::UdpSocket;
-use mio::{Events, Interest, Poll, Token};
+use mio::net::{TcpListener, TcpStream, UdpSocket};
+use mio::{Events, Interest, Poll, Registry, Token};
 use serde::*;
-use std::collections::hash_map::Entry;
-use std::collections::{HashMap, HashSet};
-use std::convert::TryFrom;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io;
-use std::net::SocketAddr;
-use std::sync::mpsc::{Receiver, Sender};
-use std::time::Duration;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::mpsc::{sync_channel, Receiver, Sender, SyncSender};
+use std::sync::Arc;
 use uuid::Uuid;
 
-use std::sync::atomic::AtomicBool;
-use std::sync::atomic::Ordering;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use kaos::flunk;
 
 use crate::constants::*;
 
-pub type ArtilleryClusterEvent = (Vec<ArtilleryMember>, ArtilleryMemberEvent);
-pub type WaitList = HashMap<SocketAddr, Vec<SocketAddr>>;
+/// Emission stamp of a cluster event.
+/// Sequence ids increase monotonically per cluster instance, a gap means missed events.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArtilleryEventStamp {
+    pub seq: u64,
+    /// Time of the emission on the cluster thread.
+    pub timestamp: Timestamp,
+}
+
+pub type ArtilleryClusterEvent = (
+    Vec<ArtilleryMember>,
+    ArtilleryMemberEvent,
+    ArtilleryEventStamp,
+);
+/// Members waiting for the answer of an indirect probe, by target, with their request time.
+pub type WaitList = HashMap<SocketAddr, Vec<(Instant, SocketAddr)>>;
+pub type MemberFilter = Arc<dyn Fn(&ArtilleryMember) -> bool + Send + Sync>;
+pub type HeartbeatCallback = Arc<dyn Fn(&ArtilleryMember, &[u8]) + Send + Sync>;
 
-#[derive(Debug)]
+#[derive(Serialize, Debug, Clone)]
 pub enum ArtilleryMemberEvent {
     Joined(ArtilleryMember),
     WentUp(ArtilleryMember),
-    SuspectedDown(ArtilleryMember),
-    WentDown(ArtilleryMember),
+    SuspectedDown(ArtilleryMember, TransitionReason),
+    WentDown(ArtilleryMember, TransitionReason),
     Left(ArtilleryMember),
     Payload(ArtilleryMember, String),
+    RemovalTransactionChanged(RemovalTransaction),
+    /// The member came back with a new run id, the process restarted.
+    Restarted(ArtilleryMember),
+    /// The member restarted this many times within the window, it is likely crash-looping.
+    MemberRestartStorm(ArtilleryMember, usize, Duration),
+    /// A down or left member was removed from the member list.
+    Reaped(ArtilleryMember),
+    /// The member is reached by part of the cluster, but not by these members.
+    PartiallyUnreachable(ArtilleryMember, Vec<ArtilleryMember>),
+    /// The member partially unreachable so far is reached by every live member again.
+    ReachabilityRestored(ArtilleryMember),
+    /// An annotation of a member was written or removed.
+    AnnotationChanged(Annotation),
+    /// This node couldn't send or receive anything for a while, it flagged itself degraded.
+    LocalConnectivityLost,
+    /// Traffic flows again, this node is rejoining.
+    LocalConnectivityRestored,
+    /// Another running node at this address uses the host key of this node.
+    IdentityConflict(SocketAddr),
+    /// A lease of this node was overruled by the lease of another holder.
+    LockLost(Lease),
+    /// A seed answered for the first time.
+    SeedContacted(SeedContact),
+    /// This node obeys the shutdown order with this id, it leaves and stops shortly.
+    ShutdownOrdered(OperationId),
+    /// Ticks of this node keep running late, by this much the last time. It flagged itself
+    /// degraded, its failure detection can't be trusted.
+    EventLoopSlow(Duration),
+    /// Ticks are on time again.
+    EventLoopRecovered,
+    /// The member updated its metadata, see `ArtilleryMember::metadata`.
+    MemberMetadataChanged(ArtilleryMember),
+    /// Activity of the round which just ended, see `round_summaries`.
+    ProtocolRoundSummary(ProtocolRoundSummary),
+    /// Payload the member broadcast with `Cluster::broadcast_payload`.
+    MemberPayload(ArtilleryMember, Vec<u8>),
+    /// Message the member sent to this node with `Cluster::send_to`.
+    MemberMessage(ArtilleryMember, Vec<u8>),
+    /// The message of `Cluster::send_to` with this id didn't reach the member with the
+    /// other id: it is unknown, not live or didn't acknowledge any of the retries.
+    MessageUndelivered(OperationId, NodeId),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ArtilleryMessage {
-    sender: Uuid,
-    cluster_key: Vec<u8>,
-    request: Request,
-    state_changes: Vec<ArtilleryStateChange>,
+    pub(crate) sender: Uuid,
+    #[serde(default)]
+    pub(crate) cluster_name: String,
+    pub(crate) cluster_key: Vec<u8>,
+    pub(crate) request: Request,
+    pub(crate) state_changes: Vec<ArtilleryStateChange>,
+    #[serde(default)]
+    pub(crate) heartbeat: Option<Vec<u8>>,
+    #[serde(default)]
+    pub(crate) transactions: Vec<RemovalTransaction>,
+    #[serde(default)]
+    pub(crate) annotations: Vec<Annotation>,
+    #[serde(default)]
+    pub(crate) reaps: Vec<ReapNotice>,
+    #[serde(default)]
+    pub(crate) reachability: Vec<ReachabilityReport>,
+    #[serde(default)]
+    pub(crate) relay: Option<RelayEnvelope>,
+    #[serde(default)]
+    pub(crate) locks: Vec<Lease>,
+    /// Run id of the sender, tells two nodes using the same host key apart.
+    #[serde(default)]
+    pub(crate) run: Option<Uuid>,
+    /// Membership summary of a pull gossip ping.
+    #[serde(default)]
+    pub(crate) digest: Option<MemberDigest>,
+    #[serde(default)]
+    pub(crate) shutdown: Vec<ShutdownOrder>,
+    /// Self-description of the sender, until the recipient acknowledged a ping.
+    #[serde(default)]
+    pub(crate) hello: Option<Greeting>,
+    #[serde(default)]
+    pub(crate) payloads: Vec<UserPayload>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
-struct EncSocketAddr(SocketAddr);
+pub(crate) struct EncSocketAddr(pub(crate) SocketAddr);
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
-enum Request {
+pub(crate) enum Request {
     Heartbeat,
     Ack,
     Ping(EncSocketAddr),
     AckHost(ArtilleryMember),
+    /// Asks a rendezvous to introduce the sender to the peer at the address.
+    Introduce(EncSocketAddr),
+    /// Asks to send to the peer at the address directly, punching the NATs in between.
+    Punch(EncSocketAddr),
     Payload(Uuid, String),
+    Sync {
+        members: Vec<ArtilleryMember>,
+        reply: bool,
+    },
+    /// Summary of part of the membership of the sender, multicast on LANs.
+    Digest(MemberDigest),
+    /// Asks for the records of these members, answered with a `Sync`.
+    Pull(Vec<Uuid>),
+    /// Application message for the recipient, acknowledged with a `MessageAck`.
+    Message(Uuid, Vec<u8>),
+    MessageAck(Uuid),
+    /// Call of a method by correlation id, answered with an `RpcReply` of the same id.
+    RpcCall(Uuid, String, Vec<u8>),
+    RpcReply(Uuid, RpcResponse),
 }
 
 #[derive(Debug, Clone)]
 pub struct TargetedRequest {
-    request: Request,
-    target: SocketAddr,
+    pub(crate) request: Request,
+    pub(crate) target: SocketAddr,
 }
 
 #[derive(Clone)]
 pub enum ArtilleryClusterRequest {
-    AddSeed(SocketAddr),
-    Respond(SocketAddr, ArtilleryMessage),
+    AddSeed(PeerAddr),
+    SeedResolved(PeerAddr, Vec<SocketAddr>),
+    Respond(SocketAddr, Box<ArtilleryMessage>),
     React(TargetedRequest),
     LeaveCluster,
+    Rejoin,
+    ConfirmDown(Uuid),
+    ExternalFailure(Uuid),
+    ExternalAlive(Uuid),
+    InstallKey(Vec<u8>, Sender<Result<()>>),
+    UseKey(Vec<u8>, Sender<Result<()>>),
+    RemoveKey(Vec<u8>, Sender<Result<()>>),
+    Annotate(Uuid, String, Option<String>),
+    TryLock(String, Duration, Sender<Option<Lease>>),
+    Unlock(String),
+    LockHolder(String, Sender<Option<Lease>>),
+    ImportMembers(Vec<(NodeId, SocketAddr)>),
+    SampleMembers(usize, MemberFilter, Sender<Vec<ArtilleryMember>>),
+    PendingOutbound(Sender<usize>),
+    SeedContacts(Sender<Vec<SeedContact>>),
+    OrderShutdown(String, Sender<Option<OperationId>>),
+    LocalMember(Sender<ArtilleryMember>),
+    AdvertisedAddr(Sender<SocketAddr>),
+    GetMembers(Sender<Vec<ArtilleryMember>>),
+    /// Page of the members after the cursor, at most this many.
+    GetMembersPage(Option<Uuid>, usize, Sender<MemberPage>),
+    Health(Sender<ClusterHealth>),
+    SetHeartbeatPayload(Option<Vec<u8>>),
+    SetMaintenance(bool, Option<DateTime<Utc>>),
+    UpdateMetadata(HashMap<String, Vec<u8>>),
+    OnHeartbeatPayload(HeartbeatCallback),
+    ScheduleRemoval(Uuid, Vec<Uuid>),
+    FinishRemoval(Uuid, RemovalTransactionStatus),
     Exit(Sender<()>),
     Payload(Uuid, String),
+    BroadcastPayload(Vec<u8>),
+    SendMessage(Uuid, Uuid, Vec<u8>),
+    Call(Uuid, String, Vec<u8>, Duration, Sender<Result<Vec<u8>>>),
+    OnRpc(String, Option<RpcHandler>),
+}
+
+/// Sizes of the protocol buffers, which stay bounded on a healthy node whatever the uptime.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StateFootprint {
+    pub members: usize,
+    pub state_changes: usize,
+    /// Indirect probe requesters waiting for an answer, over all the targets.
+    pub wait_list: usize,
+    pub pending_responses: usize,
+    pub deferred_work: usize,
+}
+
+/// Counters of the overload shedding done by the event loop.
+#[derive(Debug, Clone, Default)]
+pub struct WorkStats {
+    /// Bulk requests processed after being deferred.
+    pub processed: u64,
+    /// Bulk requests dropped because the deferred queue was full.
+    pub shed: u64,
+    /// Bulk requests waiting for the next iterations.
+    pub backlog: usize,
+    /// State changes dropped because the state change buffer was full.
+    pub dropped_state_changes: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WorkClass {
+    Critical,
+    Bulk,
 }
 
 const UDP_SERVER: Token = Token(0);
@@ -80,480 +283,4178 @@ pub struct ArtilleryEpidemic {
     config: ClusterConfig,
     members: ArtilleryMemberList,
     seed_queue: Vec<SocketAddr>,
-    pending_responses: Vec<(DateTime<Utc>, SocketAddr, Vec<ArtilleryStateChange>)>,
+    /// Every seed ever added, contacted again on rejoin.
+    known_seeds: Vec<SocketAddr>,
+    /// Seeds given by host name, contacted until answered and kept for rejoins.
+    host_seeds: Vec<HostSeed>,
+    /// Started with the first host name to resolve.
+    resolver: Option<ResolverWorker>,
+    seed_tracker: SeedTracker,
+    /// Next attempt and current delay of contacting the seeds after a rejoin.
+    rejoin_backoff: Option<(Instant, Duration)>,
+    /// A member acked or synced since the rejoin started.
+    rejoin_answered: bool,
+    pending_responses: Vec<(Instant, SocketAddr, Vec<ArtilleryStateChange>)>,
     state_changes: Vec<ArtilleryStateChange>,
     wait_list: WaitList,
-    server_socket: UdpSocket,
-    request_tx: ArchPadding<Sender<ArtilleryClusterRequest>>,
+    server_socket: Box<dyn Transport>,
+    /// Joined to the digest multicast group, registered with the same token as the gossip socket.
+    multicast_socket: Option<UdpSocket>,
+    /// Position in the member list of the next multicast digest.
+    digest_cursor: usize,
+    /// Records the senders of pull gossip pings lack, sent with the next ack to them.
+    pull_answers: HashMap<SocketAddr, Vec<ArtilleryStateChange>>,
+    request_tx: ArchPadding<RequestSender>,
+    /// Requests of the state machine to itself, handled before the queued ones.
+    reactions: VecDeque<ArtilleryClusterRequest>,
     event_tx: ArchPadding<Sender<ArtilleryClusterEvent>>,
-    running: AtomicBool,
+    deferred_work: VecDeque<ArtilleryClusterRequest>,
+    work_stats: WorkStats,
+    reported_shed: u64,
+    traffic: TrafficAccount,
+    heartbeat_payload: Option<Vec<u8>>,
+    heartbeat_callback: Option<HeartbeatCallback>,
+    removal_transactions: RemovalTransactions,
+    annotations: Annotations,
+    reaper: Reaper,
+    shutdown_orders: ShutdownOrders,
+    user_payloads: UserPayloads,
+    /// Set once this node obeys a shutdown order, it stops by then at the latest.
+    ordered_shutdown_at: Option<Instant>,
+    reachability: Reachability,
+    locks: Locks,
+    mailbox: Mailbox,
+    rpc: RpcRouter,
+    nat: NatTraversal,
+    last_full_sync: Instant,
+    /// Set when state changes were dropped, the next tick syncs the full state.
+    full_sync_requested: bool,
+    /// Receives the messages of the peers too large for datagrams, and their state syncs.
+    tcp_listener: Option<TcpListener>,
+    tcp_exchanges: Vec<TcpExchange>,
+    event_seq: u64,
+    change_seq: u64,
+    #[cfg(unix)]
+    event_bridge: Option<EventBridge>,
+    restart_history: HashMap<Uuid, VecDeque<Instant>>,
+    health: HealthTracker,
+    rounds: RoundTracker,
+    suspicions: SuspicionTracker,
+    view: SharedMembershipView,
+    event_log: EventLog,
+    journal: Option<ChangeJournal>,
+    /// Peers which acknowledged a ping, they don't need our greeting anymore.
+    greeted: HashSet<SocketAddr>,
+    burst_rounds_left: usize,
+    last_inbound: Instant,
+    send_failing_since: Option<Instant>,
+    last_tick: Option<(Instant, DateTime<Utc>)>,
+    resume_grace_until: Option<Instant>,
+    connectivity_lost: bool,
+    /// Late and on time ticks in a row, for the event loop watchdog.
+    late_ticks: usize,
+    timely_ticks: usize,
+    loop_slow: bool,
+    diagnostics_tx: SyncSender<ClusterDiagnostic>,
+    diagnostics_rx: Option<Receiver<ClusterDiagnostic>>,
+    clock: Arc<dyn Clock>,
+    clock_offset: Duration,
+    outbound_sink: Option<Sender<TapRecord>>,
+    shutdown: ShutdownLatch,
+    /// Addresses gossiped for our host key by another run, probed once each.
+    identity_probes: HashSet<SocketAddr>,
+    /// Address of this run as gossiped by the peers.
+    advertised_addr: Option<SocketAddr>,
+    identity_conflict: Option<SocketAddr>,
+    event_consumer_gone: bool,
 }
 
 pub type ClusterReactor = (Poll, ArtilleryEpidemic);
 
 impl ArtilleryEpidemic {
-    pub fn new(
+    /// State machine gossiping over the given transport. The listen address of the
+    /// configuration becomes the local address of the transport.
+    pub fn with_transport(
         host_key: Uuid,
-        config: ClusterConfig,
+        mut config: ClusterConfig,
+        mut server_socket: Box<dyn Transport>,
         event_tx: Sender<ArtilleryClusterEvent>,
-        internal_tx: Sender<ArtilleryClusterRequest>,
+        internal_tx: RequestSender,
     ) -> Result<ClusterReactor> {
+        config.validate()?;
+        config.listen_addr = server_socket.local_addr()?;
+        let (listen_addr, dual_stack) = (config.listen_addr, config.dual_stack);
+        for (_, addr) in &mut config.initial_members {
+            *addr = peer_addr_for(listen_addr, *addr, dual_stack)
+                .map_err(ArtilleryError::InvalidConfiguration)?;
+        }
+        for addr in &mut config.rendezvous_members {
+            *addr = peer_addr_for(listen_addr, *addr, dual_stack)
+                .map_err(ArtilleryError::InvalidConfiguration)?;
+        }
         let poll: Poll = Poll::new()?;
+        server_socket.register(poll.registry(), UDP_SERVER)?;
+        let mut multicast_socket = config.multicast_digests.map(join_multicast).transpose()?;
+        if let Some(socket) = multicast_socket.as_mut() {
+            Transport::register(socket, poll.registry(), UDP_SERVER)?;
+        }
+        let mut tcp_listener = if config.tcp_sync || config.tcp_fallback {
+            Some(TcpListener::bind(config.listen_addr)?)
+        } else {
+            None
+        };
+        if let Some(listener) = tcp_listener.as_mut() {
+            poll.registry()
+                .register(listener, UDP_SERVER, Interest::READABLE)?;
+        }
+
+        let me = ArtilleryMember::current(host_key)
+            .with_zone(config.zone.clone())
+            .with_ports(config.ports.clone())
+            .with_metadata(config.metadata.clone());
+        let removal_transactions = RemovalTransactions::new(config.broadcast_retransmits);
+        let annotations = Annotations::new(
+            config.broadcast_retransmits,
+            config.annotation_policy,
+            config.annotation_scopes.clone(),
+        );
+        let reaper = Reaper::new(config.broadcast_retransmits);
+        let shutdown_orders = ShutdownOrders::new(
+            config.broadcast_retransmits,
+            chrono::Duration::from_std(CONST_SHUTDOWN_ORDER_RETENTION)
+                .unwrap_or_else(|_| chrono::Duration::zero()),
+        );
+        let user_payloads = UserPayloads::new(
+            config.broadcast_retransmits,
+            chrono::Duration::from_std(CONST_USER_PAYLOAD_RETENTION)
+                .unwrap_or_else(|_| chrono::Duration::zero()),
+        );
+        let locks = Locks::new(config.broadcast_retransmits);
+        let mailbox = Mailbox::new(config.message_retries, config.message_retry_interval);
+        let reachability =
+            Reachability::new(config.broadcast_retransmits, CONST_REACHABILITY_REFRESH);
+        let nat = NatTraversal::new(
+            config.rendezvous_members.clone(),
+            config.hole_punch_attempts,
+            config.max_relayed_per_sec,
+        );
 
-        let interests = Interest::READABLE.add(Interest::WRITABLE);
-        let mut server_socket = UdpSocket::bind(config.listen_addr)?;
-        poll.registry()
-            .register(&mut server_socket, UDP_SERVER, interests)?;
+        #[cfg(unix)]
+        let event_bridge = config
+            .event_bridge
+            .as_ref()
+            .map(|path| EventBridge::bind(path, config.codec))
+            .transpose()?;
 
-        let me = ArtilleryMember::current(host_key);
+        let (diagnostics_tx, diagnostics_rx) = sync_channel(CONST_DIAGNOSTICS_CAPACITY);
+        let members = ArtilleryMemberList::new(me.clone());
+        let view = SharedMembershipView::new(members.available_nodes());
+        let event_log = EventLog::new(config.event_log_capacity);
+        let journal = config
+            .change_store
+            .clone()
+            .map(ChangeJournal::open)
+            .transpose()?;
+        let clock: Arc<dyn Clock> = config
+            .clock
+            .clone()
+            .unwrap_or_else(|| Arc::new(SystemClock));
+        let traffic = TrafficAccount::new(config.max_gossip_bytes_per_sec, clock.now());
+        let health = HealthTracker::new(config.health_window);
 
-        let state = ArtilleryEpidemic {
+        let mut state = ArtilleryEpidemic {
             host_key,
             config,
-            members: ArtilleryMemberList::new(me.clone()),
+            members,
             seed_queue: Vec::new(),
+            known_seeds: Vec::new(),
+            host_seeds: Vec::new(),
+            resolver: None,
+            seed_tracker: SeedTracker::default(),
+            rejoin_backoff: None,
+            rejoin_answered: false,
             pending_responses: Vec::new(),
             state_changes: vec![ArtilleryStateChange::new(me)],
             wait_list: HashMap::new(),
             server_socket,
+            multicast_socket,
+            digest_cursor: 0,
+            pull_answers: HashMap::new(),
             request_tx: ArchPadding::new(internal_tx),
+            reactions: VecDeque::new(),
             event_tx: ArchPadding::new(event_tx),
-            running: AtomicBool::new(true),
+            deferred_work: VecDeque::new(),
+            work_stats: WorkStats::default(),
+            reported_shed: 0,
+            traffic,
+            heartbeat_payload: None,
+            heartbeat_callback: None,
+            removal_transactions,
+            annotations,
+            reaper,
+            shutdown_orders,
+            user_payloads,
+            ordered_shutdown_at: None,
+            reachability,
+            locks,
+            mailbox,
+            rpc: RpcRouter::default(),
+            nat,
+            last_full_sync: clock.now(),
+            full_sync_requested: false,
+            tcp_listener,
+            tcp_exchanges: Vec::new(),
+            event_seq: 0,
+            change_seq: 0,
+            #[cfg(unix)]
+            event_bridge,
+            restart_history: HashMap::new(),
+            health,
+            rounds: RoundTracker::default(),
+            suspicions: SuspicionTracker::default(),
+            view,
+            event_log,
+            journal,
+            greeted: HashSet::new(),
+            burst_rounds_left: 0,
+            last_inbound: clock.now(),
+            send_failing_since: None,
+            last_tick: None,
+            resume_grace_until: None,
+            connectivity_lost: false,
+            late_ticks: 0,
+            timely_ticks: 0,
+            loop_slow: false,
+            diagnostics_tx,
+            diagnostics_rx: Some(diagnostics_rx),
+            clock,
+            clock_offset: Duration::from_secs(0),
+            outbound_sink: None,
+            shutdown: ShutdownLatch::new(),
+            identity_probes: HashSet::new(),
+            advertised_addr: None,
+            identity_conflict: None,
+            event_consumer_gone: false,
         };
+        state.import_members(state.config.initial_members.clone());
+        state.replay_journal();
+        for seed in state.config.seeds.clone() {
+            state.add_seed(seed);
+        }
 
         Ok((poll, state))
     }
 
     pub(crate) fn event_loop(
-        receiver: &mut Receiver<ArtilleryClusterRequest>,
+        receiver: &RequestReceiver,
         mut poll: Poll,
         mut state: ArtilleryEpidemic,
     ) -> Result<()> {
         let mut events = Events::with_capacity(1);
         let mut buf = [0_u8; CONST_PACKET_SIZE];
 
-        let mut start = Instant::now();
-        let timeout = Duration::from_millis(u64::try_from(
-            state.config.ping_interval.num_milliseconds(),
-        )?);
+        let mut start = state.now();
+        let timeout = state.config.ping_interval;
 
-        debug!("Starting Event Loop");
+        log_runtime!(Debug, "Starting Event Loop");
         // Our event loop.
         loop {
-            let elapsed = start.elapsed();
+            let elapsed = state.now().saturating_duration_since(start);
 
             if elapsed >= timeout {
-                state.enqueue_seed_nodes();
-                state.enqueue_random_ping();
-                start = Instant::now();
+                state.watch_tick_lag(elapsed - timeout);
+                state.tick();
+                start = state.now();
             }
 
-            if !state.running.load(Ordering::SeqCst) {
-                debug!("Stopping artillery epidemic evloop");
+            if !state.is_running() {
+                log_runtime!(Debug, "Stopping artillery epidemic evloop");
+                state.check_identity()?;
                 break;
             }
 
             // Poll to check if we have events waiting for us.
             if let Some(remaining) = timeout.checked_sub(elapsed) {
-                poll.poll(&mut events, Some(remaining))?;
+                poll.poll(&mut events, Some(state.real_duration(remaining)))?;
             }
 
             // Process our own events that are submitted to event loop
             // Aka outbound events
-            while let Ok(msg) = receiver.try_recv() {
-                let exit_tx = state.process_internal_request(msg);
-
-                if let Some(exit_tx) = exit_tx {
-                    state.running.swap(false, Ordering::SeqCst);
-                    exit_tx.send(()).unwrap();
-                }
-            }
+            state.drain_requests(receiver);
 
             // Process inbound events
             for event in events.iter() {
                 if let UDP_SERVER = event.token() {
-                    loop {
-                        match state.server_socket.recv_from(&mut buf) {
-                            Ok((packet_size, source_address)) => {
-                                let message = serde_json::from_slice(&buf[..packet_size])?;
-                                state.request_tx.send(ArtilleryClusterRequest::Respond(
-                                    source_address,
-                                    message,
-                                ))?;
-                            }
-                            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
-                                // If we get a `WouldBlock` error we know our socket
-                                // has no more packets queued, so we can return to
-                                // polling and wait for some more.
-                                break;
-                            }
-                            Err(e) => {
-                                // If it was any other kind of error, something went
-                                // wrong and we terminate with an error.
-                                bail!(
-                                    ArtilleryError::Unexpected,
-                                    format!(
-                                        "Unexpected error occured in event loop: {}",
-                                        e.to_string()
-                                    )
-                                )
-                            }
-                        }
-                    }
+                    state.receive_datagrams(&mut buf)?;
                 } else {
-                    warn!("Got event for unexpected token: {:?}", event);
+                    log_runtime!(Warn, "Got event for unexpected token: {:?}", event);
                 }
             }
         }
 
-        info!("Exiting...");
+        log_runtime!(Info, "Exiting...");
         Ok(())
     }
 
-    fn process_request(&mut self, request: &TargetedRequest) {
-        use Request::*;
+    /// Reads the queued datagrams until the sockets would block.
+    pub(crate) fn receive_datagrams(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.drain_socket(false, buf)?;
+        if self.multicast_socket.is_some() {
+            self.drain_socket(true, buf)?;
+        }
+        self.accept_tcp();
+        self.progress_tcp();
 
-        let timeout = Utc::now() + self.config.ping_timeout;
-        // It was Ping before
-        let should_add_pending = request.request == Heartbeat;
-        let message = build_message(
-            &self.host_key,
-            &self.config.cluster_key,
-            &request.request,
-            &self.state_changes,
-            self.config.network_mtu,
-        );
+        Ok(())
+    }
 
-        if should_add_pending {
-            self.pending_responses
-                .push((timeout, request.target, message.state_changes.clone()));
+    fn drain_socket(&mut self, multicast: bool, buf: &mut [u8]) -> Result<()> {
+        loop {
+            let received = if multicast {
+                self.multicast_socket.as_ref().map_or_else(
+                    || Err(io::Error::from(io::ErrorKind::WouldBlock)),
+                    |socket| socket.recv_from(buf),
+                )
+            } else {
+                self.server_socket.recv_from(buf)
+            };
+
+            match received {
+                Ok((packet_size, source_address)) => {
+                    self.rounds.received(packet_size);
+                    let message = match decode_datagram(&self.config, &buf[..packet_size]) {
+                        Ok(message) => message,
+                        Err(e) => {
+                            log_transport!(
+                                Warn,
+                                "Dropping undecodable message from {}: {}",
+                                source_address,
+                                e
+                            );
+                            self.diagnose(ClusterDiagnostic::DecodeFailure {
+                                from: source_address,
+                                reason: e.to_string(),
+                            });
+                            continue;
+                        }
+                    };
+                    if multicast && self.is_own_digest(&message) {
+                        continue;
+                    }
+                    self.tap(
+                        TapDirection::Inbound,
+                        source_address,
+                        &buf[..packet_size],
+                        &message,
+                    );
+                    self.reactions.push_back(ArtilleryClusterRequest::Respond(
+                        source_address,
+                        Box::new(message),
+                    ));
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    // If we get a `WouldBlock` error we know our socket
+                    // has no more packets queued, so we can return to
+                    // polling and wait for some more.
+                    return Ok(());
+                }
+                Err(e) => {
+                    // If it was any other kind of error, something went
+                    // wrong and we terminate with an error.
+                    bail!(
+                        ArtilleryError::Unexpected,
+                        format!("Unexpected error occured in event loop: {}", e.to_string())
+                    );
+                }
+            }
+        }
+    }
+
+    /// Moves the gossip socket to another reactor.
+    pub(crate) fn reregister_socket(
+        &mut self,
+        from: &Registry,
+        to: &Registry,
+        token: Token,
+    ) -> io::Result<()> {
+        self.server_socket.deregister(from)?;
+        self.server_socket.register(to, token)?;
+
+        if let Some(socket) = self.multicast_socket.as_mut() {
+            Transport::deregister(socket, from)?;
+            Transport::register(socket, to, token)?;
         }
 
-        let encoded = serde_json::to_string(&message).unwrap();
+        if let Some(listener) = self.tcp_listener.as_mut() {
+            from.deregister(listener)?;
+            to.register(listener, token, Interest::READABLE)?;
+        }
 
-        assert!(encoded.len() < self.config.network_mtu);
+        Ok(())
+    }
 
-        let buf = encoded.as_bytes();
-        self.server_socket.send_to(buf, request.target).unwrap();
+    pub(crate) fn is_running(&self) -> bool {
+        self.shutdown.is_running()
     }
 
-    fn enqueue_seed_nodes(&self) {
-        for seed_node in &self.seed_queue {
-            self.request_tx
-                .send(ArtilleryClusterRequest::React(TargetedRequest {
-                    request: Request::Heartbeat,
-                    target: *seed_node,
-                }))
-                .unwrap();
+    pub(crate) fn config(&self) -> &ClusterConfig {
+        &self.config
+    }
+
+    /// Fails once the loop stopped because another node uses our host key.
+    pub(crate) fn check_identity(&self) -> Result<()> {
+        match self.identity_conflict {
+            Some(addr) if self.config.stop_on_identity_conflict => {
+                Err(ArtilleryError::IdentityConflict(format!(
+                    "Host key {} is used by the running node at {} too",
+                    self.host_key, addr
+                )))
+            }
+            Some(_) | None => Ok(()),
         }
     }
 
-    fn enqueue_random_ping(&mut self) {
-        if let Some(member) = self.members.next_random_member() {
-            self.request_tx
-                .send(ArtilleryClusterRequest::React(TargetedRequest {
-                    request: Request::Heartbeat,
-                    target: member.remote_host().unwrap(),
-                }))
-                .unwrap();
+    /// Periodic protocol work, done once per ping interval.
+    pub(crate) fn tick(&mut self) {
+        self.detect_suspend();
+        self.resolve_host_seeds();
+        self.enqueue_seed_nodes();
+        self.retry_rejoin();
+        self.enqueue_random_ping();
+        self.punch_relayed_peers();
+        self.gossip_burst_round();
+        self.check_local_connectivity();
+        self.probe_suspects();
+        self.full_sync_if_due();
+        self.progress_tcp();
+        self.multicast_digest();
+        self.report_work_stats();
+        self.removal_transactions
+            .prune(self.config.removal_transaction_retention);
+        let now = self.now();
+        prune_wait_list(&mut self.wait_list, now, self.config.wait_list_ttl);
+        self.reap_gone_members();
+        self.track_reachability();
+        self.arbitrate_locks();
+        self.locks.prune(self.wall_now());
+        self.user_payloads.prune(Utc::now());
+        self.retry_messages();
+        self.rpc.expire(self.now());
+        self.settle_journal();
+        self.health
+            .observe(self.state_changes.is_empty(), self.now());
+        self.summarize_round();
+        self.stop_if_ordered();
+    }
+
+    fn summarize_round(&mut self) {
+        let summary = self.rounds.finish(self.state_changes.len());
+        if self.config.round_summaries {
+            self.send_member_event(ArtilleryMemberEvent::ProtocolRoundSummary(summary));
         }
     }
 
-    fn prune_timed_out_responses(&mut self) {
-        let now = Utc::now();
+    /// Protocol-critical work goes first, bulk work is processed within the budget.
+    /// Payloads are taken off their lane while the deferred queue has room, their senders
+    /// wait meanwhile.
+    pub(crate) fn drain_requests(&mut self, receiver: &RequestReceiver) {
+        while let Some(msg) = self
+            .reactions
+            .pop_front()
+            .or_else(|| receiver.try_recv_control().ok())
+        {
+            match classify_work(&msg) {
+                WorkClass::Critical => self.handle_internal_request(msg),
+                WorkClass::Bulk => self.defer_work(msg),
+            }
+        }
 
-        let (remaining, expired): (Vec<_>, Vec<_>) = self
-            .pending_responses
-            .iter()
-            .cloned()
-            .partition(|&(t, _, _)| t < now);
+        let room = self
+            .config
+            .max_deferred_work
+            .saturating_sub(self.deferred_work.len());
+        self.deferred_work
+            .extend(std::iter::from_fn(|| receiver.try_recv_data().ok()).take(room));
 
-        let expired_hosts: HashSet<SocketAddr> = expired.iter().map(|&(_, a, _)| a).collect();
+        self.process_deferred_work();
+    }
 
-        self.pending_responses = remaining;
+    /// Handles the request as if the `Cluster` sent it, used in tests.
+    #[cfg(any(test, feature = "test-kit"))]
+    pub(crate) fn inject_request(&mut self, request: ArtilleryClusterRequest) {
+        self.handle_internal_request(request);
+    }
 
-        let (suspect, down) = self.members.time_out_nodes(&expired_hosts);
+    /// Queues the message as if it was read off the socket, used in tests.
+    #[cfg(any(test, feature = "test-kit"))]
+    pub(crate) fn queue_inbound(&mut self, from: SocketAddr, message: ArtilleryMessage) {
+        self.reactions
+            .push_back(ArtilleryClusterRequest::Respond(from, Box::new(message)));
+    }
 
-        enqueue_state_change(&mut self.state_changes, &down);
-        enqueue_state_change(&mut self.state_changes, &suspect);
+    /// Requests of the state machine to itself not handled yet, used in tests.
+    #[cfg(test)]
+    pub(crate) fn take_reactions(&mut self) -> Vec<ArtilleryClusterRequest> {
+        self.reactions.drain(..).collect()
+    }
 
-        for member in suspect {
-            self.send_ping_requests(&member);
-            self.send_member_event(ArtilleryMemberEvent::SuspectedDown(member.clone()));
-        }
+    /// Moves the protocol clock forward, used to script timeouts in tests.
+    #[cfg(any(test, feature = "test-kit"))]
+    pub(crate) fn advance_clock(&mut self, by: Duration) {
+        self.clock_offset += by;
+    }
 
-        for member in down {
-            self.send_member_event(ArtilleryMemberEvent::WentDown(member.clone()));
-        }
+    /// Exchanges over TCP in progress, used in tests.
+    #[cfg(any(test, feature = "test-kit"))]
+    pub(crate) fn tcp_exchanges(&self) -> usize {
+        self.tcp_exchanges.len()
     }
 
-    fn send_ping_requests(&self, target: &ArtilleryMember) {
-        if let Some(target_host) = target.remote_host() {
-            for relay in self
-                .members
-                .hosts_for_indirect_ping(self.config.ping_request_host_count, &target_host)
-            {
-                self.request_tx
-                    .send(ArtilleryClusterRequest::React(TargetedRequest {
-                        request: Request::Ping(EncSocketAddr::from_addr(&target_host)),
-                        target: relay,
-                    }))
-                    .unwrap();
-            }
-        }
+    /// Hands the outbound messages to the sink instead of the network, used by replays.
+    #[cfg(any(test, feature = "test-kit"))]
+    pub(crate) fn divert_outbound(&mut self, sink: Sender<TapRecord>) {
+        self.outbound_sink = Some(sink);
     }
 
-    fn process_internal_request(&mut self, message: ArtilleryClusterRequest) -> Option<Sender<()>> {
-        use ArtilleryClusterRequest::*;
+    /// Protocol time, see `ClusterConfig::clock`.
+    pub(crate) fn now(&self) -> Instant {
+        self.clock.now() + self.clock_offset
+    }
 
-        match message {
-            AddSeed(addr) => self.seed_queue.push(addr),
-            Respond(src_addr, message) => self.respond_to_message(src_addr, message),
-            React(request) => {
-                self.prune_timed_out_responses();
-                self.process_request(&request);
-            }
-            LeaveCluster => {
-                let myself = self.members.leave();
-                enqueue_state_change(&mut self.state_changes, &[myself]);
-            }
-            Payload(id, msg) => {
-                if let Some(target_peer) = self.members.get_member(&id) {
-                    if !target_peer.is_remote() {
-                        error!("Current node can't send payload to self over LAN");
-                        return None;
-                    }
+    /// Wall clock moved along with the protocol clock, for the leases gossiped by wall time.
+    pub(crate) fn wall_now(&self) -> DateTime<Utc> {
+        let ahead = self.now().saturating_duration_since(Instant::now());
+        Utc::now() + chrono::Duration::from_std(ahead).unwrap_or_else(|_| chrono::Duration::zero())
+    }
 
-                    self.process_request(&TargetedRequest {
-                        request: Request::Payload(id, msg),
-                        target: target_peer
-                            .remote_host()
-                            .expect("Expected target peer addr"),
-                    });
-                    return None;
-                }
-                warn!(
-                    "Unable to find the peer with an id - {} to send the payload",
-                    id
-                );
-            }
-            Exit(tx) => return Some(tx),
-        };
+    /// Real time to wait until `protocol` time went by.
+    pub(crate) fn real_duration(&self, protocol: Duration) -> Duration {
+        self.clock.real_duration(protocol)
+    }
 
-        None
+    /// Receiver of the protocol diagnostics, it can be taken only once.
+    pub fn take_diagnostics(&mut self) -> Option<Receiver<ClusterDiagnostic>> {
+        self.diagnostics_rx.take()
     }
 
-    fn respond_to_message(&mut self, src_addr: SocketAddr, message: ArtilleryMessage) {
-        use Request::*;
+    /// Never blocks the event loop, diagnostics are dropped while the consumer lags.
+    fn diagnose(&self, diagnostic: ClusterDiagnostic) {
+        let _ = self.diagnostics_tx.try_send(diagnostic);
+    }
 
-        if message.cluster_key == self.config.cluster_key {
-            self.apply_state_changes(message.state_changes, src_addr);
-            remove_potential_seed(&mut self.seed_queue, src_addr);
+    /// Handle of the membership read model, updated on every emitted event.
+    pub fn view(&self) -> SharedMembershipView {
+        self.view.clone()
+    }
 
-            self.ensure_node_is_member(src_addr, message.sender);
+    pub fn event_log(&self) -> EventLog {
+        self.event_log.clone()
+    }
 
-            let response = match message.request {
-                Heartbeat => Some(TargetedRequest {
-                    request: Ack,
-                    target: src_addr,
-                }),
-                Ack => {
-                    self.ack_response(src_addr);
-                    self.mark_node_alive(src_addr);
-                    None
-                }
-                Ping(dest_addr) => {
-                    let EncSocketAddr(dest_addr) = dest_addr;
-                    add_to_wait_list(&mut self.wait_list, &dest_addr, &src_addr);
-                    Some(TargetedRequest {
-                        request: Heartbeat,
-                        target: dest_addr,
-                    })
-                }
-                AckHost(member) => {
-                    self.ack_response(member.remote_host().unwrap());
-                    self.mark_node_alive(member.remote_host().unwrap());
-                    None
-                }
-                Payload(peer_id, msg) => {
-                    if let Some(member) = self.members.get_member(&peer_id) {
-                        self.send_member_event(ArtilleryMemberEvent::Payload(member, msg));
-                    } else {
-                        warn!("Got payload request from an unknown peer {}", peer_id);
-                    }
-                    None
-                }
-            };
+    pub fn work_stats(&self) -> &WorkStats {
+        &self.work_stats
+    }
 
-            if let Some(response) = response {
-                self.request_tx
-                    .send(ArtilleryClusterRequest::React(response))
-                    .unwrap()
-            }
-        } else {
-            error!("Mismatching cluster keys, ignoring message");
-        }
+    pub fn traffic_stats(&self) -> &TrafficStats {
+        self.traffic.stats()
     }
 
-    fn ack_response(&mut self, src_addr: SocketAddr) {
-        let mut to_remove = Vec::new();
+    pub fn relay_stats(&self) -> RelayStats {
+        self.nat.stats()
+    }
 
-        for &(ref t, ref addr, ref state_changes) in &self.pending_responses {
-            if src_addr != *addr {
-                continue;
-            }
+    /// Address the peers see this node at, the listen address until a peer gossiped it.
+    pub fn advertised_addr(&self) -> SocketAddr {
+        self.advertised_addr.unwrap_or(self.config.listen_addr)
+    }
 
-            to_remove.push((*t, *addr, state_changes.clone()));
+    pub fn health(&self) -> ClusterHealth {
+        self.health
+            .health(&self.members.available_nodes(), self.now())
+    }
 
-            self.state_changes.retain(|os| {
-                !state_changes
-                    .iter()
-                    .any(|is| is.member().host_key() == os.member().host_key())
-            })
+    pub fn footprint(&self) -> StateFootprint {
+        StateFootprint {
+            members: self.members.all_members().len(),
+            state_changes: self.state_changes.len(),
+            wait_list: self.wait_list.values().map(Vec::len).sum(),
+            pending_responses: self.pending_responses.len(),
+            deferred_work: self.deferred_work.len(),
         }
-
-        self.pending_responses
-            .retain(|op| !to_remove.iter().any(|ip| ip == op));
     }
 
-    fn ensure_node_is_member(&mut self, src_addr: SocketAddr, sender: Uuid) {
-        if self.members.has_member(&src_addr) {
-            return;
+    fn handle_internal_request(&mut self, msg: ArtilleryClusterRequest) {
+        if let Some(exit_tx) = self.process_internal_request(msg) {
+            self.shutdown.acknowledge_exit(&exit_tx);
         }
+    }
 
-        let new_member = ArtilleryMember::new(sender, src_addr, 0, ArtilleryMemberState::Alive);
+    /// State changes not acknowledged by any member yet, plus the deferred bulk work.
+    fn pending_outbound(&self) -> usize {
+        self.state_changes.len() + self.deferred_work.len()
+    }
 
-        self.members.add_member(new_member.clone());
-        enqueue_state_change(&mut self.state_changes, &[new_member.clone()]);
-        self.send_member_event(ArtilleryMemberEvent::Joined(new_member));
+    fn defer_work(&mut self, msg: ArtilleryClusterRequest) {
+        if self.deferred_work.len() >= self.config.max_deferred_work {
+            self.deferred_work.pop_front();
+            self.work_stats.shed += 1;
+        }
+
+        self.deferred_work.push_back(msg);
     }
 
-    fn send_member_event(&self, event: ArtilleryMemberEvent) {
-        use ArtilleryMemberEvent::*;
+    fn process_deferred_work(&mut self) {
+        let budget = self.config.bulk_work_budget.min(self.deferred_work.len());
 
-        match event {
-            Joined(_) | Payload(..) => {}
-            WentUp(ref m) => assert_eq!(m.state(), ArtilleryMemberState::Alive),
-            WentDown(ref m) => assert_eq!(m.state(), ArtilleryMemberState::Down),
-            SuspectedDown(ref m) => assert_eq!(m.state(), ArtilleryMemberState::Suspect),
-            Left(ref m) => assert_eq!(m.state(), ArtilleryMemberState::Left),
-        };
+        for _ in 0..budget {
+            if let Some(msg) = self.deferred_work.pop_front() {
+                self.handle_internal_request(msg);
+                self.work_stats.processed += 1;
+            }
+        }
 
-        self.event_tx
-            .send((self.members.available_nodes(), event))
-            .unwrap();
+        self.work_stats.backlog = self.deferred_work.len();
     }
 
-    fn apply_state_changes(&mut self, state_changes: Vec<ArtilleryStateChange>, from: SocketAddr) {
-        let (new, changed) = self.members.apply_state_changes(state_changes, &from);
+    fn report_work_stats(&mut self) {
+        if self.work_stats.shed > self.reported_shed {
+            let shed = self.work_stats.shed - self.reported_shed;
+            log_runtime!(
+                Warn,
+                "Overloaded, shed {} bulk requests ({} in total), backlog: {}",
+                shed,
+                self.work_stats.shed,
+                self.work_stats.backlog
+            );
+            self.diagnose(ClusterDiagnostic::WorkShed {
+                shed,
+                backlog: self.work_stats.backlog,
+            });
+            self.reported_shed = self.work_stats.shed;
+        }
+    }
+
+    fn process_request(&mut self, request: &TargetedRequest) {
+        use Request::*;
 
-        enqueue_state_change(&mut self.state_changes, &new);
-        enqueue_state_change(&mut self.state_changes, &changed);
+        // Over the bandwidth cap only protocol messages go out, without piggybacked changes.
+        let now = self.now();
+        let over_budget = self.traffic.over_budget(now);
+        if over_budget {
+            self.traffic.record_deferral();
 
-        for member in new {
-            self.send_member_event(ArtilleryMemberEvent::Joined(member));
+            if request.request.is_bulk() {
+                self.defer_work(ArtilleryClusterRequest::React(request.clone()));
+                return;
+            }
         }
 
-        for member in changed {
-            self.send_member_event(determine_member_event(member));
+        if matches!(request.request, Heartbeat | Ping(_)) {
+            self.rounds.probe_sent();
         }
-    }
 
-    fn mark_node_alive(&mut self, src_addr: SocketAddr) {
-        if let Some(member) = self.members.mark_node_alive(&src_addr) {
-            if let Some(wait_list) = self.wait_list.get_mut(&src_addr) {
-                for remote in wait_list.iter() {
-                    self.request_tx
-                        .send(ArtilleryClusterRequest::React(TargetedRequest {
-                            request: Request::AckHost(member.clone()),
-                            target: *remote,
-                        }))
-                        .unwrap();
+        let timeout = self.now() + self.config.ping_timeout;
+        // It was Ping before
+        let should_add_pending = request.request == Heartbeat;
+        let digest = self.outgoing_digest(&request.request);
+        let pull_answer = if request.request == Ack {
+            self.pull_answers.remove(&request.target)
+        } else {
+            None
+        };
+        let from_buffer = !over_budget && digest.is_none() && pull_answer.is_none();
+        let shuffled = if from_buffer && self.config.shuffle_state_changes {
+            Some(shuffled_state_changes(&self.state_changes))
+        } else {
+            None
+        };
+        let state_changes: &[ArtilleryStateChange] = if over_budget || digest.is_some() {
+            &[]
+        } else {
+            pull_answer
+                .as_deref()
+                .or(shuffled.as_deref())
+                .unwrap_or(&self.state_changes)
+        };
+        let mut base = ArtilleryMessage {
+            sender: self.host_key,
+            cluster_name: self.config.cluster_name.clone(),
+            cluster_key: self.config.cluster_key.clone(),
+            request: request.request.clone(),
+            state_changes: Vec::new(),
+            heartbeat: self
+                .heartbeat_payload
+                .clone()
+                .filter(|_| should_add_pending),
+            transactions: self.removal_transactions.next_batch(CONST_BROADCAST_BATCH),
+            annotations: self
+                .annotations
+                .next_batch(CONST_BROADCAST_BATCH, self.in_my_zone(request.target)),
+            reaps: self.reaper.next_batch(CONST_BROADCAST_BATCH),
+            reachability: self.reachability.next_batch(CONST_BROADCAST_BATCH),
+            relay: None,
+            locks: self.locks.next_batch(CONST_BROADCAST_BATCH),
+            run: self.members.current_run_id(),
+            digest,
+            shutdown: self.shutdown_orders.next_batch(CONST_BROADCAST_BATCH),
+            hello: self.greeting_for(request.target),
+            payloads: self.user_payloads.next_batch(CONST_USER_PAYLOAD_BATCH),
+        };
+        // A greeting leaves room for the state changes, the later gossip describes us then
+        let greeting_too_large = base.hello.is_some()
+            && self
+                .config
+                .codec
+                .encode(&base)
+                .map_or(true, |e| e.len() > self.config.network_mtu / 2);
+        if greeting_too_large {
+            base.hello = None;
+        }
+        let relay = self.nat.relay_for(&request.target);
+        let padding_overhead = self.config.padding_bucket.map_or(0, |_| PADDING_HEADER);
+        let relay_overhead = relay.map_or(0, |_| CONST_RELAY_ENVELOPE_OVERHEAD);
+        let mut message = build_message(
+            base,
+            state_changes,
+            self.config.codec,
+            self.config
+                .network_mtu
+                .saturating_sub(padding_overhead)
+                .saturating_sub(relay_overhead)
+                .saturating_sub(self.config.codec.header_len())
+                .saturating_sub(self.config.encryption.overhead()),
+        );
+        message.relay = relay.map(|_| RelayEnvelope::to(request.target));
+        if from_buffer {
+            for carried in &message.state_changes {
+                if let Some(queued) = self.state_changes.iter_mut().find(|c| {
+                    c.member().host_key() == carried.member().host_key() && c.id() == carried.id()
+                }) {
+                    queued.record_transmission();
                 }
-
-                wait_list.clear();
             }
+        }
 
-            enqueue_state_change(&mut self.state_changes, &[member.clone()]);
-            self.send_member_event(ArtilleryMemberEvent::WentUp(member));
+        if should_add_pending {
+            self.pending_responses
+                .push((timeout, request.target, message.state_changes.clone()));
         }
-    }
-}
 
-fn build_message(
-    sender: &Uuid,
-    cluster_key: &[u8],
-    request: &Request,
-    state_changes: &[ArtilleryStateChange],
-    network_mtu: usize,
-) -> ArtilleryMessage {
-    let mut message = ArtilleryMessage {
-        sender: *sender,
-        cluster_key: cluster_key.into(),
-        request: request.clone(),
-        state_changes: Vec::new(),
-    };
+        if let Some(encoded) = self.encode_for(request.target, &message) {
+            self.transmit(relay.unwrap_or(request.target), &encoded, &message);
+        }
+    }
 
-    for i in 0..=state_changes.len() {
-        flunk!("epidemic-state-change-tail-follow-fp");
-        message = ArtilleryMessage {
-            sender: *sender,
-            cluster_key: cluster_key.into(),
-            request: request.clone(),
-            state_changes: (&state_changes[..i]).to_vec(),
+    /// Whether the member at `target` is known in the zone of this node.
+    fn in_my_zone(&self, target: SocketAddr) -> bool {
+        let zone = match self.config.zone.as_deref() {
+            Some(zone) => zone,
+            None => return false,
         };
+        self.members
+            .member_at(&target)
+            .is_some_and(|m| m.zone() == Some(zone))
+    }
 
-        let encoded = serde_json::to_string(&message).unwrap();
-        if encoded.len() >= network_mtu {
-            return message;
+    /// Self-description carried to the target until it acknowledged one of our pings.
+    fn greeting_for(&self, target: SocketAddr) -> Option<Greeting> {
+        if self.greeted.contains(&target) {
+            return None;
         }
-    }
 
-    message
-}
+        let myself = self.members.myself()?.clone();
+        let same_zone = self.in_my_zone(target);
+        let mut annotations = self.annotations.written_by(&self.host_key);
+        annotations.retain(|a| self.annotations.reaches(a, same_zone));
+        annotations.truncate(CONST_BROADCAST_BATCH);
+        Some(Greeting::new(
+            self.config.instance_name().to_string(),
+            myself,
+            annotations,
+        ))
+    }
 
-fn add_to_wait_list(wait_list: &mut WaitList, wait_addr: &SocketAddr, notify_addr: &SocketAddr) {
-    match wait_list.entry(*wait_addr) {
-        Entry::Occupied(mut entry) => {
-            entry.get_mut().push(notify_addr.clone());
+    /// Digest replacing the piggybacked state changes of a ping in pull gossip mode.
+    fn outgoing_digest(&self, request: &Request) -> Option<MemberDigest> {
+        if !self.config.pull_gossip || *request != Request::Heartbeat {
+            return None;
         }
-        Entry::Vacant(entry) => {
-            entry.insert(vec![*notify_addr]);
+
+        let members = self.members.all_members();
+        if members.len() > CONST_PULL_DIGEST_LIMIT {
+            return None;
         }
-    };
-}
 
-fn remove_potential_seed(seed_queue: &mut Vec<SocketAddr>, src_addr: SocketAddr) {
-    seed_queue.retain(|&addr| addr != src_addr)
-}
+        Some(MemberDigest::of(&members))
+    }
 
-fn determine_member_event(member: ArtilleryMember) -> ArtilleryMemberEvent {
-    match member.state() {
-        ArtilleryMemberState::Alive => ArtilleryMemberEvent::WentUp(member),
-        ArtilleryMemberState::Suspect => ArtilleryMemberEvent::SuspectedDown(member),
-        ArtilleryMemberState::Down => ArtilleryMemberEvent::WentDown(member),
-        ArtilleryMemberState::Left => ArtilleryMemberEvent::Left(member),
+    /// Prepares the ack of a pull gossip ping, carrying the records its sender lacks.
+    /// Queued changes the sender already knows are retired, as if it acknowledged them.
+    fn answer_digest(&mut self, src_addr: SocketAddr, digest: &MemberDigest) {
+        let answer = digest
+            .outdated_in(&self.members.all_members())
+            .into_iter()
+            .map(ArtilleryStateChange::new)
+            .collect();
+        digest.retain_unknown(&mut self.state_changes);
+        self.pull_answers.insert(src_addr, answer);
     }
-}
 
-fn enqueue_state_change(
-    state_changes: &mut Vec<ArtilleryStateChange>,
-    members: &[ArtilleryMember],
-) {
-    for member in members {
-        for state_change in state_changes.iter_mut() {
-            if state_change.member().host_key() == member.host_key() {
-                state_change.update(member.clone());
-                return;
+    fn encode_for(&mut self, target: SocketAddr, message: &ArtilleryMessage) -> Option<Vec<u8>> {
+        match encode_datagram(&self.config, message) {
+            Ok(encoded) => Some(encoded),
+            Err(e) => {
+                log_transport!(Error, "Unable to encode message to {}: {}", target, e);
+                self.diagnose(ClusterDiagnostic::SendError {
+                    to: target,
+                    reason: e.to_string(),
+                });
+                None
             }
         }
+    }
+
+    fn send_datagram(
+        &mut self,
+        destination: SocketAddr,
+        encoded: &[u8],
+        message: &ArtilleryMessage,
+    ) {
+        self.tap(TapDirection::Outbound, destination, encoded, message);
+        let now = self.now();
+        self.traffic.record(encoded.len(), now);
+        self.rounds.sent(encoded.len());
+
+        if let Some(sink) = &self.outbound_sink {
+            let _ = sink.send(tap_record(TapDirection::Outbound, destination, encoded));
+            return;
+        }
 
-        state_changes.push(ArtilleryStateChange::new(member.clone()));
+        match self.server_socket.send_to(encoded, destination) {
+            Ok(_) => self.send_failing_since = None,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => {
+                log_transport!(Debug, "Sending to {} failed: {}", destination, e);
+                self.diagnose(ClusterDiagnostic::SendError {
+                    to: destination,
+                    reason: e.to_string(),
+                });
+                if self.send_failing_since.is_none() {
+                    self.send_failing_since = Some(self.now());
+                }
+            }
+        }
     }
-}
 
-impl EncSocketAddr {
+    /// Sends a bare request straight to the address, whatever the route to it is.
+    fn send_direct(&mut self, target: SocketAddr, request: Request) {
+        let message = self.direct_message(request);
+
+        if let Some(encoded) = self.encode_for(target, &message) {
+            self.transmit(target, &encoded, &message);
+        }
+    }
+
+    /// Messages larger than the MTU go over TCP with the `tcp_fallback`, they are dropped
+    /// otherwise.
+    fn transmit(&mut self, destination: SocketAddr, encoded: &[u8], message: &ArtilleryMessage) {
+        if encoded.len() < self.config.network_mtu {
+            self.send_datagram(destination, encoded, message);
+            return;
+        }
+
+        if self.config.tcp_fallback {
+            log_transport!(
+                Debug,
+                "Sending a message of {} bytes to {} over TCP",
+                encoded.len(),
+                destination
+            );
+            self.tap(TapDirection::Outbound, destination, encoded, message);
+            let now = self.now();
+            self.traffic.record(encoded.len(), now);
+            self.rounds.sent(encoded.len());
+            self.connect_tcp(destination, message, false);
+        } else {
+            log_transport!(
+                Warn,
+                "Dropping a message of {} bytes to {}, larger than the MTU",
+                encoded.len(),
+                destination
+            );
+            self.diagnose(ClusterDiagnostic::SendError {
+                to: destination,
+                reason: format!("message of {} bytes exceeds the MTU", encoded.len()),
+            });
+        }
+    }
+
+    /// Message carrying the request alone, without piggybacked records.
+    fn direct_message(&self, request: Request) -> ArtilleryMessage {
+        ArtilleryMessage {
+            sender: self.host_key,
+            cluster_name: self.config.cluster_name.clone(),
+            cluster_key: self.config.cluster_key.clone(),
+            request,
+            state_changes: Vec::new(),
+            heartbeat: None,
+            transactions: Vec::new(),
+            annotations: Vec::new(),
+            reaps: Vec::new(),
+            reachability: Vec::new(),
+            relay: None,
+            locks: Vec::new(),
+            run: self.members.current_run_id(),
+            digest: None,
+            shutdown: Vec::new(),
+            hello: None,
+            payloads: Vec::new(),
+        }
+    }
+
+    /// Passes a message on to the member it is addressed to, telling where it came from.
+    fn forward_relayed(
+        &mut self,
+        origin: SocketAddr,
+        to: SocketAddr,
+        mut message: ArtilleryMessage,
+    ) {
+        if to == origin || to == self.config.listen_addr || !self.members.has_member(&to) {
+            log_transport!(Debug, "Not relaying a message from {} to {}", origin, to);
+            return;
+        }
+
+        message.relay = Some(RelayEnvelope {
+            to,
+            from: Some(origin),
+        });
+
+        if let Some(encoded) = self.encode_for(to, &message) {
+            if encoded.len() >= self.config.network_mtu {
+                log_transport!(
+                    Warn,
+                    "Relayed message from {} to {} is too large",
+                    origin,
+                    to
+                );
+            } else if self.nat.admit_forward(encoded.len()) {
+                self.send_datagram(to, &encoded, &message);
+            } else {
+                log_transport!(
+                    Debug,
+                    "Relaying cap reached, dropped a message from {} to {}",
+                    origin,
+                    to
+                );
+            }
+        }
+    }
+
+    /// Members reachable only through others are reached through the relay answering for them.
+    fn relay_if_only_indirect(&mut self, host: SocketAddr, relay: SocketAddr) {
+        if let Some(threshold) = self.config.relay_after_indirect_acks {
+            if self.nat.indirect_ack(host, relay, threshold) {
+                log_transport!(
+                    Info,
+                    "{} only answers through {}, relaying through it",
+                    host,
+                    relay
+                );
+            }
+        }
+    }
+
+    /// Introduces this node again to the peers it relays to, and opens its NAT towards them.
+    fn punch_relayed_peers(&mut self) {
+        for (peer, via) in self.nat.punch_round() {
+            self.reactions
+                .push_back(ArtilleryClusterRequest::React(TargetedRequest {
+                    request: Request::Introduce(EncSocketAddr::from_addr(&peer)),
+                    target: via,
+                }));
+            self.send_direct(peer, Request::Heartbeat);
+        }
+    }
+
+    fn tap(
+        &self,
+        direction: TapDirection,
+        peer: SocketAddr,
+        bytes: &[u8],
+        message: &ArtilleryMessage,
+    ) {
+        if let Some(tap) = &self.config.message_tap {
+            tap.observe(&tap_record(direction, peer, bytes), message);
+        }
+    }
+
+    /// Address the socket reaches `addr` at, `None` for a peer of another address family.
+    fn peer_addr(&self, addr: SocketAddr) -> Option<SocketAddr> {
+        match peer_addr_for(self.config.listen_addr, addr, self.config.dual_stack) {
+            Ok(reachable) => Some(reachable),
+            Err(mismatch) => {
+                log_transport!(Warn, "Skipping the peer {}", mismatch);
+                None
+            }
+        }
+    }
+
+    fn add_seed(&mut self, seed: PeerAddr) {
+        match seed.socket_addr() {
+            Some(socket_addr) => {
+                let addr = match self.peer_addr(socket_addr) {
+                    Some(addr) => addr,
+                    None => return,
+                };
+                if !self.known_seeds.contains(&addr) {
+                    self.known_seeds.push(addr);
+                }
+                self.seed_queue.push(addr);
+            }
+            None => {
+                if !self.host_seeds.iter().any(|s| *s.addr() == seed) {
+                    self.host_seeds.push(HostSeed::new(seed));
+                }
+            }
+        }
+    }
+
+    /// Host names are resolved off the event loop, the addresses come back as a request.
+    fn resolve_host_seeds(&mut self) {
+        let now = self.now();
+
+        for seed in &mut self.host_seeds {
+            if !seed.needs_resolution(now, self.config.seed_resolution_interval) {
+                continue;
+            }
+
+            if self.resolver.is_none() {
+                let resolver = self
+                    .config
+                    .resolver
+                    .clone()
+                    .unwrap_or_else(|| Arc::new(SystemResolver));
+                let spawned = ResolverWorker::spawn(
+                    resolver,
+                    self.config.instance_name().to_string(),
+                    RequestSender::clone(&self.request_tx),
+                );
+                match spawned {
+                    Ok(worker) => self.resolver = Some(worker),
+                    Err(e) => {
+                        log_transport!(Warn, "Unable to start the seed resolver: {}", e);
+                        return;
+                    }
+                }
+            }
+
+            let queued = self
+                .resolver
+                .as_ref()
+                .is_some_and(|r| r.resolve(seed.addr().clone()));
+            if queued {
+                seed.start_resolution();
+            } else {
+                // Restarted on the next round
+                self.resolver = None;
+            }
+        }
+    }
+
+    fn enqueue_seed_nodes(&mut self) {
+        let now = self.now();
+
+        for seed_node in &self.seed_queue {
+            self.seed_tracker
+                .attempted(&PeerAddr::from(*seed_node), *seed_node, now);
+            self.reactions
+                .push_back(ArtilleryClusterRequest::React(TargetedRequest {
+                    request: Request::Heartbeat,
+                    target: *seed_node,
+                }));
+        }
+
+        for seed in self.host_seeds.iter_mut().filter(|s| s.is_joining()) {
+            if let Some(target) = seed.target() {
+                self.seed_tracker.attempted(seed.addr(), target, now);
+                self.reactions
+                    .push_back(ArtilleryClusterRequest::React(TargetedRequest {
+                        request: Request::Heartbeat,
+                        target,
+                    }));
+            }
+            seed.fail_over();
+        }
+    }
+
+    fn enqueue_random_ping(&mut self) {
+        if let Some(member) = self.members.next_random_member() {
+            self.reactions
+                .push_back(ArtilleryClusterRequest::React(TargetedRequest {
+                    request: Request::Heartbeat,
+                    target: member.remote_host().unwrap(),
+                }));
+        }
+    }
+
+    /// Monotonic clocks stop during a suspend on some platforms, the wall clock doesn't.
+    fn detect_suspend(&mut self) {
+        let now = self.now();
+        let wall_now = Utc::now();
+        let last_tick = self.last_tick.replace((now, wall_now));
+
+        let threshold = match self.config.suspend_detection_threshold {
+            Some(threshold) => threshold,
+            None => return,
+        };
+
+        if let Some((last, wall_last)) = last_tick {
+            let wall_gap = (wall_now - wall_last).to_std().unwrap_or_default();
+            let gap = now.saturating_duration_since(last).max(wall_gap);
+
+            if gap > self.config.ping_interval + threshold {
+                log_detector!(
+                    Warn,
+                    "Ticks were {:?} apart, assuming the host was suspended",
+                    gap
+                );
+                self.diagnose(ClusterDiagnostic::ClockJump(gap));
+                self.enter_resume_grace(now, gap);
+            }
+        }
+    }
+
+    /// Probes in flight during the suspend never had a chance to be answered, they restart
+    /// with fresh deadlines and every member is probed again.
+    fn enter_resume_grace(&mut self, now: Instant, gap: Duration) {
+        self.resume_grace_until = Some(now + self.config.resume_grace_period);
+        self.last_inbound = now;
+        self.send_failing_since = None;
+        self.suspicions.postpone(gap);
+
+        let deadline = now + self.config.ping_timeout;
+        for pending in &mut self.pending_responses {
+            pending.0 = deadline;
+        }
+
+        for member in self.members.available_nodes() {
+            if let Some(target) = member.remote_host() {
+                self.reactions
+                    .push_back(ArtilleryClusterRequest::React(TargetedRequest {
+                        request: Request::Heartbeat,
+                        target,
+                    }));
+            }
+        }
+    }
+
+    /// Event loop watchdog, given how late the tick about to run is. A starved loop probes
+    /// late and reads acks late, its accusations about the others are likely false.
+    pub(crate) fn watch_tick_lag(&mut self, lag: Duration) {
+        if lag > self.config.loop_lag_threshold {
+            self.diagnose(ClusterDiagnostic::LoopLag(lag));
+            self.late_ticks += 1;
+            self.timely_ticks = 0;
+        } else {
+            self.timely_ticks += 1;
+            self.late_ticks = 0;
+        }
+
+        let limit = match self.config.slow_loop_ticks {
+            Some(limit) => limit,
+            None => return,
+        };
+
+        if !self.loop_slow && self.late_ticks >= limit {
+            log_runtime!(
+                Warn,
+                "{} ticks in a row ran late, {:?} the last time, marking self degraded",
+                self.late_ticks,
+                lag
+            );
+            self.loop_slow = true;
+            self.set_self_degraded(true);
+            self.send_member_event(ArtilleryMemberEvent::EventLoopSlow(lag));
+            return;
+        }
+        if self.loop_slow && self.timely_ticks >= limit {
+            log_runtime!(Info, "Ticks are on time again");
+            self.loop_slow = false;
+            self.set_self_degraded(self.connectivity_lost);
+            self.send_member_event(ArtilleryMemberEvent::EventLoopRecovered);
+        }
+    }
+
+    /// Gossips the degraded flag of this node when it changes.
+    fn set_self_degraded(&mut self, degraded: bool) {
+        let flagged = self
+            .members
+            .myself()
+            .is_some_and(ArtilleryMember::is_degraded);
+        if flagged != degraded {
+            let myself = self.members.set_self_degraded(degraded);
+            self.enqueue_state_change(&[myself]);
+        }
+    }
+
+    fn in_resume_grace(&self, now: Instant) -> bool {
+        self.resume_grace_until.is_some_and(|until| now < until)
+    }
+
+    fn probe_suspects(&mut self) {
+        if !self.config.probe_suspects_every_tick {
+            return;
+        }
+
+        for member in self.members.suspected_members() {
+            if let Some(target) = member.remote_host() {
+                self.reactions
+                    .push_back(ArtilleryClusterRequest::React(TargetedRequest {
+                        request: Request::Heartbeat,
+                        target,
+                    }));
+            }
+
+            self.send_ping_requests(&member);
+        }
+    }
+
+    fn full_sync_if_due(&mut self) {
+        let now = self.now();
+        let due = self
+            .config
+            .full_sync_interval
+            .is_some_and(|interval| now.saturating_duration_since(self.last_full_sync) >= interval);
+        if !due && !self.full_sync_requested {
+            return;
+        }
+        self.last_full_sync = now;
+        self.full_sync_requested = false;
+
+        if let Some(target) = self
+            .members
+            .sample_members(1, |_| true)
+            .pop()
+            .and_then(|m| m.remote_host())
+        {
+            log_dissemination!(Debug, "Starting full state sync with {}", target);
+            if self.config.tcp_sync {
+                self.start_tcp_sync(target);
+            } else {
+                self.send_full_state(target, true);
+            }
+        }
+    }
+
+    fn start_tcp_sync(&mut self, target: SocketAddr) {
+        let message = self.full_state_message(true);
+        self.connect_tcp(target, &message, true);
+    }
+
+    /// Full state of this node, asking for the state of the peer in return if `reply`.
+    fn full_state_message(&self, reply: bool) -> ArtilleryMessage {
+        self.direct_message(Request::Sync {
+            members: self.members.all_members(),
+            reply,
+        })
+    }
+
+    /// Sends the message over a TCP connection to the listen address of the target, which
+    /// answers on the connection if `answered`.
+    fn connect_tcp(&mut self, target: SocketAddr, message: &ArtilleryMessage, answered: bool) {
+        let deadline = self.now() + CONST_TCP_TIMEOUT;
+        let port = self.config.listen_addr.port();
+        let connected = seal_message(&self.config, message)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+            .and_then(|sealed| {
+                let stream = TcpStream::connect(target)?;
+                TcpExchange::connect(stream, target, port, &sealed, answered, deadline)
+            });
+
+        match connected {
+            Ok(exchange) => self.tcp_exchanges.push(exchange),
+            Err(e) => {
+                log_transport!(Debug, "Sending to {} over TCP failed: {}", target, e);
+                self.diagnose(ClusterDiagnostic::SendError {
+                    to: target,
+                    reason: e.to_string(),
+                });
+            }
+        }
+    }
+
+    /// Awaits the messages of the peers connecting over TCP.
+    fn accept_tcp(&mut self) {
+        let deadline = self.now() + CONST_TCP_TIMEOUT;
+        loop {
+            let accepted = match self.tcp_listener.as_ref() {
+                Some(listener) => listener.accept(),
+                None => return,
+            };
+
+            match accepted {
+                Ok((stream, peer)) => self
+                    .tcp_exchanges
+                    .push(TcpExchange::accept(stream, peer, deadline)),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    log_transport!(Warn, "Accepting a TCP connection failed: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// The connections aren't polled, they progress every round and on every datagram.
+    fn progress_tcp(&mut self) {
+        let now = self.now();
+        let mut pending = Vec::new();
+        let mut received = Vec::new();
+        for mut exchange in self.tcp_exchanges.drain(..) {
+            match exchange.progress(now) {
+                TcpProgress::Pending => pending.push(exchange),
+                TcpProgress::Received(from, message) => {
+                    received.push((pending.len(), from, message));
+                    pending.push(exchange);
+                }
+                TcpProgress::Done => {}
+                TcpProgress::Failed(e) => {
+                    log_transport!(Debug, "TCP exchange with {} failed: {}", exchange.peer(), e)
+                }
+            }
+        }
+        self.tcp_exchanges = pending;
+
+        for (exchange, from, message) in received {
+            if let Some(answer) = self.receive_over_tcp(from, &message) {
+                self.answer_over_tcp(exchange, &answer);
+            }
+        }
+    }
+
+    /// Handles the message like a datagram, but the state syncs. Returns the answer due.
+    fn receive_over_tcp(&mut self, from: SocketAddr, sealed: &[u8]) -> Option<ArtilleryMessage> {
+        self.rounds.received(sealed.len());
+        let message = match open_message(&self.config, sealed) {
+            Ok(message) => message,
+            Err(e) => {
+                log_transport!(Warn, "Dropping undecodable message from {}: {}", from, e);
+                self.diagnose(ClusterDiagnostic::DecodeFailure {
+                    from,
+                    reason: e.to_string(),
+                });
+                return None;
+            }
+        };
+        self.tap(TapDirection::Inbound, from, sealed, &message);
+
+        if !matches!(message.request, Request::Sync { .. }) {
+            self.reactions
+                .push_back(ArtilleryClusterRequest::Respond(from, Box::new(message)));
+            return None;
+        }
+        if message.cluster_key != self.config.cluster_key {
+            log_transport!(Warn, "Dropping the state of {} from another cluster", from);
+            return None;
+        }
+
+        if let Request::Sync { members, reply } = message.request {
+            self.rejoin_answered = true;
+            self.merge_remote_state(members, message.sender, Some(from));
+            if reply {
+                return Some(self.full_state_message(false));
+            }
+        }
+        None
+    }
+
+    fn answer_over_tcp(&mut self, index: usize, answer: &ArtilleryMessage) {
+        let port = self.config.listen_addr.port();
+        let sealed = match seal_message(&self.config, answer) {
+            Ok(sealed) => sealed,
+            Err(e) => {
+                log_transport!(Error, "Unable to encode the answer over TCP: {}", e);
+                return;
+            }
+        };
+
+        if let Some(exchange) = self.tcp_exchanges.get_mut(index) {
+            if let Err(e) = exchange.answer(port, &sealed) {
+                log_transport!(Warn, "Unable to answer {} over TCP: {}", exchange.peer(), e);
+            }
+        }
+    }
+
+    /// Multicasts the digest of the next members, every round summarizes another chunk.
+    fn multicast_digest(&mut self) {
+        let group = match self.config.multicast_digests {
+            Some(group) => SocketAddr::V4(group),
+            None => return,
+        };
+
+        let mut members = self.members.all_members();
+        members.sort_by_key(ArtilleryMember::host_key);
+        if self.digest_cursor >= members.len() {
+            self.digest_cursor = 0;
+        }
+        let chunk: Vec<ArtilleryMember> = members
+            .into_iter()
+            .skip(self.digest_cursor)
+            .take(CONST_DIGEST_CHUNK)
+            .collect();
+        self.digest_cursor += chunk.len();
+
+        self.send_direct(group, Request::Digest(MemberDigest::of(&chunk)));
+    }
+
+    /// Multicast loops our own digests back.
+    fn is_own_digest(&self, message: &ArtilleryMessage) -> bool {
+        message.sender == self.host_key && message.run == self.members.current_run_id()
+    }
+
+    fn send_full_state(&mut self, target: SocketAddr, reply: bool) {
+        let members = self.members.all_members();
+
+        for (i, chunk) in members.chunks(CONST_SYNC_CHUNK).enumerate() {
+            self.reactions
+                .push_back(ArtilleryClusterRequest::React(TargetedRequest {
+                    request: Request::Sync {
+                        members: chunk.to_vec(),
+                        // Only the first chunk asks for the remote state in return
+                        reply: reply && i == 0,
+                    },
+                    target,
+                }));
+        }
+    }
+
+    fn prune_timed_out_responses(&mut self) {
+        let now = self.now();
+
+        let (remaining, expired): (Vec<_>, Vec<_>) = self
+            .pending_responses
+            .iter()
+            .cloned()
+            .partition(|&(t, _, _)| t >= now);
+
+        let expired_hosts: HashSet<SocketAddr> = expired.iter().map(|&(_, a, _)| a).collect();
+
+        self.pending_responses = remaining;
+
+        // Indirect probes of a target which didn't answer us either go unanswered
+        for host in &expired_hosts {
+            self.wait_list.remove(host);
+        }
+
+        // Re-probes of the members suspected already are evidence towards their failure
+        for host in &expired_hosts {
+            if self.members.is_suspect(host) {
+                self.suspicions.start(*host, now);
+                self.suspicions.direct_probe_failed(host);
+            }
+        }
+
+        let suspect = self.members.time_out_nodes(&expired_hosts);
+        self.enqueue_state_change(&suspect);
+
+        for member in suspect {
+            if let Some(host) = member.remote_host() {
+                self.suspicions.start(host, now);
+                if let Some(via) = self.nat.start(host) {
+                    log_transport!(Debug, "Reaching {} through rendezvous {}", host, via);
+                }
+            }
+            self.send_ping_requests(&member);
+            self.send_member_event(ArtilleryMemberEvent::SuspectedDown(
+                member.clone(),
+                TransitionReason::DirectTimeout,
+            ));
+        }
+
+        if self.in_resume_grace(now) || self.loop_slow {
+            return;
+        }
+
+        let policy = SuspicionPolicy {
+            timeout: self.config.suspicion_timeout,
+            direct_probes: self.config.suspicion_direct_probes,
+            indirect_probes: self.config.suspicion_indirect_probes,
+        };
+        let wall_clock = Timestamp::now();
+        let members = &self.members;
+        let (maintained, escalated): (Vec<_>, Vec<_>) = self
+            .suspicions
+            .escalations(now, &policy)
+            .into_iter()
+            .partition(|(host, _)| {
+                members
+                    .member_at(host)
+                    .is_some_and(|m| m.is_under_maintenance(wall_clock))
+            });
+        // Members in maintenance stay suspect, the evidence is collected again after the ETA
+        for (host, _) in maintained {
+            self.suspicions.start(host, now);
+        }
+
+        let down: Vec<(ArtilleryMember, TransitionReason)> = escalated
+            .iter()
+            .filter_map(|(host, reason)| Some((self.members.mark_down(host)?, *reason)))
+            .collect();
+        let went_down: Vec<ArtilleryMember> = down.iter().map(|(m, _)| m.clone()).collect();
+        self.journal(&went_down);
+        self.enqueue_state_change(&went_down);
+
+        for (member, reason) in down {
+            self.send_member_event(ArtilleryMemberEvent::WentDown(member, reason));
+        }
+    }
+
+    fn send_ping_requests(&mut self, target: &ArtilleryMember) {
+        if let Some(target_host) = target.remote_host() {
+            let deadline = self.now() + self.config.ping_timeout;
+
+            for relay in self.members.hosts_for_indirect_ping(
+                self.config.ping_request_host_count,
+                &target_host,
+                self.config.indirect_ping_strategy,
+            ) {
+                self.suspicions.indirect_probe_sent(&target_host, deadline);
+                self.reactions
+                    .push_back(ArtilleryClusterRequest::React(TargetedRequest {
+                        request: Request::Ping(EncSocketAddr::from_addr(&target_host)),
+                        target: relay,
+                    }));
+            }
+        }
+    }
+
+    fn process_internal_request(&mut self, message: ArtilleryClusterRequest) -> Option<Sender<()>> {
+        use ArtilleryClusterRequest::*;
+
+        match message {
+            AddSeed(seed) => self.add_seed(seed),
+            SeedResolved(seed, addrs) => {
+                let now = self.now();
+                let reachable: Vec<SocketAddr> = addrs
+                    .into_iter()
+                    .filter_map(|addr| self.peer_addr(addr))
+                    .collect();
+                if let Some(host_seed) = self.host_seeds.iter_mut().find(|s| *s.addr() == seed) {
+                    log_transport!(Debug, "Seed {} resolved to {:?}", seed, reachable);
+                    host_seed.resolved(reachable, now);
+                }
+            }
+            Respond(src_addr, message) => self.respond_to_message(src_addr, *message),
+            React(request) => {
+                self.prune_timed_out_responses();
+                self.process_request(&request);
+            }
+            LeaveCluster => self.leave(),
+            Rejoin => self.rejoin_cluster(),
+            ConfirmDown(id) => self.confirm_down(id, TransitionReason::Confirmed),
+            ExternalFailure(id) => self.on_external_failure(id),
+            ExternalAlive(id) => self.on_external_alive(id),
+            InstallKey(key, tx) => {
+                let _ = tx.send(self.keyring().install(key));
+            }
+            UseKey(key, tx) => {
+                let used = self.keyring().promote(&key);
+                if used.is_ok() {
+                    log_transport!(Info, "Sealing the messages with another key");
+                }
+                let _ = tx.send(used);
+            }
+            RemoveKey(key, tx) => {
+                let _ = tx.send(self.keyring().remove(&key));
+            }
+            Annotate(member, key, value) => {
+                let annotation = Annotation::new(member, key, value, self.host_key);
+                if self.annotations.permits(&annotation) {
+                    if let Some(applied) = self.annotations.apply(annotation) {
+                        self.on_annotation(applied);
+                    }
+                } else {
+                    log_dissemination!(
+                        Warn,
+                        "Annotation policy only allows member {} to annotate itself",
+                        member
+                    );
+                }
+            }
+            TryLock(name, ttl, tx) => {
+                let lease = self
+                    .locks
+                    .try_lock(&name, self.host_key, ttl, self.wall_now());
+                self.arbitrate_locks();
+                let _ = tx.send(lease);
+            }
+            Unlock(name) => {
+                self.locks.unlock(&name, self.host_key, self.wall_now());
+            }
+            LockHolder(name, tx) => {
+                let _ = tx.send(self.locks.holder(&name, self.wall_now()));
+            }
+            ImportMembers(members) => self.import_members(members),
+            SampleMembers(k, filter, tx) => {
+                let _ = tx.send(self.members.sample_members(k, |m| filter(m)));
+            }
+            PendingOutbound(tx) => {
+                let _ = tx.send(self.pending_outbound());
+            }
+            SeedContacts(tx) => {
+                let _ = tx.send(self.seed_tracker.contacts());
+            }
+            OrderShutdown(selector, tx) => {
+                let signed = self
+                    .config
+                    .operator_key
+                    .clone()
+                    .map(|key| ShutdownOrder::new(key, selector, Utc::now()));
+                let _ = tx.send(signed.as_ref().map(ShutdownOrder::id));
+                if let Some(order) = signed {
+                    self.apply_shutdown_order(&order);
+                }
+            }
+            LocalMember(tx) => {
+                if let Some(myself) = self.members.myself() {
+                    let _ = tx.send(myself.clone());
+                }
+            }
+            AdvertisedAddr(tx) => {
+                let _ = tx.send(self.advertised_addr());
+            }
+            GetMembers(tx) => {
+                let _ = tx.send(self.members.available_nodes());
+            }
+            GetMembersPage(after, limit, tx) => {
+                let _ = tx.send(self.members.page(after, limit));
+            }
+            Health(tx) => {
+                let _ = tx.send(self.health());
+            }
+            SetHeartbeatPayload(payload) => self.heartbeat_payload = payload,
+            SetMaintenance(maintenance, eta) => {
+                log_detector!(Info, "Maintenance {}, until {:?}", maintenance, eta);
+                let myself = self.members.set_self_maintenance(maintenance, eta);
+                self.enqueue_state_change(&[myself]);
+            }
+            UpdateMetadata(metadata) => {
+                let myself = self.members.set_self_metadata(metadata);
+                log_dissemination!(
+                    Debug,
+                    "Metadata updated to version {}",
+                    myself.metadata_version()
+                );
+                self.enqueue_state_change(&[myself]);
+            }
+            OnHeartbeatPayload(callback) => self.heartbeat_callback = Some(callback),
+            ScheduleRemoval(id, members) => {
+                let scheduled = RemovalTransaction::new(id, self.host_key, members);
+                if let Some(txn) = self.removal_transactions.apply(scheduled) {
+                    self.on_removal_transaction(txn);
+                }
+            }
+            FinishRemoval(id, status) => {
+                if let Some(txn) = self.removal_transactions.finish(&id, status) {
+                    self.on_removal_transaction(txn);
+                } else {
+                    log_dissemination!(
+                        Warn,
+                        "Removal transaction {} is unknown or already finished",
+                        id
+                    );
+                }
+            }
+            Payload(id, msg) => {
+                if let Some(target_peer) = self.members.get_member(&id) {
+                    if !target_peer.is_remote() {
+                        log_transport!(Error, "Current node can't send payload to self over LAN");
+                        return None;
+                    }
+
+                    self.process_request(&TargetedRequest {
+                        request: Request::Payload(id, msg),
+                        target: target_peer
+                            .remote_host()
+                            .expect("Expected target peer addr"),
+                    });
+                    return None;
+                }
+                log_transport!(
+                    Warn,
+                    "Unable to find the peer with an id - {} to send the payload",
+                    id
+                );
+            }
+            BroadcastPayload(data) => {
+                let payload = UserPayload::new(self.host_key, data, Utc::now());
+                log_dissemination!(Debug, "Broadcasting the payload {}", payload.id());
+                self.user_payloads.apply(payload, Utc::now());
+            }
+            Call(member, method, body, timeout, reply) => {
+                let target = if let Some(target) = self.live_member_addr(&member) {
+                    target
+                } else {
+                    let _ = reply.send(Err(ArtilleryError::Rpc(format!(
+                        "{} is not a live member",
+                        member
+                    ))));
+                    return None;
+                };
+
+                let id = Uuid::new_v4();
+                self.rpc
+                    .await_response(id, member, reply, self.now() + timeout);
+                self.process_request(&TargetedRequest {
+                    request: Request::RpcCall(id, method, body),
+                    target,
+                });
+            }
+            OnRpc(method, handler) => self.rpc.register(method, handler),
+            SendMessage(id, member, data) => {
+                let outgoing = OutgoingMessage { id, member, data };
+                if self.send_message(&outgoing) {
+                    self.mailbox.sent(outgoing, self.now());
+                } else {
+                    self.message_undelivered(&outgoing);
+                }
+            }
+            Exit(tx) => return Some(tx),
+        };
+
+        None
+    }
+
+    pub(crate) fn respond_to_message(
+        &mut self,
+        src_addr: SocketAddr,
+        mut message: ArtilleryMessage,
+    ) {
+        let relay = message.relay.take();
+
+        match relay.filter(|_| message.cluster_key == self.config.cluster_key) {
+            Some(RelayEnvelope { to, from: None }) => {
+                self.note_inbound();
+                self.forward_relayed(src_addr, to, message);
+            }
+            Some(RelayEnvelope {
+                from: Some(origin), ..
+            }) => {
+                self.nat.relayed_from(origin, src_addr);
+                self.process_message(origin, message);
+            }
+            None => {
+                if self.nat.direct(&src_addr) {
+                    log_transport!(Info, "Reaching {} directly again", src_addr);
+                }
+                self.process_message(src_addr, message);
+            }
+        }
+    }
+
+    fn process_message(&mut self, src_addr: SocketAddr, message: ArtilleryMessage) {
+        use Request::*;
+
+        self.note_inbound();
+
+        if message.cluster_key == self.config.cluster_key {
+            if self.is_identity_conflict(&message) {
+                self.on_identity_conflict(src_addr);
+                return;
+            }
+
+            let sender = message.sender;
+            let mut state_changes = message.state_changes;
+            if let Some(greeting) = message.hello.and_then(|g| g.of_sender(sender)) {
+                state_changes.insert(0, ArtilleryStateChange::new(greeting.member().clone()));
+                self.on_greeting(greeting);
+            }
+            self.apply_state_changes(state_changes, sender, src_addr);
+            remove_potential_seed(&mut self.seed_queue, src_addr);
+            for seed in self.host_seeds.iter_mut().filter(|s| s.is_at(src_addr)) {
+                seed.answered();
+            }
+            let answered_at = self.now();
+            for contact in self.seed_tracker.answered(src_addr, answered_at) {
+                log_transport!(
+                    Info,
+                    "Seed {} answered after {} attempts",
+                    contact.seed,
+                    contact.attempts
+                );
+                self.send_member_event(ArtilleryMemberEvent::SeedContacted(contact));
+            }
+
+            self.ensure_node_is_member(src_addr, message.sender);
+
+            if let Some(payload) = message.heartbeat {
+                self.deliver_heartbeat_payload(message.sender, &payload);
+            }
+
+            for gossiped in message.transactions {
+                if let Some(txn) = self.removal_transactions.apply(gossiped) {
+                    self.on_removal_transaction(txn);
+                }
+            }
+
+            for annotation in message.annotations {
+                if let Some(applied) = self.annotations.apply(annotation) {
+                    self.on_annotation(applied);
+                }
+            }
+
+            for notice in message.reaps {
+                self.reaper.apply(notice);
+            }
+
+            for lease in message.locks {
+                self.apply_lease(lease);
+            }
+
+            for order in &message.shutdown {
+                self.apply_shutdown_order(order);
+            }
+
+            for payload in &message.payloads {
+                self.apply_user_payload(payload);
+            }
+
+            if self.config.track_reachability {
+                for report in message.reachability {
+                    self.reachability.apply(report);
+                }
+            }
+
+            if let (Heartbeat, Some(digest)) = (&message.request, &message.digest) {
+                self.answer_digest(src_addr, digest);
+            }
+
+            let response = match message.request {
+                Heartbeat => Some(TargetedRequest {
+                    request: Ack,
+                    target: src_addr,
+                }),
+                Ack => {
+                    self.rounds.ack_received();
+                    self.rejoin_answered = true;
+                    self.greeted.insert(src_addr);
+                    self.ack_response(src_addr);
+                    self.mark_node_alive(src_addr, message.run);
+                    None
+                }
+                Ping(dest_addr) => {
+                    let EncSocketAddr(dest_addr) = dest_addr;
+                    let now = self.now();
+                    add_to_wait_list(
+                        &mut self.wait_list,
+                        dest_addr,
+                        src_addr,
+                        now,
+                        self.config.max_waiting_per_target,
+                    );
+                    Some(TargetedRequest {
+                        request: Heartbeat,
+                        target: dest_addr,
+                    })
+                }
+                AckHost(member) => {
+                    let host = member.remote_host().unwrap();
+                    self.ack_response(host);
+                    self.mark_node_alive(host, member.run_id().map(Uuid::from));
+                    self.relay_if_only_indirect(host, src_addr);
+                    None
+                }
+                Introduce(EncSocketAddr(peer)) => {
+                    if self.members.has_member(&peer) {
+                        Some(TargetedRequest {
+                            request: Punch(EncSocketAddr(src_addr)),
+                            target: peer,
+                        })
+                    } else {
+                        None
+                    }
+                }
+                Punch(EncSocketAddr(peer)) => {
+                    self.send_direct(peer, Heartbeat);
+                    None
+                }
+                Payload(peer_id, msg) => {
+                    if let Some(member) = self.members.get_member(&peer_id) {
+                        self.send_member_event(ArtilleryMemberEvent::Payload(member, msg));
+                    } else {
+                        log_transport!(
+                            Warn,
+                            "Got payload request from an unknown peer {}",
+                            peer_id
+                        );
+                    }
+                    None
+                }
+                Message(id, data) => {
+                    if let Some(member) = self.members.get_member(&message.sender) {
+                        if self.mailbox.delivered(id, self.now()) {
+                            self.send_member_event(ArtilleryMemberEvent::MemberMessage(
+                                member, data,
+                            ));
+                        }
+                        Some(TargetedRequest {
+                            request: MessageAck(id),
+                            target: src_addr,
+                        })
+                    } else {
+                        log_transport!(
+                            Warn,
+                            "Got the message {} of an unknown member {}",
+                            id,
+                            message.sender
+                        );
+                        None
+                    }
+                }
+                MessageAck(id) => {
+                    self.mailbox.acknowledged(id);
+                    None
+                }
+                RpcCall(id, method, body) => {
+                    let handled = match self.members.get_member(&message.sender) {
+                        Some(caller) => self.rpc.handle(&method, &caller, &body),
+                        None => Err(format!("{} is not a member", message.sender)),
+                    };
+                    let response = handled.and_then(|r| {
+                        if r.len() > CONST_MESSAGE_LIMIT {
+                            Err(format!(
+                                "response of {} bytes, limit is {}",
+                                r.len(),
+                                CONST_MESSAGE_LIMIT
+                            ))
+                        } else {
+                            Ok(r)
+                        }
+                    });
+                    Some(TargetedRequest {
+                        request: RpcReply(id, response),
+                        target: src_addr,
+                    })
+                }
+                RpcReply(id, response) => {
+                    if !self.rpc.respond(id, message.sender, response) {
+                        log_transport!(Debug, "Dropping the late or unknown response {}", id);
+                    }
+                    None
+                }
+                Sync { members, reply } => {
+                    self.rejoin_answered = true;
+                    let full_state = members.into_iter().map(ArtilleryStateChange::new).collect();
+                    self.apply_state_changes(full_state, message.sender, src_addr);
+
+                    if reply {
+                        self.send_full_state(src_addr, false);
+                    }
+                    None
+                }
+                Digest(digest) => {
+                    let missing = digest.missing_from(&self.members.to_map(), self.host_key);
+                    if missing.is_empty() {
+                        None
+                    } else {
+                        log_dissemination!(
+                            Debug,
+                            "Pulling {} members from the digest of {}",
+                            missing.len(),
+                            src_addr
+                        );
+                        Some(TargetedRequest {
+                            request: Pull(missing),
+                            target: src_addr,
+                        })
+                    }
+                }
+                Pull(keys) => {
+                    let members: Vec<ArtilleryMember> = keys
+                        .iter()
+                        .take(CONST_SYNC_CHUNK)
+                        .filter_map(|key| self.members.get_member(key))
+                        .collect();
+                    if members.is_empty() {
+                        None
+                    } else {
+                        Some(TargetedRequest {
+                            request: Sync {
+                                members,
+                                reply: false,
+                            },
+                            target: src_addr,
+                        })
+                    }
+                }
+            };
+
+            if let Some(response) = response {
+                self.reactions
+                    .push_back(ArtilleryClusterRequest::React(response))
+            }
+        } else {
+            let their_name = if message.cluster_name.is_empty() {
+                "(unnamed)"
+            } else {
+                message.cluster_name.as_str()
+            };
+
+            log_transport!(
+                Error,
+                "Node {} at {} tried to join cluster '{}' but we are '{}', mismatching cluster keys, ignoring message",
+                message.sender,
+                src_addr,
+                their_name,
+                self.config.cluster_name
+            );
+            self.diagnose(ClusterDiagnostic::KeyMismatch {
+                from: src_addr,
+                sender: message.sender.into(),
+                cluster_name: message.cluster_name.clone(),
+            });
+        }
+    }
+
+    /// Adds the unknown members as suspects and probes them, they turn alive on the first ack.
+    fn import_members(&mut self, members: Vec<(NodeId, SocketAddr)>) {
+        for (node, addr) in members {
+            let id = Uuid::from(node);
+            if id == self.host_key || self.members.has_member(&addr) {
+                continue;
+            }
+
+            match self.config.initial_member_state {
+                InitialMemberState::Suspect => self.members.add_member(ArtilleryMember::new(
+                    id,
+                    addr,
+                    0,
+                    ArtilleryMemberState::Suspect,
+                )),
+                InitialMemberState::Unknown => {
+                    if !self.seed_queue.contains(&addr) {
+                        self.seed_queue.push(addr);
+                    }
+                }
+            }
+            self.reactions
+                .push_back(ArtilleryClusterRequest::React(TargetedRequest {
+                    request: Request::Heartbeat,
+                    target: addr,
+                }));
+        }
+    }
+
+    fn enqueue_state_change(&mut self, members: &[ArtilleryMember]) {
+        for member in members {
+            self.change_seq += 1;
+            let change = ArtilleryStateChange::with_id(member.clone(), self.change_seq);
+
+            match self
+                .state_changes
+                .iter_mut()
+                .find(|c| c.member().host_key() == member.host_key())
+            {
+                Some(queued) => *queued = change,
+                None => self.state_changes.push(change),
+            }
+        }
+
+        while self.state_changes.len() > self.config.max_state_changes {
+            if !self.drop_state_change() {
+                break;
+            }
+        }
+    }
+
+    /// Makes room in the state change buffer as configured, returns whether a change was dropped.
+    fn drop_state_change(&mut self) -> bool {
+        let retransmits = self.config.broadcast_retransmits;
+        let droppable = self.state_changes.iter().filter(|c| c.member().is_remote());
+        let oldest = droppable.clone().min_by_key(|c| c.id());
+        let oldest_transmitted = droppable
+            .filter(|c| c.transmissions() >= retransmits)
+            .min_by_key(|c| c.id());
+        let victim = match self.config.state_change_overflow {
+            StateChangeOverflow::DropOldestTransmitted => oldest_transmitted.or(oldest),
+            StateChangeOverflow::FullSync => oldest,
+        }
+        .map(|c| (c.member().host_key(), c.id()));
+
+        let (key, id) = match victim {
+            Some(change) => change,
+            None => return false,
+        };
+        self.state_changes
+            .retain(|c| c.member().host_key() != key || c.id() != id);
+        self.work_stats.dropped_state_changes += 1;
+        if self.config.state_change_overflow == StateChangeOverflow::FullSync {
+            self.full_sync_requested = true;
+        }
+        log_dissemination!(
+            Debug,
+            "State change buffer full, dropped the change of {}",
+            key
+        );
+
+        true
+    }
+
+    fn leave(&mut self) {
+        let myself = self.members.leave();
+        self.view.set_draining(true);
+        self.enqueue_state_change(&[myself]);
+    }
+
+    /// Address of the member if it is a live remote one.
+    fn live_member_addr(&self, member: &Uuid) -> Option<SocketAddr> {
+        self.members
+            .get_member(member)
+            .filter(|m| {
+                matches!(
+                    m.state(),
+                    ArtilleryMemberState::Alive | ArtilleryMemberState::Suspect
+                )
+            })
+            .and_then(|m| m.remote_host())
+    }
+
+    /// Sends the message to the member if it is live, returns whether it was sent.
+    fn send_message(&mut self, message: &OutgoingMessage) -> bool {
+        let target = match self.live_member_addr(&message.member) {
+            Some(target) => target,
+            None => return false,
+        };
+
+        self.process_request(&TargetedRequest {
+            request: Request::Message(message.id, message.data.clone()),
+            target,
+        });
+        true
+    }
+
+    /// Sends the unacknowledged messages again, gives up on those out of retries.
+    fn retry_messages(&mut self) {
+        let now = self.now();
+        self.mailbox.prune(now);
+        let (retries, failed) = self.mailbox.due(now);
+        for message in retries {
+            if !self.send_message(&message) {
+                self.mailbox.acknowledged(message.id);
+                self.message_undelivered(&message);
+            }
+        }
+        for message in failed {
+            self.message_undelivered(&message);
+        }
+    }
+
+    fn message_undelivered(&mut self, message: &OutgoingMessage) {
+        log_transport!(
+            Debug,
+            "Message {} to {} undelivered",
+            message.id,
+            message.member
+        );
+        self.send_member_event(ArtilleryMemberEvent::MessageUndelivered(
+            message.id.into(),
+            message.member.into(),
+        ));
+    }
+
+    /// Gossips the new payloads on, and delivers those of the other members.
+    fn apply_user_payload(&mut self, payload: &UserPayload) {
+        if !self.user_payloads.apply(payload.clone(), Utc::now())
+            || Uuid::from(payload.origin()) == self.host_key
+        {
+            return;
+        }
+
+        match self.members.get_member(&payload.origin().into()) {
+            Some(member) => self.send_member_event(ArtilleryMemberEvent::MemberPayload(
+                member,
+                payload.data().to_vec(),
+            )),
+            None => log_dissemination!(
+                Warn,
+                "Got the payload {} of an unknown member {}",
+                payload.id(),
+                payload.origin()
+            ),
+        }
+    }
+
+    /// Gossips the authentic orders on, and leaves when this node is one of their targets.
+    fn apply_shutdown_order(&mut self, order: &ShutdownOrder) {
+        let operator_key = self.config.operator_key.as_deref();
+        if !self
+            .shutdown_orders
+            .apply(order.clone(), operator_key, Utc::now())
+        {
+            return;
+        }
+
+        let selector: Selector = match order.selector().parse() {
+            Ok(selector) => selector,
+            Err(e) => {
+                log_runtime!(Warn, "Ignoring the shutdown order {}: {}", order.id(), e);
+                return;
+            }
+        };
+        let labels = self.view.read().labels(&self.host_key.into());
+        if self.ordered_shutdown_at.is_some() || !selector.matches(&labels) {
+            return;
+        }
+
+        log_runtime!(
+            Warn,
+            "Leaving the cluster and stopping on the shutdown order {}",
+            order.id()
+        );
+        self.ordered_shutdown_at = Some(self.now() + CONST_SHUTDOWN_ORDER_GRACE);
+        self.send_member_event(ArtilleryMemberEvent::ShutdownOrdered(order.id()));
+        self.leave();
+    }
+
+    /// Stops once the leave and the orders are gossiped, or the grace period is over.
+    fn stop_if_ordered(&mut self) {
+        self.shutdown_orders.prune(Utc::now());
+
+        if let Some(deadline) = self.ordered_shutdown_at {
+            let gossiped = self.pending_outbound() == 0 && self.shutdown_orders.is_gossiped();
+            if gossiped || self.now() >= deadline {
+                log_runtime!(Info, "Stopping the event loop on a shutdown order");
+                self.shutdown.stop();
+            }
+        }
+    }
+
+    /// Resumes the protocol on the same socket after a leave, as a new run of this node.
+    fn rejoin_cluster(&mut self) {
+        if !self.members.has_left() {
+            log_detector!(
+                Warn,
+                "Rejoin requested while still in the cluster, ignoring"
+            );
+            return;
+        }
+
+        log_detector!(Info, "Rejoining the cluster");
+        let myself = self.members.rejoin();
+        self.view.set_draining(false);
+        self.enqueue_state_change(&[myself]);
+        self.rejoin_backoff = Some((self.now(), self.config.ping_interval));
+        self.rejoin_answered = false;
+        self.retry_rejoin();
+        self.start_gossip_burst();
+    }
+
+    /// Contacts the known members and seeds with doubling delays, until somebody answers.
+    fn retry_rejoin(&mut self) {
+        let (next_attempt, delay) = match self.rejoin_backoff {
+            Some(backoff) => backoff,
+            None => return,
+        };
+
+        // The records of the other members outlive the leave, only an answer tells we're back
+        let alive = self
+            .members
+            .myself()
+            .is_some_and(|m| m.state() == ArtilleryMemberState::Alive);
+        if alive && self.rejoin_answered {
+            self.rejoin_backoff = None;
+            return;
+        }
+
+        let now = self.now();
+        if now < next_attempt {
+            return;
+        }
+
+        let mut targets: Vec<SocketAddr> = self
+            .members
+            .available_nodes()
+            .iter()
+            .filter_map(ArtilleryMember::remote_host)
+            .collect();
+        let host_seed_targets = self.host_seeds.iter().filter_map(HostSeed::target);
+        for seed in self.known_seeds.iter().copied().chain(host_seed_targets) {
+            if !targets.contains(&seed) {
+                targets.push(seed);
+            }
+        }
+
+        for target in targets {
+            self.reactions
+                .push_back(ArtilleryClusterRequest::React(TargetedRequest {
+                    request: Request::Heartbeat,
+                    target,
+                }));
+        }
+
+        let next_delay = (delay * 2).min(self.config.rejoin_backoff_limit);
+        self.rejoin_backoff = Some((now + delay, next_delay));
+    }
+
+    fn on_removal_transaction(&mut self, txn: RemovalTransaction) {
+        let leaving =
+            txn.status() == RemovalTransactionStatus::Committed && txn.includes(&self.host_key);
+
+        self.send_member_event(ArtilleryMemberEvent::RemovalTransactionChanged(txn));
+
+        if leaving {
+            log_dissemination!(
+                Info,
+                "Removal of this node is committed, leaving the cluster"
+            );
+            self.leave();
+        }
+    }
+
+    /// Labels and description of a greeting sender are known before its `Joined` event.
+    fn on_greeting(&mut self, greeting: Greeting) {
+        for annotation in greeting.annotations().to_vec() {
+            if let Some(applied) = self.annotations.apply(annotation) {
+                self.on_annotation(applied);
+            }
+        }
+        self.view.update_greeting(greeting);
+    }
+
+    fn on_annotation(&mut self, annotation: Annotation) {
+        let member = Uuid::from(annotation.member());
+        self.view
+            .update_annotations(member, self.annotations.of_member(&member));
+        self.send_member_event(ArtilleryMemberEvent::AnnotationChanged(annotation));
+    }
+
+    fn apply_lease(&mut self, lease: Lease) {
+        if let Some(overruled) = self.locks.apply(lease, self.wall_now()) {
+            if Uuid::from(overruled.holder()) == self.host_key {
+                log_dissemination!(
+                    Warn,
+                    "Lease on lock {} overruled by a conflicting one",
+                    overruled.name()
+                );
+                self.send_member_event(ArtilleryMemberEvent::LockLost(overruled));
+            }
+        }
+    }
+
+    /// The live member with the lowest host key arbitrates the conflicting leases.
+    fn arbitrate_locks(&mut self) {
+        let arbiter = self
+            .members
+            .available_nodes()
+            .iter()
+            .filter(|m| m.state() == ArtilleryMemberState::Alive)
+            .map(ArtilleryMember::host_key)
+            .min();
+
+        if arbiter == Some(self.host_key) {
+            self.locks.arbitrate(self.host_key, self.wall_now());
+        }
+    }
+
+    fn deliver_heartbeat_payload(&self, sender: Uuid, payload: &[u8]) {
+        if payload.len() > CONST_HEARTBEAT_PAYLOAD_LIMIT {
+            log_transport!(
+                Warn,
+                "Ignoring oversized heartbeat payload of {} bytes from {}",
+                payload.len(),
+                sender
+            );
+            return;
+        }
+
+        if let Some(callback) = &self.heartbeat_callback {
+            if let Some(member) = self.members.get_member(&sender) {
+                callback(&member, payload);
+            }
+        }
+    }
+
+    fn ack_response(&mut self, src_addr: SocketAddr) {
+        let mut to_remove = Vec::new();
+        let acker = self.members.member_at(&src_addr).map(|m| m.host_key());
+
+        for &(ref t, ref addr, ref state_changes) in &self.pending_responses {
+            if src_addr != *addr {
+                continue;
+            }
+
+            to_remove.push((*t, *addr, state_changes.clone()));
+
+            if let (Some(journal), Some(by)) = (self.journal.as_mut(), acker) {
+                for change in state_changes {
+                    journal.acked(change.member(), by);
+                }
+            }
+
+            // Newer changes of the same member queued since then stay, journaled ones are
+            // gossiped until every live member acknowledged them
+            let journaled = &self.journal;
+            self.state_changes.retain(|os| {
+                journaled
+                    .as_ref()
+                    .is_some_and(|j| j.is_pending(os.member()))
+                    || !state_changes.iter().any(|is| {
+                        is.member().host_key() == os.member().host_key() && is.id() == os.id()
+                    })
+            })
+        }
+
+        self.pending_responses
+            .retain(|op| !to_remove.iter().any(|ip| ip == op));
+        self.settle_journal();
+    }
+
+    /// Keyring of the encryption, started with the cluster key if none was configured.
+    fn keyring(&mut self) -> &mut Keyring {
+        let cluster_key = &self.config.cluster_key;
+        self.config
+            .keyring
+            .get_or_insert_with(|| Keyring::new(cluster_key.clone()))
+    }
+
+    fn confirm_down(&mut self, id: Uuid, reason: TransitionReason) {
+        if let Some(member) = self.members.confirm_down(&id) {
+            self.journal(std::slice::from_ref(&member));
+            self.enqueue_state_change(std::slice::from_ref(&member));
+            self.send_member_event(ArtilleryMemberEvent::WentDown(member, reason));
+        } else {
+            log_detector!(
+                Warn,
+                "Unable to confirm down for member {}, unknown or local",
+                id
+            );
+        }
+    }
+
+    /// Remote member which may still be refuted, alive or suspect.
+    fn live_remote_member(&self, id: &Uuid) -> Option<(ArtilleryMember, SocketAddr)> {
+        let member = self.members.get_member(id)?;
+        let host = member.remote_host().filter(|_| member.is_remote())?;
+        match member.state() {
+            ArtilleryMemberState::Alive | ArtilleryMemberState::Suspect => Some((member, host)),
+            ArtilleryMemberState::Down | ArtilleryMemberState::Left => None,
+        }
+    }
+
+    fn on_external_failure(&mut self, id: Uuid) {
+        let host = if let Some((_, host)) = self.live_remote_member(&id) {
+            host
+        } else {
+            log_detector!(Debug, "External failure report of {} not live here", id);
+            return;
+        };
+
+        match self.config.external_signals {
+            ExternalSignals::Ignore => {
+                log_detector!(Info, "Ignoring the external failure report of {}", id);
+            }
+            ExternalSignals::Corroborate => {
+                log_detector!(Info, "External failure report of {}", id);
+                let now = self.now();
+                let suspect = self
+                    .members
+                    .time_out_nodes(&[host].iter().copied().collect());
+                self.suspicions.start(host, now);
+                self.suspicions.direct_probe_failed(&host);
+                self.enqueue_state_change(&suspect);
+
+                for member in suspect {
+                    self.send_ping_requests(&member);
+                    self.send_member_event(ArtilleryMemberEvent::SuspectedDown(
+                        member,
+                        TransitionReason::External,
+                    ));
+                }
+            }
+            ExternalSignals::Trust => self.confirm_down(id, TransitionReason::External),
+        }
+    }
+
+    fn on_external_alive(&mut self, id: Uuid) {
+        if self.config.external_signals == ExternalSignals::Ignore {
+            log_detector!(Info, "Ignoring the external alive report of {}", id);
+            return;
+        }
+
+        if let Some((member, host)) = self.live_remote_member(&id) {
+            if member.state() == ArtilleryMemberState::Suspect {
+                log_detector!(
+                    Info,
+                    "External alive report of suspect {}, collecting the evidence again",
+                    id
+                );
+                let now = self.now();
+                self.suspicions.clear(&host);
+                self.suspicions.start(host, now);
+            }
+        }
+    }
+
+    /// Stores the `Down` declarations of this node before they are gossiped.
+    fn journal(&mut self, members: &[ArtilleryMember]) {
+        let journal = match self.journal.as_mut() {
+            Some(journal) => journal,
+            None => return,
+        };
+
+        for member in members {
+            if let Err(e) = journal.record(member) {
+                log_dissemination!(
+                    Warn,
+                    "Unable to journal the change of {}: {}",
+                    member.host_key(),
+                    e
+                );
+            }
+        }
+    }
+
+    /// Gossips the changes journaled by the previous run again.
+    fn replay_journal(&mut self) {
+        let replayed = match self.journal.as_ref() {
+            Some(journal) => journal.pending(),
+            None => return,
+        };
+        if replayed.is_empty() {
+            return;
+        }
+
+        log_dissemination!(Info, "Replaying {} journaled state changes", replayed.len());
+        let changes = replayed
+            .iter()
+            .cloned()
+            .map(ArtilleryStateChange::new)
+            .collect();
+        self.members
+            .apply_state_changes(changes, &self.config.listen_addr);
+        self.enqueue_state_change(&replayed);
+    }
+
+    /// Forgets the journaled changes every live member acknowledged, or superseded since.
+    fn settle_journal(&mut self) {
+        if self.journal.is_none() {
+            return;
+        }
+
+        let live: Vec<Uuid> = self
+            .members
+            .available_nodes()
+            .iter()
+            .filter(|m| m.is_remote() && m.state() != ArtilleryMemberState::Down)
+            .map(ArtilleryMember::host_key)
+            .collect();
+        let members = &self.members;
+        let journal = match self.journal.as_mut() {
+            Some(journal) => journal,
+            None => return,
+        };
+
+        let settled = journal.settle(&live, |change| {
+            members.get_member(&change.host_key()).is_some_and(|m| {
+                m.state() == ArtilleryMemberState::Down
+                    && m.incarnation_number() == change.incarnation_number()
+            })
+        });
+        if let Err(e) = settled {
+            log_dissemination!(Warn, "Unable to update the change journal: {}", e);
+        }
+    }
+
+    fn ensure_node_is_member(&mut self, src_addr: SocketAddr, sender: Uuid) {
+        if self.members.has_member(&src_addr) {
+            return;
+        }
+
+        let new_member = ArtilleryMember::new(sender, src_addr, 0, ArtilleryMemberState::Alive);
+
+        self.members.add_member(new_member.clone());
+        self.enqueue_state_change(std::slice::from_ref(&new_member));
+        self.send_member_event(ArtilleryMemberEvent::Joined(new_member));
+    }
+
+    fn send_member_event(&mut self, event: ArtilleryMemberEvent) {
+        use ArtilleryMemberEvent::*;
+
+        match event {
+            Joined(_)
+            | Payload(..)
+            | RemovalTransactionChanged(_)
+            | Restarted(_)
+            | MemberRestartStorm(..)
+            | AnnotationChanged(_)
+            | Reaped(_)
+            | PartiallyUnreachable(..)
+            | ReachabilityRestored(_)
+            | LocalConnectivityLost
+            | LocalConnectivityRestored
+            | IdentityConflict(_)
+            | LockLost(_)
+            | SeedContacted(_)
+            | ShutdownOrdered(_)
+            | EventLoopSlow(_)
+            | EventLoopRecovered
+            | MemberMetadataChanged(_)
+            | ProtocolRoundSummary(_)
+            | MemberPayload(..)
+            | MemberMessage(..)
+            | MessageUndelivered(..) => {}
+            WentUp(ref m) => {
+                assert_eq!(m.state(), ArtilleryMemberState::Alive);
+                self.rounds.suspicion_settled(m.host_key());
+            }
+            WentDown(ref m, _) => {
+                assert_eq!(m.state(), ArtilleryMemberState::Down);
+                self.rounds.suspicion_settled(m.host_key());
+                self.rpc.fail_calls_to(m.host_key(), "the member went down");
+            }
+            SuspectedDown(ref m, _) => {
+                assert_eq!(m.state(), ArtilleryMemberState::Suspect);
+                self.rounds.suspicion_opened(m.host_key());
+            }
+            Left(ref m) => {
+                assert_eq!(m.state(), ArtilleryMemberState::Left);
+                self.rounds.suspicion_settled(m.host_key());
+                self.rpc.fail_calls_to(m.host_key(), "the member left");
+            }
+        };
+        if matches!(event, WentUp(_) | SuspectedDown(..) | WentDown(..)) {
+            self.health.record_flap(self.now());
+        }
+
+        self.event_seq += 1;
+        let stamp = ArtilleryEventStamp {
+            seq: self.event_seq,
+            timestamp: Timestamp::now(),
+        };
+
+        let members = self.members.available_nodes();
+        self.view.update(members.clone(), stamp);
+        self.event_log.push(&event, stamp);
+        if is_topology_change(&event) || matches!(event, MemberMetadataChanged(_)) {
+            self.export_wireguard_peers(&members);
+        }
+
+        let cluster_event = (members, event, stamp);
+        let burst = is_topology_change(&cluster_event.1);
+        self.publish_to_bridge(&cluster_event);
+        if self.event_tx.send(cluster_event).is_err() {
+            self.on_event_consumer_gone();
+        }
+
+        if burst {
+            self.start_gossip_burst();
+        }
+    }
+
+    fn on_event_consumer_gone(&mut self) {
+        if self.event_consumer_gone {
+            return;
+        }
+        self.event_consumer_gone = true;
+
+        match self.config.event_consumer_gone {
+            EventConsumerPolicy::DropEvents => {}
+            EventConsumerPolicy::LogAndDrop => {
+                log_events!(
+                    Warn,
+                    "Event receiver dropped, events are discarded from now on"
+                )
+            }
+            EventConsumerPolicy::Shutdown => {
+                log_events!(Info, "Event receiver dropped, stopping the event loop");
+                self.shutdown.stop();
+            }
+        }
+    }
+
+    fn note_inbound(&mut self) {
+        self.last_inbound = self.now();
+        if self.connectivity_lost {
+            self.rejoin();
+        }
+    }
+
+    /// Flags this node degraded before the peers declare it down, when sends keep failing
+    /// or nothing comes in while there are live members to hear from.
+    fn check_local_connectivity(&mut self) {
+        if self.connectivity_lost {
+            return;
+        }
+
+        let now = self.now();
+        let timeout = self.config.local_connectivity_timeout;
+        let sends_failing = self
+            .send_failing_since
+            .is_some_and(|since| now.saturating_duration_since(since) >= timeout);
+        let deaf = self.members.has_alive_remote_members()
+            && now.saturating_duration_since(self.last_inbound) >= timeout;
+
+        if sends_failing || deaf {
+            log_detector!(
+                Warn,
+                "Local connectivity lost for {:?}, marking self degraded",
+                timeout
+            );
+            self.connectivity_lost = true;
+
+            self.set_self_degraded(true);
+            self.send_member_event(ArtilleryMemberEvent::LocalConnectivityLost);
+        }
+    }
+
+    /// Probes everyone known right away and asks for the full state, instead of waiting
+    /// for the regular rounds to catch up with what was missed.
+    fn rejoin(&mut self) {
+        log_detector!(Info, "Local connectivity restored, rejoining the cluster");
+        self.connectivity_lost = false;
+        self.send_failing_since = None;
+
+        self.set_self_degraded(self.loop_slow);
+        self.send_member_event(ArtilleryMemberEvent::LocalConnectivityRestored);
+
+        for member in self.members.all_members() {
+            if member.state() == ArtilleryMemberState::Left {
+                continue;
+            }
+
+            if let Some(target) = member.remote_host() {
+                self.reactions
+                    .push_back(ArtilleryClusterRequest::React(TargetedRequest {
+                        request: Request::Heartbeat,
+                        target,
+                    }));
+            }
+        }
+        self.enqueue_seed_nodes();
+        self.start_gossip_burst();
+
+        if let Some(target) = self
+            .members
+            .sample_members(1, |_| true)
+            .pop()
+            .and_then(|m| m.remote_host())
+        {
+            self.send_full_state(target, true);
+        }
+    }
+
+    /// First round goes out right away, the rest with the next ticks.
+    fn start_gossip_burst(&mut self) {
+        if self.config.gossip_burst_rounds == 0 {
+            return;
+        }
+
+        self.burst_rounds_left = self.config.gossip_burst_rounds;
+        self.gossip_burst_round();
+    }
+
+    fn gossip_burst_round(&mut self) {
+        if self.burst_rounds_left == 0 {
+            return;
+        }
+        self.burst_rounds_left -= 1;
+
+        for member in self
+            .members
+            .sample_members(self.config.gossip_burst_fanout, |_| true)
+        {
+            if let Some(target) = member.remote_host() {
+                self.reactions
+                    .push_back(ArtilleryClusterRequest::React(TargetedRequest {
+                        request: Request::Heartbeat,
+                        target,
+                    }));
+            }
+        }
+    }
+
+    fn export_wireguard_peers(&self, members: &[ArtilleryMember]) {
+        if let Some(export) = self.config.wireguard.as_ref() {
+            if let Err(e) = wireguard::write_wireguard_peers(members, export) {
+                log_runtime!(Warn, "Writing the WireGuard peers failed: {}", e);
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn publish_to_bridge(&mut self, event: &ArtilleryClusterEvent) {
+        if let Some(bridge) = self.event_bridge.as_mut() {
+            bridge.publish(event);
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn publish_to_bridge(&mut self, _event: &ArtilleryClusterEvent) {}
+
+    fn apply_state_changes(
+        &mut self,
+        mut state_changes: Vec<ArtilleryStateChange>,
+        sender: Uuid,
+        from: SocketAddr,
+    ) {
+        self.admit_state_changes(&mut state_changes);
+        let changes = self.members.apply_state_changes(state_changes, &from);
+        self.publish_membership_changes(changes, sender);
+    }
+
+    /// Merges the full member list of a peer, from a state sync over TCP.
+    fn merge_remote_state(
+        &mut self,
+        members: Vec<ArtilleryMember>,
+        sender: Uuid,
+        from: Option<SocketAddr>,
+    ) {
+        let mut state_changes = members.into_iter().map(ArtilleryStateChange::new).collect();
+        self.admit_state_changes(&mut state_changes);
+        let changes = self.members.merge_remote_state(state_changes, from);
+        self.publish_membership_changes(changes, sender);
+    }
+
+    fn admit_state_changes(&mut self, state_changes: &mut Vec<ArtilleryStateChange>) {
+        let reaper = &self.reaper;
+        state_changes.retain(|change| !reaper.is_buried(change.member()));
+        self.probe_identity_claims(state_changes);
+        self.note_advertised_addr(state_changes);
+    }
+
+    fn publish_membership_changes(&mut self, changes: MembershipChanges, sender: Uuid) {
+        self.enqueue_state_change(&changes.new);
+        self.enqueue_state_change(&changes.changed);
+        self.enqueue_state_change(&changes.restarted);
+        self.enqueue_state_change(&changes.refreshed);
+
+        for member in changes.new {
+            self.send_member_event(ArtilleryMemberEvent::Joined(member));
+        }
+
+        for member in changes.changed {
+            self.send_member_event(determine_member_event(member, sender));
+        }
+
+        for member in changes.restarted {
+            self.send_member_event(ArtilleryMemberEvent::Restarted(member.clone()));
+            self.track_restart(&member);
+        }
+
+        for member in changes.metadata {
+            self.send_member_event(ArtilleryMemberEvent::MemberMetadataChanged(member));
+        }
+    }
+
+    /// Another run claiming our host key elsewhere is most likely stale gossip about a
+    /// previous run of this node. Only a live answer from that address is a conflict.
+    fn probe_identity_claims(&mut self, state_changes: &[ArtilleryStateChange]) {
+        let own_run = self.members.current_run_id();
+        let listen_addr = self.config.listen_addr;
+        let claims: Vec<SocketAddr> = state_changes
+            .iter()
+            .filter_map(|change| {
+                let member = change.member();
+                if member.host_key() == self.host_key && member.run_id().map(Uuid::from) != own_run
+                {
+                    member.remote_host().filter(|addr| *addr != listen_addr)
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        for addr in claims {
+            if self.identity_probes.insert(addr) {
+                self.send_direct(addr, Request::Heartbeat);
+            }
+        }
+    }
+
+    /// The peers gossip this run at the address they see, behind a NAT it isn't the
+    /// listen address.
+    fn note_advertised_addr(&mut self, state_changes: &[ArtilleryStateChange]) {
+        let own_run = self.members.current_run_id();
+        let gossiped = state_changes.iter().find_map(|change| {
+            let member = change.member();
+            if member.host_key() == self.host_key && member.run_id().map(Uuid::from) == own_run {
+                member.remote_host()
+            } else {
+                None
+            }
+        });
+
+        if let Some(addr) = gossiped {
+            if self.advertised_addr != Some(addr) {
+                log_transport!(Debug, "Peers see this node at {}", addr);
+                self.advertised_addr = Some(addr);
+            }
+        }
+    }
+
+    fn is_identity_conflict(&self, message: &ArtilleryMessage) -> bool {
+        message.sender == self.host_key
+            && message.run.is_some()
+            && message.run != self.members.current_run_id()
+    }
+
+    /// Reports the node using our host key and tells it, so both sides fail fast.
+    fn on_identity_conflict(&mut self, addr: SocketAddr) {
+        if self.identity_conflict == Some(addr) {
+            return;
+        }
+
+        log_runtime!(
+            Error,
+            "Host key {} is used by the running node at {} too",
+            self.host_key,
+            addr
+        );
+        self.identity_conflict = Some(addr);
+        self.send_direct(addr, Request::Heartbeat);
+        self.send_member_event(ArtilleryMemberEvent::IdentityConflict(addr));
+
+        if self.config.stop_on_identity_conflict {
+            self.shutdown.stop();
+        }
+    }
+
+    /// Announces the removal of the members gone for long enough, and removes the ones
+    /// whose announced time has come.
+    fn reap_gone_members(&mut self) {
+        let reap_after = match self.config.reap_after {
+            Some(reap_after) => reap_after,
+            None => return,
+        };
+        let now = Utc::now();
+        let lead = chrono::Duration::from_std(self.config.reap_announcement_lead)
+            .unwrap_or_else(|_| chrono::Duration::zero());
+
+        for member in self.members.all_members() {
+            let gone = member.state() == ArtilleryMemberState::Down
+                || member.state() == ArtilleryMemberState::Left;
+
+            if member.is_remote()
+                && gone
+                && member.state_change_older_than(reap_after)
+                && !self.reaper.is_scheduled(&member.host_key())
+            {
+                self.reaper.apply(ReapNotice::new(&member, now + lead));
+            }
+        }
+
+        let tombstone_until = reaping::tombstone_until(now, self.config.reap_tombstone_retention);
+        for notice in self.reaper.due(now) {
+            if let Some(member) = self
+                .members
+                .reap(&notice.member().into(), notice.incarnation())
+            {
+                self.reaper.bury(member.clone(), tombstone_until);
+                self.state_changes
+                    .retain(|c| c.member().host_key() != member.host_key());
+                self.send_member_event(ArtilleryMemberEvent::Reaped(member));
+            }
+        }
+
+        self.reaper.prune(now);
+    }
+
+    /// Gossips the members this node only reaches through others, if at all, and reports
+    /// the ones unreachable from part of the cluster.
+    fn track_reachability(&mut self) {
+        if !self.config.track_reachability {
+            return;
+        }
+
+        let members = self.members.all_members();
+        let nat = &self.nat;
+        let unreachable = members
+            .iter()
+            .filter_map(|m| {
+                let host = m.remote_host()?;
+                let unreached =
+                    m.state() == ArtilleryMemberState::Suspect || nat.relay_for(&host).is_some();
+                if unreached {
+                    Some(m.host_key())
+                } else {
+                    None
+                }
+            })
+            .collect();
+        self.reachability
+            .observe(self.host_key, unreachable, Utc::now());
+
+        for change in self.reachability.changes(&members) {
+            match change {
+                ReachabilityChange::Partial(target, observer_keys) => {
+                    if let Some(member) = self.members.get_member(&target) {
+                        let observers: Vec<ArtilleryMember> = observer_keys
+                            .iter()
+                            .filter_map(|k| self.members.get_member(k))
+                            .collect();
+                        log_detector!(
+                            Warn,
+                            "{} is unreachable from {:?} only",
+                            target,
+                            observer_keys
+                        );
+                        self.send_member_event(ArtilleryMemberEvent::PartiallyUnreachable(
+                            member, observers,
+                        ));
+                    }
+                }
+                ReachabilityChange::Restored(target) => {
+                    if let Some(member) = self.members.get_member(&target) {
+                        self.send_member_event(ArtilleryMemberEvent::ReachabilityRestored(member));
+                    }
+                }
+            }
+        }
+    }
+
+    fn track_restart(&mut self, member: &ArtilleryMember) {
+        let threshold = match self.config.restart_storm_threshold {
+            Some(threshold) => threshold,
+            None => return,
+        };
+        let window = self.config.restart_storm_window;
+        let now = self.now();
+
+        let history = self.restart_history.entry(member.host_key()).or_default();
+        history.push_back(now);
+        while history
+            .front()
+            .is_some_and(|&at| now.saturating_duration_since(at) > window)
+        {
+            history.pop_front();
+        }
+
+        let count = history.len();
+        if count > threshold {
+            log_detector!(
+                Warn,
+                "Member {} restarted {} times in {:?}",
+                member.host_key(),
+                count,
+                window
+            );
+            self.send_member_event(ArtilleryMemberEvent::MemberRestartStorm(
+                member.clone(),
+                count,
+                window,
+            ));
+        }
+    }
+
+    fn mark_node_alive(&mut self, src_addr: SocketAddr, run: Option<Uuid>) {
+        self.suspicions.clear(&src_addr);
+        let revived = self.members.mark_node_alive(&src_addr, run);
+
+        // The members waiting on an indirect probe get their answer, alive before or not
+        let waiting = self.wait_list.remove(&src_addr).unwrap_or_default();
+        let known = revived
+            .clone()
+            .or_else(|| self.members.member_at(&src_addr));
+        if let Some(member) = known {
+            for (_, remote) in waiting {
+                self.reactions
+                    .push_back(ArtilleryClusterRequest::React(TargetedRequest {
+                        request: Request::AckHost(member.clone()),
+                        target: remote,
+                    }));
+            }
+        }
+
+        if let Some(member) = revived {
+            self.enqueue_state_change(std::slice::from_ref(&member));
+            self.send_member_event(ArtilleryMemberEvent::WentUp(member));
+        }
+    }
+}
+
+/// Socket receiving the digests multicast to the group, on every interface.
+fn join_multicast(group: SocketAddrV4) -> Result<UdpSocket> {
+    let socket = UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], group.port())))?;
+    socket.join_multicast_v4(group.ip(), &Ipv4Addr::UNSPECIFIED)?;
+
+    Ok(socket)
+}
+
+/// Queued changes in a random order, the least transmitted ones first.
+fn shuffled_state_changes(state_changes: &[ArtilleryStateChange]) -> Vec<ArtilleryStateChange> {
+    let mut shuffled = state_changes.to_vec();
+    math::shuffle_linear(&mut shuffled);
+    // Stable sort keeps the shuffled order within the groups.
+    shuffled.sort_by_key(ArtilleryStateChange::transmissions);
+
+    shuffled
+}
+
+/// Fills the message with the longest prefix of the state changes fitting the MTU. The
+/// encoded size is accounted change by change, the message is never encoded.
+fn build_message(
+    base: ArtilleryMessage,
+    state_changes: &[ArtilleryStateChange],
+    codec: MessageCodec,
+    network_mtu: usize,
+) -> ArtilleryMessage {
+    let mut message = base;
+    let base_len = codec.encode(&message).map_or(usize::MAX, |e| e.len());
+    let mut elements_len: usize = 0;
+
+    for (i, change) in state_changes.iter().enumerate() {
+        flunk!("epidemic-state-change-tail-follow-fp");
+        let change_len = codec.encode(change).map_or(usize::MAX, |e| e.len());
+        let fitting = elements_len.saturating_add(change_len);
+        let total = base_len
+            .saturating_add(fitting)
+            .saturating_add(codec.sequence_overhead(i + 1));
+        if total >= network_mtu {
+            break;
+        }
+
+        elements_len = fitting;
+        message.state_changes.push(change.clone());
+    }
+
+    message
+}
+
+/// Encodes the message with the cluster codec, padded when configured.
+pub(crate) fn encode_datagram(
+    config: &ClusterConfig,
+    message: &ArtilleryMessage,
+) -> Result<Vec<u8>> {
+    let sealed = seal_message(config, message)?;
+
+    match config.padding_bucket {
+        Some(bucket) => codec::pad(&sealed, bucket, config.network_mtu - 1),
+        None => Ok(sealed),
+    }
+}
+
+pub(crate) fn decode_datagram(config: &ClusterConfig, datagram: &[u8]) -> Result<ArtilleryMessage> {
+    let unpadded = match config.padding_bucket {
+        Some(_) => codec::unpad(datagram)?,
+        None => datagram,
+    };
+
+    open_message(config, unpadded)
+}
+
+/// Encodes, compresses and encrypts the message as configured, without padding.
+pub(crate) fn seal_message(config: &ClusterConfig, message: &ArtilleryMessage) -> Result<Vec<u8>> {
+    let tagged = config.codec.tag(config.codec.encode(message)?);
+    #[cfg(feature = "compression")]
+    let encoded = match &config.compression {
+        Some(compression) => compression.compress(&tagged)?,
+        None => tagged,
+    };
+    #[cfg(not(feature = "compression"))]
+    let encoded = tagged;
+    match config.encryption {
+        Encryption::Plaintext => Ok(encoded),
+        Encryption::Permissive | Encryption::Required => {
+            let key = config
+                .keyring
+                .as_ref()
+                .map_or(config.cluster_key.as_slice(), Keyring::primary);
+            GossipCipher::new(key).seal(&encoded)
+        }
+    }
+}
+
+fn open_message(config: &ClusterConfig, unpadded: &[u8]) -> Result<ArtilleryMessage> {
+    let opened = if config.encryption != Encryption::Plaintext && encryption::is_sealed(unpadded) {
+        Some(open_sealed(config, unpadded)?)
+    } else if config.encryption == Encryption::Required {
+        bail!(
+            ArtilleryError::ClusterMessageDecode,
+            "plaintext message refused, encryption is required"
+        );
+    } else {
+        None
+    };
+    let encoded = opened.as_deref().unwrap_or(unpadded);
+
+    #[cfg(feature = "compression")]
+    {
+        if let Some(compression) = &config.compression {
+            let decompressed = compression.decompress(encoded)?;
+            let (codec, message) = MessageCodec::detect(&decompressed)?;
+            return codec.decode(message);
+        }
+    }
+
+    let (codec, message) = MessageCodec::detect(encoded)?;
+    codec.decode(message)
+}
+
+/// Opens with the keys of the keyring in turn, or the cluster key.
+fn open_sealed(config: &ClusterConfig, sealed: &[u8]) -> Result<Vec<u8>> {
+    let keyring = match &config.keyring {
+        Some(keyring) => keyring,
+        None => return GossipCipher::new(&config.cluster_key).open(sealed),
+    };
+
+    let mut failure = None;
+    for key in keyring.keys() {
+        match GossipCipher::new(key).open(sealed) {
+            Ok(message) => return Ok(message),
+            Err(e) => failure = Some(e),
+        }
+    }
+    Err(failure
+        .unwrap_or_else(|| ArtilleryError::ClusterMessageDecode("the keyring has no key".into())))
+}
+
+fn tap_record(direction: TapDirection, peer: SocketAddr, bytes: &[u8]) -> TapRecord {
+    TapRecord {
+        direction,
+        peer,
+        timestamp: Timestamp::now(),
+        bytes: bytes.to_vec(),
+    }
+}
+
+pub(crate) fn classify_work(request: &ArtilleryClusterRequest) -> WorkClass {
+    use ArtilleryClusterRequest::*;
+
+    let bulk = match request {
+        Respond(_, message) => message.request.is_bulk(),
+        React(targeted) => targeted.request.is_bulk(),
+        Payload(..) | BroadcastPayload(_) | SendMessage(..) | Call(..) => true,
+        AddSeed(_)
+        | SeedResolved(..)
+        | LeaveCluster
+        | Rejoin
+        | ConfirmDown(_)
+        | ExternalFailure(_)
+        | ExternalAlive(_)
+        | InstallKey(..)
+        | UseKey(..)
+        | RemoveKey(..)
+        | Annotate(..)
+        | TryLock(..)
+        | Unlock(_)
+        | LockHolder(..)
+        | ImportMembers(_)
+        | SampleMembers(..)
+        | PendingOutbound(_)
+        | SeedContacts(_)
+        | OrderShutdown(..)
+        | LocalMember(_)
+        | AdvertisedAddr(_)
+        | GetMembers(_)
+        | GetMembersPage(..)
+        | Health(_)
+        | SetHeartbeatPayload(_)
+        | SetMaintenance(..)
+        | UpdateMetadata(_)
+        | OnHeartbeatPayload(_)
+        | OnRpc(..)
+        | ScheduleRemoval(..)
+        | FinishRemoval(..)
+        | Exit(_) => false,
+    };
+
+    if bulk {
+        WorkClass::Bulk
+    } else {
+        WorkClass::Critical
+    }
+}
+
+/// A requester asking again only refreshes its request, past the cap the oldest one goes.
+fn add_to_wait_list(
+    wait_list: &mut WaitList,
+    wait_addr: SocketAddr,
+    notify_addr: SocketAddr,
+    now: Instant,
+    cap: usize,
+) {
+    let waiting = wait_list.entry(wait_addr).or_default();
+    waiting.retain(|(_, addr)| *addr != notify_addr);
+    while waiting.len() >= cap {
+        waiting.remove(0);
+    }
+
+    waiting.push((now, notify_addr));
+}
+
+fn prune_wait_list(wait_list: &mut WaitList, now: Instant, ttl: Duration) {
+    wait_list.retain(|_, waiting| {
+        waiting.retain(|(since, _)| now.saturating_duration_since(*since) < ttl);
+        !waiting.is_empty()
+    });
+}
+
+fn remove_potential_seed(seed_queue: &mut Vec<SocketAddr>, src_addr: SocketAddr) {
+    seed_queue.retain(|&addr| addr != src_addr)
+}
+
+/// Changes worth disseminating with a gossip burst.
+pub(crate) fn is_topology_change(event: &ArtilleryMemberEvent) -> bool {
+    use ArtilleryMemberEvent::*;
+
+    match event {
+        Joined(_) | WentDown(..) | Left(_) | Restarted(_) => true,
+        WentUp(_)
+        | SuspectedDown(..)
+        | Payload(..)
+        | RemovalTransactionChanged(_)
+        | MemberRestartStorm(..)
+        | AnnotationChanged(_)
+        | Reaped(_)
+        | PartiallyUnreachable(..)
+        | ReachabilityRestored(_)
+        | LocalConnectivityLost
+        | LocalConnectivityRestored
+        | IdentityConflict(_)
+        | LockLost(_)
+        | SeedContacted(_)
+        | ShutdownOrdered(_)
+        | EventLoopSlow(_)
+        | EventLoopRecovered
+        | MemberMetadataChanged(_)
+        | ProtocolRoundSummary(_)
+        | MemberPayload(..)
+        | MemberMessage(..)
+        | MessageUndelivered(..) => false,
+    }
+}
+
+/// Event of a change gossiped by `sender`.
+fn determine_member_event(member: ArtilleryMember, sender: Uuid) -> ArtilleryMemberEvent {
+    let reason = TransitionReason::Gossip(sender.into());
+    match member.state() {
+        ArtilleryMemberState::Alive => ArtilleryMemberEvent::WentUp(member),
+        ArtilleryMemberState::Suspect => ArtilleryMemberEvent::SuspectedDown(member, reason),
+        ArtilleryMemberState::Down => ArtilleryMemberEvent::WentDown(member, reason),
+        ArtilleryMemberState::Left => ArtilleryMemberEvent::Left(member),
+    }
+}
+
+impl Request {
+    /// User level traffic which can be deferred under overload.
+    fn is_bulk(&self) -> bool {
+        match self {
+            Request::Payload(..)
+            | Request::Sync { .. }
+            | Request::Digest(_)
+            | Request::Pull(_)
+            | Request::Message(..)
+            | Request::RpcCall(..) => true,
+            Request::Heartbeat
+            | Request::Ack
+            | Request::MessageAck(_)
+            | Request::RpcReply(..)
+            | Request::Ping(_)
+            | Request::AckHost(_)
+            | Request::Introduce(_)
+            | Request::Punch(_) => false,
+        }
+    }
+}
+
+impl EncSocketAddr {
     fn from_addr(addr: &SocketAddr) -> Self {
         EncSocketAddr(*addr)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::test_kit::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn stops_on_identity_conflict() {
+        let a = TestPeer::new();
+        let mut tester = ProtocolTester::new();
+        let twin = TestPeer::with_id(tester.host_key());
+
+        tester
+            .recv(
+                heartbeat_from(&a)
+                    .with_state_changes(vec![twin.member(ArtilleryMemberState::Alive)]),
+            )
+            .expect_sent(heartbeat_to(&twin))
+            .recv(ack_from(&twin).with_run(Uuid::new_v4()))
+            .expect_event("identity conflict", |e| {
+                if let ArtilleryMemberEvent::IdentityConflict(addr) = e {
+                    *addr == twin.addr()
+                } else {
+                    false
+                }
+            });
+
+        assert!(!tester.state().is_running());
+        assert!(tester.state().check_identity().is_err());
+    }
+
+    #[test]
+    fn event_consumer_policy_applies_once_the_receiver_is_gone() {
+        let a = TestPeer::new();
+        let mut tester = tester_with(|config| {
+            config.event_consumer_gone = EventConsumerPolicy::LogAndDrop;
+        });
+        tester.drop_events();
+        tester.recv(heartbeat_from(&a)).expect_sent(ack_to(&a));
+        assert!(tester.state().is_running());
+
+        let mut tester = ProtocolTester::new();
+        tester.drop_events();
+        tester.recv(heartbeat_from(&a));
+        assert!(!tester.state().is_running());
+    }
+
+    #[test]
+    fn caps_and_expires_indirect_probe_requests() {
+        let target = TestPeer::new();
+        let requesters: Vec<TestPeer> = (0..3).map(|_| TestPeer::new()).collect();
+        let mut tester = tester_with(|config| {
+            config.ping_timeout = Duration::from_secs(30);
+            config.wait_list_ttl = Duration::from_secs(4);
+            config.max_waiting_per_target = 2;
+            config.suspend_detection_threshold = None;
+        });
+
+        tester.recv(
+            heartbeat_from(&requesters[0])
+                .with_state_changes(vec![target.member(ArtilleryMemberState::Alive)]),
+        );
+        for requester in requesters.iter().chain(requesters.iter()) {
+            tester.recv(ping_from(requester, &target));
+        }
+        assert_eq!(tester.state().footprint().wait_list, 2);
+
+        tester.tick(Duration::from_secs(5));
+        assert_eq!(tester.state().footprint().wait_list, 0);
+    }
+
+    #[test]
+    fn maintenance_keeps_unresponsive_members_from_going_down() {
+        let went_down = |eta: Option<DateTime<Utc>>| {
+            let a = TestPeer::new();
+            let mut maintained = a.member(ArtilleryMemberState::Alive);
+            maintained.set_maintenance(true, eta.map(Timestamp::from));
+            let mut tester = tester_with(|config| {
+                config.suspend_detection_threshold = None;
+            });
+
+            tester.recv(heartbeat_from(&a).with_state_changes(vec![maintained]));
+            for _ in 0..8 {
+                tester.tick(Duration::from_secs(4));
+            }
+
+            let events: Vec<ArtilleryMemberEvent> = tester.drain_events();
+            assert!(events.iter().any(
+                |e| matches!(e, ArtilleryMemberEvent::SuspectedDown(m, _) if m.host_key() == a.id())
+            ));
+            events.iter().any(
+                |e| matches!(e, ArtilleryMemberEvent::WentDown(m, _) if m.host_key() == a.id()),
+            )
+        };
+
+        assert!(!went_down(None));
+        assert!(went_down(Some(Utc::now() - chrono::Duration::seconds(1))));
+    }
+
+    #[test]
+    fn caps_the_state_change_buffer() {
+        let a = TestPeer::new();
+        let others: Vec<TestPeer> = (0..4).map(|_| TestPeer::new()).collect();
+        let mut tester = tester_with(|config| {
+            config.max_state_changes = 2;
+        });
+
+        tester.recv(
+            heartbeat_from(&a).with_state_changes(
+                others
+                    .iter()
+                    .map(|peer| peer.member(ArtilleryMemberState::Alive))
+                    .collect(),
+            ),
+        );
+
+        let state = tester.state();
+        assert_eq!(state.footprint().state_changes, 2);
+        assert_eq!(state.work_stats().dropped_state_changes, 4);
+    }
+
+    #[test]
+    fn slow_ticks_flag_this_node_degraded() {
+        let a = TestPeer::new();
+        let late = Duration::from_secs(2);
+        let mut tester = ProtocolTester::new();
+
+        tester
+            .recv(heartbeat_from(&a))
+            .tick_late(Duration::from_secs(1), late)
+            .tick_late(Duration::from_secs(1), late)
+            .tick_late(Duration::from_secs(1), late)
+            .expect_event("slow event loop", |e| {
+                if let ArtilleryMemberEvent::EventLoopSlow(lag) = e {
+                    *lag == late
+                } else {
+                    false
+                }
+            })
+            .expect_sent(expectation(&a, "degraded self", |m| {
+                m.state_changes
+                    .iter()
+                    .any(|c| c.member().is_current() && c.member().is_degraded())
+            }));
+
+        for _ in 0..3 {
+            tester.tick(Duration::from_secs(1));
+        }
+        tester.expect_event("recovered event loop", |e| {
+            matches!(e, ArtilleryMemberEvent::EventLoopRecovered)
+        });
+    }
+
+    #[test]
+    fn tells_why_members_were_suspected_and_declared_down() {
+        let a = TestPeer::new();
+        let b = TestPeer::new();
+        let mut tester = ProtocolTester::new();
+
+        tester
+            .recv(
+                heartbeat_from(&a).with_state_changes(vec![b.member(ArtilleryMemberState::Alive)]),
+            )
+            .recv(
+                heartbeat_from(&a)
+                    .with_state_changes(vec![b.member(ArtilleryMemberState::Suspect)]),
+            )
+            .expect_event("b suspected on the gossip of a", |e| {
+                if let ArtilleryMemberEvent::SuspectedDown(m, TransitionReason::Gossip(from)) = e {
+                    m.host_key() == b.id() && *from == a.id().into()
+                } else {
+                    false
+                }
+            });
+
+        // Alone with a, no other member can probe it indirectly
+        let mut alone = ProtocolTester::new();
+        alone.recv(heartbeat_from(&a));
+        for _ in 0..6 {
+            alone.tick(Duration::from_secs(4));
+        }
+        let events: Vec<ArtilleryMemberEvent> = alone.drain_events();
+        let suspected = events.iter().find_map(|e| {
+            if let ArtilleryMemberEvent::SuspectedDown(m, reason) = e {
+                Some(*reason).filter(|_| m.host_key() == a.id())
+            } else {
+                None
+            }
+        });
+        let down = events.iter().find_map(|e| {
+            if let ArtilleryMemberEvent::WentDown(m, reason) = e {
+                Some(*reason).filter(|_| m.host_key() == a.id())
+            } else {
+                None
+            }
+        });
+        assert_eq!(suspected, Some(TransitionReason::DirectTimeout));
+        assert_eq!(down, Some(TransitionReason::DirectTimeout));
+    }
+
+    #[test]
+    fn weighs_external_failure_reports_as_configured() {
+        let a = TestPeer::new();
+        let b = TestPeer::new();
+        let mut tester = ProtocolTester::new();
+
+        tester
+            .recv(heartbeat_from(&a))
+            .recv(heartbeat_from(&b))
+            .request(ArtilleryClusterRequest::ExternalFailure(a.id()))
+            .expect_event("a suspected on the report", |e| {
+                if let ArtilleryMemberEvent::SuspectedDown(m, TransitionReason::External) = e {
+                    m.host_key() == a.id()
+                } else {
+                    false
+                }
+            })
+            .expect_sent(ping_request_to(&b));
+
+        let mut trusting = tester_with(|config| {
+            config.external_signals = ExternalSignals::Trust;
+        });
+        trusting
+            .recv(heartbeat_from(&a))
+            .request(ArtilleryClusterRequest::ExternalFailure(a.id()))
+            .expect_event("a down on the report", |e| {
+                if let ArtilleryMemberEvent::WentDown(m, TransitionReason::External) = e {
+                    m.host_key() == a.id()
+                } else {
+                    false
+                }
+            });
+    }
+
+    #[test]
+    fn answers_the_members_of_the_cluster() {
+        let a = TestPeer::new();
+        let b = TestPeer::new();
+        let (tx, rx) = channel();
+
+        ProtocolTester::new()
+            .recv(heartbeat_from(&a))
+            .recv(heartbeat_from(&b))
+            .request(ArtilleryClusterRequest::GetMembers(tx));
+
+        let members = rx.recv().unwrap();
+        assert_eq!(members.len(), 3);
+        assert!(members.iter().any(|m| m.host_key() == a.id()));
+        assert!(members.iter().any(|m| m.host_key() == b.id()));
+    }
+
+    #[test]
+    fn learns_the_advertised_address_from_the_gossip() {
+        let a = TestPeer::new();
+        let mut tester = ProtocolTester::new();
+        let listen_addr = tester.state().config().listen_addr;
+        assert_eq!(tester.state().advertised_addr(), listen_addr);
+
+        let (tx, rx) = channel();
+        tester.request(ArtilleryClusterRequest::LocalMember(tx));
+        let observed: SocketAddr = "203.0.113.7:7946".parse().unwrap();
+        let myself = rx.recv().unwrap().member_by_changing_host(observed);
+        tester.recv(heartbeat_from(&a).with_state_changes(vec![myself]));
+
+        assert_eq!(tester.state().advertised_addr(), observed);
+    }
+
+    #[test]
+    fn announces_the_metadata_updates_of_members() {
+        let a = TestPeer::new();
+        let b = TestPeer::new();
+        let updated = ArtilleryMember::new(b.id(), b.addr(), 1, ArtilleryMemberState::Alive)
+            .with_metadata(
+                vec![("role".to_string(), b"storage".to_vec())]
+                    .into_iter()
+                    .collect(),
+            );
+
+        ProtocolTester::new()
+            .recv(
+                heartbeat_from(&a).with_state_changes(vec![b.member(ArtilleryMemberState::Alive)]),
+            )
+            .recv(heartbeat_from(&a).with_state_changes(vec![updated]))
+            .expect_event("b metadata changed", |e| {
+                if let ArtilleryMemberEvent::MemberMetadataChanged(m) = e {
+                    m.host_key() == b.id() && m.metadata().get("role") == Some(&b"storage".to_vec())
+                } else {
+                    false
+                }
+            });
+    }
+
+    #[test]
+    fn probes_the_initial_members_as_suspects() {
+        let (a, b) = (TestPeer::new(), TestPeer::new());
+        let mut tester = tester_with(|config| {
+            config.initial_members = vec![(a.id().into(), a.addr()), (b.id().into(), b.addr())];
+        });
+        let (tx, rx) = channel();
+        tester
+            .request(ArtilleryClusterRequest::GetMembers(tx))
+            .expect_sent(heartbeat_to(&a))
+            .expect_sent(heartbeat_to(&b));
+
+        let members = rx.recv().unwrap();
+        assert_eq!(members.len(), 3);
+        assert!(members
+            .iter()
+            .filter(|m| m.is_remote())
+            .all(|m| m.state() == ArtilleryMemberState::Suspect));
+
+        tester.recv(ack_from(&a)).expect_event(
+            "a alive once it answered",
+            |e| matches!(e, ArtilleryMemberEvent::WentUp(m) if m.host_key() == a.id()),
+        );
+    }
+
+    #[test]
+    fn probes_suspects_directly_and_indirectly_every_tick() {
+        let (a, c) = (TestPeer::new(), TestPeer::new());
+        let mut tester = tester_with(|config| {
+            config.initial_members = vec![(c.id().into(), c.addr())];
+        });
+        tester.recv(heartbeat_from(&a));
+        tester.take_sent(&a);
+        tester.take_sent(&c);
+
+        for _ in 0..3 {
+            tester.tick(Duration::from_secs(1));
+            assert!(tester
+                .take_sent(&c)
+                .iter()
+                .any(|m| m.request == Request::Heartbeat));
+            assert!(tester
+                .take_sent(&a)
+                .iter()
+                .any(|m| m.request == Request::Ping(EncSocketAddr(c.addr()))));
+        }
+    }
+
+    #[test]
+    fn piggybacks_heartbeat_payloads_both_ways() {
+        let a = TestPeer::new();
+        let (tx, rx) = channel();
+        let mut tester = ProtocolTester::new();
+        let callback: HeartbeatCallback = Arc::new(move |member, payload| {
+            tx.send((member.host_key(), payload.to_vec())).unwrap();
+        });
+
+        tester
+            .request(ArtilleryClusterRequest::OnHeartbeatPayload(callback))
+            .recv(heartbeat_from(&a).with_heartbeat_payload(b"load=3"));
+        assert_eq!(rx.try_recv().unwrap(), (a.id(), b"load=3".to_vec()));
+        tester.take_sent(&a);
+
+        tester
+            .request(ArtilleryClusterRequest::SetHeartbeatPayload(Some(
+                b"load=7".to_vec(),
+            )))
+            .tick(Duration::from_secs(1));
+        let sent = tester.take_sent(&a);
+        let heartbeat = sent
+            .iter()
+            .find(|m| m.request == Request::Heartbeat)
+            .expect("No heartbeat sent");
+        assert_eq!(heartbeat.heartbeat, Some(b"load=7".to_vec()));
+
+        tester
+            .recv(heartbeat_from(&a).with_heartbeat_payload(&[0; 1024]))
+            .request(ArtilleryClusterRequest::SetHeartbeatPayload(None))
+            .tick(Duration::from_secs(1));
+        assert!(rx.try_recv().is_err());
+        assert!(tester
+            .take_sent(&a)
+            .iter()
+            .filter(|m| m.request == Request::Heartbeat)
+            .all(|m| m.heartbeat.is_none()));
+    }
+
+    #[test]
+    fn tells_the_cluster_name_of_mismatching_senders() {
+        let a = TestPeer::new();
+        let mut tester = tester_with(|config| {
+            config.cluster_name = "prod".to_string();
+        });
+
+        tester
+            .recv(heartbeat_from(&a).from_cluster("staging", b"staging key"))
+            .expect_diagnostic("the key mismatch of a", |diagnostic| {
+                matches!(
+                    diagnostic,
+                    ClusterDiagnostic::KeyMismatch { from, cluster_name, .. }
+                        if *from == a.addr() && cluster_name == "staging"
+                )
+            })
+            .expect_no_event("a joined", |e| matches!(e, ArtilleryMemberEvent::Joined(_)));
+        assert!(tester.take_sent(&a).is_empty());
+    }
+
+    #[test]
+    fn exchanges_the_full_state_with_a_peer_periodically() {
+        let (a, b) = (TestPeer::new(), TestPeer::new());
+        let mut tester = tester_with(|config| {
+            config.full_sync_interval = Some(Duration::from_secs(10));
+            config.tcp_sync = false;
+        });
+        let full_sync = |sent: Vec<ArtilleryMessage>| {
+            sent.into_iter().find_map(|m| match m.request {
+                Request::Sync {
+                    members,
+                    reply: true,
+                } => Some(members),
+                _ => None,
+            })
+        };
+        tester.recv(heartbeat_from(&a));
+
+        // a answers the probes meanwhile, it is the only live peer to sync with
+        for _ in 0..9 {
+            tester.tick(Duration::from_secs(1)).recv(ack_from(&a));
+            assert!(full_sync(tester.take_sent(&a)).is_none());
+        }
+        tester.tick(Duration::from_secs(1));
+        let members = full_sync(tester.take_sent(&a)).expect("No full sync sent");
+        assert!(members.iter().any(|m| m.host_key() == a.id()));
+        assert!(members.iter().any(|m| m.host_key() == tester.host_key()));
+
+        tester
+            .recv(sync_from(
+                &a,
+                vec![
+                    a.member(ArtilleryMemberState::Alive),
+                    b.member(ArtilleryMemberState::Alive),
+                ],
+                false,
+            ))
+            .expect_event(
+                "b learnt from the sync",
+                |e| matches!(e, ArtilleryMemberEvent::Joined(m) if m.host_key() == b.id()),
+            );
+    }
+
+    #[test]
+    fn flags_members_restarting_too_often_within_the_window() {
+        let (a, b) = (TestPeer::new(), TestPeer::new());
+        let mut tester = tester_with(|config| {
+            config.restart_storm_threshold = Some(2);
+            config.restart_storm_window = Duration::from_secs(60);
+        });
+        let mut b_run = ArtilleryMember::current(b.id()).member_by_changing_host(b.addr());
+        tester.recv(heartbeat_from(&a).with_state_changes(vec![b_run.clone()]));
+        let mut restart = |tester: &mut ProtocolTester| {
+            b_run.start_new_run();
+            tester.recv(heartbeat_from(&a).with_state_changes(vec![b_run.clone()]));
+        };
+        let storm = |e: &ArtilleryMemberEvent| match e {
+            ArtilleryMemberEvent::MemberRestartStorm(m, restarts, _) => {
+                m.host_key() == b.id() && *restarts == 3
+            }
+            _ => false,
+        };
+
+        restart(&mut tester);
+        restart(&mut tester);
+        tester.expect_no_event("restart storm of b", storm);
+        restart(&mut tester);
+        tester.expect_event("restart storm of b", storm);
+
+        // The earlier restarts fell out of the window
+        tester.tick(Duration::from_secs(61));
+        restart(&mut tester);
+        tester.expect_no_event("restart storm of b", |e| {
+            matches!(e, ArtilleryMemberEvent::MemberRestartStorm(..))
+        });
+    }
+
+    #[test]
+    fn gossips_to_more_peers_for_a_few_rounds_after_a_join() {
+        let peers: Vec<TestPeer> = (0..3).map(|_| TestPeer::new()).collect();
+        let mut tester = tester_with(|config| {
+            config.gossip_burst_rounds = 2;
+            config.gossip_burst_fanout = 3;
+        });
+        let heartbeats_per_round = |tester: &mut ProtocolTester| {
+            tester.tick(Duration::from_secs(1));
+            let mut heartbeats = 0;
+            for peer in &peers {
+                heartbeats += tester
+                    .take_sent(peer)
+                    .iter()
+                    .filter(|m| m.request == Request::Heartbeat)
+                    .count();
+                tester.recv(ack_from(peer));
+            }
+            heartbeats
+        };
+
+        for peer in &peers {
+            tester.recv(heartbeat_from(peer));
+        }
+        // The join started the burst, its second round goes to all the peers
+        assert!(heartbeats_per_round(&mut tester) >= 3);
+        assert_eq!(heartbeats_per_round(&mut tester), 1);
+    }
+
+    #[test]
+    fn degrades_itself_while_cut_off_and_rejoins_once_heard_again() {
+        let a = TestPeer::new();
+        let mut tester = tester_with(|config| {
+            config.local_connectivity_timeout = Duration::from_secs(3);
+        });
+        let degraded = |tester: &mut ProtocolTester| {
+            let (tx, rx) = channel();
+            tester.request(ArtilleryClusterRequest::LocalMember(tx));
+            rx.recv().unwrap().is_degraded()
+        };
+
+        tester
+            .recv(heartbeat_from(&a))
+            .tick(Duration::from_secs(2))
+            .expect_no_event("connectivity lost", |e| {
+                matches!(e, ArtilleryMemberEvent::LocalConnectivityLost)
+            });
+        assert!(!degraded(&mut tester));
+
+        tester
+            .tick(Duration::from_secs(2))
+            .expect_event("connectivity lost", |e| {
+                matches!(e, ArtilleryMemberEvent::LocalConnectivityLost)
+            });
+        assert!(degraded(&mut tester));
+        tester.take_sent(&a);
+
+        tester
+            .recv(heartbeat_from(&a))
+            .expect_event("connectivity restored", |e| {
+                matches!(e, ArtilleryMemberEvent::LocalConnectivityRestored)
+            });
+        assert!(!degraded(&mut tester));
+        tester.expect_sent(heartbeat_to(&a));
+    }
+
+    #[test]
+    fn packs_other_changes_into_the_next_messages_when_shuffling() {
+        // Changes carried by the acks of two heartbeats, more than a datagram holds
+        let carried_in_two_rounds = |shuffle: bool| {
+            let a = TestPeer::new();
+            let mut tester = tester_with(|config| {
+                config.network_mtu = 512;
+                config.gossip_burst_rounds = 0;
+                config.shuffle_state_changes = shuffle;
+            });
+            let gone: Vec<ArtilleryMember> = (0..30)
+                .map(|port| {
+                    let addr = SocketAddr::from(([127, 0, 0, 1], 20000 + port));
+                    ArtilleryMember::new(Uuid::new_v4(), addr, 0, ArtilleryMemberState::Down)
+                })
+                .collect();
+            tester.recv(heartbeat_from(&a).with_state_changes(gone));
+            tester.take_sent(&a);
+
+            let mut rounds = (0..2).map(|_| {
+                tester.recv(heartbeat_from(&a));
+                let sent = tester.take_sent(&a);
+                let ack = sent
+                    .iter()
+                    .find(|m| m.request == Request::Ack)
+                    .expect("No ack sent");
+                assert!(ack.state_changes.len() < 30);
+                ack.state_changes
+                    .iter()
+                    .map(|change| change.member().host_key())
+                    .collect::<HashSet<Uuid>>()
+            });
+            (rounds.next().unwrap(), rounds.next().unwrap())
+        };
+
+        let (first, second) = carried_in_two_rounds(false);
+        assert_eq!(first, second);
+        let (first, second) = carried_in_two_rounds(true);
+        assert!(first.is_disjoint(&second));
+    }
+
+    #[test]
+    fn contacts_unknown_initial_members_without_suspecting_them() {
+        let a = TestPeer::new();
+        let mut tester = tester_with(|config| {
+            config.initial_members = vec![(a.id().into(), a.addr())];
+            config.initial_member_state = InitialMemberState::Unknown;
+        });
+
+        tester
+            .tick(Duration::from_secs(10))
+            .tick(Duration::from_secs(10))
+            .expect_sent(heartbeat_to(&a));
+        let (tx, rx) = channel();
+        tester.request(ArtilleryClusterRequest::GetMembers(tx));
+        assert_eq!(rx.recv().unwrap().len(), 1);
+
+        tester.recv(heartbeat_from(&a)).expect_event(
+            "a joined once reached",
+            |e| matches!(e, ArtilleryMemberEvent::Joined(m) if m.host_key() == a.id()),
+        );
+    }
+
+    #[test]
+    fn drops_the_messages_too_large_for_a_datagram() {
+        let a = TestPeer::new();
+        tester_with(|config| {
+            config.network_mtu = 1024;
+        })
+        .recv(heartbeat_from(&a))
+        .expect_sent(ack_to(&a))
+        .request(ArtilleryClusterRequest::Payload(a.id(), "x".repeat(4096)))
+        .expect_diagnostic("the payload dropped", |diagnostic| {
+            matches!(diagnostic, ClusterDiagnostic::SendError { to, .. } if *to == a.addr())
+        })
+        .recv(heartbeat_from(&a))
+        .expect_sent(ack_to(&a));
+    }
+
+    #[test]
+    fn receives_the_messages_too_large_for_a_datagram_over_tcp() {
+        let a = TestPeer::new();
+        let large = "x".repeat(4096);
+
+        tester_with(|config| {
+            config.network_mtu = 1024;
+            config.tcp_fallback = true;
+        })
+        .recv(heartbeat_from(&a))
+        .recv_over_tcp(payload_from(&a, &large))
+        .expect_event("the payload sent over TCP", |event| {
+            matches!(event, ArtilleryMemberEvent::Payload(m, payload) if m.host_key() == a.id() && payload.len() == 4096)
+        })
+        .recv_over_tcp(heartbeat_from(&a))
+        .expect_sent(ack_to(&a));
+    }
+
+    #[test]
+    fn rejoin_contacts_the_seeds_with_backoff_until_answered() {
+        let seed = TestPeer::new();
+        let mut tester = tester_with(|config| {
+            config.ping_timeout = Duration::from_secs(60);
+            config.rejoin_backoff_limit = Duration::from_secs(4);
+            config.gossip_burst_rounds = 0;
+            config.suspend_detection_threshold = None;
+        });
+        let heartbeats = |tester: &mut ProtocolTester| {
+            tester
+                .take_sent(&seed)
+                .iter()
+                .filter(|m| m.request == Request::Heartbeat)
+                .count()
+        };
+
+        tester
+            .request(ArtilleryClusterRequest::AddSeed(PeerAddr::from(
+                seed.addr(),
+            )))
+            .recv(heartbeat_from(&seed))
+            .request(ArtilleryClusterRequest::LeaveCluster);
+        heartbeats(&mut tester);
+
+        // Every round probes the seed, the rounds due for a retry of the rejoin once more
+        tester.request(ArtilleryClusterRequest::Rejoin);
+        let mut sent = vec![heartbeats(&mut tester)];
+        for _ in 0..7 {
+            tester.tick(Duration::from_secs(1));
+            sent.push(heartbeats(&mut tester));
+        }
+        assert_eq!(sent, vec![1, 2, 1, 2, 1, 1, 1, 2]);
+
+        tester.recv(ack_from(&seed));
+        heartbeats(&mut tester);
+        for _ in 0..4 {
+            tester.tick(Duration::from_secs(1));
+            assert_eq!(heartbeats(&mut tester), 1);
+        }
+    }
+
+    #[test]
+    fn sheds_the_oldest_bulk_work_past_the_queue_capacity() {
+        let a = TestPeer::new();
+        let mut tester = tester_with(|config| {
+            config.max_deferred_work = 2;
+            config.bulk_work_budget = 2;
+        });
+        tester.recv(heartbeat_from(&a)).drain_events();
+
+        tester
+            .recv_all((0..4).map(|i| payload_from(&a, &i.to_string())).collect())
+            .tick(Duration::from_secs(1))
+            .expect_diagnostic("two requests shed", |d| {
+                *d == ClusterDiagnostic::WorkShed {
+                    shed: 2,
+                    backlog: 0,
+                }
+            });
+
+        let payloads: Vec<String> = tester
+            .drain_events()
+            .into_iter()
+            .filter_map(|e| match e {
+                ArtilleryMemberEvent::Payload(_, payload) => Some(payload),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(payloads, vec!["2", "3"]);
+    }
+
+    #[test]
+    fn keeps_the_changes_of_pings_over_the_byte_budget_for_the_next_window() {
+        let (a, b) = (TestPeer::new(), TestPeer::new());
+        let mut tester = tester_with(|config| {
+            config.max_gossip_bytes_per_sec = Some(1);
+            config.gossip_burst_rounds = 0;
+        });
+        let b_id = b.id();
+        let carries_b = |tester: &mut ProtocolTester| {
+            let mut sent = tester.take_sent(&a);
+            sent.extend(tester.take_sent(&b));
+            sent.iter().any(|m| {
+                m.state_changes
+                    .iter()
+                    .any(|c| c.member().host_key() == b_id)
+            })
+        };
+
+        // The ack to a spends the budget of the window, the join of b waits
+        tester.recv(heartbeat_from(&a)).recv(heartbeat_from(&b));
+        tester.tick(Duration::from_millis(100));
+        assert!(!carries_b(&mut tester));
+
+        tester.tick(Duration::from_secs(1));
+        assert!(carries_b(&mut tester));
+    }
+
+    #[test]
+    fn acks_remove_only_the_versions_they_cover() {
+        let (a, c) = (TestPeer::new(), TestPeer::new());
+        let mut tester = tester_with(|config| {
+            config.gossip_burst_rounds = 0;
+        });
+        let c_id = c.id();
+        // Peer probed in the round, with the incarnations of c it was sent
+        let probe = |tester: &mut ProtocolTester| {
+            tester.tick(Duration::from_secs(1));
+            [&a, &c]
+                .iter()
+                .find_map(|peer| {
+                    let sent = tester.take_sent(peer);
+                    let heartbeat = sent.iter().find(|m| m.request == Request::Heartbeat)?;
+                    let carried = heartbeat
+                        .state_changes
+                        .iter()
+                        .filter(|change| change.member().host_key() == c_id)
+                        .map(|change| change.member().incarnation_number())
+                        .collect::<Vec<_>>();
+                    Some(((*peer).clone(), carried))
+                })
+                .expect("No peer probed")
+        };
+
+        tester.recv(
+            heartbeat_from(&a).with_state_changes(vec![c.member(ArtilleryMemberState::Alive)]),
+        );
+        let (probed, carried) = probe(&mut tester);
+        assert_eq!(carried, vec![0]);
+
+        // The ack of the stale version leaves the refreshed one queued
+        let refreshed = ArtilleryMember::new(c_id, c.addr(), 1, ArtilleryMemberState::Alive);
+        tester
+            .recv(heartbeat_from(&a).with_state_changes(vec![refreshed]))
+            .recv(ack_from(&probed));
+        let (probed, carried) = probe(&mut tester);
+        assert_eq!(carried, vec![1]);
+
+        tester.recv(ack_from(&probed));
+        let (_, carried) = probe(&mut tester);
+        assert!(carried.is_empty());
+    }
+
+    #[test]
+    fn stamps_events_in_emission_order() {
+        let (a, b) = (TestPeer::new(), TestPeer::new());
+        let mut tester = ProtocolTester::new();
+        tester.recv(heartbeat_from(&a)).recv(heartbeat_from(&b));
+
+        let stamps = tester.drain_stamps();
+        assert_eq!(stamps.len(), 2);
+        assert_eq!(stamps[1].seq, stamps[0].seq + 1);
+        assert!(stamps[0].timestamp <= stamps[1].timestamp);
+    }
+}
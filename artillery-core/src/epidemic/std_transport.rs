@@ -0,0 +1,202 @@
+use super::transport::Transport;
+use crate::constants::CONST_PACKET_SIZE;
+use mio::{Registry, Token, Waker};
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Datagrams received beyond this and not read yet are dropped, like a full socket buffer.
+const INBOUND_CAPACITY: usize = 4096;
+/// Datagrams queued for the writer thread beyond this make the sends `WouldBlock`.
+const OUTBOUND_CAPACITY: usize = 4096;
+/// How often the blocked reader thread checks whether the transport was dropped.
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+type Datagram = (SocketAddr, Vec<u8>);
+
+#[derive(Default)]
+struct Shared {
+    inbound: Mutex<VecDeque<Datagram>>,
+    waker: Mutex<Option<Waker>>,
+    /// Last send failure of the writer thread, returned by the next send.
+    failure: Mutex<Option<io::Error>>,
+    closed: AtomicBool,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// UDP endpoint on a blocking `std::net::UdpSocket`, for the targets where the mio UDP
+/// sockets are unavailable. A reader thread receives the datagrams and wakes the poll
+/// with a mio `Waker`, a writer thread sends them, so the sends still never block.
+/// `Cluster::new_cluster` binds it instead of the mio socket with the `std-transport`
+/// feature.
+///
+/// Takes the only `Waker` a mio poll supports, like the
+/// [`MemoryTransport`](super::loopback::MemoryTransport).
+pub struct StdUdpTransport {
+    local_addr: SocketAddr,
+    shared: Arc<Shared>,
+    outbound: Option<SyncSender<Datagram>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl StdUdpTransport {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_read_timeout(Some(READ_TIMEOUT))?;
+        let local_addr = socket.local_addr()?;
+        let writer_socket = socket.try_clone()?;
+        let shared = Arc::new(Shared::default());
+        let (outbound, queued) = sync_channel(OUTBOUND_CAPACITY);
+
+        let reader_shared = shared.clone();
+        let reader = thread::Builder::new()
+            .name("artillery-udp-reader".to_string())
+            .spawn(move || read_datagrams(&socket, &reader_shared))?;
+        let writer_shared = shared.clone();
+        let writer = thread::Builder::new()
+            .name("artillery-udp-writer".to_string())
+            .spawn(move || write_datagrams(&writer_socket, &queued, &writer_shared))?;
+
+        Ok(StdUdpTransport {
+            local_addr,
+            shared,
+            outbound: Some(outbound),
+            threads: vec![reader, writer],
+        })
+    }
+}
+
+fn read_datagrams(socket: &UdpSocket, shared: &Shared) {
+    let mut buf = vec![0_u8; CONST_PACKET_SIZE];
+
+    while !shared.closed.load(Ordering::Acquire) {
+        match socket.recv_from(&mut buf) {
+            Ok((len, from)) => {
+                {
+                    let mut inbound = lock(&shared.inbound);
+                    if inbound.len() >= INBOUND_CAPACITY {
+                        continue;
+                    }
+                    inbound.push_back((from, buf[..len].to_vec()));
+                }
+
+                if let Some(waker) = lock(&shared.waker).as_ref() {
+                    let _ = waker.wake();
+                }
+            }
+            Err(ref e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock
+                        | io::ErrorKind::TimedOut
+                        | io::ErrorKind::Interrupted
+                ) => {}
+            Err(e) => log_transport!(Debug, "Receiving on the std socket failed: {}", e),
+        }
+    }
+}
+
+fn write_datagrams(socket: &UdpSocket, queued: &Receiver<Datagram>, shared: &Shared) {
+    for (target, datagram) in queued {
+        if let Err(e) = socket.send_to(&datagram, target) {
+            *lock(&shared.failure) = Some(e);
+        }
+    }
+}
+
+impl Transport for StdUdpTransport {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    fn send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        if let Some(failure) = lock(&self.shared.failure).take() {
+            return Err(failure);
+        }
+
+        let outbound = self
+            .outbound
+            .as_ref()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))?;
+        match outbound.try_send((target, buf.to_vec())) {
+            Ok(()) => Ok(buf.len()),
+            Err(TrySendError::Full(_)) => Err(io::Error::from(io::ErrorKind::WouldBlock)),
+            Err(TrySendError::Disconnected(_)) => Err(io::Error::from(io::ErrorKind::BrokenPipe)),
+        }
+    }
+
+    /// Longer datagrams are truncated to the buffer, as with UDP.
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let (from, datagram) = lock(&self.shared.inbound)
+            .pop_front()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::WouldBlock))?;
+        let len = datagram.len().min(buf.len());
+        buf[..len].copy_from_slice(&datagram[..len]);
+
+        Ok((len, from))
+    }
+
+    fn register(&mut self, registry: &Registry, token: Token) -> io::Result<()> {
+        let waker = Waker::new(registry, token)?;
+        // Datagrams received before the registration
+        if !lock(&self.shared.inbound).is_empty() {
+            waker.wake()?;
+        }
+        *lock(&self.shared.waker) = Some(waker);
+
+        Ok(())
+    }
+
+    fn deregister(&mut self, _registry: &Registry) -> io::Result<()> {
+        *lock(&self.shared.waker) = None;
+        Ok(())
+    }
+}
+
+impl Drop for StdUdpTransport {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Release);
+        // The writer thread drains the queue and stops
+        self.outbound = None;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mio::{Events, Poll};
+
+    #[test]
+    fn wakes_the_poll_on_datagrams() {
+        let any: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let a = StdUdpTransport::bind(any).unwrap();
+        let mut b = StdUdpTransport::bind(any).unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(4);
+        b.register(poll.registry(), Token(7)).unwrap();
+
+        a.send_to(b"ping", b.local_addr().unwrap()).unwrap();
+        poll.poll(&mut events, Some(Duration::from_secs(5)))
+            .unwrap();
+        assert!(events.iter().any(|e| e.token() == Token(7)));
+
+        let mut buf = [0_u8; 16];
+        let (len, from) = b.recv_from(&mut buf).unwrap();
+        assert_eq!((&buf[..len], from), (&b"ping"[..], a.local_addr().unwrap()));
+        assert_eq!(
+            b.recv_from(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+    }
+}
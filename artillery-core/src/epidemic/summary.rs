@@ -0,0 +1,135 @@
+use serde::*;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// Activity of this node over one protocol round, the period between two ticks. Emitted
+/// as `ProtocolRoundSummary` with `round_summaries`, to log one structured line per round.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProtocolRoundSummary {
+    /// Rounds since the start of this node.
+    pub round: u64,
+    /// Direct and indirect probes sent.
+    pub probes_sent: usize,
+    pub acks_received: usize,
+    /// Members which turned suspect.
+    pub suspects_opened: usize,
+    /// Suspects which were refuted, declared down or left.
+    pub suspects_closed: usize,
+    pub bytes_in: usize,
+    pub bytes_out: usize,
+    /// State changes queued for dissemination at the end of the round.
+    pub changes_pending: usize,
+}
+
+/// Counters of the round in progress.
+#[derive(Debug, Default)]
+pub(crate) struct RoundTracker {
+    current: ProtocolRoundSummary,
+    suspects: HashSet<Uuid>,
+}
+
+impl RoundTracker {
+    pub(crate) fn probe_sent(&mut self) {
+        self.current.probes_sent += 1;
+    }
+
+    pub(crate) fn ack_received(&mut self) {
+        self.current.acks_received += 1;
+    }
+
+    pub(crate) fn received(&mut self, bytes: usize) {
+        self.current.bytes_in += bytes;
+    }
+
+    pub(crate) fn sent(&mut self, bytes: usize) {
+        self.current.bytes_out += bytes;
+    }
+
+    pub(crate) fn suspicion_opened(&mut self, member: Uuid) {
+        if self.suspects.insert(member) {
+            self.current.suspects_opened += 1;
+        }
+    }
+
+    /// The member left the suspect state, if it was in.
+    pub(crate) fn suspicion_settled(&mut self, member: Uuid) {
+        if self.suspects.remove(&member) {
+            self.current.suspects_closed += 1;
+        }
+    }
+
+    /// Summary of the round, the next one starts.
+    pub(crate) fn finish(&mut self, changes_pending: usize) -> ProtocolRoundSummary {
+        let next = ProtocolRoundSummary {
+            round: self.current.round + 1,
+            ..Default::default()
+        };
+        let mut summary = std::mem::replace(&mut self.current, next);
+        summary.changes_pending = changes_pending;
+
+        summary
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::state::ArtilleryMemberEvent;
+    use crate::epidemic::test_kit::*;
+    use std::time::Duration;
+
+    #[test]
+    fn summarizes_each_round_on_its_own() {
+        let suspect = Uuid::new_v4();
+        let mut tracker = RoundTracker::default();
+        tracker.probe_sent();
+        tracker.ack_received();
+        tracker.sent(120);
+        tracker.received(80);
+        tracker.suspicion_opened(suspect);
+        tracker.suspicion_opened(suspect);
+
+        let first = tracker.finish(2);
+        assert_eq!(first.round, 0);
+        assert_eq!((first.probes_sent, first.acks_received), (1, 1));
+        assert_eq!((first.bytes_out, first.bytes_in), (120, 80));
+        assert_eq!((first.suspects_opened, first.suspects_closed), (1, 0));
+        assert_eq!(first.changes_pending, 2);
+
+        tracker.suspicion_settled(suspect);
+        tracker.suspicion_settled(suspect);
+        let second = tracker.finish(0);
+        assert_eq!(second.round, 1);
+        assert_eq!((second.suspects_opened, second.suspects_closed), (0, 1));
+        assert_eq!(second.bytes_out, 0);
+    }
+
+    #[test]
+    fn summarizes_every_protocol_round() {
+        let a = TestPeer::new();
+        let mut tester = tester_with(|config| {
+            config.round_summaries = true;
+        });
+
+        tester
+            .recv(heartbeat_from(&a))
+            .tick(Duration::from_secs(1))
+            .expect_event("first round", |e| {
+                if let ArtilleryMemberEvent::ProtocolRoundSummary(summary) = e {
+                    summary.round == 0 && summary.bytes_out > 0
+                } else {
+                    false
+                }
+            })
+            .expect_sent(heartbeat_to(&a))
+            .recv(ack_from(&a))
+            .tick(Duration::from_secs(1))
+            .expect_event("second round", |e| {
+                if let ArtilleryMemberEvent::ProtocolRoundSummary(summary) = e {
+                    summary.round == 1 && summary.probes_sent >= 1 && summary.acks_received == 1
+                } else {
+                    false
+                }
+            });
+    }
+}
@@ -0,0 +1,231 @@
+use super::member::TransitionReason;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Evidence required before a suspect is declared down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SuspicionPolicy {
+    /// Minimum time a member stays suspect.
+    pub timeout: Duration,
+    /// Failed direct re-probes of the suspect.
+    pub direct_probes: usize,
+    /// Failed indirect probes through relays, capped to the number of probes sent.
+    pub indirect_probes: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Suspicion {
+    since: Instant,
+    direct_failures: usize,
+    indirect_sent: usize,
+    indirect_failures: usize,
+    /// Deadlines of the indirect probes waiting for an `AckHost`.
+    indirect_pending: Vec<Instant>,
+}
+
+/// Failed probes per suspect, Suspect to Down escalation only happens on enough evidence.
+#[derive(Debug, Default)]
+pub struct SuspicionTracker {
+    suspects: HashMap<SocketAddr, Suspicion>,
+}
+
+impl SuspicionTracker {
+    /// Starts tracking the suspect, keeps the evidence collected if it is already tracked.
+    pub fn start(&mut self, suspect: SocketAddr, now: Instant) {
+        self.suspects.entry(suspect).or_insert_with(|| Suspicion {
+            since: now,
+            direct_failures: 0,
+            indirect_sent: 0,
+            indirect_failures: 0,
+            indirect_pending: Vec::new(),
+        });
+    }
+
+    #[cfg(test)]
+    pub fn is_tracked(&self, suspect: &SocketAddr) -> bool {
+        self.suspects.contains_key(suspect)
+    }
+
+    pub fn direct_probe_failed(&mut self, suspect: &SocketAddr) {
+        if let Some(suspicion) = self.suspects.get_mut(suspect) {
+            suspicion.direct_failures += 1;
+        }
+    }
+
+    /// The probe counts as failed if the suspect isn't cleared until the deadline.
+    pub fn indirect_probe_sent(&mut self, suspect: &SocketAddr, deadline: Instant) {
+        if let Some(suspicion) = self.suspects.get_mut(suspect) {
+            suspicion.indirect_sent += 1;
+            suspicion.indirect_pending.push(deadline);
+        }
+    }
+
+    /// The suspect answered, directly or through a relay.
+    pub fn clear(&mut self, suspect: &SocketAddr) {
+        self.suspects.remove(suspect);
+    }
+
+    /// Moves the evidence collection forward, e.g. over a time the host was suspended.
+    pub fn postpone(&mut self, by: Duration) {
+        for suspicion in self.suspects.values_mut() {
+            suspicion.since += by;
+            for deadline in &mut suspicion.indirect_pending {
+                *deadline += by;
+            }
+        }
+    }
+
+    /// Suspects to declare down, they are not tracked anymore.
+    pub fn escalations(
+        &mut self,
+        now: Instant,
+        policy: &SuspicionPolicy,
+    ) -> Vec<(SocketAddr, TransitionReason)> {
+        let mut escalated = Vec::new();
+
+        for (suspect, suspicion) in &mut self.suspects {
+            let pending = suspicion.indirect_pending.len();
+            suspicion
+                .indirect_pending
+                .retain(|&deadline| deadline > now);
+            suspicion.indirect_failures += pending - suspicion.indirect_pending.len();
+
+            let required_indirect = policy.indirect_probes.min(suspicion.indirect_sent);
+            if now.saturating_duration_since(suspicion.since) >= policy.timeout
+                && suspicion.direct_failures >= policy.direct_probes
+                && suspicion.indirect_failures >= required_indirect
+            {
+                let reason = if suspicion.indirect_failures > 0 {
+                    TransitionReason::IndirectFailure
+                } else {
+                    TransitionReason::DirectTimeout
+                };
+                escalated.push((*suspect, reason));
+            }
+        }
+
+        for (suspect, _) in &escalated {
+            self.suspects.remove(suspect);
+        }
+
+        escalated
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::state::ArtilleryMemberEvent;
+    use crate::epidemic::test_kit::*;
+
+    fn policy() -> SuspicionPolicy {
+        SuspicionPolicy {
+            timeout: Duration::from_secs(3),
+            direct_probes: 1,
+            indirect_probes: 2,
+        }
+    }
+
+    #[test]
+    fn postponed_suspicion_needs_the_full_timeout() {
+        let suspect = "127.0.0.1:40001".parse().unwrap();
+        let start = Instant::now();
+        let mut tracker = SuspicionTracker::default();
+
+        tracker.start(suspect, start);
+        tracker.direct_probe_failed(&suspect);
+        tracker.postpone(Duration::from_secs(60));
+
+        assert!(tracker
+            .escalations(start + Duration::from_secs(10), &policy())
+            .is_empty());
+        assert_eq!(
+            tracker.escalations(start + Duration::from_secs(63), &policy()),
+            vec![(suspect, TransitionReason::DirectTimeout)]
+        );
+    }
+
+    #[test]
+    fn escalates_only_on_exhausted_probes() {
+        let suspect = "127.0.0.1:40001".parse().unwrap();
+        let start = Instant::now();
+        let mut tracker = SuspicionTracker::default();
+
+        tracker.start(suspect, start);
+        tracker.indirect_probe_sent(&suspect, start + Duration::from_secs(1));
+        tracker.indirect_probe_sent(&suspect, start + Duration::from_secs(5));
+
+        // Window passed, but no direct re-probe failed yet
+        assert!(tracker
+            .escalations(start + Duration::from_secs(3), &policy())
+            .is_empty());
+
+        tracker.direct_probe_failed(&suspect);
+        // Second indirect probe is still in flight
+        assert!(tracker
+            .escalations(start + Duration::from_secs(4), &policy())
+            .is_empty());
+
+        assert_eq!(
+            tracker.escalations(start + Duration::from_secs(6), &policy()),
+            vec![(suspect, TransitionReason::IndirectFailure)]
+        );
+        assert!(!tracker.is_tracked(&suspect));
+    }
+
+    #[test]
+    fn cleared_suspects_are_not_escalated() {
+        let suspect = "127.0.0.1:40001".parse().unwrap();
+        let start = Instant::now();
+        let mut tracker = SuspicionTracker::default();
+
+        tracker.start(suspect, start);
+        tracker.direct_probe_failed(&suspect);
+        tracker.clear(&suspect);
+
+        assert!(tracker
+            .escalations(start + Duration::from_secs(10), &policy())
+            .is_empty());
+    }
+
+    #[test]
+    fn indirect_probes_are_capped_to_the_sent_ones() {
+        let suspect = "127.0.0.1:40001".parse().unwrap();
+        let start = Instant::now();
+        let mut tracker = SuspicionTracker::default();
+
+        // No relays available, direct evidence is all we can get
+        tracker.start(suspect, start);
+        tracker.direct_probe_failed(&suspect);
+
+        assert_eq!(
+            tracker.escalations(start + Duration::from_secs(3), &policy()),
+            vec![(suspect, TransitionReason::DirectTimeout)]
+        );
+    }
+
+    #[test]
+    fn resume_reprobes_before_declaring_down() {
+        let a = TestPeer::new();
+        let mut tester = ProtocolTester::new();
+
+        tester
+            .recv(heartbeat_from(&a))
+            .tick(Duration::from_secs(1))
+            .tick(Duration::from_secs(30))
+            .expect_sent(heartbeat_to(&a))
+            .tick(Duration::from_secs(4))
+            .tick(Duration::from_secs(4));
+
+        assert!(tester
+            .drain_events()
+            .iter()
+            .all(|e| !matches!(e, ArtilleryMemberEvent::WentDown(..))));
+
+        tester.tick(Duration::from_secs(4)).expect_event(
+            "a went down after the grace period",
+            |e| matches!(e, ArtilleryMemberEvent::WentDown(m, _) if m.host_key() == a.id()),
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use super::primitives::Timestamp;
+use super::state::ArtilleryMessage;
+use serde::*;
+use std::fmt::{self, Debug, Formatter};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapDirection {
+    Inbound,
+    Outbound,
+}
+
+/// Message captured on the wire, serializable to record traffic for a later replay.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TapRecord {
+    pub direction: TapDirection,
+    /// Source of an inbound, target of an outbound message.
+    pub peer: SocketAddr,
+    pub timestamp: Timestamp,
+    /// Message as encoded by the cluster codec.
+    pub bytes: Vec<u8>,
+}
+
+type TapObserver = Arc<dyn Fn(&TapRecord, &ArtilleryMessage) + Send + Sync>;
+
+/// Observer of all the encoded outbound and decoded inbound messages of a cluster.
+/// Runs on the cluster thread, so it should hand the records off quickly.
+#[derive(Clone)]
+pub struct MessageTap(TapObserver);
+
+impl MessageTap {
+    pub fn new<F>(observer: F) -> Self
+    where
+        F: Fn(&TapRecord, &ArtilleryMessage) + Send + Sync + 'static,
+    {
+        MessageTap(Arc::new(observer))
+    }
+
+    pub(crate) fn observe(&self, record: &TapRecord, message: &ArtilleryMessage) {
+        (self.0)(record, message)
+    }
+}
+
+impl Debug for MessageTap {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("MessageTap")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::state::Request;
+    use crate::epidemic::test_kit::*;
+    use std::sync::mpsc::channel;
+    use std::sync::Mutex;
+
+    #[test]
+    fn observes_the_messages_both_ways() {
+        let (a, b) = (TestPeer::new(), TestPeer::new());
+        let (tx, rx) = channel();
+        let tx = Mutex::new(tx);
+        let mut tester = tester_with(|config| {
+            config.tcp_fallback = true;
+            config.message_tap = Some(MessageTap::new(move |record, message| {
+                assert!(!record.bytes.is_empty());
+                let _ = tx.lock().unwrap().send((
+                    record.direction,
+                    record.peer,
+                    message.request.clone(),
+                ));
+            }));
+        });
+
+        tester.recv(heartbeat_from(&a)).expect_sent(ack_to(&a));
+        assert!(rx
+            .try_iter()
+            .any(|tapped| tapped == (TapDirection::Outbound, a.addr(), Request::Ack)));
+
+        tester.recv_over_tcp(heartbeat_from(&b));
+        assert!(rx
+            .try_iter()
+            .any(|(direction, _, request)| direction == TapDirection::Inbound
+                && request == Request::Heartbeat));
+    }
+}
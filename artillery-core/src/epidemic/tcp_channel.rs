@@ -0,0 +1,221 @@
+use crate::constants::CONST_TCP_FRAME_LIMIT;
+use mio::net::TcpStream;
+use std::convert::{TryFrom, TryInto};
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::time::Instant;
+
+/// Big endian length of the frames.
+const FRAME_HEADER: usize = 4;
+/// Big endian gossip port of the sender, ahead of the message.
+const PORT_HEADER: usize = 2;
+
+/// Progress of an exchange over TCP.
+#[derive(Debug)]
+pub(crate) enum TcpProgress {
+    Pending,
+    /// Message of the peer, with the gossip address of the peer.
+    Received(SocketAddr, Vec<u8>),
+    /// Everything is written and read.
+    Done,
+    Failed(io::Error),
+}
+
+/// Side channel to a peer for the messages too large for a datagram, e.g. the full state
+/// of a push/pull state sync. Carries a single message each way at most, framed with its
+/// length and the gossip port of its sender. Non-blocking, progressed by the event loop
+/// until done or past its deadline.
+#[derive(Debug)]
+pub(crate) struct TcpExchange {
+    stream: TcpStream,
+    peer: SocketAddr,
+    outbound: Vec<u8>,
+    written: usize,
+    inbound: Vec<u8>,
+    awaits_message: bool,
+    deadline: Instant,
+}
+
+impl TcpExchange {
+    /// Sends the message to the peer, which answers on the same connection if `answered`.
+    pub(crate) fn connect(
+        stream: TcpStream,
+        peer: SocketAddr,
+        gossip_port: u16,
+        message: &[u8],
+        answered: bool,
+        deadline: Instant,
+    ) -> io::Result<Self> {
+        let mut exchange = TcpExchange::accept(stream, peer, deadline);
+        exchange.answer(gossip_port, message)?;
+        exchange.awaits_message = answered;
+
+        Ok(exchange)
+    }
+
+    /// Awaits the message of the peer which connected.
+    pub(crate) fn accept(stream: TcpStream, peer: SocketAddr, deadline: Instant) -> Self {
+        TcpExchange {
+            stream,
+            peer,
+            outbound: Vec::new(),
+            written: 0,
+            inbound: Vec::new(),
+            awaits_message: true,
+            deadline,
+        }
+    }
+
+    /// Address of the other end, an ephemeral port for the accepted connections.
+    pub(crate) fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// Sends the message back, once the one of the peer is received.
+    pub(crate) fn answer(&mut self, gossip_port: u16, message: &[u8]) -> io::Result<()> {
+        let len = u32::try_from(PORT_HEADER + message.len())
+            .ok()
+            .filter(|_| message.len() <= CONST_TCP_FRAME_LIMIT)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "message too large"))?;
+        self.outbound = Vec::with_capacity(FRAME_HEADER + PORT_HEADER + message.len());
+        self.outbound.extend_from_slice(&len.to_be_bytes());
+        self.outbound.extend_from_slice(&gossip_port.to_be_bytes());
+        self.outbound.extend_from_slice(message);
+        self.written = 0;
+
+        Ok(())
+    }
+
+    /// Writes and reads as much as the socket takes without blocking.
+    pub(crate) fn progress(&mut self, now: Instant) -> TcpProgress {
+        if now >= self.deadline {
+            return TcpProgress::Failed(io::Error::from(io::ErrorKind::TimedOut));
+        }
+
+        while self.written < self.outbound.len() {
+            match self.stream.write(&self.outbound[self.written..]) {
+                Ok(0) => return TcpProgress::Failed(io::Error::from(io::ErrorKind::WriteZero)),
+                Ok(n) => self.written += n,
+                Err(ref e) if is_pending(e) => break,
+                Err(e) => return TcpProgress::Failed(e),
+            }
+        }
+
+        if self.awaits_message {
+            return self.read_message();
+        }
+
+        if self.written == self.outbound.len() {
+            TcpProgress::Done
+        } else {
+            TcpProgress::Pending
+        }
+    }
+
+    fn read_message(&mut self) -> TcpProgress {
+        let mut buf = [0_u8; 4096];
+        loop {
+            if let Some((port, message)) = frame(&self.inbound) {
+                let received =
+                    TcpProgress::Received(SocketAddr::new(self.peer.ip(), port), message.to_vec());
+                self.awaits_message = false;
+                self.inbound = Vec::new();
+                return received;
+            }
+
+            match self.stream.read(&mut buf) {
+                Ok(0) => return TcpProgress::Failed(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                Ok(n) => self.inbound.extend_from_slice(&buf[..n]),
+                Err(ref e) if is_pending(e) => return TcpProgress::Pending,
+                Err(e) => return TcpProgress::Failed(e),
+            }
+
+            if self.inbound.len() > FRAME_HEADER + PORT_HEADER + CONST_TCP_FRAME_LIMIT {
+                return TcpProgress::Failed(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "message of the peer too large",
+                ));
+            }
+        }
+    }
+}
+
+/// Still connecting, or nothing to read or write yet.
+fn is_pending(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::NotConnected | io::ErrorKind::Interrupted
+    )
+}
+
+/// Gossip port of the sender and message of the frame, once it is received whole.
+fn frame(inbound: &[u8]) -> Option<(u16, &[u8])> {
+    let header: [u8; FRAME_HEADER] = inbound.get(..FRAME_HEADER)?.try_into().ok()?;
+    let len = usize::try_from(u32::from_be_bytes(header)).ok()?;
+    let body = inbound.get(FRAME_HEADER..FRAME_HEADER.checked_add(len)?)?;
+    let port: [u8; PORT_HEADER] = body.get(..PORT_HEADER)?.try_into().ok()?;
+
+    Some((u16::from_be_bytes(port), body.get(PORT_HEADER..)?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mio::net::TcpListener;
+    use std::time::Duration;
+
+    #[test]
+    fn answers_on_the_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut initiator =
+            TcpExchange::connect(stream, addr, 7946, b"ours", true, deadline).unwrap();
+
+        let mut acceptor: Option<TcpExchange> = None;
+        let mut done = (false, false);
+        while !done.0 || !done.1 {
+            assert!(Instant::now() < deadline);
+            if acceptor.is_none() {
+                acceptor = listener
+                    .accept()
+                    .ok()
+                    .map(|(stream, peer)| TcpExchange::accept(stream, peer, deadline));
+            }
+
+            match initiator.progress(Instant::now()) {
+                TcpProgress::Received(from, message) => {
+                    assert_eq!((from, message), (addr, b"theirs".to_vec()));
+                }
+                TcpProgress::Done => done.0 = true,
+                TcpProgress::Pending => {}
+                TcpProgress::Failed(e) => panic!("initiator failed: {}", e),
+            }
+            if let Some(acceptor) = acceptor.as_mut() {
+                match acceptor.progress(Instant::now()) {
+                    TcpProgress::Received(from, message) => {
+                        assert_eq!((from.port(), message), (7946, b"ours".to_vec()));
+                        acceptor.answer(addr.port(), b"theirs").unwrap();
+                    }
+                    TcpProgress::Done => done.1 = true,
+                    TcpProgress::Pending => {}
+                    TcpProgress::Failed(e) => panic!("acceptor failed: {}", e),
+                }
+            }
+        }
+
+        assert!(matches!(
+            initiator.progress(deadline),
+            TcpProgress::Failed(_)
+        ));
+    }
+
+    #[test]
+    fn waits_for_the_whole_frame() {
+        assert_eq!(frame(&[0, 0, 0, 3, 0, 1]), None);
+        assert_eq!(frame(&[0, 0, 0, 3, 0, 1, 2, 3]), Some((1, &[2_u8][..])));
+        assert_eq!(frame(&[0, 0, 0, 1, 0]), None);
+        assert_eq!(frame(&[0, 0]), None);
+    }
+}
@@ -0,0 +1,655 @@
+//!
+//! Scripted protocol scenarios against a single cluster state machine.
+//!
+//! The tester drives an [`ArtilleryEpidemic`] directly: inbound messages are injected
+//! without a network hop, time moves only when the script ticks, and outbound messages
+//! are captured on the loopback sockets of the [`TestPeer`]s they are addressed to.
+//!
+//! ```ignore
+//! let a = TestPeer::new();
+//! ProtocolTester::new()
+//!     .recv(heartbeat_from(&a))
+//!     .expect_sent(ack_to(&a))
+//!     .tick(Duration::from_secs(2))
+//!     .expect_sent(heartbeat_to(&a));
+//! ```
+//!
+//! Suspect members are timed out by their wall-clock state change age, which ticking doesn't affect.
+use super::admin::ShutdownOrder;
+use super::annotation::Annotation;
+use super::cluster_config::ClusterConfig;
+use super::diagnostics::ClusterDiagnostic;
+use super::digest::MemberDigest;
+use super::greeting::Greeting;
+use super::lanes::{request_lanes, RequestReceiver};
+use super::member::{ArtilleryMember, ArtilleryMemberState, ArtilleryStateChange};
+use super::nat::RelayEnvelope;
+use super::reachability::ReachabilityReport;
+use super::state::*;
+use super::tcp_channel::{TcpExchange, TcpProgress};
+use super::transport::DefaultTransport;
+use super::user_payload::UserPayload;
+use mio::net::TcpStream;
+use mio::Poll;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// How long an expectation waits for a message to show up on the loopback.
+const EXPECT_TIMEOUT: Duration = Duration::from_millis(500);
+/// How long the loopback is given to deliver the messages sent so far.
+const SETTLE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Remote node of a scripted scenario, backed by a loopback socket capturing what is sent to it.
+#[derive(Clone)]
+pub struct TestPeer {
+    id: Uuid,
+    socket: Arc<UdpSocket>,
+}
+
+impl TestPeer {
+    pub fn new() -> Self {
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("Can't bind the test peer socket");
+        socket
+            .set_read_timeout(Some(EXPECT_TIMEOUT))
+            .expect("Can't set the test peer read timeout");
+
+        TestPeer {
+            id: Uuid::new_v4(),
+            socket: Arc::new(socket),
+        }
+    }
+
+    /// Peer using the given host key, e.g. the one of the node under test.
+    pub fn with_id(id: Uuid) -> Self {
+        TestPeer {
+            id,
+            ..TestPeer::new()
+        }
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.socket
+            .local_addr()
+            .expect("Test peer socket has no address")
+    }
+
+    /// Member record of this peer in the given state, to be gossiped as a state change.
+    pub fn member(&self, state: ArtilleryMemberState) -> ArtilleryMember {
+        ArtilleryMember::new(self.id, self.addr(), 0, state)
+    }
+}
+
+impl Default for TestPeer {
+    fn default() -> Self {
+        TestPeer::new()
+    }
+}
+
+/// Message a peer sends to the node under test.
+#[derive(Clone)]
+pub struct Inbound {
+    from: TestPeer,
+    request: Request,
+    state_changes: Vec<ArtilleryStateChange>,
+    relay: Option<RelayEnvelope>,
+    run: Option<Uuid>,
+    digest: Option<MemberDigest>,
+    shutdown: Vec<ShutdownOrder>,
+    hello: Option<Greeting>,
+    payloads: Vec<UserPayload>,
+    reachability: Option<ReachabilityReport>,
+    heartbeat: Option<Vec<u8>>,
+    cluster: Option<(String, Vec<u8>)>,
+}
+
+impl Inbound {
+    /// Piggybacks member records on the message.
+    pub fn with_state_changes(mut self, members: Vec<ArtilleryMember>) -> Self {
+        self.state_changes
+            .extend(members.into_iter().map(ArtilleryStateChange::new));
+        self
+    }
+
+    /// Tells the run id of the sender, as nodes do since they detect identity conflicts.
+    pub fn with_run(mut self, run: Uuid) -> Self {
+        self.run = Some(run);
+        self
+    }
+
+    /// Summarizes the membership of the sender, as pull gossip pings do.
+    pub fn with_digest(mut self, members: &[ArtilleryMember]) -> Self {
+        self.digest = Some(MemberDigest::of(members));
+        self
+    }
+
+    /// Carries a shutdown order for the members matching the selector.
+    pub fn with_shutdown_order(mut self, operator_key: &[u8], selector: &str) -> Self {
+        self.shutdown.push(ShutdownOrder::new(
+            operator_key.to_vec(),
+            selector.to_string(),
+            chrono::Utc::now(),
+        ));
+        self
+    }
+
+    /// Carries a payload broadcast by `origin`, a member passes on those of the others too.
+    pub fn with_user_payload(mut self, origin: &TestPeer, data: &[u8]) -> Self {
+        self.payloads.push(UserPayload::new(
+            origin.id,
+            data.to_vec(),
+            chrono::Utc::now(),
+        ));
+        self
+    }
+
+    /// Reports the peers the sender can't reach directly, none withdraws its last report.
+    pub fn with_reachability(mut self, unreachable: &[&TestPeer]) -> Self {
+        self.reachability = Some(ReachabilityReport::new(
+            self.from.id,
+            unreachable.iter().map(|peer| peer.id).collect(),
+            chrono::Utc::now(),
+        ));
+        self
+    }
+
+    /// Piggybacks the heartbeat payload the sender's application registered.
+    pub fn with_heartbeat_payload(mut self, payload: &[u8]) -> Self {
+        self.heartbeat = Some(payload.to_vec());
+        self
+    }
+
+    /// Sends the message as a member of another cluster, by default the one under test.
+    pub fn from_cluster(mut self, name: &str, key: &[u8]) -> Self {
+        self.cluster = Some((name.to_string(), key.to_vec()));
+        self
+    }
+
+    /// Describes the sender with its zone and self-written annotations, as on first contact.
+    pub fn with_greeting(mut self, zone: &str, annotations: &[(&str, &str)]) -> Self {
+        let member = ArtilleryMember::current(self.from.id).with_zone(Some(zone.to_string()));
+        let written = annotations
+            .iter()
+            .map(|(key, value)| {
+                Annotation::new(
+                    self.from.id,
+                    key.to_string(),
+                    Some(value.to_string()),
+                    self.from.id,
+                )
+            })
+            .collect();
+        self.hello = Some(Greeting::new(self.from.id.to_string(), member, written));
+        self
+    }
+
+    /// Sends the message through the node under test on to `target`.
+    pub fn relayed_to(mut self, target: &TestPeer) -> Self {
+        self.relay = Some(RelayEnvelope::to(target.addr()));
+        self
+    }
+}
+
+pub fn heartbeat_from(peer: &TestPeer) -> Inbound {
+    inbound(peer, Request::Heartbeat)
+}
+
+pub fn ack_from(peer: &TestPeer) -> Inbound {
+    inbound(peer, Request::Ack)
+}
+
+/// Indirect ping request, asking the node under test to probe `target`.
+pub fn ping_from(peer: &TestPeer, target: &TestPeer) -> Inbound {
+    inbound(peer, Request::Ping(EncSocketAddr(target.addr())))
+}
+
+/// Rendezvous request, asking the node under test to introduce the peer to `target`.
+pub fn introduce_from(peer: &TestPeer, target: &TestPeer) -> Inbound {
+    inbound(peer, Request::Introduce(EncSocketAddr(target.addr())))
+}
+
+pub fn payload_from(peer: &TestPeer, payload: &str) -> Inbound {
+    inbound(peer, Request::Payload(peer.id, payload.to_string()))
+}
+
+/// Message sent with `Cluster::send_to`, the same id for the retries.
+pub fn message_from(peer: &TestPeer, id: Uuid, data: &[u8]) -> Inbound {
+    inbound(peer, Request::Message(id, data.to_vec()))
+}
+
+pub fn rpc_call_from(peer: &TestPeer, id: Uuid, method: &str, body: &[u8]) -> Inbound {
+    inbound(
+        peer,
+        Request::RpcCall(id, method.to_string(), body.to_vec()),
+    )
+}
+
+/// Full state of the peer for anti-entropy, asking for ours in return if `reply`.
+pub fn sync_from(peer: &TestPeer, members: Vec<ArtilleryMember>, reply: bool) -> Inbound {
+    inbound(peer, Request::Sync { members, reply })
+}
+
+/// Multicast summary of the given member records.
+pub fn digest_from(peer: &TestPeer, members: &[ArtilleryMember]) -> Inbound {
+    inbound(peer, Request::Digest(MemberDigest::of(members)))
+}
+
+fn inbound(peer: &TestPeer, request: Request) -> Inbound {
+    Inbound {
+        from: peer.clone(),
+        request,
+        state_changes: Vec::new(),
+        relay: None,
+        run: None,
+        digest: None,
+        shutdown: Vec::new(),
+        hello: None,
+        payloads: Vec::new(),
+        reachability: None,
+        heartbeat: None,
+        cluster: None,
+    }
+}
+
+/// Outbound message the node under test is expected to send to a peer.
+pub struct Expectation {
+    to: TestPeer,
+    description: &'static str,
+    matches: fn(&ArtilleryMessage) -> bool,
+}
+
+pub fn ack_to(peer: &TestPeer) -> Expectation {
+    expectation(peer, "ack", |m| m.request == Request::Ack)
+}
+
+pub fn heartbeat_to(peer: &TestPeer) -> Expectation {
+    expectation(peer, "heartbeat", |m| m.request == Request::Heartbeat)
+}
+
+pub fn ping_request_to(peer: &TestPeer) -> Expectation {
+    expectation(peer, "indirect ping request", |m| {
+        matches!(m.request, Request::Ping(_))
+    })
+}
+
+/// Message passed on by the node under test, telling the peer where it came from.
+pub fn relayed_message_to(peer: &TestPeer) -> Expectation {
+    expectation(peer, "relayed message", |m| {
+        m.relay.as_ref().is_some_and(|r| r.from.is_some())
+    })
+}
+
+pub fn pull_request_to(peer: &TestPeer) -> Expectation {
+    expectation(peer, "pull request", |m| {
+        matches!(m.request, Request::Pull(_))
+    })
+}
+
+pub fn message_to(peer: &TestPeer) -> Expectation {
+    expectation(peer, "message", |m| {
+        matches!(m.request, Request::Message(..))
+    })
+}
+
+pub fn message_ack_to(peer: &TestPeer) -> Expectation {
+    expectation(peer, "message ack", |m| {
+        matches!(m.request, Request::MessageAck(_))
+    })
+}
+
+pub fn rpc_call_to(peer: &TestPeer) -> Expectation {
+    expectation(peer, "rpc call", |m| {
+        matches!(m.request, Request::RpcCall(..))
+    })
+}
+
+pub fn rpc_reply_to(peer: &TestPeer) -> Expectation {
+    expectation(peer, "rpc reply", |m| {
+        matches!(m.request, Request::RpcReply(_, Ok(_)))
+    })
+}
+
+pub fn rpc_failure_to(peer: &TestPeer) -> Expectation {
+    expectation(peer, "rpc failure", |m| {
+        matches!(m.request, Request::RpcReply(_, Err(_)))
+    })
+}
+
+pub fn punch_request_to(peer: &TestPeer) -> Expectation {
+    expectation(peer, "punch request", |m| {
+        matches!(m.request, Request::Punch(_))
+    })
+}
+
+/// Any message to the peer matching the predicate.
+pub fn expectation(
+    peer: &TestPeer,
+    description: &'static str,
+    matches: fn(&ArtilleryMessage) -> bool,
+) -> Expectation {
+    Expectation {
+        to: peer.clone(),
+        description,
+        matches,
+    }
+}
+
+/// Driver of scripted message sequences, every step panics with a description on failure.
+pub struct ProtocolTester {
+    host_key: Uuid,
+    state: ArtilleryEpidemic,
+    requests: RequestReceiver,
+    events: Receiver<ArtilleryClusterEvent>,
+    diagnostics: Receiver<ClusterDiagnostic>,
+    config: ClusterConfig,
+    _poll: Poll,
+}
+
+impl ProtocolTester {
+    pub fn new() -> Self {
+        tester_with(|_| {})
+    }
+
+    pub fn with_config(config: ClusterConfig) -> Self {
+        let (event_tx, events): (Sender<ArtilleryClusterEvent>, _) = channel();
+        let (internal_tx, requests) = request_lanes(&config);
+        let host_key = Uuid::new_v4();
+        let socket = DefaultTransport::bind(config.listen_addr)
+            .expect("Can't bind the socket of the state machine under test");
+        let (poll, mut state) = ArtilleryEpidemic::with_transport(
+            host_key,
+            config.clone(),
+            Box::new(socket),
+            event_tx,
+            internal_tx,
+        )
+        .expect("Can't start the state machine under test");
+        let diagnostics = state
+            .take_diagnostics()
+            .expect("Diagnostics of a new state machine");
+
+        ProtocolTester {
+            host_key,
+            state,
+            requests,
+            events,
+            diagnostics,
+            config,
+            _poll: poll,
+        }
+    }
+
+    pub fn state(&self) -> &ArtilleryEpidemic {
+        &self.state
+    }
+
+    pub fn host_key(&self) -> Uuid {
+        self.host_key
+    }
+
+    /// Delivers the messages as read off the socket in one go, queued like the other work.
+    pub fn recv_all(&mut self, inbounds: Vec<Inbound>) -> &mut Self {
+        for inbound in inbounds {
+            let from = inbound.from.addr();
+            let message = self.message_of(inbound);
+            self.state.queue_inbound(from, message);
+        }
+        self.state.drain_requests(&self.requests);
+        self
+    }
+
+    /// Delivers a message from a peer and processes everything it triggered.
+    pub fn recv(&mut self, inbound: Inbound) -> &mut Self {
+        let from = inbound.from.addr();
+        let message = self.message_of(inbound);
+
+        self.state.respond_to_message(from, message);
+        self.state.drain_requests(&self.requests);
+        self
+    }
+
+    /// Delivers a message from a peer over the TCP side channel, see `tcp_fallback`.
+    pub fn recv_over_tcp(&mut self, inbound: Inbound) -> &mut Self {
+        let port = inbound.from.addr().port();
+        let message = self.message_of(inbound);
+        let sealed = seal_message(&self.config, &message).expect("Unencodable inbound message");
+        let listen_addr = self.state.config().listen_addr;
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let stream = TcpStream::connect(listen_addr).expect("Can't connect over TCP");
+        let mut exchange =
+            TcpExchange::connect(stream, listen_addr, port, &sealed, false, deadline)
+                .expect("Inbound message too large");
+        let mut buf = vec![0_u8; crate::constants::CONST_PACKET_SIZE];
+        let mut accepted = false;
+
+        loop {
+            assert!(Instant::now() < deadline, "Message over TCP not received");
+            if let TcpProgress::Failed(e) = exchange.progress(Instant::now()) {
+                panic!("Sending over TCP failed: {}", e);
+            }
+            self.state
+                .receive_datagrams(&mut buf)
+                .expect("Receiving failed");
+            self.state.drain_requests(&self.requests);
+
+            let open = self.state.tcp_exchanges();
+            accepted |= open > 0;
+            if accepted && open == 0 {
+                return self;
+            }
+        }
+    }
+
+    fn message_of(&self, inbound: Inbound) -> ArtilleryMessage {
+        let (cluster_name, cluster_key) = inbound.cluster.unwrap_or_else(|| {
+            (
+                self.config.cluster_name.clone(),
+                self.config.cluster_key.clone(),
+            )
+        });
+
+        ArtilleryMessage {
+            sender: inbound.from.id,
+            cluster_name,
+            cluster_key,
+            request: inbound.request,
+            state_changes: inbound.state_changes,
+            heartbeat: inbound.heartbeat,
+            transactions: Vec::new(),
+            annotations: Vec::new(),
+            reaps: Vec::new(),
+            reachability: inbound.reachability.into_iter().collect(),
+            relay: inbound.relay,
+            locks: Vec::new(),
+            run: inbound.run,
+            digest: inbound.digest,
+            shutdown: inbound.shutdown,
+            hello: inbound.hello,
+            payloads: inbound.payloads,
+        }
+    }
+
+    /// Handles a request as if the application sent it through the `Cluster`.
+    pub fn request(&mut self, request: ArtilleryClusterRequest) -> &mut Self {
+        self.state.inject_request(request);
+        self.state.drain_requests(&self.requests);
+        self
+    }
+
+    /// Moves the clock forward and runs one round of the periodic protocol work.
+    /// Steps beyond `suspend_detection_threshold` look like a suspend and resume of the host.
+    pub fn tick(&mut self, by: Duration) -> &mut Self {
+        self.tick_late(by, Duration::from_secs(0))
+    }
+
+    /// Runs one round as if the event loop ran `lag` behind schedule, for the watchdog.
+    pub fn tick_late(&mut self, by: Duration, lag: Duration) -> &mut Self {
+        self.state.watch_tick_lag(lag);
+        self.state.advance_clock(by);
+        self.state.tick();
+        self.state.drain_requests(&self.requests);
+        self
+    }
+
+    /// Waits for a matching message to the peer, skipping the others sent to it before.
+    pub fn expect_sent(&mut self, expectation: Expectation) -> &mut Self {
+        let Expectation {
+            to,
+            description,
+            matches,
+        } = expectation;
+        let mut buf = [0_u8; crate::constants::CONST_PACKET_SIZE];
+
+        loop {
+            let (size, _) = to
+                .socket
+                .recv_from(&mut buf)
+                .unwrap_or_else(|_| panic!("Expected {} to be sent to {}", description, to.addr()));
+            let message =
+                decode_datagram(&self.config, &buf[..size]).expect("Undecodable outbound message");
+
+            if matches(&message) {
+                return self;
+            }
+        }
+    }
+
+    /// Checks that an event matching the predicate was emitted, skipping the others before it.
+    pub fn expect_event<F>(&mut self, description: &str, predicate: F) -> &mut Self
+    where
+        F: Fn(&ArtilleryMemberEvent) -> bool,
+    {
+        while let Ok((_, event, _)) = self.events.try_recv() {
+            if predicate(&event) {
+                return self;
+            }
+        }
+
+        panic!("Expected event: {}", description)
+    }
+
+    /// Checks that a matching diagnostic was reported, skipping the others before it.
+    pub fn expect_diagnostic<F>(&mut self, description: &str, predicate: F) -> &mut Self
+    where
+        F: Fn(&ClusterDiagnostic) -> bool,
+    {
+        while let Ok(diagnostic) = self.diagnostics.try_recv() {
+            if predicate(&diagnostic) {
+                return self;
+            }
+        }
+
+        panic!("Expected diagnostic: {}", description)
+    }
+
+    /// Asserts that none of the events emitted so far matches.
+    pub fn expect_no_event<F>(&mut self, description: &str, predicate: F) -> &mut Self
+    where
+        F: Fn(&ArtilleryMemberEvent) -> bool,
+    {
+        while let Ok((_, event, _)) = self.events.try_recv() {
+            assert!(!predicate(&event), "Unexpected event: {}", description);
+        }
+
+        self
+    }
+
+    /// Takes the messages sent to the peer so far, in order.
+    pub fn take_sent(&mut self, peer: &TestPeer) -> Vec<ArtilleryMessage> {
+        let mut buf = [0_u8; crate::constants::CONST_PACKET_SIZE];
+        let mut sent = Vec::new();
+
+        peer.socket
+            .set_read_timeout(Some(SETTLE_TIMEOUT))
+            .expect("Can't set the test peer read timeout");
+        while let Ok((size, _)) = peer.socket.recv_from(&mut buf) {
+            sent.push(
+                decode_datagram(&self.config, &buf[..size]).expect("Undecodable outbound message"),
+            );
+        }
+        peer.socket
+            .set_read_timeout(Some(EXPECT_TIMEOUT))
+            .expect("Can't set the test peer read timeout");
+
+        sent
+    }
+
+    /// Takes the events emitted so far, in order.
+    pub fn drain_events(&mut self) -> Vec<ArtilleryMemberEvent> {
+        self.events.try_iter().map(|(_, event, _)| event).collect()
+    }
+
+    /// Takes the diagnostics reported so far, in order.
+    pub fn drain_diagnostics(&mut self) -> Vec<ClusterDiagnostic> {
+        self.diagnostics.try_iter().collect()
+    }
+
+    /// Takes the emission stamps of the events emitted so far, in order.
+    pub fn drain_stamps(&mut self) -> Vec<ArtilleryEventStamp> {
+        self.events.try_iter().map(|(_, _, stamp)| stamp).collect()
+    }
+
+    /// Drops the event receiver, like an application which stopped consuming the events.
+    pub fn drop_events(&mut self) {
+        self.events = channel().1;
+    }
+}
+
+/// Tester on a free loopback port, with the default config changed by `configure`.
+pub fn tester_with<F: FnOnce(&mut ClusterConfig)>(configure: F) -> ProtocolTester {
+    let mut config = ClusterConfig {
+        listen_addr: SocketAddr::from(([127, 0, 0, 1], 0)),
+        ..Default::default()
+    };
+    configure(&mut config);
+
+    ProtocolTester::with_config(config)
+}
+
+impl Default for ProtocolTester {
+    fn default() -> Self {
+        ProtocolTester::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn acks_heartbeats_and_probes_members() {
+        let a = TestPeer::new();
+
+        ProtocolTester::new()
+            .recv(heartbeat_from(&a))
+            .expect_sent(ack_to(&a))
+            .expect_event("a joined", |e| {
+                if let ArtilleryMemberEvent::Joined(m) = e {
+                    m.host_key() == a.id()
+                } else {
+                    false
+                }
+            })
+            .tick(Duration::from_secs(1))
+            .expect_sent(heartbeat_to(&a));
+    }
+
+    #[test]
+    fn relays_indirect_pings() {
+        let a = TestPeer::new();
+        let b = TestPeer::new();
+
+        ProtocolTester::new()
+            .recv(
+                heartbeat_from(&a).with_state_changes(vec![b.member(ArtilleryMemberState::Alive)]),
+            )
+            .recv(ping_from(&a, &b))
+            .expect_sent(heartbeat_to(&b));
+    }
+}
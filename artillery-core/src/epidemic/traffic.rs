@@ -0,0 +1,59 @@
+use std::time::{Duration, Instant};
+
+/// Snapshot of the gossip traffic accounting.
+#[derive(Debug, Clone, Default)]
+pub struct TrafficStats {
+    /// Bytes sent since the start.
+    pub total_bytes: u64,
+    /// Bytes sent in the current one second window.
+    pub window_bytes: usize,
+    /// Number of retransmissions and payloads deferred because of the cap.
+    pub deferred: u64,
+}
+
+/// Accounts the bytes sent in one second windows of the protocol clock against an
+/// optional cap.
+#[derive(Debug)]
+pub struct TrafficAccount {
+    cap: Option<usize>,
+    window_start: Instant,
+    stats: TrafficStats,
+}
+
+impl TrafficAccount {
+    pub fn new(cap: Option<usize>, now: Instant) -> Self {
+        TrafficAccount {
+            cap,
+            window_start: now,
+            stats: TrafficStats::default(),
+        }
+    }
+
+    fn roll_window(&mut self, now: Instant) {
+        if now.saturating_duration_since(self.window_start) >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.stats.window_bytes = 0;
+        }
+    }
+
+    pub fn record(&mut self, bytes: usize, now: Instant) {
+        self.roll_window(now);
+        self.stats.window_bytes += bytes;
+        self.stats.total_bytes += bytes as u64;
+    }
+
+    pub fn record_deferral(&mut self) {
+        self.stats.deferred += 1;
+    }
+
+    pub fn over_budget(&mut self, now: Instant) -> bool {
+        self.roll_window(now);
+        let window_bytes = self.stats.window_bytes;
+
+        self.cap.is_some_and(|cap| window_bytes >= cap)
+    }
+
+    pub fn stats(&self) -> &TrafficStats {
+        &self.stats
+    }
+}
@@ -0,0 +1,251 @@
+use super::broadcast::BroadcastQueue;
+use super::primitives::{NodeId, OperationId};
+use chrono::{DateTime, Utc};
+use serde::*;
+use std::collections::HashMap;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq)]
+pub enum RemovalTransactionStatus {
+    /// Members are scheduled for removal, consumers can plan data movement
+    #[serde(rename = "s")]
+    Scheduled,
+    /// Removal is confirmed, scheduled members leave the cluster gracefully
+    #[serde(rename = "c")]
+    Committed,
+    /// Removal is called off
+    #[serde(rename = "a")]
+    Aborted,
+}
+
+/// Coordinated scale-down of a set of members, gossiped as a single record.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RemovalTransaction {
+    #[serde(rename = "id")]
+    id: Uuid,
+    #[serde(rename = "o")]
+    coordinator: Uuid,
+    #[serde(rename = "m")]
+    members: Vec<Uuid>,
+    #[serde(rename = "s")]
+    status: RemovalTransactionStatus,
+    #[serde(rename = "t")]
+    updated_at: DateTime<Utc>,
+}
+
+impl RemovalTransaction {
+    pub(crate) fn new(id: Uuid, coordinator: Uuid, members: Vec<Uuid>) -> Self {
+        RemovalTransaction {
+            id,
+            coordinator,
+            members,
+            status: RemovalTransactionStatus::Scheduled,
+            updated_at: Utc::now(),
+        }
+    }
+
+    pub fn id(&self) -> OperationId {
+        self.id.into()
+    }
+
+    pub fn coordinator(&self) -> NodeId {
+        self.coordinator.into()
+    }
+
+    pub fn members(&self) -> Vec<NodeId> {
+        self.members.iter().copied().map(NodeId::from).collect()
+    }
+
+    pub fn status(&self) -> RemovalTransactionStatus {
+        self.status
+    }
+
+    pub(crate) fn includes(&self, member: &Uuid) -> bool {
+        self.members.contains(member)
+    }
+
+    fn finish(&self, status: RemovalTransactionStatus) -> Self {
+        RemovalTransaction {
+            status,
+            updated_at: Utc::now(),
+            ..self.clone()
+        }
+    }
+
+    /// Final states win against `Scheduled`, otherwise the latest update wins.
+    /// Ties are broken towards `Aborted` which is the conservative outcome.
+    fn supersedes(&self, other: &RemovalTransaction) -> bool {
+        let rank = |s: RemovalTransactionStatus| s != RemovalTransactionStatus::Scheduled;
+
+        (rank(self.status), self.updated_at, self.status)
+            > (rank(other.status), other.updated_at, other.status)
+    }
+}
+
+/// Known removal transactions of the cluster and their dissemination queue.
+#[derive(Debug)]
+pub(crate) struct RemovalTransactions {
+    table: HashMap<Uuid, RemovalTransaction>,
+    queue: BroadcastQueue<RemovalTransaction>,
+}
+
+impl RemovalTransactions {
+    pub fn new(retransmits: usize) -> Self {
+        RemovalTransactions {
+            table: HashMap::new(),
+            queue: BroadcastQueue::new(retransmits),
+        }
+    }
+
+    /// Merges the transaction, returns it back if it changed our view.
+    pub fn apply(&mut self, txn: RemovalTransaction) -> Option<RemovalTransaction> {
+        if let Some(known) = self.table.get(&txn.id) {
+            if !txn.supersedes(known) {
+                return None;
+            }
+        }
+
+        let id = txn.id;
+        self.table.insert(id, txn.clone());
+        self.queue
+            .push_superseding(txn.clone(), |pending| pending.id == id);
+
+        Some(txn)
+    }
+
+    /// Moves a scheduled transaction to a final status.
+    pub fn finish(
+        &mut self,
+        id: &Uuid,
+        status: RemovalTransactionStatus,
+    ) -> Option<RemovalTransaction> {
+        let finished = match self.table.get(id) {
+            Some(txn) if txn.status == RemovalTransactionStatus::Scheduled => txn.finish(status),
+            Some(_) | None => return None,
+        };
+
+        self.apply(finished)
+    }
+
+    pub fn next_batch(&mut self, max: usize) -> Vec<RemovalTransaction> {
+        self.queue.next_batch(max)
+    }
+
+    /// Forgets the finished transactions which weren't updated within `retention`.
+    pub fn prune(&mut self, retention: Duration) {
+        let horizon = match chrono::Duration::from_std(retention)
+            .ok()
+            .and_then(|r| Utc::now().checked_sub_signed(r))
+        {
+            Some(horizon) => horizon,
+            None => return,
+        };
+
+        self.table.retain(|_, t| {
+            t.status == RemovalTransactionStatus::Scheduled || t.updated_at > horizon
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::member::ArtilleryMemberState;
+    use crate::epidemic::state::{ArtilleryClusterRequest, ArtilleryMemberEvent};
+    use crate::epidemic::test_kit::*;
+
+    #[test]
+    fn finishes_a_scheduled_transaction_once() {
+        let mut txns = RemovalTransactions::new(2);
+        let committed = RemovalTransaction::new(Uuid::new_v4(), Uuid::new_v4(), vec![]);
+        let aborted = RemovalTransaction::new(Uuid::new_v4(), Uuid::new_v4(), vec![]);
+        assert!(txns.apply(committed.clone()).is_some());
+        assert!(txns.apply(aborted.clone()).is_some());
+
+        let finished = txns.finish(&committed.id, RemovalTransactionStatus::Committed);
+        assert_eq!(
+            finished.map(|t| t.status),
+            Some(RemovalTransactionStatus::Committed)
+        );
+        let finished = txns.finish(&aborted.id, RemovalTransactionStatus::Aborted);
+        assert_eq!(
+            finished.map(|t| t.status),
+            Some(RemovalTransactionStatus::Aborted)
+        );
+
+        // Final states are final, and late gossip of the scheduled one is ignored
+        assert!(txns
+            .finish(&committed.id, RemovalTransactionStatus::Aborted)
+            .is_none());
+        assert!(txns
+            .finish(&aborted.id, RemovalTransactionStatus::Committed)
+            .is_none());
+        assert!(txns.apply(committed).is_none());
+    }
+
+    #[test]
+    fn forgets_finished_transactions_after_the_retention() {
+        let mut txns = RemovalTransactions::new(2);
+        let scheduled = RemovalTransaction::new(Uuid::new_v4(), Uuid::new_v4(), vec![]);
+        let aborted = RemovalTransaction::new(Uuid::new_v4(), Uuid::new_v4(), vec![]);
+        txns.apply(scheduled.clone());
+        txns.apply(aborted.clone());
+        txns.finish(&aborted.id, RemovalTransactionStatus::Aborted);
+
+        txns.prune(Duration::from_secs(60));
+        assert_eq!(txns.table.len(), 2);
+
+        // Scheduled transactions wait for their coordinator however long it takes
+        txns.prune(Duration::from_secs(0));
+        assert_eq!(txns.table.keys().collect::<Vec<_>>(), vec![&scheduled.id]);
+    }
+
+    #[test]
+    fn leaves_once_the_removal_of_this_node_is_committed() {
+        let a = TestPeer::new();
+        let mut tester = tester_with(|config| {
+            config.gossip_burst_rounds = 0;
+        });
+        let me = tester.host_key();
+        let aborted = Uuid::new_v4();
+        let committed = Uuid::new_v4();
+        let left = |tester: &mut ProtocolTester, peer: &TestPeer| {
+            tester.take_sent(peer).iter().any(|m| {
+                m.state_changes.iter().any(|c| {
+                    c.member().host_key() == me && c.member().state() == ArtilleryMemberState::Left
+                })
+            })
+        };
+
+        tester
+            .recv(heartbeat_from(&a))
+            .request(ArtilleryClusterRequest::ScheduleRemoval(aborted, vec![me]))
+            .request(ArtilleryClusterRequest::FinishRemoval(
+                aborted,
+                RemovalTransactionStatus::Aborted,
+            ))
+            .expect_event("aborted removal", |e| {
+                matches!(e, ArtilleryMemberEvent::RemovalTransactionChanged(t)
+                    if t.status() == RemovalTransactionStatus::Aborted)
+            })
+            .tick(Duration::from_secs(1));
+        assert!(!left(&mut tester, &a));
+
+        tester
+            .request(ArtilleryClusterRequest::ScheduleRemoval(
+                committed,
+                vec![me],
+            ))
+            .request(ArtilleryClusterRequest::FinishRemoval(
+                committed,
+                RemovalTransactionStatus::Committed,
+            ))
+            .expect_event("committed removal", |e| {
+                matches!(e, ArtilleryMemberEvent::RemovalTransactionChanged(t)
+                    if t.status() == RemovalTransactionStatus::Committed)
+            })
+            .tick(Duration::from_secs(1));
+        assert!(left(&mut tester, &a));
+    }
+}
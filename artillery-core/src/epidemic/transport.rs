@@ -0,0 +1,46 @@
+use mio::net::UdpSocket;
+use mio::{Interest, Registry, Token};
+use std::io;
+use std::net::SocketAddr;
+
+/// Transport `Cluster::new_cluster` binds, the blocking std socket with `std-transport`.
+#[cfg(not(feature = "std-transport"))]
+pub type DefaultTransport = UdpSocket;
+#[cfg(feature = "std-transport")]
+pub type DefaultTransport = super::std_transport::StdUdpTransport;
+
+/// Datagram endpoint of a node. Sends never block, receives return `WouldBlock` once
+/// drained, and readiness is signalled through the registry it's registered with.
+pub trait Transport: Send {
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    fn send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize>;
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)>;
+
+    fn register(&mut self, registry: &Registry, token: Token) -> io::Result<()>;
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()>;
+}
+
+impl Transport for UdpSocket {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        UdpSocket::local_addr(self)
+    }
+
+    fn send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        UdpSocket::send_to(self, buf, target)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        UdpSocket::recv_from(self, buf)
+    }
+
+    fn register(&mut self, registry: &Registry, token: Token) -> io::Result<()> {
+        registry.register(self, token, Interest::READABLE.add(Interest::WRITABLE))
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        registry.deregister(self)
+    }
+}
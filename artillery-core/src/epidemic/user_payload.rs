@@ -0,0 +1,142 @@
+use super::broadcast::BroadcastQueue;
+use super::primitives::{NodeId, OperationId};
+use chrono::{DateTime, Duration, Utc};
+use serde::*;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Opaque application payload broadcast by a member, piggybacked on the protocol
+/// messages until every member got it. Delivered as `MemberPayload` on every member
+/// but its origin.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UserPayload {
+    #[serde(rename = "i")]
+    id: Uuid,
+    #[serde(rename = "o")]
+    origin: Uuid,
+    #[serde(rename = "d")]
+    data: Vec<u8>,
+    #[serde(rename = "t")]
+    sent_at: DateTime<Utc>,
+}
+
+impl UserPayload {
+    pub(crate) fn new(origin: Uuid, data: Vec<u8>, sent_at: DateTime<Utc>) -> Self {
+        UserPayload {
+            id: Uuid::new_v4(),
+            origin,
+            data,
+            sent_at,
+        }
+    }
+
+    pub fn id(&self) -> OperationId {
+        self.id.into()
+    }
+
+    /// Host key of the member which broadcast the payload.
+    pub fn origin(&self) -> NodeId {
+        self.origin.into()
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Payloads seen by this node, the new ones are gossiped on.
+#[derive(Debug)]
+pub(crate) struct UserPayloads {
+    seen: HashMap<Uuid, DateTime<Utc>>,
+    retention: Duration,
+    queue: BroadcastQueue<UserPayload>,
+}
+
+impl UserPayloads {
+    pub fn new(retransmits: usize, retention: Duration) -> Self {
+        UserPayloads {
+            seen: HashMap::new(),
+            retention,
+            queue: BroadcastQueue::new(retransmits),
+        }
+    }
+
+    /// Returns whether the payload is new. Payloads older than the retention are refused,
+    /// they might have been delivered and forgotten already.
+    pub fn apply(&mut self, payload: UserPayload, now: DateTime<Utc>) -> bool {
+        let expired = payload
+            .sent_at
+            .checked_add_signed(self.retention)
+            .is_some_and(|until| until <= now);
+        if expired || self.seen.contains_key(&payload.id) {
+            return false;
+        }
+
+        self.seen.insert(payload.id, payload.sent_at);
+        self.queue.push(payload);
+
+        true
+    }
+
+    pub fn next_batch(&mut self, max: usize) -> Vec<UserPayload> {
+        self.queue.next_batch(max)
+    }
+
+    pub fn prune(&mut self, now: DateTime<Utc>) {
+        let retention = self.retention;
+        self.seen.retain(|_, sent_at| {
+            sent_at
+                .checked_add_signed(retention)
+                .is_none_or(|until| until > now)
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::member::ArtilleryMemberState;
+    use crate::epidemic::state::ArtilleryMemberEvent;
+    use crate::epidemic::test_kit::*;
+
+    #[test]
+    fn gossips_fresh_payloads_once() {
+        let now = Utc::now();
+        let mut payloads = UserPayloads::new(2, Duration::minutes(5));
+
+        let payload = UserPayload::new(Uuid::new_v4(), b"hello".to_vec(), now);
+        assert!(payloads.apply(payload.clone(), now));
+        assert!(!payloads.apply(payload.clone(), now));
+        assert_eq!(payloads.next_batch(4), vec![payload.clone()]);
+        assert_eq!(payloads.next_batch(4), vec![payload]);
+        assert!(payloads.next_batch(4).is_empty());
+
+        let stale = UserPayload::new(Uuid::new_v4(), Vec::new(), now - Duration::minutes(6));
+        assert!(!payloads.apply(stale, now));
+
+        payloads.prune(now + Duration::minutes(6));
+        assert!(payloads.seen.is_empty());
+    }
+
+    #[test]
+    fn delivers_the_payloads_broadcast_by_members_once() {
+        let a = TestPeer::new();
+        let b = TestPeer::new();
+        let inbound = heartbeat_from(&a)
+            .with_state_changes(vec![b.member(ArtilleryMemberState::Alive)])
+            .with_user_payload(&b, b"hello");
+        ProtocolTester::new()
+            .recv(inbound.clone())
+            .expect_event("payload of b", |e| {
+                if let ArtilleryMemberEvent::MemberPayload(m, data) = e {
+                    m.host_key() == b.id() && data == b"hello"
+                } else {
+                    false
+                }
+            })
+            .recv(inbound)
+            .expect_no_event("payload of b again", |e| {
+                matches!(e, ArtilleryMemberEvent::MemberPayload(..))
+            });
+    }
+}
@@ -0,0 +1,253 @@
+use super::greeting::Greeting;
+use super::member::{ArtilleryMember, ArtilleryMemberState};
+use super::primitives::NodeId;
+use super::selector::{LabelIndex, Selector, ZONE_LABEL};
+use super::state::ArtilleryEventStamp;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard};
+use uuid::Uuid;
+
+/// Snapshot of the membership as of the last emitted cluster event.
+#[derive(Debug, Clone, Default)]
+pub struct MembershipView {
+    members: Vec<ArtilleryMember>,
+    annotations: HashMap<Uuid, BTreeMap<String, String>>,
+    greetings: HashMap<Uuid, Greeting>,
+    labels: LabelIndex,
+    last_event: Option<ArtilleryEventStamp>,
+    draining: bool,
+}
+
+impl MembershipView {
+    /// All the members which didn't leave, including this node.
+    pub fn members(&self) -> &[ArtilleryMember] {
+        &self.members
+    }
+
+    pub fn alive_members(&self) -> Vec<ArtilleryMember> {
+        self.in_state(ArtilleryMemberState::Alive)
+    }
+
+    pub fn suspected_members(&self) -> Vec<ArtilleryMember> {
+        self.in_state(ArtilleryMemberState::Suspect)
+    }
+
+    pub fn get(&self, id: &NodeId) -> Option<&ArtilleryMember> {
+        self.members.iter().find(|m| m.node_id() == *id)
+    }
+
+    /// Annotations written to the member by any node of the cluster.
+    pub fn annotations(&self, id: &NodeId) -> Option<&BTreeMap<String, String>> {
+        self.annotations.get(&Uuid::from(*id))
+    }
+
+    /// Self-description the member sent when it first contacted this node.
+    pub(crate) fn greeting(&self, id: &NodeId) -> Option<&Greeting> {
+        self.greetings.get(&Uuid::from(*id))
+    }
+
+    /// Protocol features the member announced when it first contacted this node.
+    pub fn capabilities(&self, id: &NodeId) -> Option<&[String]> {
+        self.greeting(id).map(Greeting::capabilities)
+    }
+
+    /// Members carrying all the labels of the selector, in any state but left.
+    pub fn select(&self, selector: &Selector) -> Vec<ArtilleryMember> {
+        if selector.is_empty() {
+            return self.members.clone();
+        }
+
+        let selected = self.labels.select(selector);
+        self.members
+            .iter()
+            .filter(|m| selected.contains(&m.host_key()))
+            .cloned()
+            .collect()
+    }
+
+    /// Annotations of the member and its zone.
+    pub fn labels(&self, id: &NodeId) -> BTreeMap<String, String> {
+        self.labels
+            .labels(&Uuid::from(*id))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Whether this node left the cluster and didn't rejoin since.
+    pub fn is_draining(&self) -> bool {
+        self.draining
+    }
+
+    /// Stamp of the event this view reflects, `None` before the first one.
+    pub fn last_event(&self) -> Option<ArtilleryEventStamp> {
+        self.last_event
+    }
+
+    fn in_state(&self, state: ArtilleryMemberState) -> Vec<ArtilleryMember> {
+        self.members
+            .iter()
+            .filter(|m| m.state() == state)
+            .cloned()
+            .collect()
+    }
+
+    fn reindex(&mut self, host_key: Uuid) {
+        let mut labels = self.annotations.get(&host_key).cloned().unwrap_or_default();
+        if let Some(zone) = self.get(&host_key.into()).and_then(ArtilleryMember::zone) {
+            labels.insert(ZONE_LABEL.to_string(), zone.to_string());
+        }
+        self.labels.set(host_key, labels);
+    }
+}
+
+/// Membership read model kept up to date by the cluster event loop.
+/// Cheap to clone and readable from any thread without a channel round trip.
+#[derive(Debug, Clone, Default)]
+pub struct SharedMembershipView(Arc<RwLock<MembershipView>>);
+
+impl SharedMembershipView {
+    pub(crate) fn new(members: Vec<ArtilleryMember>) -> Self {
+        let mut view = MembershipView {
+            members,
+            annotations: HashMap::new(),
+            greetings: HashMap::new(),
+            labels: LabelIndex::default(),
+            last_event: None,
+            draining: false,
+        };
+        let host_keys: Vec<Uuid> = view.members.iter().map(ArtilleryMember::host_key).collect();
+        for host_key in host_keys {
+            view.reindex(host_key);
+        }
+
+        SharedMembershipView(Arc::new(RwLock::new(view)))
+    }
+
+    /// Read access to the whole view, keep the guard short-lived as it blocks the updates.
+    pub fn read(&self) -> RwLockReadGuard<'_, MembershipView> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn alive_members(&self) -> Vec<ArtilleryMember> {
+        self.read().alive_members()
+    }
+
+    pub fn suspected_members(&self) -> Vec<ArtilleryMember> {
+        self.read().suspected_members()
+    }
+
+    pub fn get(&self, id: &NodeId) -> Option<ArtilleryMember> {
+        self.read().get(id).cloned()
+    }
+
+    pub fn annotations(&self, id: &NodeId) -> BTreeMap<String, String> {
+        self.read().annotations(id).cloned().unwrap_or_default()
+    }
+
+    /// Members carrying all the labels of the selector, without scanning the member list.
+    pub fn select(&self, selector: &Selector) -> Vec<ArtilleryMember> {
+        self.read().select(selector)
+    }
+
+    pub fn capabilities(&self, id: &NodeId) -> Option<Vec<String>> {
+        self.read().capabilities(id).map(<[String]>::to_vec)
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.read().is_draining()
+    }
+
+    pub(crate) fn set_draining(&self, draining: bool) {
+        self.0
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .draining = draining;
+    }
+
+    pub(crate) fn update_greeting(&self, greeting: Greeting) {
+        let mut view = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        view.greetings
+            .insert(greeting.member().host_key(), greeting);
+    }
+
+    pub(crate) fn update_annotations(&self, host_key: Uuid, annotations: BTreeMap<String, String>) {
+        let mut view = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        view.annotations.insert(host_key, annotations);
+        view.reindex(host_key);
+    }
+
+    /// Only the members which joined, left or moved to another zone are reindexed.
+    pub(crate) fn update(&self, members: Vec<ArtilleryMember>, stamp: ArtilleryEventStamp) {
+        let mut view = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        let previous: HashMap<Uuid, Option<String>> = view
+            .members
+            .iter()
+            .map(|m| (m.host_key(), m.zone().map(str::to_string)))
+            .collect();
+
+        let changed: Vec<Uuid> = members
+            .iter()
+            .filter_map(|m| {
+                let zone = m.zone().map(str::to_string);
+                if previous.get(&m.host_key()) == Some(&zone) {
+                    None
+                } else {
+                    Some(m.host_key())
+                }
+            })
+            .collect();
+        let departed: Vec<Uuid> = previous
+            .keys()
+            .filter(|host_key| members.iter().all(|m| m.host_key() != **host_key))
+            .copied()
+            .collect();
+
+        view.members = members;
+        view.last_event = Some(stamp);
+        for host_key in changed {
+            view.reindex(host_key);
+        }
+        for host_key in departed {
+            view.labels.remove(&host_key);
+            view.greetings.remove(&host_key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::epidemic::test_kit::*;
+    use std::thread;
+
+    #[test]
+    fn follows_the_membership_as_events_are_emitted() {
+        let (a, b) = (TestPeer::new(), TestPeer::new());
+        let mut tester = ProtocolTester::new();
+        let view = tester.state().view();
+        assert_eq!(view.alive_members().len(), 1);
+        assert!(view.read().last_event().is_none());
+
+        tester.recv(
+            heartbeat_from(&a).with_state_changes(vec![b.member(ArtilleryMemberState::Suspect)]),
+        );
+        let last = tester.drain_stamps().pop().unwrap();
+
+        let reader = view.clone();
+        let (alive, suspected, last_event) = thread::spawn(move || {
+            let snapshot = reader.read();
+            (
+                snapshot.alive_members(),
+                snapshot.suspected_members(),
+                snapshot.last_event(),
+            )
+        })
+        .join()
+        .unwrap();
+        assert_eq!(alive.len(), 2);
+        assert!(alive.iter().any(|m| m.host_key() == a.id()));
+        assert_eq!(suspected.len(), 1);
+        assert_eq!(suspected[0].host_key(), b.id());
+        assert_eq!(last_event, Some(last));
+    }
+}
@@ -0,0 +1,159 @@
+//! Wire compatibility harness.
+//!
+//! Every directory under `fixtures/wire` holds messages as they were serialized by a
+//! previous protocol revision. The current build has to decode all of them and still
+//! handle joins, probes, payloads and leaves coming from such peers.
+//!
+//! Messages captured from other builds (e.g. an older published binary) can be checked too,
+//! by pointing `ARTILLERY_COMPAT_FIXTURES` at a directory of JSON encoded messages.
+
+use super::cluster_config::ClusterConfig;
+use super::lanes::{request_lanes, RequestReceiver};
+use super::state::{
+    ArtilleryClusterEvent, ArtilleryClusterRequest, ArtilleryEpidemic, ArtilleryMemberEvent,
+    ArtilleryMessage,
+};
+use super::transport::DefaultTransport;
+use std::env;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use uuid::Uuid;
+
+const FIXTURE_PEER: &str = "6f0c1b7e-2f5a-4d8e-9a43-0d1f3c6b8a11";
+const FIXTURE_VERSION: &str = "v0";
+
+struct CompatNode {
+    state: ArtilleryEpidemic,
+    events: Receiver<ArtilleryClusterEvent>,
+    /// Keeps the lanes of the state machine open.
+    _requests: RequestReceiver,
+}
+
+impl CompatNode {
+    fn new() -> Self {
+        let (event_tx, events) = channel();
+        let config = ClusterConfig {
+            listen_addr: "127.0.0.1:0".parse().unwrap(),
+            ..Default::default()
+        };
+        let (internal_tx, requests) = request_lanes(&config);
+
+        let socket = DefaultTransport::bind(config.listen_addr).unwrap();
+        let (_poll, state) = ArtilleryEpidemic::with_transport(
+            Uuid::new_v4(),
+            config,
+            Box::new(socket),
+            event_tx,
+            internal_tx,
+        )
+        .unwrap();
+
+        CompatNode {
+            state,
+            events,
+            _requests: requests,
+        }
+    }
+
+    fn receive(&mut self, fixture: &str) {
+        let path = fixtures_root().join(FIXTURE_VERSION).join(fixture);
+        self.state.respond_to_message(peer_addr(), decode(&path));
+    }
+
+    fn next_event(&self) -> ArtilleryMemberEvent {
+        self.events.try_recv().expect("Expected a member event").1
+    }
+
+    fn reactions(&mut self) -> usize {
+        self.state
+            .take_reactions()
+            .iter()
+            .filter(|r| matches!(r, ArtilleryClusterRequest::React(_)))
+            .count()
+    }
+}
+
+fn fixtures_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures")
+        .join("wire")
+}
+
+fn peer_addr() -> SocketAddr {
+    "127.0.0.1:40001".parse().unwrap()
+}
+
+fn decode(path: &Path) -> ArtilleryMessage {
+    let raw = fs::read_to_string(path).unwrap();
+    serde_json::from_str(&raw)
+        .unwrap_or_else(|e| panic!("{} no longer decodes: {}", path.display(), e))
+}
+
+fn json_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files.extend(json_files(&path));
+        } else if path.extension().is_some_and(|e| e == "json") {
+            files.push(path);
+        }
+    }
+
+    files
+}
+
+#[test]
+fn fixtures_decode() {
+    let mut files = json_files(&fixtures_root());
+    if let Ok(extra) = env::var("ARTILLERY_COMPAT_FIXTURES") {
+        files.extend(json_files(Path::new(&extra)));
+    }
+
+    assert!(!files.is_empty());
+    for path in files {
+        decode(&path);
+    }
+}
+
+#[test]
+fn join_probe_and_leave() {
+    let peer: Uuid = FIXTURE_PEER.parse().unwrap();
+    let mut node = CompatNode::new();
+
+    node.receive("heartbeat_join.json");
+    if let ArtilleryMemberEvent::Joined(member) = node.next_event() {
+        assert_eq!(member.host_key(), peer);
+        assert_eq!(member.remote_host(), Some(peer_addr()));
+    } else {
+        panic!("Peer didn't join");
+    }
+    // Heartbeat is acknowledged, the join starts a gossip burst to the peer
+    assert_eq!(node.reactions(), 2);
+
+    node.receive("ack.json");
+    assert!(node.events.try_recv().is_err());
+
+    // Indirect probe is relayed to the target
+    node.receive("ping.json");
+    assert_eq!(node.reactions(), 1);
+
+    node.receive("ack_host.json");
+    node.receive("payload.json");
+    if let ArtilleryMemberEvent::Payload(member, msg) = node.next_event() {
+        assert_eq!(member.host_key(), peer);
+        assert_eq!(msg, "hello");
+    } else {
+        panic!("Payload wasn't delivered");
+    }
+
+    node.receive("leave.json");
+    if let ArtilleryMemberEvent::Left(member) = node.next_event() {
+        assert_eq!(member.host_key(), peer);
+    } else {
+        panic!("Peer didn't leave");
+    }
+}
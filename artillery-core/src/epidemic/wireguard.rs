@@ -0,0 +1,159 @@
+//!
+//! `WireGuard` peer configuration of the live members, so that the gossip and the application
+//! traffic run in an encrypted mesh maintained from the membership instead of encrypting
+//! the messages. Members take part by publishing their public key and the tunnel addresses
+//! routed to them in their metadata:
+//!
+//! ```ignore
+//! metadata.insert(WIREGUARD_PUBLIC_KEY.to_string(), b"xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg=".to_vec());
+//! metadata.insert(WIREGUARD_ALLOWED_IPS.to_string(), b"10.99.0.2/32".to_vec());
+//! ```
+//!
+//! The endpoint is the gossip address of the member with its `wireguard` port, see
+//! [`ArtilleryMember::ports`]. Members without a key or addresses are left out.
+use super::member::{ArtilleryMember, ArtilleryMemberState};
+use crate::errors::*;
+use std::fmt::Write;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// Metadata key of the base64 public key of a member.
+pub const WIREGUARD_PUBLIC_KEY: &str = "wireguard.public_key";
+/// Metadata key of the comma separated tunnel addresses routed to a member.
+pub const WIREGUARD_ALLOWED_IPS: &str = "wireguard.allowed_ips";
+/// Port name of the `WireGuard` listen port of a member.
+pub const WIREGUARD_PORT: &str = "wireguard";
+
+/// Settings of the `[Peer]` sections, e.g. `wg syncconf wg0 <(wg-quick strip wg0)` after
+/// including the file in the interface configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WireGuardExport {
+    /// File rewritten with the peers on every membership change, `None` writes none.
+    pub path: Option<PathBuf>,
+    /// Listen port of the members not publishing a `wireguard` port.
+    pub default_port: u16,
+    /// Keepalive interval in seconds, for members behind NATs.
+    pub persistent_keepalive: Option<u16>,
+}
+
+impl Default for WireGuardExport {
+    fn default() -> Self {
+        WireGuardExport {
+            path: None,
+            default_port: 51820,
+            persistent_keepalive: None,
+        }
+    }
+}
+
+/// `[Peer]` sections of the live and suspect remote members, by host key.
+pub fn wireguard_peers(members: &[ArtilleryMember], export: &WireGuardExport) -> String {
+    let mut peers: Vec<(&ArtilleryMember, SocketAddr)> = members
+        .iter()
+        .filter(|m| {
+            matches!(
+                m.state(),
+                ArtilleryMemberState::Alive | ArtilleryMemberState::Suspect
+            )
+        })
+        .filter_map(|m| m.remote_host().map(|addr| (m, addr)))
+        .collect();
+    peers.sort_by_key(|(m, _)| m.host_key());
+
+    let mut config = String::new();
+    for (member, addr) in peers {
+        let (public_key, allowed_ips) = match (
+            metadata_str(member, WIREGUARD_PUBLIC_KEY),
+            metadata_str(member, WIREGUARD_ALLOWED_IPS),
+        ) {
+            (Some(public_key), Some(allowed_ips)) => (public_key, allowed_ips),
+            (None, _) | (_, None) => continue,
+        };
+        let port = member.port(WIREGUARD_PORT).unwrap_or(export.default_port);
+
+        let _ = writeln!(config, "[Peer]");
+        let _ = writeln!(config, "# {}", member.host_key());
+        let _ = writeln!(config, "PublicKey = {}", public_key);
+        let _ = writeln!(config, "AllowedIPs = {}", allowed_ips);
+        let _ = writeln!(config, "Endpoint = {}", SocketAddr::new(addr.ip(), port));
+        if let Some(keepalive) = export.persistent_keepalive {
+            let _ = writeln!(config, "PersistentKeepalive = {}", keepalive);
+        }
+        let _ = writeln!(config);
+    }
+
+    config
+}
+
+/// Replaces the file of the export with the peers at once, `WireGuard` never reads half of it.
+pub(crate) fn write_wireguard_peers(
+    members: &[ArtilleryMember],
+    export: &WireGuardExport,
+) -> Result<()> {
+    let path = match export.path.as_ref() {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    let staging = path.with_extension("tmp");
+    fs::write(&staging, wireguard_peers(members, export))?;
+    fs::rename(&staging, path)?;
+
+    Ok(())
+}
+
+fn metadata_str<'a>(member: &'a ArtilleryMember, key: &str) -> Option<&'a str> {
+    member
+        .metadata()
+        .get(key)
+        .and_then(|value| std::str::from_utf8(value).ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn lists_the_members_publishing_a_key() {
+        let addr: SocketAddr = "10.0.0.2:7946".parse().unwrap();
+        let host_key = Uuid::new_v4();
+        let meshed = ArtilleryMember::new(host_key, addr, 0, ArtilleryMemberState::Alive)
+            .with_ports(
+                vec![(WIREGUARD_PORT.to_string(), 51821)]
+                    .into_iter()
+                    .collect(),
+            )
+            .with_metadata(
+                vec![
+                    (WIREGUARD_PUBLIC_KEY.to_string(), b"key=".to_vec()),
+                    (WIREGUARD_ALLOWED_IPS.to_string(), b"10.99.0.2/32".to_vec()),
+                ]
+                .into_iter()
+                .collect(),
+            );
+        let mut down = meshed.clone();
+        down.set_state(ArtilleryMemberState::Down);
+        let unmeshed = ArtilleryMember::new(Uuid::new_v4(), addr, 0, ArtilleryMemberState::Alive);
+        let export = WireGuardExport {
+            persistent_keepalive: Some(25),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            wireguard_peers(
+                &[ArtilleryMember::current(Uuid::new_v4()), unmeshed, meshed],
+                &export
+            ),
+            format!(
+                "[Peer]\n# {}\nPublicKey = key=\nAllowedIPs = 10.99.0.2/32\n\
+                 Endpoint = 10.0.0.2:51821\nPersistentKeepalive = 25\n\n",
+                host_key
+            )
+        );
+        assert_eq!(wireguard_peers(&[down], &export), "");
+    }
+}
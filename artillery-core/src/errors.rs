@@ -1,3 +1,4 @@
+use crate::epidemic::primitives::NodeId;
 use failure::*;
 use std::io;
 
@@ -26,6 +27,38 @@ pub enum ArtilleryError {
     Decoding(String),
     #[fail(display = "Artillery :: Numeric Cast Error: {}", _0)]
     NumericCast(String),
+    #[fail(display = "Artillery :: Invalid Argument: {}", _0)]
+    InvalidArgument(String),
+    #[fail(display = "Artillery :: Invalid Configuration: {}", _0)]
+    InvalidConfiguration(String),
+    #[fail(display = "Artillery :: Identity Conflict: {}", _0)]
+    IdentityConflict(String),
+    #[fail(display = "Artillery :: RPC Error: {}", _0)]
+    Rpc(String),
+    #[fail(display = "Artillery :: RPC Timeout: {}", _0)]
+    RpcTimeout(String),
+}
+
+/// Why `Cluster::try_send_payload` didn't queue the payload, nothing is retried.
+#[derive(Fail, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendPayloadError {
+    /// Not a member of the cluster, or a member which left.
+    #[fail(display = "Artillery :: Unknown Member: {}", _0)]
+    UnknownMember(NodeId),
+    /// This node left the cluster and didn't rejoin.
+    #[fail(display = "Artillery :: Draining, this node left the cluster")]
+    Draining,
+    #[fail(display = "Artillery :: Payload Queue Full")]
+    QueueFull,
+    /// The event loop is gone.
+    #[fail(display = "Artillery :: Not Connected")]
+    NotConnected,
+}
+
+impl From<SendPayloadError> for ArtilleryError {
+    fn from(e: SendPayloadError) -> Self {
+        ArtilleryError::Send(e.to_string())
+    }
 }
 
 impl From<io::Error> for ArtilleryError {
@@ -40,6 +73,24 @@ impl From<serde_json::error::Error> for ArtilleryError {
     }
 }
 
+impl From<serde_cbor::Error> for ArtilleryError {
+    fn from(e: serde_cbor::Error) -> Self {
+        ArtilleryError::ClusterMessageDecode(e.to_string())
+    }
+}
+
+impl From<rmp_serde::decode::Error> for ArtilleryError {
+    fn from(e: rmp_serde::decode::Error) -> Self {
+        ArtilleryError::ClusterMessageDecode(e.to_string())
+    }
+}
+
+impl From<bincode::Error> for ArtilleryError {
+    fn from(e: bincode::Error) -> Self {
+        ArtilleryError::ClusterMessageDecode(e.to_string())
+    }
+}
+
 impl<T> From<std::sync::mpsc::SendError<T>> for ArtilleryError {
     fn from(e: SendError<T>) -> Self {
         ArtilleryError::Send(e.to_string())
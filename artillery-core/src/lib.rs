@@ -67,6 +67,10 @@ extern crate log;
 #[macro_use]
 pub mod errors;
 
+/// Per-subsystem logging
+#[macro_use]
+pub mod logging;
+
 /// Constants of the Artillery
 pub mod constants;
 
@@ -78,3 +82,18 @@ pub mod service_discovery;
 
 /// Cluster types
 pub mod cluster;
+
+/// Items most applications need: `use artillery_core::prelude::*;`
+pub mod prelude {
+    pub use crate::epidemic::address::PeerAddr;
+    pub use crate::epidemic::cluster::Cluster;
+    pub use crate::epidemic::cluster_config::ClusterConfig;
+    pub use crate::epidemic::diagnostics::ClusterDiagnostic;
+    pub use crate::epidemic::identity::NodeIdentity;
+    pub use crate::epidemic::member::{ArtilleryMember, ArtilleryMemberState};
+    pub use crate::epidemic::state::{
+        ArtilleryClusterEvent, ArtilleryEventStamp, ArtilleryMemberEvent,
+    };
+    pub use crate::epidemic::view::SharedMembershipView;
+    pub use crate::errors::{ArtilleryError, Result as ArtilleryResult};
+}
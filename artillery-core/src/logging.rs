@@ -0,0 +1,247 @@
+//!
+//! Logging of the cluster by subsystem. Every subsystem logs under its own target
+//! (`artillery::transport`...) and has a level adjustable at runtime, on top of the
+//! filtering of the installed logger:
+//!
+//! ```rust
+//! use artillery_core::logging::{self, Subsystem};
+//! use log::LevelFilter;
+//!
+//! logging::set_level(Subsystem::Transport, LevelFilter::Trace);
+//! logging::configure("dissemination=warn,events=off").unwrap();
+//! ```
+//!
+//! Lines logged while a thread works for a cluster are prefixed with the instance name of
+//! the cluster, e.g. `[orders]`, telling apart the clusters of a process.
+
+use crate::errors::*;
+use log::{Level, LevelFilter};
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Subsystems logging separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    /// Sockets, encoding and message exchange.
+    Transport,
+    /// Failure detection: suspicion, probes, connectivity.
+    Detector,
+    /// Gossip of state changes, transactions and annotations.
+    Dissemination,
+    /// Delivery of the cluster events to the application.
+    Events,
+    /// Event loop and thread lifecycle.
+    Runtime,
+}
+
+static TRANSPORT: AtomicUsize = AtomicUsize::new(5);
+static DETECTOR: AtomicUsize = AtomicUsize::new(5);
+static DISSEMINATION: AtomicUsize = AtomicUsize::new(5);
+static EVENTS: AtomicUsize = AtomicUsize::new(5);
+static RUNTIME: AtomicUsize = AtomicUsize::new(5);
+
+impl Subsystem {
+    pub fn target(self) -> &'static str {
+        match self {
+            Subsystem::Transport => "artillery::transport",
+            Subsystem::Detector => "artillery::detector",
+            Subsystem::Dissemination => "artillery::dissemination",
+            Subsystem::Events => "artillery::events",
+            Subsystem::Runtime => "artillery::runtime",
+        }
+    }
+
+    fn level(self) -> &'static AtomicUsize {
+        match self {
+            Subsystem::Transport => &TRANSPORT,
+            Subsystem::Detector => &DETECTOR,
+            Subsystem::Dissemination => &DISSEMINATION,
+            Subsystem::Events => &EVENTS,
+            Subsystem::Runtime => &RUNTIME,
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "transport" => Some(Subsystem::Transport),
+            "detector" => Some(Subsystem::Detector),
+            "dissemination" => Some(Subsystem::Dissemination),
+            "events" => Some(Subsystem::Events),
+            "runtime" => Some(Subsystem::Runtime),
+            _ => None,
+        }
+    }
+}
+
+/// Everything is logged by default, as far as the installed logger lets it through.
+pub fn set_level(subsystem: Subsystem, level: LevelFilter) {
+    let value = match level {
+        LevelFilter::Off => 0,
+        LevelFilter::Error => 1,
+        LevelFilter::Warn => 2,
+        LevelFilter::Info => 3,
+        LevelFilter::Debug => 4,
+        LevelFilter::Trace => 5,
+    };
+    subsystem.level().store(value, Ordering::Relaxed);
+}
+
+pub fn level(subsystem: Subsystem) -> LevelFilter {
+    match subsystem.level().load(Ordering::Relaxed) {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Applies a `subsystem=level` list separated with commas, e.g. `transport=debug,events=off`.
+pub fn configure(spec: &str) -> Result<()> {
+    let mut levels = Vec::new();
+
+    for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        let mut parts = directive.splitn(2, '=');
+        let name = parts.next().unwrap_or_default().trim();
+        let value = parts.next().unwrap_or_default().trim();
+
+        let subsystem = Subsystem::parse(name).ok_or_else(|| {
+            ArtilleryError::InvalidArgument(format!("Unknown logging subsystem '{}'", name))
+        })?;
+        let filter = value.parse::<LevelFilter>().map_err(|_| {
+            ArtilleryError::InvalidArgument(format!("Invalid log level '{}' for {}", value, name))
+        })?;
+
+        levels.push((subsystem, filter));
+    }
+
+    for (subsystem, filter) in levels {
+        set_level(subsystem, filter);
+    }
+
+    Ok(())
+}
+
+thread_local! {
+    static INSTANCE: RefCell<Option<Arc<str>>> = const { RefCell::new(None) };
+}
+
+/// Marks the current thread as working for the named cluster instance until dropped.
+pub struct InstanceGuard {
+    previous: Option<Arc<str>>,
+}
+
+impl Drop for InstanceGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        INSTANCE.with(|instance| *instance.borrow_mut() = previous);
+    }
+}
+
+/// Prefixes the lines logged by the current thread with `name`, the guard restores the
+/// previous instance.
+pub fn enter_instance<N: Into<Arc<str>>>(name: N) -> InstanceGuard {
+    let previous = INSTANCE.with(|instance| instance.replace(Some(name.into())));
+    InstanceGuard { previous }
+}
+
+#[doc(hidden)]
+pub struct InstancePrefix;
+
+impl fmt::Display for InstancePrefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        INSTANCE.with(|instance| match instance.borrow().as_ref() {
+            Some(name) => write!(f, "[{}] ", name),
+            None => Ok(()),
+        })
+    }
+}
+
+#[doc(hidden)]
+pub fn enabled(subsystem: Subsystem, level: Level) -> bool {
+    level <= self::level(subsystem)
+}
+
+macro_rules! log_subsystem {
+    ($subsystem:expr, $level:ident, $($arg:tt)+) => {
+        if $crate::logging::enabled($subsystem, log::Level::$level) {
+            log!(
+                target: $subsystem.target(),
+                log::Level::$level,
+                "{}{}",
+                $crate::logging::InstancePrefix,
+                format_args!($($arg)+)
+            );
+        }
+    };
+}
+
+macro_rules! log_transport {
+    ($level:ident, $($arg:tt)+) => {
+        log_subsystem!($crate::logging::Subsystem::Transport, $level, $($arg)+)
+    };
+}
+
+macro_rules! log_detector {
+    ($level:ident, $($arg:tt)+) => {
+        log_subsystem!($crate::logging::Subsystem::Detector, $level, $($arg)+)
+    };
+}
+
+macro_rules! log_dissemination {
+    ($level:ident, $($arg:tt)+) => {
+        log_subsystem!($crate::logging::Subsystem::Dissemination, $level, $($arg)+)
+    };
+}
+
+macro_rules! log_events {
+    ($level:ident, $($arg:tt)+) => {
+        log_subsystem!($crate::logging::Subsystem::Events, $level, $($arg)+)
+    };
+}
+
+macro_rules! log_runtime {
+    ($level:ident, $($arg:tt)+) => {
+        log_subsystem!($crate::logging::Subsystem::Runtime, $level, $($arg)+)
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn configures_levels_by_subsystem() {
+        configure("detector=debug, events=off").unwrap();
+
+        assert_eq!(level(Subsystem::Detector), LevelFilter::Debug);
+        assert!(enabled(Subsystem::Detector, Level::Info));
+        assert!(!enabled(Subsystem::Detector, Level::Trace));
+        assert!(!enabled(Subsystem::Events, Level::Error));
+
+        assert!(configure("detector=loud").is_err());
+        assert!(configure("gossip=info").is_err());
+        assert_eq!(level(Subsystem::Detector), LevelFilter::Debug);
+
+        set_level(Subsystem::Detector, LevelFilter::Trace);
+        set_level(Subsystem::Events, LevelFilter::Trace);
+    }
+
+    #[test]
+    fn prefixes_lines_with_the_instance() {
+        assert_eq!(InstancePrefix.to_string(), "");
+
+        let outer = enter_instance("orders");
+        {
+            let _inner = enter_instance(String::from("billing"));
+            assert_eq!(InstancePrefix.to_string(), "[billing] ");
+        }
+        assert_eq!(InstancePrefix.to_string(), "[orders] ");
+
+        drop(outer);
+        assert_eq!(InstancePrefix.to_string(), "");
+    }
+}